@@ -1,22 +1,24 @@
 //! Decoding of [`Event`]s from binary
 
-use std::borrow::Cow;
+use std::{any::type_name, borrow::Cow, collections::HashMap, fmt, io::Read};
 
-use blake3::Hash;
-use bytes::{Buf, Bytes};
+use bytes::{Buf, Bytes, BytesMut};
 use ed25519_dalek::Signature;
 use xh_reports::prelude::*;
 
 use crate::{
-    Event, Object, ObjectContent,
-    utils::{ArchiveCompat, MAGIC, Marker, VERSION, hash_object},
+    Digest, Event, HashAlgorithm, Object, ObjectContent, PathBytes,
+    utils::{
+        ALGORITHM_VERSION, DIGEST_LEN, Hasher, INDEX_VERSION, LEGACY_VERSION, MAGIC, Marker,
+        PREFIX, State, VERSION, hash_object,
+    },
 };
 
 /// An unexpected token was encountered
 #[derive(Debug, IntoReport)]
 #[message("unexpected token encountered")]
 #[suggestion("provide {expected}")]
-#[context(token)]
+#[attachment(bytes: token)]
 pub struct UnexpectedTokenError {
     #[allow(missing_docs)]
     token: Bytes,
@@ -28,7 +30,7 @@ pub struct UnexpectedTokenError {
 /// The archive had an unsupported version
 #[derive(Debug, IntoReport)]
 #[message("unsupported version")]
-#[suggestion("provide version {VERSION}")]
+#[suggestion("provide a version from {LEGACY_VERSION} to {VERSION}")]
 #[context(version)]
 pub struct UnsupportedVersionError {
     version: u16,
@@ -45,10 +47,111 @@ pub struct DigestMismatchError {
     #[allow(missing_docs)]
     #[format(message)]
     #[format(suggestion)]
-    expected: Hash,
+    pub expected: Digest,
     #[allow(missing_docs)]
     #[format(message)]
-    found: Hash,
+    pub found: Digest,
+}
+
+/// A length prefix exceeded the decoder's maximum object size
+#[derive(Debug, IntoReport)]
+#[message("length exceeds maximum object size")]
+#[suggestion("raise the decoder's maximum object size, or provide a trusted archive")]
+#[context(length, max)]
+pub struct ObjectSizeError {
+    length: u64,
+    max: usize,
+}
+
+/// A footer claimed more signatures than the decoder's maximum, see [`Decoder::with_max_signatures`]
+#[derive(Debug, IntoReport)]
+#[message("footer exceeds maximum signature count")]
+#[suggestion("raise the decoder's maximum signature count, or provide a trusted archive")]
+#[context(amount, max)]
+pub struct SignatureCountError {
+    amount: u64,
+    max: usize,
+}
+
+/// A [`Event::Header`] was decoded before the previous archive's [`Event::Footer`]
+#[derive(Debug, Default, IntoReport)]
+#[message("header encountered before the previous archive's footer")]
+#[suggestion("provide a footer for the current archive first, or disable strict mode")]
+pub struct MissingFooterError;
+
+/// The archive was encoded without an index, see [`RandomAccessReader`]
+#[derive(Debug, Default, IntoReport)]
+#[message("archive has no index")]
+#[suggestion("encode the archive with an index")]
+pub struct MissingIndexError;
+
+/// An index entry did not point at the object it was listed for
+#[derive(Debug, IntoReport)]
+#[message("index entry does not point at its object")]
+#[context(location)]
+pub struct IndexMismatchError {
+    location: PathBytes,
+}
+
+/// The buffer ended before a complete token or field could be read
+#[derive(Debug, IntoReport)]
+#[message("unexpected end of buffer")]
+#[suggestion("provide at least {requested} bytes")]
+#[context(requested, available)]
+pub struct IncompleteError {
+    #[format(suggestion)]
+    requested: usize,
+    available: usize,
+}
+
+impl From<bytes::TryGetError> for IncompleteError {
+    fn from(error: bytes::TryGetError) -> Self {
+        Self {
+            requested: error.requested,
+            available: error.available,
+        }
+    }
+}
+
+/// Object counts and sizes tallied by a [`Decoder`], see [`Decoder::with_stats`]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeStats {
+    /// Amount of file objects
+    pub files: usize,
+    /// Total size of file contents, in bytes
+    pub file_bytes: u64,
+    /// Amount of symlink objects
+    pub symlinks: usize,
+    /// Total size of symlink targets, in bytes
+    pub symlink_bytes: u64,
+    /// Amount of directory objects
+    pub directories: usize,
+}
+
+impl DecodeStats {
+    fn record(&mut self, content: &ObjectContent) {
+        match content {
+            ObjectContent::File { data } => {
+                self.files += 1;
+                self.file_bytes += data.len() as u64;
+            }
+            ObjectContent::Symlink { target } => {
+                self.symlinks += 1;
+                self.symlink_bytes += target.len() as u64;
+            }
+            ObjectContent::Directory => self.directories += 1,
+        }
+    }
+}
+
+impl fmt::Display for DecodeStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} files ({} bytes), {} symlinks ({} bytes), {} directories",
+            self.files, self.file_bytes, self.symlinks, self.symlink_bytes, self.directories
+        )
+    }
 }
 
 /// Error type for decoding
@@ -60,10 +163,34 @@ pub struct Error;
 ///
 /// The decoder consumes [`Bytes`] and outputs [`Event`]s
 ///
-/// A single decoder can decode multiple archives.
-#[derive(Default)]
+/// A single decoder can decode multiple archives, one after the other.
+/// By default, a header also starts a new archive when the previous one is missing its footer,
+/// see [`Self::with_strict`] to reject that instead.
 pub struct Decoder {
-    hasher: blake3::Hasher,
+    hasher: Hasher,
+    max_object_size: Option<usize>,
+    max_signatures: usize,
+    strict: bool,
+    stats: Option<DecodeStats>,
+    indexed: bool,
+    state: State,
+}
+
+/// Default for [`Decoder::with_max_signatures`]
+pub const DEFAULT_MAX_SIGNATURES: usize = 16;
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self {
+            hasher: Hasher::default(),
+            max_object_size: None,
+            max_signatures: DEFAULT_MAX_SIGNATURES,
+            strict: false,
+            stats: None,
+            indexed: false,
+            state: State::default(),
+        }
+    }
 }
 
 impl Decoder {
@@ -73,6 +200,61 @@ impl Decoder {
         Self::default()
     }
 
+    /// Rejects any length-prefixed field (locations, file contents, and symlink targets)
+    /// longer than `max` bytes, before reading it.
+    ///
+    /// Unbounded by default. Set this when decoding untrusted archives.
+    #[inline]
+    pub fn with_max_object_size(mut self, max: usize) -> Self {
+        self.max_object_size = Some(max);
+        self
+    }
+
+    /// Rejects any footer claiming more than `max` signatures, before reading them.
+    ///
+    /// Defaults to [`DEFAULT_MAX_SIGNATURES`].
+    #[inline]
+    pub fn with_max_signatures(mut self, max: usize) -> Self {
+        self.max_signatures = max;
+        self
+    }
+
+    /// Rejects an [`Event::Header`] decoded before the previous archive's [`Event::Footer`],
+    /// so concatenated archives with a missing footer are not silently merged.
+    ///
+    /// Disabled by default.
+    #[inline]
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Tallies decoded objects by content type, see [`Self::stats`].
+    ///
+    /// Corrupt objects skipped by [`Self::decode_iter_lossy`] are not counted.
+    #[inline]
+    pub fn with_stats(mut self) -> Self {
+        self.stats = Some(DecodeStats::default());
+        self
+    }
+
+    /// Gets the objects tallied so far, across every decoded archive.
+    ///
+    /// Returns [`None`] unless enabled with [`Self::with_stats`].
+    #[inline]
+    pub fn stats(&self) -> Option<&DecodeStats> {
+        self.stats.as_ref()
+    }
+
+    /// Discards the current archive's state, so the next decoded event starts a fresh archive.
+    ///
+    /// Options such as [`Self::with_max_object_size`], and tallied [`Self::stats`], are kept.
+    #[inline]
+    pub fn reset(&mut self) {
+        self.hasher = Hasher::default();
+        self.state = State::Outside;
+    }
+
     /// Decodes [`Bytes`] into an iterator of [`Event`]s.
     ///
     /// # Errors
@@ -95,15 +277,146 @@ impl Decoder {
         })
     }
 
+    /// Decodes an [`Read`]er into an iterator of [`Event`]s.
+    ///
+    /// Only the undecoded remainder of the archive is buffered,
+    /// so memory use is bounded by the largest object instead of the whole archive.
+    ///
+    /// # Errors
+    ///
+    /// Events cut off by the end of `reader` fail like they would in [`Self::decode_iter`].
+    pub fn decode_reader(
+        &mut self,
+        mut reader: impl Read,
+    ) -> impl Iterator<Item = Result<Event, Error>> {
+        let mut buffer = Bytes::new();
+        let mut eof = false;
+        std::iter::from_fn(move || {
+            loop {
+                if !buffer.is_empty() {
+                    let mut attempt = buffer.clone();
+                    match self.process(&mut attempt) {
+                        Ok(event) => {
+                            buffer = attempt;
+                            return Some(Ok(event));
+                        }
+                        Err(error) if eof || !is_incomplete(&error) => return Some(Err(error)),
+                        Err(_) => (),
+                    }
+                } else if eof {
+                    return None;
+                }
+
+                // grows geometrically, so large objects aren't copied for every chunk
+                let additional = buffer.len().max(READ_CHUNK);
+                let mut grown = BytesMut::with_capacity(buffer.len() + additional);
+                grown.extend_from_slice(&buffer);
+                grown.resize(buffer.len() + additional, 0);
+
+                let mut filled = buffer.len();
+                while filled < grown.len() {
+                    match reader.read(&mut grown[filled..]) {
+                        Ok(0) => {
+                            eof = true;
+                            break;
+                        }
+                        Ok(read) => filled += read,
+                        Err(error) if error.kind() == std::io::ErrorKind::Interrupted => (),
+                        Err(error) => return Some(Err(error).wrap()),
+                    }
+                }
+
+                grown.truncate(filled);
+                buffer = grown.freeze();
+            }
+        })
+    }
+
+    /// Decodes [`Bytes`] into an iterator of [`Event`]s, skipping corrupt objects.
+    ///
+    /// When an object's digest does not match its contents, the object is skipped
+    /// using its length prefixes, and its byte offset (relative to the start of `buffer`)
+    /// is pushed onto `skipped`. Objects whose framing is damaged are skipped by scanning
+    /// ahead to the next `xuehua-archive@` prefix instead, which leaves their digest
+    /// out of the archive digest, so the footer will fail to verify.
+    ///
+    /// # Errors
+    ///
+    /// Any other error behaves as it does in [`Self::decode_iter`].
+    pub fn decode_iter_lossy(
+        &mut self,
+        buffer: &mut Bytes,
+        skipped: &mut Vec<usize>,
+    ) -> impl Iterator<Item = Result<Event, Error>> {
+        let len = buffer.len();
+        std::iter::from_fn(move || {
+            loop {
+                if buffer.is_empty() {
+                    return None;
+                }
+
+                let mut attempt = buffer.clone();
+                let error = match self.process(&mut attempt) {
+                    Ok(event) => {
+                        *buffer = attempt;
+                        return Some(Ok(event));
+                    }
+                    Err(error) => error,
+                };
+
+                let Some((next, found)) = self.corrupt_object(buffer) else {
+                    return Some(Err(error));
+                };
+
+                let position = len - buffer.len();
+                tracing::warn!(position, "skipping corrupt object");
+                skipped.push(position);
+
+                // fold in the stored digest, so the footer can still be verified
+                if let Some(found) = found {
+                    self.hasher.update(found.as_bytes());
+                }
+                buffer.advance(next);
+            }
+        })
+    }
+
     /// Gets the current digest of the archive.
     #[inline]
-    pub fn digest(&self) -> blake3::Hash {
+    pub fn digest(&self) -> Digest {
         self.hasher.finalize()
     }
 
+    /// Gets the digest algorithm of the current archive.
+    #[inline]
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.hasher.algorithm()
+    }
+
+    /// Returns the length of the corrupt object `buffer` starts with, if any.
+    ///
+    /// If the object is intact but its digest does not match its contents, the stored digest is returned too.
+    /// If its framing is damaged, the length extends to the next `xuehua-archive@` prefix.
+    fn corrupt_object(&self, buffer: &Bytes) -> Option<(usize, Option<Digest>)> {
+        let mut remaining = buffer.clone();
+        let token = try_split_to(&mut remaining, PREFIX.len() + Marker::len()).ok()?;
+        if token[..PREFIX.len()] != *PREFIX.as_bytes() || token[PREFIX.len()..] != *b"ob" {
+            return None;
+        }
+
+        let algorithm = self.hasher.algorithm();
+        match read_object(&mut remaining, algorithm, self.max_object_size) {
+            Ok((object, found)) => (hash_object(algorithm, &object) != found)
+                .then_some((buffer.len() - remaining.len(), Some(found))),
+            Err(_) => buffer[1..]
+                .windows(PREFIX.len())
+                .position(|window| window == PREFIX.as_bytes())
+                .map(|offset| (offset + 1, None)),
+        }
+    }
+
     #[tracing::instrument(level = "trace", skip_all)]
     fn process(&mut self, buffer: &mut Bytes) -> Result<Event, Error> {
-        const PREFIX: &str = "xuehua-archive@";
         let token = try_split_to(buffer, PREFIX.len())?;
         if token != PREFIX {
             return Err(UnexpectedTokenError {
@@ -118,15 +431,20 @@ impl Decoder {
             b"hd" => self.process_header(buffer),
             b"ft" => self.process_footer(buffer),
             b"ob" => self.process_object(buffer),
+            b"ix" if self.indexed => self.process_index(buffer),
             _ => Err(UnexpectedTokenError {
                 token,
-                expected: r#""hd", "ft", or "ob""#.into(),
+                expected: r#""hd", "ft", "ob", or "ix""#.into(),
             }
             .wrap()),
         }
     }
 
     fn process_header(&mut self, buffer: &mut Bytes) -> Result<Event, Error> {
+        if self.strict && self.state == State::Inside {
+            return Err(MissingFooterError.wrap());
+        }
+
         let magic = try_split_to(buffer, MAGIC.len())?;
         if magic != MAGIC {
             return Err(UnexpectedTokenError {
@@ -136,23 +454,52 @@ impl Decoder {
             .wrap());
         }
 
-        let version = buffer.try_get_u16_le().compat().wrap()?;
-        if version != VERSION {
-            return Err(UnsupportedVersionError { version }.wrap());
-        }
+        let version = buffer
+            .try_get_u16_le()
+            .map_err(IncompleteError::from)
+            .wrap()?;
+        let algorithm = match version {
+            LEGACY_VERSION => HashAlgorithm::Blake3,
+            ALGORITHM_VERSION..=VERSION => {
+                let algorithm = buffer.try_get_u8().map_err(IncompleteError::from).wrap()?;
+                HashAlgorithm::from_u8(algorithm).ok_or_else(|| {
+                    UnexpectedTokenError {
+                        token: Bytes::copy_from_slice(&[algorithm]),
+                        expected: "0 or 1".into(),
+                    }
+                    .wrap()
+                })?
+            }
+            version => return Err(UnsupportedVersionError { version }.wrap()),
+        };
 
-        self.hasher.reset();
+        self.hasher = Hasher::new(algorithm);
+        self.indexed = version >= INDEX_VERSION;
+        self.state = State::Inside;
         Ok(Event::Header)
     }
 
-    fn process_footer(&self, buffer: &mut Bytes) -> Result<Event, Error> {
-        let hash = self.hasher.finalize();
-        verify_hash(buffer, hash)?;
+    fn process_footer(&mut self, buffer: &mut Bytes) -> Result<Event, Error> {
+        let digest = self.hasher.finalize();
+        verify_digest(buffer, digest)?;
+
+        let amount = buffer
+            .try_get_u64_le()
+            .map_err(IncompleteError::from)
+            .wrap()?;
+        if amount > self.max_signatures as u64 {
+            return Err(SignatureCountError {
+                amount,
+                max: self.max_signatures,
+            }
+            .wrap());
+        }
 
-        let amount = buffer.try_get_u64_le().compat().wrap()?.try_into().wrap()?;
         let signatures = (0..amount)
             .map(|_| {
-                let fingerprint = try_get_hash(buffer)?;
+                let fingerprint = try_split_to(buffer, DIGEST_LEN).map(|bytes| {
+                    blake3::Hash::from_slice(&bytes).expect("bytes should be DIGEST_LEN long")
+                })?;
                 let signature = Signature::from_slice(&try_split_to(buffer, Signature::BYTE_SIZE)?)
                     .expect("bytes should be BYTE_SIZE long");
 
@@ -160,71 +507,300 @@ impl Decoder {
             })
             .collect::<Result<_, _>>()?;
 
+        self.state = State::Outside;
         Ok(Event::Footer(signatures))
     }
 
+    /// Skips the index, which is only used by [`RandomAccessReader`], and decodes the footer following it.
+    fn process_index(&mut self, buffer: &mut Bytes) -> Result<Event, Error> {
+        read_index(buffer, self.max_object_size)?;
+
+        let token = try_split_to(buffer, PREFIX.len() + Marker::len())?;
+        if token[..PREFIX.len()] != *PREFIX.as_bytes() || token[PREFIX.len()..] != *b"ft" {
+            return Err(UnexpectedTokenError {
+                token,
+                expected: "a footer after the index".into(),
+            }
+            .wrap());
+        }
+
+        self.process_footer(buffer)
+    }
+
     fn process_object(&mut self, buffer: &mut Bytes) -> Result<Event, Error> {
-        let location = process_plen(buffer)?.into();
-        let permissions = buffer.try_get_u32_le().compat().wrap()?;
-
-        let variant = buffer.try_get_u8().compat().wrap()?;
-        let content = match variant {
-            0 => ObjectContent::File {
-                data: process_plen(buffer)?,
-            },
-            1 => ObjectContent::Symlink {
-                target: process_plen(buffer)?.into(),
-            },
-            2 => ObjectContent::Directory,
-            _ => {
-                return Err(UnexpectedTokenError {
-                    token: Bytes::copy_from_slice(&[variant]),
-                    expected: "0, 1, or 2".into(),
-                }
-                .wrap());
+        let algorithm = self.hasher.algorithm();
+        let (object, found) = read_object(buffer, algorithm, self.max_object_size)?;
+        let expected = hash_object(algorithm, &object);
+        check_digest(found, expected)
+            .with_frame(|| Frame::context("location", format_args!("{:?}", object.location)))?;
+        self.hasher.update(expected.as_bytes());
+        if let Some(stats) = &mut self.stats {
+            stats.record(&object.content);
+        }
+
+        Ok(Event::Object(object))
+    }
+}
+
+/// Reader for single objects of an archive encoded with an index,
+/// see [`Encoder::with_index`](crate::encoding::Encoder::with_index)
+///
+/// Each read object is verified against its own digest,
+/// but the archive's digest is not, as that requires decoding every object.
+/// Use a [`Decoder`] to verify the whole archive.
+pub struct RandomAccessReader {
+    archive: Bytes,
+    algorithm: HashAlgorithm,
+    index: HashMap<PathBytes, (u64, u64)>,
+}
+
+impl RandomAccessReader {
+    /// Reads the header and index of a single archive, which must span the whole of `archive`.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`MissingIndexError`] if the archive was encoded without an index.
+    pub fn new(archive: Bytes) -> Result<Self, Error> {
+        let mut buffer = archive.clone();
+        let token = try_split_to(&mut buffer, PREFIX.len() + Marker::len())?;
+        if token[..PREFIX.len()] != *PREFIX.as_bytes() || token[PREFIX.len()..] != *b"hd" {
+            return Err(UnexpectedTokenError {
+                token,
+                expected: "a header".into(),
             }
+            .wrap());
+        }
+
+        let mut decoder = Decoder::new();
+        decoder.process_header(&mut buffer)?;
+        let footer = find_footer(&archive)
+            .filter(|_| decoder.indexed)
+            .ok_or_else(|| MissingIndexError.wrap())?;
+
+        let end = footer
+            .checked_sub(8)
+            .ok_or_else(|| MissingIndexError.wrap())?;
+        let offset = (&archive[end..footer]).get_u64_le();
+        let start = usize::try_from(offset)
+            .ok()
+            .filter(|start| *start <= end)
+            .ok_or_else(|| MissingIndexError.wrap())?;
+
+        let mut buffer = archive.slice(start..footer);
+        let token = try_split_to(&mut buffer, PREFIX.len() + Marker::len())?;
+        if token[..PREFIX.len()] != *PREFIX.as_bytes() || token[PREFIX.len()..] != *b"ix" {
+            return Err(MissingIndexError.wrap());
+        }
+
+        let index = read_index(&mut buffer, None)?
+            .into_iter()
+            .map(|(location, offset, length)| (location, (offset, length)))
+            .collect();
+
+        Ok(Self {
+            archive,
+            algorithm: decoder.algorithm(),
+            index,
+        })
+    }
+
+    /// Gets the locations of every indexed object, in no particular order.
+    #[inline]
+    pub fn locations(&self) -> impl Iterator<Item = &PathBytes> {
+        self.index.keys()
+    }
+
+    /// Reads the object at `location`, or [`None`] if it is not indexed.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`IndexMismatchError`] if the index entry does not point at an intact object at `location`.
+    pub fn read(&self, location: &PathBytes) -> Result<Option<Object>, Error> {
+        let Some(&(offset, length)) = self.index.get(location) else {
+            return Ok(None);
         };
 
-        let object = Object {
-            location,
-            permissions,
-            content,
+        let mismatch = || IndexMismatchError {
+            location: location.clone(),
         };
+        let object = self.read_at(offset, length).wrap_with_fn(mismatch).wrap()?;
+        if object.location != *location {
+            return Err(mismatch().wrap());
+        }
 
-        let hash = hash_object(&object);
-        verify_hash(buffer, hash)?;
-        self.hasher.update(hash.as_bytes());
+        Ok(Some(object))
+    }
 
-        Ok(Event::Object(object))
+    fn read_at(&self, offset: u64, length: u64) -> Result<Object, Error> {
+        let mut buffer = self.archive.clone();
+        try_split_to(&mut buffer, offset.try_into().wrap()?)?;
+        let mut buffer = try_split_to(&mut buffer, length.try_into().wrap()?)?;
+
+        let token = try_split_to(&mut buffer, PREFIX.len() + Marker::len())?;
+        if token[..PREFIX.len()] != *PREFIX.as_bytes() || token[PREFIX.len()..] != *b"ob" {
+            return Err(UnexpectedTokenError {
+                token,
+                expected: "an object".into(),
+            }
+            .wrap());
+        }
+
+        let (object, found) = read_object(&mut buffer, self.algorithm, None)?;
+        check_digest(found, hash_object(self.algorithm, &object))?;
+        if !buffer.is_empty() {
+            return Err(UnexpectedTokenError {
+                token: buffer,
+                expected: "the end of the object".into(),
+            }
+            .wrap());
+        }
+
+        Ok(object)
     }
 }
 
-fn try_get_hash(buffer: &mut Bytes) -> Result<blake3::Hash, Error> {
-    try_split_to(buffer, blake3::OUT_LEN)
-        .map(|bytes| Hash::from_slice(&bytes).expect("bytes should be OUT_LEN long"))
+/// Finds the start of the footer ending `archive`, by matching its signature count against its length.
+fn find_footer(archive: &[u8]) -> Option<usize> {
+    let fixed = PREFIX.len() + Marker::len() + DIGEST_LEN + 8;
+    let signature = DIGEST_LEN + Signature::BYTE_SIZE;
+
+    (0..)
+        .map(|amount| (amount, fixed + amount * signature))
+        .take_while(|(_, length)| *length <= archive.len())
+        .find_map(|(amount, length)| {
+            let start = archive.len() - length;
+            let footer = &archive[start..];
+            let count = (&footer[fixed - 8..fixed]).get_u64_le();
+
+            (footer.starts_with(PREFIX.as_bytes())
+                && footer[PREFIX.len()..].starts_with(b"ft")
+                && count == amount as u64)
+                .then_some(start)
+        })
+}
+
+/// Reads index entries, and the index's trailing offset.
+fn read_index(buffer: &mut Bytes, max: Option<usize>) -> Result<Vec<(PathBytes, u64, u64)>, Error> {
+    let amount = buffer
+        .try_get_u64_le()
+        .map_err(IncompleteError::from)
+        .wrap()?;
+    let entries = (0..amount)
+        .map(|_| {
+            let location = process_plen(buffer, max)?.into();
+            let offset = buffer
+                .try_get_u64_le()
+                .map_err(IncompleteError::from)
+                .wrap()?;
+            let length = buffer
+                .try_get_u64_le()
+                .map_err(IncompleteError::from)
+                .wrap()?;
+
+            Ok((location, offset, length))
+        })
+        .collect::<Result<_, _>>()?;
+    buffer
+        .try_get_u64_le()
+        .map_err(IncompleteError::from)
+        .wrap()?;
+
+    Ok(entries)
+}
+
+/// Reads an object and its stored digest, without verifying it.
+fn read_object(
+    buffer: &mut Bytes,
+    algorithm: HashAlgorithm,
+    max: Option<usize>,
+) -> Result<(Object, Digest), Error> {
+    let location = process_plen(buffer, max)?.into();
+    let permissions = buffer
+        .try_get_u32_le()
+        .map_err(IncompleteError::from)
+        .wrap()?;
+
+    let variant = buffer.try_get_u8().map_err(IncompleteError::from).wrap()?;
+    let content = match variant {
+        0 => ObjectContent::File {
+            data: process_plen(buffer, max)?,
+        },
+        1 => ObjectContent::Symlink {
+            target: process_plen(buffer, max)?.into(),
+        },
+        2 => ObjectContent::Directory,
+        _ => {
+            return Err(UnexpectedTokenError {
+                token: Bytes::copy_from_slice(&[variant]),
+                expected: "0, 1, or 2".into(),
+            }
+            .wrap());
+        }
+    };
+
+    let object = Object {
+        location,
+        permissions,
+        content,
+    };
+
+    Ok((object, try_get_digest(buffer, algorithm)?))
+}
+
+fn try_get_digest(buffer: &mut Bytes, algorithm: HashAlgorithm) -> Result<Digest, Error> {
+    try_split_to(buffer, DIGEST_LEN).map(|bytes| {
+        Digest::new(
+            algorithm,
+            bytes[..]
+                .try_into()
+                .expect("bytes should be DIGEST_LEN long"),
+        )
+    })
 }
 
-fn verify_hash(buffer: &mut Bytes, expected: blake3::Hash) -> Result<(), Error> {
-    let found = try_get_hash(buffer).wrap()?;
+fn verify_digest(buffer: &mut Bytes, expected: Digest) -> Result<(), Error> {
+    check_digest(
+        try_get_digest(buffer, expected.algorithm()).wrap()?,
+        expected,
+    )
+}
+
+fn check_digest(found: Digest, expected: Digest) -> Result<(), Error> {
     (found == expected)
         .then_some(())
         .ok_or_else(|| DigestMismatchError { expected, found }.wrap())
 }
 
-fn process_plen(buffer: &mut Bytes) -> Result<Bytes, Error> {
-    let len = buffer.try_get_u64_le().compat().wrap()?.try_into().wrap()?;
-    try_split_to(buffer, len)
+fn process_plen(buffer: &mut Bytes, max: Option<usize>) -> Result<Bytes, Error> {
+    let length = buffer
+        .try_get_u64_le()
+        .map_err(IncompleteError::from)
+        .wrap()?;
+    if let Some(max) = max
+        && length > max as u64
+    {
+        return Err(ObjectSizeError { length, max }.wrap());
+    }
+
+    try_split_to(buffer, length.try_into().wrap()?)
+}
+
+/// Amount of bytes [`Decoder::decode_reader`] reads at once.
+const READ_CHUNK: usize = 64 * 1024;
+
+/// Whether decoding failed only because the buffer ended early.
+fn is_incomplete(error: &Report<Error>) -> bool {
+    error.root_cause().metadata.type_name == type_name::<IncompleteError>()
 }
 
 fn try_split_to(buffer: &mut Bytes, at: usize) -> Result<Bytes, Error> {
-    let len = buffer.len();
-    if at > len {
-        Err(bytes::TryGetError {
+    let available = buffer.len();
+    if at > available {
+        Err(IncompleteError {
             requested: at,
-            available: len,
-        })
-        .compat()
-        .wrap()
+            available,
+        }
+        .wrap())
     } else {
         Ok(buffer.split_to(at))
     }
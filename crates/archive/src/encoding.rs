@@ -1,21 +1,100 @@
 //! Encoding of [`Event`]s into binary
 
-use std::borrow::Borrow;
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
 
 use bytes::{BufMut, Bytes};
 use ed25519_dalek::Signature;
+use xh_reports::prelude::*;
 
 use crate::{
-    Event, Fingerprint, Object, ObjectContent,
-    utils::{MAGIC, Marker, VERSION, hash_object},
+    Digest, Event, Fingerprint, HashAlgorithm, Object, ObjectContent, PathBytes,
+    utils::{DIGEST_LEN, Hasher, MAGIC, Marker, PREFIX, State, VERSION, hash_object},
 };
 
+/// An [`Event`] was encoded out of order
+#[derive(Debug, IntoReport)]
+#[message("{found} encountered out of order")]
+#[suggestion("provide {expected}")]
+pub struct OrderError {
+    #[allow(missing_docs)]
+    #[format(message)]
+    found: &'static str,
+    #[allow(missing_docs)]
+    #[format(suggestion)]
+    expected: &'static str,
+}
+
+/// Identifies a file's contents by its metadata, see [`DigestCache`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DigestKey {
+    /// Directory the archive was packed from
+    pub root: PathBytes,
+    /// Location of the object within the archive
+    pub location: PathBytes,
+    /// Permissions of the object
+    pub permissions: u32,
+    /// Size of the file's contents
+    pub size: u64,
+    /// Last modification time of the file
+    pub modified: SystemTime,
+}
+
+/// Cache of object digests, consulted by an [`Encoder`] for events with a [`DigestKey`].
+pub trait DigestCache: Send + Sync {
+    /// Gets the cached digest of the object identified by `key`.
+    fn get(&self, algorithm: HashAlgorithm, key: &DigestKey) -> Option<Digest>;
+
+    /// Caches the digest of the object identified by `key`.
+    fn insert(&self, algorithm: HashAlgorithm, key: DigestKey, digest: Digest);
+}
+
+/// In-memory [`DigestCache`].
+#[derive(Debug, Default)]
+pub struct MemoryDigestCache {
+    digests: Mutex<HashMap<(HashAlgorithm, DigestKey), Digest>>,
+}
+
+impl DigestCache for MemoryDigestCache {
+    fn get(&self, algorithm: HashAlgorithm, key: &DigestKey) -> Option<Digest> {
+        let digests = self
+            .digests
+            .lock()
+            .expect("digest cache should not be poisoned");
+        digests.get(&(algorithm, key.clone())).copied()
+    }
+
+    fn insert(&self, algorithm: HashAlgorithm, key: DigestKey, digest: Digest) {
+        let mut digests = self
+            .digests
+            .lock()
+            .expect("digest cache should not be poisoned");
+        digests.insert((algorithm, key), digest);
+    }
+}
+
+/// Error type for encoding
+#[derive(Default, Debug, IntoReport)]
+#[message("could not encode archive")]
+pub struct Error;
+
 /// Encoder for archive events
 ///
 /// The encoder consumes [`Event`]s and outputs binary data.
+///
+/// Each archive must be a [`Event::Header`], then any amount of [`Event::Object`]s, then an [`Event::Footer`].
+/// A single encoder can encode multiple archives, one after the other.
 #[derive(Clone, Default)]
 pub struct Encoder {
-    hasher: blake3::Hasher,
+    hasher: Hasher,
+    state: State,
+    cache: Option<Arc<dyn DigestCache>>,
+    index: Option<Vec<(PathBytes, u64, u64)>>,
+    position: u64,
 }
 
 impl Encoder {
@@ -25,62 +104,177 @@ impl Encoder {
         Self::default()
     }
 
+    /// Constructs a new encoder, using `algorithm` for digests.
+    #[inline]
+    pub fn with_algorithm(algorithm: HashAlgorithm) -> Self {
+        Self {
+            hasher: Hasher::new(algorithm),
+            ..Self::default()
+        }
+    }
+
+    /// Reuses object digests from `cache` for events encoded with a [`DigestKey`],
+    /// see [`Self::encode_keyed`].
+    #[inline]
+    pub fn with_digest_cache(mut self, cache: Arc<dyn DigestCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Writes an index of every object's offset and length before each archive's footer,
+    /// so single objects can be read with a [`RandomAccessReader`](crate::decoding::RandomAccessReader).
+    ///
+    /// The index is not covered by the archive's digest.
+    #[inline]
+    pub fn with_index(mut self) -> Self {
+        self.index = Some(Vec::new());
+        self
+    }
+
     /// Encodes an iterator of [`Event`]s into `buffer`.
+    ///
+    /// # Errors
+    ///
+    /// See [`Self::encode`].
     #[inline]
     pub fn encode_iter(
         &mut self,
         buffer: &mut impl BufMut,
         events: impl IntoIterator<Item = impl Borrow<Event>>,
-    ) {
+    ) -> Result<(), Error> {
         events
             .into_iter()
-            .for_each(|event| self.encode(buffer, event));
+            .try_for_each(|event| self.encode(buffer, event))
     }
 
     /// Encodes a single [`Event`] into `buffer`.
+    ///
+    /// # Errors
+    ///
+    /// If the event is out of order, or is an invalid [`Object`] (see [`Object::validate`]),
+    /// nothing is written to `buffer`, and the internal state is unmodified.
     #[inline]
-    pub fn encode(&mut self, buffer: &mut impl BufMut, event: impl Borrow<Event>) {
-        match event.borrow() {
-            Event::Header => self.process_header(buffer),
-            Event::Object(object) => self.process_object(buffer, object),
-            Event::Footer(signatures) => self.process_footer(buffer, signatures),
+    pub fn encode(
+        &mut self,
+        buffer: &mut impl BufMut,
+        event: impl Borrow<Event>,
+    ) -> Result<(), Error> {
+        self.encode_keyed(buffer, event, None)
+    }
+
+    /// Encodes a single [`Event`] into `buffer`,
+    /// looking up and storing its digest in the digest cache under `key`.
+    ///
+    /// Keys are usually produced by [`Packer::pack_keyed_iter`](crate::packing::Packer::pack_keyed_iter).
+    ///
+    /// # Errors
+    ///
+    /// See [`Self::encode`].
+    pub fn encode_keyed(
+        &mut self,
+        buffer: &mut impl BufMut,
+        event: impl Borrow<Event>,
+        key: Option<DigestKey>,
+    ) -> Result<(), Error> {
+        let event = event.borrow();
+        self.check_order(event)?;
+
+        match event {
+            Event::Header => {
+                self.process_header(buffer);
+                self.state = State::Inside;
+            }
+            Event::Object(object) => {
+                object.validate().wrap()?;
+                self.process_object(buffer, object, key)
+            }
+            Event::Footer(signatures) => {
+                if let Some(entries) = &mut self.index {
+                    Self::process_index(buffer, entries, self.position);
+                    entries.clear();
+                }
+                self.process_footer(buffer, signatures);
+                self.state = State::Outside;
+            }
         }
+
+        Ok(())
     }
 
     /// Gets the current digest of the archive.
     #[inline]
-    pub fn digest(&self) -> blake3::Hash {
+    pub fn digest(&self) -> Digest {
         self.hasher.finalize()
     }
 
+    fn check_order(&self, event: &Event) -> Result<(), Error> {
+        let (found, expected) = match (self.state, event) {
+            (State::Outside, Event::Header)
+            | (State::Inside, Event::Object(_) | Event::Footer(_)) => {
+                return Ok(());
+            }
+            (State::Outside, Event::Object(_)) => ("object", "a header first"),
+            (State::Outside, Event::Footer(_)) => ("footer", "a header first"),
+            (State::Inside, Event::Header) => ("header", "a footer for the current archive first"),
+        };
+
+        Err(OrderError { found, expected }.wrap())
+    }
+
     fn process_header(&mut self, buffer: &mut impl BufMut) {
         self.hasher.reset();
+        if let Some(entries) = &mut self.index {
+            entries.clear();
+        }
 
         Marker::Header.put(buffer);
         buffer.put_slice(MAGIC.as_bytes());
         buffer.put_u16_le(VERSION);
+        buffer.put_u8(self.hasher.algorithm().as_u8());
+        self.position = (PREFIX.len() + Marker::len() + MAGIC.len() + 2 + 1) as u64;
     }
 
-    fn process_object(&mut self, buffer: &mut impl BufMut, object: &Object) {
+    fn process_object(
+        &mut self,
+        buffer: &mut impl BufMut,
+        object: &Object,
+        key: Option<DigestKey>,
+    ) {
         Marker::Object.put(buffer);
-        Self::process_lenp(buffer, &object.location.inner);
+        let location = Self::process_lenp(buffer, &object.location.inner);
         buffer.put_u32_le(object.permissions);
 
-        match &object.content {
+        let content = match &object.content {
             ObjectContent::File { data } => {
                 buffer.put_u8(0);
-                Self::process_lenp(buffer, data);
+                Self::process_lenp(buffer, data)
             }
             ObjectContent::Symlink { target } => {
                 buffer.put_u8(1);
-                Self::process_lenp(buffer, &target.inner);
+                Self::process_lenp(buffer, &target.inner)
             }
             ObjectContent::Directory => {
                 buffer.put_u8(2);
+                0
             }
+        };
+
+        let length =
+            location + content + (PREFIX.len() + Marker::len() + 4 + 1 + DIGEST_LEN) as u64;
+        if let Some(entries) = &mut self.index {
+            entries.push((object.location.clone(), self.position, length));
         }
+        self.position += length;
 
-        let hash = hash_object(object);
+        let algorithm = self.hasher.algorithm();
+        let hash = match (&self.cache, key) {
+            (Some(cache), Some(key)) => cache.get(algorithm, &key).unwrap_or_else(|| {
+                let hash = hash_object(algorithm, object);
+                cache.insert(algorithm, key, hash);
+                hash
+            }),
+            _ => hash_object(algorithm, object),
+        };
         let hash = hash.as_bytes();
         self.hasher.update(hash);
         buffer.put_slice(hash);
@@ -99,8 +293,23 @@ impl Encoder {
         }
     }
 
-    fn process_lenp(buffer: &mut impl BufMut, bytes: &Bytes) {
+    fn process_index(buffer: &mut impl BufMut, entries: &[(PathBytes, u64, u64)], offset: u64) {
+        Marker::Index.put(buffer);
+        buffer.put_u64_le(entries.len() as u64);
+        for (location, offset, length) in entries {
+            Self::process_lenp(buffer, &location.inner);
+            buffer.put_u64_le(*offset);
+            buffer.put_u64_le(*length);
+        }
+
+        // lets readers find the index from the footer
+        buffer.put_u64_le(offset);
+    }
+
+    /// Writes `bytes` with its length prefix, returning the amount of bytes written.
+    fn process_lenp(buffer: &mut impl BufMut, bytes: &Bytes) -> u64 {
         buffer.put_u64_le(bytes.len() as u64);
         buffer.put_slice(bytes);
+        8 + bytes.len() as u64
     }
 }
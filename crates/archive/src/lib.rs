@@ -31,9 +31,15 @@ use std::{
 
 use bytes::Bytes;
 use ed25519_dalek::Signature;
+use xh_reports::prelude::*;
+
+/// Callback reporting progress while packing or unpacking.
+///
+/// Called once per object with its location, the amount of objects done, and the total amount of objects.
+pub type ProgressFn = Box<dyn Fn(&PathBytes, usize, usize) + Send>;
 
 /// A path internally represented with [`Bytes`].
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct PathBytes {
     inner: Bytes,
 }
@@ -44,6 +50,12 @@ impl fmt::Debug for PathBytes {
     }
 }
 
+impl PathBytes {
+    pub(crate) fn len(&self) -> usize {
+        self.inner.len()
+    }
+}
+
 impl From<PathBytes> for Bytes {
     fn from(value: PathBytes) -> Self {
         value.inner
@@ -94,17 +106,168 @@ pub struct Object {
     pub content: ObjectContent,
 }
 
+/// An [`Object`] was in an inconsistent state
+#[derive(Debug, IntoReport)]
+#[message("invalid object: {reason}")]
+#[context(location)]
+pub struct InvalidObjectError {
+    #[allow(missing_docs)]
+    #[format(message)]
+    reason: &'static str,
+    #[allow(missing_docs)]
+    location: PathBytes,
+}
+
+const PERMISSION_MASK: u32 = 0o7777;
+const TYPE_MASK: u32 = 0o170000;
+
 impl Object {
+    /// Constructs a new file object.
+    #[inline]
+    pub fn file(location: impl Into<PathBytes>, permissions: u32, data: Bytes) -> Self {
+        Self {
+            location: location.into(),
+            permissions,
+            content: ObjectContent::File { data },
+        }
+    }
+
+    /// Constructs a new symlink object.
+    #[inline]
+    pub fn symlink(
+        location: impl Into<PathBytes>,
+        permissions: u32,
+        target: impl Into<PathBytes>,
+    ) -> Self {
+        Self {
+            location: location.into(),
+            permissions,
+            content: ObjectContent::Symlink {
+                target: target.into(),
+            },
+        }
+    }
+
+    /// Constructs a new directory object.
+    #[inline]
+    pub fn directory(location: impl Into<PathBytes>, permissions: u32) -> Self {
+        Self {
+            location: location.into(),
+            permissions,
+            content: ObjectContent::Directory,
+        }
+    }
+
     #[allow(missing_docs)]
     #[inline]
     pub fn permissions(&self) -> std::fs::Permissions {
         std::os::unix::fs::PermissionsExt::from_mode(self.permissions)
     }
+
+    /// Checks that this object is in a consistent state.
+    ///
+    /// Objects are invalid if:
+    /// - their location is empty, absolute, or contains empty, `.`, or `..` segments
+    /// - their permissions contain file type bits that don't match their content
+    /// - they are a symlink with an empty target
+    pub fn validate(&self) -> Result<(), InvalidObjectError> {
+        let invalid = |reason| {
+            Err(InvalidObjectError {
+                reason,
+                location: self.location.clone(),
+            }
+            .into())
+        };
+
+        let location = &self.location.inner;
+        if location.is_empty() {
+            return invalid("location is empty");
+        }
+
+        if location
+            .split(|byte| *byte == b'/')
+            .any(|segment| matches!(segment, b"" | b"." | b".."))
+        {
+            return invalid("location is not a normalized relative path");
+        }
+
+        let ty = match self.content {
+            ObjectContent::File { .. } => 0o100000,
+            ObjectContent::Symlink { ref target } => {
+                if target.inner.is_empty() {
+                    return invalid("symlink target is empty");
+                }
+
+                0o120000
+            }
+            ObjectContent::Directory => 0o040000,
+        };
+
+        let type_bits = self.permissions & !PERMISSION_MASK;
+        if type_bits & !TYPE_MASK != 0 {
+            return invalid("permissions contain unknown bits");
+        }
+
+        if type_bits != 0 && type_bits != ty {
+            return invalid("permissions file type does not match content");
+        }
+
+        Ok(())
+    }
 }
 
 /// The fingerprint of a public key
 pub type Fingerprint = blake3::Hash;
 
+/// The algorithm used for object and archive digests.
+///
+/// Digests from every algorithm are 32 bytes long, and are represented as [`Digest`]s.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HashAlgorithm {
+    /// [BLAKE3](https://github.com/BLAKE3-team/BLAKE3-specs)
+    #[default]
+    Blake3,
+    /// [SHA-256](https://datatracker.ietf.org/doc/html/rfc6234)
+    Sha256,
+}
+
+/// An object or archive digest, along with the [`HashAlgorithm`] that produced it.
+///
+/// Digests display as lowercase hex, without the algorithm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Digest {
+    algorithm: HashAlgorithm,
+    bytes: [u8; utils::DIGEST_LEN],
+}
+
+impl Digest {
+    /// Constructs a digest from the raw output of `algorithm`.
+    #[inline]
+    pub const fn new(algorithm: HashAlgorithm, bytes: [u8; utils::DIGEST_LEN]) -> Self {
+        Self { algorithm, bytes }
+    }
+
+    /// Gets the algorithm that produced this digest.
+    #[inline]
+    pub const fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    /// Gets the raw bytes of this digest.
+    #[inline]
+    pub const fn as_bytes(&self) -> &[u8; utils::DIGEST_LEN] {
+        &self.bytes
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.bytes
+            .iter()
+            .try_for_each(|byte| write!(f, "{byte:02x}"))
+    }
+}
+
 /// An individual archive event.
 ///
 /// An archive is represented as a sequence of [`Event`]s.
@@ -1,11 +1,17 @@
 //! Packing of [`Event`]s from the filesystem
 
-use std::{collections::VecDeque, fs, os::unix::fs::PermissionsExt, path::Path};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::Path,
+    time::{Duration, SystemTime},
+};
 
 use bytes::Bytes;
 use xh_reports::prelude::*;
 
-use crate::{Event, Object, ObjectContent, PathBytes};
+use crate::{Event, Object, ObjectContent, PathBytes, ProgressFn, encoding::DigestKey};
 
 /// An unsupported file type was encountered (eg. socket, pipe, etc)
 #[derive(Debug, IntoReport)]
@@ -20,20 +26,79 @@ pub struct UnsupportedTypeError {
 #[message("could not pack archive")]
 pub struct Error;
 
-type ReadFileFn = fn(&Path) -> StdResult<Bytes, std::io::Error>;
+/// Function used to read the contents of files while packing.
+pub type ReadFileFn = fn(&Path) -> StdResult<Bytes, std::io::Error>;
+
+/// Function deciding whether a path, relative to the packing root, is excluded from the archive.
+pub type ExcludeFn = Box<dyn Fn(&Path) -> bool + Send>;
 
 enum State {
     Header,
-    Objects(VecDeque<Object>),
+    Objects(VecDeque<Stub>),
     Footer,
 }
 
+/// An object yet to be packed, along with the metadata it was indexed with.
+struct Stub {
+    object: Object,
+    metadata: fs::Metadata,
+}
+
+impl Stub {
+    fn key(&self, root: &PathBytes) -> Option<DigestKey> {
+        let ObjectContent::File { .. } = self.object.content else {
+            return None;
+        };
+
+        Some(DigestKey {
+            root: root.clone(),
+            location: self.object.location.clone(),
+            permissions: self.object.permissions,
+            size: self.metadata.len(),
+            modified: self.metadata.modified().ok()?,
+        })
+    }
+}
+
+/// Coarsest modification time granularity accounted for by [`Packer::pack_diff`], that of FAT.
+///
+/// A file modified this close before `since` may have been written after the previous pack,
+/// within the same timestamp tick, so it is read again.
+pub const MODIFIED_GRANULARITY: Duration = Duration::from_secs(2);
+
+struct Previous {
+    contents: BTreeMap<PathBytes, Bytes>,
+    since: SystemTime,
+}
+
+impl Previous {
+    fn reuse(&self, path: &Path, location: &PathBytes) -> Option<Bytes> {
+        let data = self.contents.get(location)?;
+        let metadata = fs::symlink_metadata(path).ok()?;
+        let threshold = self
+            .since
+            .checked_sub(MODIFIED_GRANULARITY)
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        let unchanged = metadata.len() == data.len() as u64
+            && metadata
+                .modified()
+                .is_ok_and(|modified| modified < threshold);
+
+        unchanged.then(|| data.clone())
+    }
+}
+
 /// Packer for archive events
 ///
 /// The packer walks a directory tree, and outputs [`Event`]s.
 pub struct Packer {
     state: State,
     root: PathBytes,
+    previous: Option<Previous>,
+    progress: Option<ProgressFn>,
+    exclude: Option<ExcludeFn>,
+    done: usize,
+    total: usize,
 }
 
 impl Packer {
@@ -43,12 +108,50 @@ impl Packer {
         Self {
             state: State::Header,
             root: root.into(),
+            previous: None,
+            progress: None,
+            exclude: None,
+            done: 0,
+            total: 0,
         }
     }
 
+    /// Calls `progress` after each object is packed.
+    ///
+    /// The total is the amount of objects in the directory tree.
+    #[inline]
+    pub fn with_progress(
+        mut self,
+        progress: impl Fn(&PathBytes, usize, usize) + Send + 'static,
+    ) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
+    }
+
+    /// Skips every path, relative to the root, that `exclude` returns `true` for.
+    ///
+    /// Excluding a directory skips its entire subtree.
+    #[inline]
+    pub fn with_exclude(mut self, exclude: impl Fn(&Path) -> bool + Send + 'static) -> Self {
+        self.exclude = Some(Box::new(exclude));
+        self
+    }
+
     /// Packs a directory into an iterator of [`Event`]s.
     #[inline]
     pub fn pack_iter(&mut self) -> impl Iterator<Item = Result<Event, Error>> {
+        std::iter::from_fn(|| self.process(read_file_default)).map(without_key)
+    }
+
+    /// Packs a directory into an iterator of [`Event`]s,
+    /// along with a [`DigestKey`] for every file.
+    ///
+    /// Pass the keys to [`Encoder::encode_keyed`](crate::encoding::Encoder::encode_keyed),
+    /// so unchanged files reuse their cached digest.
+    #[inline]
+    pub fn pack_keyed_iter(
+        &mut self,
+    ) -> impl Iterator<Item = Result<(Event, Option<DigestKey>), Error>> {
         std::iter::from_fn(|| self.process(read_file_default))
     }
 
@@ -60,22 +163,91 @@ impl Packer {
     #[cfg(feature = "mmap")]
     #[inline]
     pub unsafe fn pack_mmap_iter(&mut self) -> impl Iterator<Item = Result<Event, Error>> {
-        std::iter::from_fn(|| self.process(read_file_mmap))
+        std::iter::from_fn(|| self.process(read_file_mmap)).map(without_key)
+    }
+
+    /// Packs a directory into an owned iterator of [`Event`]s.
+    ///
+    /// See [`Self::pack_mmap_iter`] for more information.
+    ///
+    /// # Safety
+    ///
+    /// See [`memmap2::Mmap`] for why this function is unsafe.
+    #[cfg(feature = "mmap")]
+    #[inline]
+    pub unsafe fn into_pack_mmap_iter(mut self) -> impl Iterator<Item = Result<Event, Error>> {
+        std::iter::from_fn(move || self.process(read_file_mmap)).map(without_key)
+    }
+
+    /// Packs a directory into an iterator of [`Event`]s, reusing file contents from `previous`.
+    ///
+    /// Files with the same size as their previous object, that weren't modified after `since`,
+    /// are not re-read. Otherwise, the output is identical to [`Self::pack_iter`].
+    ///
+    /// Files modified within [`MODIFIED_GRANULARITY`] before `since` are re-read too,
+    /// since coarse timestamps can't tell whether they changed after the previous pack.
+    #[inline]
+    pub fn pack_diff(
+        &mut self,
+        previous: &[Object],
+        since: SystemTime,
+    ) -> impl Iterator<Item = Result<Event, Error>> {
+        self.pack_diff_with(previous, since, read_file_default)
+    }
+
+    /// Packs a directory into an iterator of [`Event`]s, reusing file contents from `previous`,
+    /// and reading changed files with `read_file`.
+    ///
+    /// See [`Self::pack_diff`] for more information.
+    pub fn pack_diff_with(
+        &mut self,
+        previous: &[Object],
+        since: SystemTime,
+        read_file: ReadFileFn,
+    ) -> impl Iterator<Item = Result<Event, Error>> {
+        let contents = previous
+            .iter()
+            .filter_map(|object| match object.content {
+                ObjectContent::File { ref data } => Some((object.location.clone(), data.clone())),
+                _ => None,
+            })
+            .collect();
+
+        self.previous = Some(Previous { contents, since });
+        std::iter::from_fn(move || self.process(read_file)).map(without_key)
     }
 
     #[tracing::instrument(level = "trace", skip(self, read_file))]
-    fn process(&mut self, read_file: ReadFileFn) -> Option<Result<Event, Error>> {
+    fn process(
+        &mut self,
+        read_file: ReadFileFn,
+    ) -> Option<Result<(Event, Option<DigestKey>), Error>> {
         Some(match self.state {
-            State::Header => build_index(&self.root).map(|index| {
+            State::Header => build_index(&self.root, self.exclude.as_ref()).map(|index| {
+                self.total = index.len();
                 self.state = State::Objects(index);
-                Event::Header
+                (Event::Header, None)
             }),
             State::Objects(ref mut index) => match index.front_mut() {
-                Some(stub) => process_object(&self.root, stub, read_file)
-                    .map(|()| Event::Object(index.pop_front().unwrap())),
+                Some(stub) => process_object(
+                    &self.root,
+                    &mut stub.object,
+                    self.previous.as_ref(),
+                    read_file,
+                )
+                .map(|()| {
+                    let stub = index.pop_front().unwrap();
+                    let key = stub.key(&self.root);
+                    self.done += 1;
+                    if let Some(progress) = &self.progress {
+                        progress(&stub.object.location, self.done, self.total);
+                    }
+
+                    (Event::Object(stub.object), key)
+                }),
                 None => {
                     self.state = State::Footer;
-                    Ok(Event::Footer(Vec::default()))
+                    Ok((Event::Footer(Vec::default()), None))
                 }
             },
             State::Footer => return None,
@@ -83,31 +255,42 @@ impl Packer {
     }
 }
 
-fn process_object(root: &PathBytes, stub: &mut Object, read_file: ReadFileFn) -> Result<(), Error> {
-    let location = stub.location.as_ref();
+fn without_key(result: Result<(Event, Option<DigestKey>), Error>) -> Result<Event, Error> {
+    result.map(|(event, _)| event)
+}
+
+fn process_object(
+    root: &PathBytes,
+    stub: &mut Object,
+    previous: Option<&Previous>,
+    read_file: ReadFileFn,
+) -> Result<(), Error> {
+    let path = stub.location.as_ref();
+    let location = path
+        .strip_prefix(root)
+        .expect("path should be a child of root")
+        .to_path_buf()
+        .into();
 
     let content = match stub.content {
         ObjectContent::File { .. } => ObjectContent::File {
-            data: read_file(location).wrap()?,
+            data: match previous.and_then(|previous| previous.reuse(path, &location)) {
+                Some(data) => data,
+                None => read_file(path).wrap()?,
+            },
         },
         ObjectContent::Symlink { .. } => ObjectContent::Symlink {
-            target: fs::read_link(location).wrap()?.into(),
+            target: fs::read_link(path).wrap()?.into(),
         },
         ObjectContent::Directory => ObjectContent::Directory,
     };
 
-    let location = location
-        .strip_prefix(root)
-        .expect("path should be a child of root")
-        .to_path_buf()
-        .into();
-
     stub.location = location;
     stub.content = content;
     Ok(())
 }
 
-fn build_index(root: &PathBytes) -> Result<VecDeque<Object>, Error> {
+fn build_index(root: &PathBytes, exclude: Option<&ExcludeFn>) -> Result<VecDeque<Stub>, Error> {
     let mut queue = Vec::from([(root.clone(), fs::symlink_metadata(root).wrap()?)]);
 
     let mut i = 0;
@@ -121,6 +304,17 @@ fn build_index(root: &PathBytes) -> Result<VecDeque<Object>, Error> {
         queue.extend(
             fs::read_dir(path)
                 .wrap()?
+                .filter(|entry| {
+                    let (Ok(entry), Some(exclude)) = (entry, exclude) else {
+                        return true;
+                    };
+
+                    let path = entry.path();
+                    let relative = path
+                        .strip_prefix(root)
+                        .expect("path should be a child of root");
+                    !exclude(relative)
+                })
                 .map(|entry| {
                     let entry = entry?;
                     let path = entry.path();
@@ -150,14 +344,16 @@ fn build_index(root: &PathBytes) -> Result<VecDeque<Object>, Error> {
                 return Err(UnsupportedTypeError { path: location }.wrap());
             };
 
-            Ok(Object {
+            let object = Object {
                 permissions: metadata.permissions().mode(),
                 location,
                 content,
-            })
+            };
+
+            Ok(Stub { object, metadata })
         })
         .collect::<Result<_, _>>()?;
-    index.sort_unstable_by(|a, b| a.location.cmp(&b.location));
+    index.sort_unstable_by(|a, b| a.object.location.cmp(&b.object.location));
 
     Ok(index.into())
 }
@@ -2,15 +2,18 @@
 
 use std::{
     borrow::Borrow,
+    ffi::OsString,
     fs,
+    io::ErrorKind,
+    iter,
     os::unix::fs::{PermissionsExt, symlink},
-    path::Path,
+    path::{Component, Path, PathBuf},
 };
 
 use bytes::Bytes;
 use xh_reports::prelude::*;
 
-use crate::{Event, Object, ObjectContent};
+use crate::{Event, Object, ObjectContent, PathBytes, ProgressFn};
 
 /// Error type for unpacking
 #[derive(Default, Debug, IntoReport)]
@@ -21,17 +24,52 @@ pub struct Error;
 /// Packer for archive events.
 ///
 /// The unpacker consumes [`Event`]s and unpacks them to the filesystem.
+/// Directory permissions are applied once the [`Event::Footer`] is unpacked.
 pub struct Unpacker<'a> {
     root: &'a Path,
+    progress: Option<ProgressFn>,
+    original_root: Option<PathBuf>,
+    directories: Vec<(PathBuf, u32)>,
 }
 
+/// Suffix of the hidden, randomly named sibling files written before being renamed into place.
+pub const PARTIAL_SUFFIX: &str = ".xh-partial";
+
 type WriteFileFn = fn(&Path, &Bytes) -> StdResult<(), std::io::Error>;
 
 impl<'a> Unpacker<'a> {
     /// Constructs a new unpacker.
     #[inline]
     pub fn new(root: &'a Path) -> Self {
-        Self { root }
+        Self {
+            root,
+            progress: None,
+            original_root: None,
+            directories: Vec::new(),
+        }
+    }
+
+    /// Rewrites absolute symlink targets within `original_root` to be relative to their link,
+    /// so trees packed from `original_root` stay consistent when unpacked elsewhere.
+    ///
+    /// Targets outside of `original_root` are kept as is.
+    #[inline]
+    pub fn with_relocation(mut self, original_root: impl Into<PathBuf>) -> Self {
+        self.original_root = Some(original_root.into());
+        self
+    }
+
+    /// Calls `progress` after each object is unpacked by [`Self::unpack_iter`]
+    /// or [`Self::unpack_mmap_iter`].
+    ///
+    /// To know the total, the events are collected and counted before unpacking.
+    #[inline]
+    pub fn with_progress(
+        mut self,
+        progress: impl Fn(&PathBytes, usize, usize) + Send + 'static,
+    ) -> Self {
+        self.progress = Some(Box::new(progress));
+        self
     }
 
     /// Unpacks an iterator of [`Event`]s onto the filesystem.
@@ -40,9 +78,7 @@ impl<'a> Unpacker<'a> {
         &mut self,
         iterator: impl IntoIterator<Item = impl Borrow<Event>>,
     ) -> Result<(), Error> {
-        iterator
-            .into_iter()
-            .try_for_each(|event| self.process(event.borrow(), write_file_default))
+        self.process_iter(iterator, write_file_default)
     }
 
     /// Unpacks an iterator of [`Event`]s onto the filesystem.
@@ -56,9 +92,7 @@ impl<'a> Unpacker<'a> {
         &mut self,
         iterator: impl IntoIterator<Item = impl Borrow<Event>>,
     ) -> Result<(), Error> {
-        iterator
-            .into_iter()
-            .try_for_each(|event| unsafe { self.unpack_mmap(event.borrow()) })
+        self.process_iter(iterator, write_file_mmap)
     }
 
     /// Unpacks a single [`Event`] onto the filesystem.
@@ -78,34 +112,170 @@ impl<'a> Unpacker<'a> {
         self.process(event.borrow(), write_file_mmap)
     }
 
+    fn process_iter(
+        &mut self,
+        iterator: impl IntoIterator<Item = impl Borrow<Event>>,
+        write_file: WriteFileFn,
+    ) -> Result<(), Error> {
+        let Some(progress) = self.progress.take() else {
+            return iterator
+                .into_iter()
+                .try_for_each(|event| self.process(event.borrow(), write_file));
+        };
+
+        let events: Vec<_> = iterator.into_iter().collect();
+        let events = || events.iter().map(<_ as Borrow<Event>>::borrow);
+        let total = events()
+            .filter(|event| matches!(event, Event::Object(_)))
+            .count();
+
+        let mut done = 0;
+        let result = events().try_for_each(|event| {
+            self.process(event, write_file)?;
+            if let Event::Object(object) = event {
+                done += 1;
+                progress(&object.location, done, total);
+            }
+
+            Ok(())
+        });
+
+        self.progress = Some(progress);
+        result
+    }
+
     #[tracing::instrument(level = "trace", skip(self, write_file))]
     fn process(&mut self, event: &Event, write_file: WriteFileFn) -> Result<(), Error> {
-        if let Event::Object(object) = event {
-            process_object(self.root, object, write_file).wrap()
-        } else {
-            Ok(())
+        match event {
+            Event::Object(object) => process_object(
+                self.root,
+                object,
+                write_file,
+                self.original_root.as_deref(),
+                &mut self.directories,
+            )
+            .wrap(),
+            Event::Footer(_) => self.finish_directories(),
+            Event::Header => Ok(()),
         }
     }
+
+    /// Applies the stored permissions of every directory unpacked so far.
+    ///
+    /// Deeper directories are handled first, so that read-only parents don't get in the way.
+    fn finish_directories(&mut self) -> Result<(), Error> {
+        while let Some((location, permissions)) = self.directories.pop() {
+            fs::set_permissions(&location, fs::Permissions::from_mode(permissions)).wrap()?;
+        }
+
+        Ok(())
+    }
 }
 
-fn process_object(root: &Path, object: &Object, write_file: WriteFileFn) -> Result<(), Error> {
+fn process_object(
+    root: &Path,
+    object: &Object,
+    write_file: WriteFileFn,
+    original_root: Option<&Path>,
+    directories: &mut Vec<(PathBuf, u32)>,
+) -> Result<(), Error> {
     let location = xh_common::safe_path(root, object.location.as_ref()).wrap()?;
 
-    let set_permissions =
-        || fs::set_permissions(&location, fs::Permissions::from_mode(object.permissions));
-
     match &object.content {
         ObjectContent::File { data } => {
-            write_file(&location, data).and_then(|()| set_permissions())
+            write_atomic(&location, data, object.permissions, write_file)
+        }
+        ObjectContent::Symlink { target } => {
+            let relocated = original_root.and_then(|original| {
+                relocated_target(original, object.location.as_ref(), target.as_ref())
+            });
+            symlink(relocated.as_deref().unwrap_or(target.as_ref()), &location)
         }
-        ObjectContent::Symlink { target } => symlink(target, &location),
-        ObjectContent::Directory => fs::create_dir(&location).and_then(|()| set_permissions()),
+        // directories stay writable until the footer, so their children can be unpacked
+        ObjectContent::Directory => fs::create_dir(&location)
+            .or_else(|err| {
+                // symlinks to directories are not followed, so they can't redirect permissions
+                let is_dir = fs::symlink_metadata(&location)
+                    .is_ok_and(|metadata| metadata.file_type().is_dir());
+                if is_dir && err.kind() == std::io::ErrorKind::AlreadyExists {
+                    Ok(())
+                } else {
+                    Err(err)
+                }
+            })
+            .and_then(|()| {
+                fs::set_permissions(
+                    &location,
+                    fs::Permissions::from_mode(object.permissions | 0o700),
+                )
+            })
+            .map(|()| directories.push((location.clone(), object.permissions))),
     }
     .wrap()?;
 
     Ok(())
 }
 
+/// Rewrites `target` relative to the parent of `location`, if it's an absolute path within `original_root`.
+fn relocated_target(original_root: &Path, location: &Path, target: &Path) -> Option<PathBuf> {
+    let is_normal = |component: &Component| matches!(component, Component::Normal(_));
+    let inner = target.strip_prefix(original_root).ok()?;
+    // targets climbing back out of the original root are external
+    if !target.is_absolute() || !inner.components().all(|component| is_normal(&component)) {
+        return None;
+    }
+
+    let depth = location
+        .parent()
+        .map_or(0, |parent| parent.components().filter(is_normal).count());
+    let mut relative: PathBuf = iter::repeat_n(Component::ParentDir, depth).collect();
+    relative.push(inner);
+    if relative.as_os_str().is_empty() {
+        relative.push(".");
+    }
+
+    Some(relative)
+}
+
+/// Writes `contents` to a uniquely named sibling of `path`, and renames it into place once complete,
+/// so an interrupted unpack never leaves a partially written file at `path`.
+fn write_atomic(
+    path: &Path,
+    contents: &Bytes,
+    permissions: u32,
+    write_file: WriteFileFn,
+) -> StdResult<(), std::io::Error> {
+    // reserve a name no other entry uses, so writing it never clobbers anything
+    let partial = loop {
+        let mut name = OsString::from(".");
+        name.push(path.file_name().unwrap_or_default());
+        name.push(format!(
+            ".{}{PARTIAL_SUFFIX}",
+            &xh_common::random_hash().to_hex()[..16]
+        ));
+
+        let partial = path.with_file_name(name);
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&partial)
+        {
+            Ok(_) => break partial,
+            Err(error) if error.kind() == ErrorKind::AlreadyExists => continue,
+            Err(error) => return Err(error),
+        }
+    };
+
+    let result = write_file(&partial, contents)
+        .and_then(|()| fs::set_permissions(&partial, fs::Permissions::from_mode(permissions)))
+        .and_then(|()| fs::rename(&partial, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+
+    result
+}
+
 fn write_file_default(path: &Path, contents: &Bytes) -> StdResult<(), std::io::Error> {
     fs::write(path, contents)
 }
@@ -1,33 +1,25 @@
-use crate::{Object, ObjectContent};
+use crate::{Digest, HashAlgorithm, Object, ObjectContent};
 
 use bytes::{BufMut, Bytes};
-use smol_str::ToSmolStr;
-use xh_reports::{Frame, Report, impl_compat};
+use sha2::{Digest as _, Sha256};
 
 pub const MAGIC: &str = "xuehua-archive";
-pub const VERSION: u16 = 1;
-
-impl_compat!(
-    ArchiveCompat,
-    (bytes::TryGetError, |error| {
-        let frames = [
-            Frame::context("requested", error.requested),
-            Frame::context("available", error.available),
-            Frame::suggestion(format_args!(
-                "provide {} more bytes",
-                error.requested - error.available
-            ).to_smolstr()),
-        ];
-
-        Report::from_error(error).with_frames(frames).cast()
-    })
-);
+pub const PREFIX: &str = "xuehua-archive@";
+pub const VERSION: u16 = 3;
+/// Last version without an algorithm byte, which always uses [`HashAlgorithm::Blake3`].
+pub const LEGACY_VERSION: u16 = 1;
+/// First version with an algorithm byte.
+pub const ALGORITHM_VERSION: u16 = 2;
+/// First version allowing an index before the footer.
+pub const INDEX_VERSION: u16 = 3;
+pub const DIGEST_LEN: usize = 32;
 
 #[derive(Clone, Copy)]
 pub enum Marker {
     Header,
     Footer,
     Object,
+    Index,
     Signature,
 }
 
@@ -37,24 +29,104 @@ impl Marker {
     }
 
     pub fn put(self, buffer: &mut impl BufMut) {
-        buffer.put_slice(b"xuehua-archive@");
+        buffer.put_slice(PREFIX.as_bytes());
         buffer.put_slice(match self {
             Marker::Header => b"hd",
             Marker::Footer => b"ft",
             Marker::Object => b"ob",
+            Marker::Index => b"ix",
             Marker::Signature => b"sg",
         });
     }
 }
 
-pub fn hash_object(object: &Object) -> blake3::Hash {
-    fn process_lenp(hasher: &mut blake3::Hasher, bytes: &Bytes) {
+/// Position of an encoder or decoder within an archive.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum State {
+    /// Before a header, or after a footer.
+    #[default]
+    Outside,
+    /// After a header, before its footer.
+    Inside,
+}
+
+impl HashAlgorithm {
+    pub(crate) fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Self::Blake3),
+            1 => Some(Self::Sha256),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_u8(self) -> u8 {
+        match self {
+            Self::Blake3 => 0,
+            Self::Sha256 => 1,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum Hasher {
+    Blake3(Box<blake3::Hasher>),
+    Sha256(Sha256),
+}
+
+impl Default for Hasher {
+    fn default() -> Self {
+        Self::new(HashAlgorithm::default())
+    }
+}
+
+impl Hasher {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Blake3 => Self::Blake3(Default::default()),
+            HashAlgorithm::Sha256 => Self::Sha256(Sha256::new()),
+        }
+    }
+
+    pub fn algorithm(&self) -> HashAlgorithm {
+        match self {
+            Self::Blake3(_) => HashAlgorithm::Blake3,
+            Self::Sha256(_) => HashAlgorithm::Sha256,
+        }
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) -> &mut Self {
+        match self {
+            Self::Blake3(hasher) => {
+                hasher.update(bytes);
+            }
+            Self::Sha256(hasher) => hasher.update(bytes),
+        }
+
+        self
+    }
+
+    pub fn finalize(&self) -> Digest {
+        let bytes = match self {
+            Self::Blake3(hasher) => hasher.finalize().into(),
+            Self::Sha256(hasher) => hasher.clone().finalize().into(),
+        };
+
+        Digest::new(self.algorithm(), bytes)
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new(self.algorithm());
+    }
+}
+
+pub fn hash_object(algorithm: HashAlgorithm, object: &Object) -> Digest {
+    fn process_lenp(hasher: &mut Hasher, bytes: &Bytes) {
         hasher
             .update(&(bytes.len() as u64).to_le_bytes())
             .update(bytes);
     }
 
-    let mut hasher = blake3::Hasher::new();
+    let mut hasher = Hasher::new(algorithm);
 
     process_lenp(&mut hasher, &object.location.inner);
     hasher.update(&object.permissions.to_le_bytes());
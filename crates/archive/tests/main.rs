@@ -1,15 +1,32 @@
-use std::ffi::OsStr;
+use std::{
+    ffi::OsStr,
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::Path,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
 
 use arbitrary::Arbitrary;
 use arbtest::arbtest;
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use include_dir::include_dir;
 use libtest_mimic::{Arguments, Trial};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
-use xh_archive::Event;
+use xh_archive::{
+    Digest, Event, HashAlgorithm, Object, ObjectContent, PathBytes,
+    decoding::{Decoder, RandomAccessReader},
+    encoding::{DigestCache, DigestKey, Encoder, MemoryDigestCache},
+    packing::Packer,
+    unpacking::{PARTIAL_SUFFIX, Unpacker},
+};
 use xh_reports::{render::{GlobalRenderer, JsonRenderer}, tracing::ReportLayer};
 
-use crate::utils::{ArbitraryArchive, BenchmarkOptions, benchmark, decode, encode};
+use crate::utils::{ArbitraryArchive, BenchmarkOptions, benchmark, decode, encode, encode_with};
 
 mod utils;
 
@@ -39,6 +56,306 @@ fn enc_dec_roundtrip(events: &Vec<Event>, assert: bool) {
     }
 }
 
+fn algorithm_roundtrip(events: &Vec<Event>, algorithm: HashAlgorithm) {
+    let mut encoded = encode_with(events, algorithm);
+    let mut decoder = Decoder::new();
+    let decoded = decoder
+        .decode_iter(&mut encoded)
+        .collect::<Result<Vec<_>, _>>()
+        .expect("decoding should not fail");
+
+    assert_eq!(events, &decoded);
+    assert_eq!(decoder.algorithm(), algorithm);
+}
+
+fn wrong_algorithm() {
+    let events = vec![
+        Event::Header,
+        Event::Object(Object::file(
+            Bytes::from_static(b"file"),
+            0o644,
+            Bytes::from_static(b"xuehua"),
+        )),
+        Event::Footer(Vec::new()),
+    ];
+
+    // marker, magic, and version precede the algorithm byte
+    let offset = "xuehua-archive@hd".len() + "xuehua-archive".len() + 2;
+    let mut encoded = encode_with(&events, HashAlgorithm::Sha256).to_vec();
+    assert_eq!(encoded[offset], 1);
+    encoded[offset] = 0;
+
+    let result = Decoder::new()
+        .decode_iter(&mut encoded.into())
+        .collect::<Result<Vec<_>, _>>();
+    assert!(
+        result.is_err(),
+        "sha256 digests should not verify as blake3"
+    );
+}
+
+fn lossy_decoding() {
+    let object = |location: &'static [u8], data: &'static [u8]| {
+        Event::Object(Object::file(
+            Bytes::from_static(location),
+            0o644,
+            Bytes::from_static(data),
+        ))
+    };
+    let events = vec![
+        Event::Header,
+        object(b"alpha", b"first"),
+        // contents that look like framing must not be mistaken for the next event
+        object(b"bravo", b"second xuehua-archive@ob"),
+        object(b"charlie", b"third"),
+        Event::Footer(Vec::new()),
+    ];
+
+    let mut encoded = encode(&events).to_vec();
+    let corrupted = encoded
+        .windows(b"second".len())
+        .position(|window| window == b"second")
+        .expect("archive should contain file contents");
+    let object_start = encoded[..corrupted]
+        .windows(b"xuehua-archive@".len())
+        .rposition(|window| window == b"xuehua-archive@")
+        .expect("object should have a prefix");
+    encoded[corrupted] ^= 0xff;
+
+    let mut skipped = Vec::new();
+    let decoded = Decoder::new()
+        .decode_iter_lossy(&mut encoded.into(), &mut skipped)
+        .collect::<Result<Vec<_>, _>>()
+        .expect("lossy decoding should skip the corrupt object");
+
+    assert_eq!(skipped, [object_start]);
+    assert_eq!(
+        decoded,
+        [
+            events[0].clone(),
+            events[1].clone(),
+            events[3].clone(),
+            events[4].clone()
+        ]
+    );
+}
+
+fn oversized_length() {
+    let events = vec![
+        Event::Header,
+        Event::Object(Object::file(
+            Bytes::from_static(b"file"),
+            0o644,
+            Bytes::from_static(b"xuehua"),
+        )),
+        Event::Footer(Vec::new()),
+    ];
+
+    let encoded = encode(&events);
+    let decode = |encoded: Vec<u8>, max| {
+        Decoder::new()
+            .with_max_object_size(max)
+            .decode_iter(&mut encoded.into())
+            .collect::<Result<Vec<_>, _>>()
+    };
+    assert_eq!(decode(encoded.to_vec(), 6).unwrap(), events);
+    assert!(decode(encoded.to_vec(), 5).is_err());
+
+    // claim a 2^63 byte location, followed by nothing
+    let object_start = encoded
+        .windows(b"xuehua-archive@ob".len())
+        .position(|window| window == b"xuehua-archive@ob")
+        .expect("archive should contain an object");
+    let mut truncated = encoded[..object_start + b"xuehua-archive@ob".len()].to_vec();
+    truncated.extend_from_slice(&(1u64 << 63).to_le_bytes());
+
+    let report = decode(truncated, 1024).expect_err("oversized length should be rejected");
+    assert_eq!(
+        report.root_cause().message,
+        "length exceeds maximum object size"
+    );
+}
+
+fn oversized_signature_count() {
+    let signature = (
+        blake3::hash(b"fingerprint"),
+        ed25519_dalek::Signature::from_bytes(&[7; 64]),
+    );
+    let events = vec![Event::Header, Event::Footer(vec![signature; 2])];
+
+    let encoded = encode(&events);
+    let decode = |encoded: Vec<u8>, max| {
+        Decoder::new()
+            .with_max_signatures(max)
+            .decode_iter(&mut encoded.into())
+            .collect::<Result<Vec<_>, _>>()
+    };
+    assert_eq!(decode(encoded.to_vec(), 2).unwrap(), events);
+    assert!(decode(encoded.to_vec(), 1).is_err());
+
+    // claim 2^64 - 1 signatures, followed by nothing
+    let footer = encoded.len() - 2 * (blake3::OUT_LEN + ed25519_dalek::Signature::BYTE_SIZE);
+    let mut truncated = encoded[..footer - 8].to_vec();
+    truncated.extend_from_slice(&u64::MAX.to_le_bytes());
+
+    let report = Decoder::new()
+        .decode_iter(&mut truncated.into())
+        .collect::<Result<Vec<_>, _>>()
+        .expect_err("oversized signature count should be rejected");
+    assert_eq!(
+        report.root_cause().message,
+        "footer exceeds maximum signature count"
+    );
+}
+
+fn object_constructors() {
+    let file = Object::file(
+        Bytes::from_static(b"dir/file"),
+        0o644,
+        Bytes::from_static(b"xuehua"),
+    );
+    assert_eq!(
+        file.content,
+        ObjectContent::File {
+            data: Bytes::from_static(b"xuehua")
+        }
+    );
+
+    let symlink = Object::symlink(
+        Bytes::from_static(b"link"),
+        0o777,
+        Bytes::from_static(b"dir/file"),
+    );
+    assert_eq!(
+        symlink.content,
+        ObjectContent::Symlink {
+            target: Bytes::from_static(b"dir/file").into()
+        }
+    );
+
+    // file type bits matching the content are allowed
+    let directory = Object::directory(Bytes::from_static(b"dir"), 0o040755);
+    assert_eq!(directory.content, ObjectContent::Directory);
+
+    for object in [&file, &symlink, &directory] {
+        object.validate().expect("object should be valid");
+    }
+}
+
+fn object_validation() {
+    let invalid = [
+        Object::directory(Bytes::new(), 0o755),
+        Object::directory(Bytes::from_static(b"/dir"), 0o755),
+        Object::directory(Bytes::from_static(b"dir/../escape"), 0o755),
+        Object::directory(Bytes::from_static(b"dir/"), 0o755),
+        Object::directory(Bytes::from_static(b"dir"), 0o100755),
+        Object::file(Bytes::from_static(b"file"), 0o1000644, Bytes::new()),
+        Object::symlink(Bytes::from_static(b"link"), 0o777, Bytes::new()),
+    ];
+
+    for object in invalid {
+        assert!(object.validate().is_err(), "{object:?} should be invalid");
+
+        let mut encoder = Encoder::new();
+        encoder
+            .encode(&mut BytesMut::new(), Event::Header)
+            .expect("header should encode");
+
+        let mut buffer = BytesMut::new();
+        let result = encoder.encode(&mut buffer, Event::Object(object));
+        assert!(result.is_err(), "encoder should reject invalid objects");
+        assert!(
+            buffer.is_empty(),
+            "encoder should not write invalid objects"
+        );
+    }
+}
+
+fn encoding_order() {
+    let object = || {
+        Event::Object(Object::file(
+            Bytes::from_static(b"file"),
+            0o644,
+            Bytes::from_static(b"xuehua"),
+        ))
+    };
+    let footer = || Event::Footer(Vec::new());
+
+    let malformed = [
+        ("object before header", vec![object()]),
+        ("footer before header", vec![footer()]),
+        ("two headers", vec![Event::Header, Event::Header]),
+        (
+            "object after footer",
+            vec![Event::Header, footer(), object()],
+        ),
+        ("two footers", vec![Event::Header, footer(), footer()]),
+    ];
+
+    for (name, events) in malformed {
+        let (last, valid) = events.split_last().expect("events should not be empty");
+        let mut encoder = Encoder::new();
+        encoder
+            .encode_iter(&mut BytesMut::new(), valid)
+            .unwrap_or_else(|_| panic!("{name}: prefix should encode"));
+
+        let mut buffer = BytesMut::new();
+        let result = encoder.encode(&mut buffer, last);
+        assert!(result.is_err(), "{name}: encoder should reject the event");
+        assert!(buffer.is_empty(), "{name}: nothing should be written");
+    }
+
+    // consecutive archives are fine
+    let mut encoder = Encoder::new();
+    let events = [Event::Header, object(), footer(), Event::Header, footer()];
+    encoder
+        .encode_iter(&mut BytesMut::new(), &events)
+        .expect("consecutive archives should encode");
+}
+
+fn object_trials() -> impl Iterator<Item = Trial> {
+    [
+        Trial::test("constructors", || {
+            object_constructors();
+            Ok(())
+        }),
+        Trial::test("validation", || {
+            object_validation();
+            Ok(())
+        }),
+        Trial::test("encoding-order", || {
+            encoding_order();
+            Ok(())
+        }),
+    ]
+    .into_iter()
+    .map(|trial| trial.with_kind("object"))
+}
+
+fn algorithm_trials() -> impl Iterator<Item = Trial> {
+    let roundtrip = |name, algorithm| {
+        Trial::test(name, move || {
+            arbtest(|u| {
+                algorithm_roundtrip(&ArbitraryArchive::arbitrary(u)?.events, algorithm);
+                Ok(())
+            })
+            .run();
+            Ok(())
+        })
+    };
+
+    [
+        roundtrip("roundtrip-blake3", HashAlgorithm::Blake3),
+        roundtrip("roundtrip-sha256", HashAlgorithm::Sha256),
+        Trial::test("wrong-algorithm", || {
+            wrong_algorithm();
+            Ok(())
+        }),
+    ]
+    .into_iter()
+    .map(|trial| trial.with_kind("algorithm"))
+}
+
 fn arbitrary_trials() -> impl Iterator<Item = Trial> {
     fn trial<F>(name: &str, runner: F) -> Trial
     where
@@ -96,12 +413,647 @@ fn blob_trials() -> impl Iterator<Item = Trial> {
         .map(|trial| trial.with_kind("blob"))
 }
 
+fn pack_diff_reuse() {
+    static READS: AtomicUsize = AtomicUsize::new(0);
+    fn counting_read(path: &Path) -> Result<Bytes, std::io::Error> {
+        READS.fetch_add(1, Ordering::Relaxed);
+        Ok(fs::read(path)?.into())
+    }
+
+    let (path, _temp) = utils::make_temp();
+    let since = SystemTime::now();
+    let set_modified = |file: &str, modified: SystemTime| {
+        fs::File::options()
+            .write(true)
+            .open(path.join(file))
+            .and_then(|file| file.set_modified(modified))
+            .expect("should be able to set modification time");
+    };
+
+    fs::create_dir(path.join("dir")).expect("should be able to create directory");
+    for file in ["a", "b", "c", "dir/d"] {
+        fs::write(path.join(file), file).expect("should be able to write file");
+        set_modified(file, since - Duration::from_secs(60));
+    }
+
+    let previous: Vec<_> = utils::pack(&path)
+        .into_iter()
+        .filter_map(|event| match event {
+            Event::Object(object) => Some(object),
+            _ => None,
+        })
+        .collect();
+
+    // modified after the previous pack, and within the same timestamp tick
+    fs::write(path.join("b"), "B").expect("should be able to modify file");
+    fs::write(path.join("c"), "C").expect("should be able to modify file");
+    set_modified("c", since);
+
+    let diffed = Packer::new(path.clone())
+        .pack_diff_with(&previous, since, counting_read)
+        .map(|event| event.expect("should be able to pack file"))
+        .collect::<Vec<_>>();
+
+    assert_eq!(READS.load(Ordering::Relaxed), 2);
+    assert_eq!(diffed, utils::pack(&path));
+}
+
+#[derive(Default)]
+struct CountingCache {
+    inner: MemoryDigestCache,
+    misses: AtomicUsize,
+}
+
+impl DigestCache for CountingCache {
+    fn get(&self, algorithm: HashAlgorithm, key: &DigestKey) -> Option<Digest> {
+        self.inner.get(algorithm, key)
+    }
+
+    fn insert(&self, algorithm: HashAlgorithm, key: DigestKey, digest: Digest) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        self.inner.insert(algorithm, key, digest);
+    }
+}
+
+fn digest_cache() {
+    let (path, _temp) = utils::make_temp();
+    fs::create_dir(path.join("dir")).expect("should be able to create directory");
+    for file in ["a", "b", "dir/c"] {
+        fs::write(path.join(file), file).expect("should be able to write file");
+    }
+
+    let cache = Arc::new(CountingCache::default());
+    let encode_cached = |path: &Path| {
+        let mut encoded = BytesMut::new();
+        let mut encoder = Encoder::new().with_digest_cache(cache.clone());
+        for result in Packer::new(path.to_path_buf()).pack_keyed_iter() {
+            let (event, key) = result.expect("should be able to pack file");
+            encoder
+                .encode_keyed(&mut encoded, event, key)
+                .expect("encoding should not fail");
+        }
+
+        encoded.freeze()
+    };
+
+    let first = encode_cached(&path);
+    assert_eq!(cache.misses.swap(0, Ordering::Relaxed), 3);
+    assert_eq!(first, encode(&utils::pack(&path)));
+
+    let second = encode_cached(&path);
+    assert_eq!(cache.misses.swap(0, Ordering::Relaxed), 0);
+    assert_eq!(first, second);
+
+    thread::sleep(Duration::from_millis(10));
+    fs::write(path.join("b"), "bb").expect("should be able to modify file");
+    let third = encode_cached(&path);
+    assert_eq!(cache.misses.load(Ordering::Relaxed), 1);
+    assert_eq!(third, encode(&utils::pack(&path)));
+
+    // a different file at the same location, with the same metadata, isn't mistaken for it
+    let (other, _temp) = utils::make_temp();
+    let modified = fs::metadata(path.join("a"))
+        .and_then(|metadata| metadata.modified())
+        .expect("should be able to read modification time");
+    fs::write(other.join("a"), "z").expect("should be able to write file");
+    fs::File::options()
+        .write(true)
+        .open(other.join("a"))
+        .and_then(|file| file.set_modified(modified))
+        .expect("should be able to set modification time");
+
+    cache.misses.store(0, Ordering::Relaxed);
+    let fourth = encode_cached(&other);
+    assert_eq!(cache.misses.load(Ordering::Relaxed), 1);
+    assert_eq!(fourth, encode(&utils::pack(&other)));
+}
+
+type ProgressLog = Arc<Mutex<Vec<(PathBytes, usize, usize)>>>;
+
+fn record_progress(log: &ProgressLog) -> impl Fn(&PathBytes, usize, usize) + Send + 'static {
+    let log = log.clone();
+    move |location, done, total| log.lock().unwrap().push((location.clone(), done, total))
+}
+
+fn assert_progress(log: &ProgressLog, events: &[Event]) {
+    let locations: Vec<_> = events
+        .iter()
+        .filter_map(|event| match event {
+            Event::Object(object) => Some(object.location.clone()),
+            _ => None,
+        })
+        .collect();
+
+    let total = locations.len();
+    let expected: Vec<_> = locations
+        .into_iter()
+        .enumerate()
+        .map(|(i, location)| (location, i + 1, total))
+        .collect();
+    assert_eq!(*log.lock().unwrap(), expected);
+}
+
+fn progress_callbacks() {
+    let (path, _temp) = utils::make_temp();
+    fs::create_dir(path.join("dir")).expect("should be able to create directory");
+    for file in ["a", "b", "dir/c"] {
+        fs::write(path.join(file), file).expect("should be able to write file");
+    }
+
+    let log = ProgressLog::default();
+    let packed = Packer::new(path.clone())
+        .with_progress(record_progress(&log))
+        .pack_iter()
+        .map(|event| event.expect("should be able to pack file"))
+        .collect::<Vec<_>>();
+    assert_eq!(packed, utils::pack(&path));
+    assert_progress(&log, &packed);
+
+    let (target, _temp) = utils::make_temp();
+    let log = ProgressLog::default();
+    Unpacker::new(&target)
+        .with_progress(record_progress(&log))
+        .unpack_iter(&packed)
+        .expect("should be able to unpack archive");
+    assert_eq!(utils::pack(&target), packed);
+    assert_progress(&log, &packed);
+}
+
+fn pack_exclude() {
+    let (path, _temp) = utils::make_temp();
+    for dir in [".git/objects", "src"] {
+        fs::create_dir_all(path.join(dir)).expect("should be able to create directory");
+    }
+    for file in [
+        ".git/HEAD",
+        ".git/objects/a",
+        "src/main.rs",
+        "src/main.rs.tmp",
+        "README",
+    ] {
+        fs::write(path.join(file), file).expect("should be able to write file");
+    }
+
+    let packed = Packer::new(path.clone())
+        .with_exclude(|path| {
+            path == Path::new(".git") || path.extension().is_some_and(|ext| ext == "tmp")
+        })
+        .pack_iter()
+        .map(|event| event.expect("should be able to pack file"))
+        .collect::<Vec<_>>();
+
+    for ignored in [".git/HEAD", ".git/objects/a", "src/main.rs.tmp"] {
+        fs::remove_file(path.join(ignored)).expect("should be able to remove file");
+    }
+    fs::remove_dir_all(path.join(".git")).expect("should be able to remove directory");
+    assert_eq!(packed, utils::pack(&path));
+}
+
+fn empty_directories() {
+    let (path, _temp) = utils::make_temp();
+    let modes = [("empty", 0o750), ("nested", 0o755), ("nested/empty", 0o700)];
+    for (dir, mode) in modes {
+        fs::create_dir(path.join(dir)).expect("should be able to create directory");
+        fs::set_permissions(path.join(dir), fs::Permissions::from_mode(mode))
+            .expect("should be able to set permissions");
+    }
+
+    let packed = utils::pack(&path);
+    let (target, _temp) = utils::make_temp();
+    Unpacker::new(&target)
+        .unpack_iter(&packed)
+        .expect("should be able to unpack archive");
+
+    for (dir, mode) in modes {
+        let metadata = fs::metadata(target.join(dir)).expect("directory should exist");
+        assert!(metadata.is_dir());
+        assert_eq!(metadata.permissions().mode() & 0o7777, mode, "{dir}");
+    }
+    assert_eq!(utils::pack(&target), packed);
+}
+
+fn interrupted_write() {
+    let (path, _temp) = utils::make_temp();
+    fs::write(path.join("data"), "new contents").expect("should be able to write file");
+    let packed = utils::pack(&path);
+
+    let partials = |target: &Path| {
+        fs::read_dir(target)
+            .expect("should be able to read directory")
+            .map(|entry| entry.expect("should be able to read entry").file_name())
+            .filter(|name| name.to_string_lossy().ends_with(PARTIAL_SUFFIX))
+            .count()
+    };
+
+    let (target, _temp) = utils::make_temp();
+    // a directory in place of the file makes renaming the partial file into place fail
+    fs::create_dir_all(target.join("data/nested")).expect("should be able to create directory");
+    let unpack = || Unpacker::new(&target).unpack_iter(&packed);
+    unpack().expect_err("unpacking should fail");
+    assert!(target.join("data/nested").is_dir());
+    assert_eq!(partials(&target), 0);
+
+    // a file named like a partial file is left alone
+    fs::remove_dir_all(target.join("data")).expect("should be able to remove directory");
+    let lookalike = target.join(format!(".data{PARTIAL_SUFFIX}"));
+    fs::write(&lookalike, "unrelated").expect("should be able to write file");
+    fs::write(target.join("data"), "old contents").expect("should be able to write file");
+    unpack().expect("should be able to unpack archive");
+    assert_eq!(
+        fs::read_to_string(target.join("data")).unwrap(),
+        "new contents"
+    );
+    assert_eq!(fs::read_to_string(&lookalike).unwrap(), "unrelated");
+    assert_eq!(partials(&target), 1);
+}
+
+fn relocated_symlinks() {
+    let (path, _temp) = utils::make_temp();
+    fs::create_dir_all(path.join("usr/lib")).expect("should be able to create directory");
+    fs::create_dir(path.join("usr/bin")).expect("should be able to create directory");
+    fs::write(path.join("usr/lib/libxh.so"), "library").expect("should be able to write file");
+    let symlink = |target: &Path, link| {
+        std::os::unix::fs::symlink(target, path.join(link)).expect("should be able to symlink")
+    };
+    symlink(&path.join("usr/lib/libxh.so"), "usr/bin/internal");
+    symlink(&path, "usr/bin/root");
+    symlink(Path::new("/etc/hostname"), "usr/bin/external");
+    let packed = utils::pack(&path);
+
+    let (target, _temp) = utils::make_temp();
+    Unpacker::new(&target)
+        .with_relocation(&path)
+        .unpack_iter(&packed)
+        .expect("should be able to unpack archive");
+    fs::remove_dir_all(&path).expect("should be able to remove directory");
+
+    let link = |name| fs::read_link(target.join("usr/bin").join(name)).unwrap();
+    assert_eq!(link("internal"), Path::new("../../usr/lib/libxh.so"));
+    assert_eq!(
+        fs::read_to_string(target.join("usr/bin/internal")).unwrap(),
+        "library"
+    );
+    assert_eq!(link("root"), Path::new("../.."));
+    assert_eq!(link("external"), Path::new("/etc/hostname"));
+}
+
+fn unpacking_trials() -> impl Iterator<Item = Trial> {
+    [
+        Trial::test("interrupted-write", || {
+            interrupted_write();
+            Ok(())
+        }),
+        Trial::test("relocated-symlinks", || {
+            relocated_symlinks();
+            Ok(())
+        }),
+    ]
+    .into_iter()
+    .map(|trial| trial.with_kind("unpacking"))
+}
+
+fn symlinked_directory() {
+    let (path, _temp) = utils::make_temp();
+    fs::create_dir(path.join("dir")).expect("should be able to create directory");
+    fs::set_permissions(path.join("dir"), fs::Permissions::from_mode(0o700))
+        .expect("should be able to set permissions");
+    let packed = utils::pack(&path);
+
+    // an existing symlink to a directory isn't reused as the directory
+    let (outside, _outside_temp) = utils::make_temp();
+    fs::set_permissions(&outside, fs::Permissions::from_mode(0o755))
+        .expect("should be able to set permissions");
+    let (target, _temp) = utils::make_temp();
+    std::os::unix::fs::symlink(&outside, target.join("dir"))
+        .expect("should be able to create symlink");
+    assert!(Unpacker::new(&target).unpack_iter(&packed).is_err());
+
+    let mode = fs::metadata(&outside)
+        .expect("directory should exist")
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o7777, 0o755);
+}
+
+fn directory_trials() -> impl Iterator<Item = Trial> {
+    [
+        Trial::test("empty", || {
+            empty_directories();
+            Ok(())
+        }),
+        Trial::test("symlinked", || {
+            symlinked_directory();
+            Ok(())
+        }),
+    ]
+    .into_iter()
+    .map(|trial| trial.with_kind("directory"))
+}
+
+fn exclude_trials() -> impl Iterator<Item = Trial> {
+    [Trial::test("subtree", || {
+        pack_exclude();
+        Ok(())
+    })]
+    .into_iter()
+    .map(|trial| trial.with_kind("exclude"))
+}
+
+fn progress_trials() -> impl Iterator<Item = Trial> {
+    [Trial::test("callbacks", || {
+        progress_callbacks();
+        Ok(())
+    })]
+    .into_iter()
+    .map(|trial| trial.with_kind("progress"))
+}
+
+fn diff_trials() -> impl Iterator<Item = Trial> {
+    [Trial::test("pack-diff-reuse", || {
+        pack_diff_reuse();
+        Ok(())
+    })]
+    .into_iter()
+    .map(|trial| trial.with_kind("diff"))
+}
+
+fn cache_trials() -> impl Iterator<Item = Trial> {
+    [Trial::test("unchanged-files", || {
+        digest_cache();
+        Ok(())
+    })]
+    .into_iter()
+    .map(|trial| trial.with_kind("cache"))
+}
+
+fn archive_boundaries() {
+    let archive = |data: &'static [u8]| {
+        vec![
+            Event::Header,
+            Event::Object(Object::file(
+                Bytes::from_static(b"file"),
+                0o644,
+                Bytes::from_static(data),
+            )),
+            Event::Footer(Vec::new()),
+        ]
+    };
+    let decode = |decoder: &mut Decoder, encoded: &[u8]| {
+        decoder
+            .decode_iter(&mut Bytes::copy_from_slice(encoded))
+            .collect::<Result<Vec<_>, _>>()
+    };
+
+    // back-to-back archives
+    let events = [archive(b"first"), archive(b"second")].concat();
+    let encoded = encode(&events);
+    let mut decoder = Decoder::new().with_strict(true);
+    assert_eq!(decode(&mut decoder, &encoded).unwrap(), events);
+
+    // second archive starts before the first one's footer
+    let mut truncated = archive(b"first");
+    truncated.pop();
+    let mut encoded = encode(&truncated).to_vec();
+    encoded.extend_from_slice(&encode(&archive(b"second")));
+
+    let mut decoder = Decoder::new().with_strict(true);
+    let report = decode(&mut decoder, &encoded).expect_err("missing footer should be rejected");
+    assert_eq!(
+        report.root_cause().message,
+        "header encountered before the previous archive's footer"
+    );
+    assert_eq!(decode(&mut Decoder::new(), &encoded).unwrap().len(), 5);
+
+    // resetting discards the unfinished archive
+    let mut decoder = Decoder::new().with_strict(true);
+    assert_eq!(
+        decode(&mut decoder, &encode(&truncated)).unwrap(),
+        truncated
+    );
+    decoder.reset();
+    assert_eq!(
+        decode(&mut decoder, &encode(&archive(b"second"))).unwrap(),
+        archive(b"second")
+    );
+}
+
+fn decode_stats() {
+    let events = vec![
+        Event::Header,
+        Event::Object(Object::directory(Bytes::from_static(b"dir"), 0o755)),
+        Event::Object(Object::file(
+            Bytes::from_static(b"dir/file"),
+            0o644,
+            Bytes::from_static(b"xuehua"),
+        )),
+        Event::Object(Object::file(
+            Bytes::from_static(b"empty"),
+            0o644,
+            Bytes::new(),
+        )),
+        Event::Object(Object::symlink(
+            Bytes::from_static(b"link"),
+            0o777,
+            Bytes::from_static(b"dir/file"),
+        )),
+        Event::Footer(Vec::new()),
+    ];
+    let encoded = encode(&events);
+
+    let mut decoder = Decoder::new();
+    decoder
+        .decode_iter(&mut encoded.clone())
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(decoder.stats(), None);
+
+    let mut decoder = Decoder::new().with_stats();
+    let decoded = decoder
+        .decode_iter(&mut encoded.clone())
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(decoded, events);
+
+    let stats = decoder.stats().copied().unwrap();
+    assert_eq!((stats.files, stats.file_bytes), (2, 6));
+    assert_eq!((stats.symlinks, stats.symlink_bytes), (1, 8));
+    assert_eq!(stats.directories, 1);
+
+    // stats accumulate across archives
+    decoder.decode_iter(&mut encoded.clone()).for_each(drop);
+    assert_eq!(decoder.stats().unwrap().files, 4);
+}
+
+fn random_access() {
+    let signature = (
+        blake3::hash(b"fingerprint"),
+        ed25519_dalek::Signature::from_bytes(&[7; 64]),
+    );
+    let events = vec![
+        Event::Header,
+        Event::Object(Object::directory(Bytes::from_static(b"dir"), 0o755)),
+        Event::Object(Object::file(
+            Bytes::from_static(b"dir/file"),
+            0o644,
+            Bytes::from_static(b"snowflake"),
+        )),
+        Event::Object(Object::symlink(
+            Bytes::from_static(b"link"),
+            0o777,
+            Bytes::from_static(b"dir/file"),
+        )),
+        Event::Footer(vec![signature]),
+    ];
+
+    let mut encoded = BytesMut::new();
+    Encoder::new()
+        .with_index()
+        .encode_iter(&mut encoded, &events)
+        .unwrap();
+    let encoded = encoded.freeze();
+
+    // the index does not change the decoded events, nor the digest
+    let mut decoder = Decoder::new();
+    let decoded = decoder
+        .decode_iter(&mut encoded.clone())
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(decoded, events);
+
+    let mut plain = Encoder::new();
+    plain.encode_iter(&mut BytesMut::new(), &events).unwrap();
+    assert_eq!(decoder.digest(), plain.digest());
+
+    let reader = RandomAccessReader::new(encoded.clone()).unwrap();
+    assert_eq!(reader.locations().count(), 3);
+    let location = PathBytes::from(Bytes::from_static(b"dir/file"));
+    let Event::Object(object) = &events[2] else {
+        unreachable!()
+    };
+    assert_eq!(reader.read(&location).unwrap().as_ref(), Some(object));
+    let missing = PathBytes::from(Bytes::from_static(b"missing"));
+    assert_eq!(reader.read(&missing).unwrap(), None);
+
+    // corrupting an indexed object fails reading it
+    let mut corrupt = encoded.to_vec();
+    let offset = corrupt
+        .windows(b"snowflake".len())
+        .position(|window| window == b"snowflake")
+        .unwrap();
+    corrupt[offset] ^= 0xff;
+    let reader = RandomAccessReader::new(corrupt.into()).unwrap();
+    let report = reader.read(&location).unwrap_err().into_payload();
+    assert_eq!(
+        report.children[0].message,
+        "index entry does not point at its object"
+    );
+
+    let report = RandomAccessReader::new(encode(&events)).err().unwrap();
+    assert_eq!(report.root_cause().message, "archive has no index");
+}
+
+fn streaming_reader() {
+    /// Hands out at most 1000 bytes per read.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl std::io::Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(self.0.len()).min(1000);
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Ok(len)
+        }
+    }
+
+    let mut events = vec![Event::Header];
+    for i in 0..64 {
+        events.push(Event::Object(Object::file(
+            Bytes::from(format!("file-{i}")),
+            0o644,
+            Bytes::from(vec![i as u8; i * 2048]),
+        )));
+    }
+    events.push(Event::Footer(Vec::new()));
+    // `utils::encode` logs the whole archive
+    let mut encoded = BytesMut::new();
+    Encoder::new()
+        .encode_iter(&mut encoded, &events)
+        .expect("encoding should not fail");
+    let encoded = encoded.freeze();
+
+    let mut decoder = Decoder::new();
+    let decoded = decoder
+        .decode_reader(Trickle(&encoded))
+        .collect::<Result<Vec<_>, _>>()
+        .expect("should be able to decode archive");
+    assert_eq!(decoded, events);
+
+    let mut buffered = Decoder::new();
+    for event in buffered.decode_iter(&mut encoded.clone()) {
+        event.expect("should be able to decode archive");
+    }
+    assert_eq!(decoder.digest(), buffered.digest());
+
+    // a truncated archive fails instead of waiting for more data
+    let truncated = &encoded[..encoded.len() - 10];
+    let result = Decoder::new()
+        .decode_reader(Trickle(truncated))
+        .collect::<Result<Vec<_>, _>>();
+    assert!(result.is_err());
+}
+
+fn decoding_trials() -> impl Iterator<Item = Trial> {
+    [
+        Trial::test("streaming-reader", || {
+            streaming_reader();
+            Ok(())
+        }),
+        Trial::test("lossy-recovery", || {
+            lossy_decoding();
+            Ok(())
+        }),
+        Trial::test("oversized-length", || {
+            oversized_length();
+            Ok(())
+        }),
+        Trial::test("oversized-signature-count", || {
+            oversized_signature_count();
+            Ok(())
+        }),
+        Trial::test("archive-boundaries", || {
+            archive_boundaries();
+            Ok(())
+        }),
+        Trial::test("stats", || {
+            decode_stats();
+            Ok(())
+        }),
+        Trial::test("random-access", || {
+            random_access();
+            Ok(())
+        }),
+    ]
+    .into_iter()
+    .map(|trial| trial.with_kind("decoding"))
+}
+
 fn main() {
     let _ = GlobalRenderer::set(JsonRenderer::new());
     tracing_subscriber::registry()
         .with(ReportLayer::new())
         .init();
 
-    let trials = blob_trials().chain(arbitrary_trials()).collect();
+    let trials = blob_trials()
+        .chain(arbitrary_trials())
+        .chain(diff_trials())
+        .chain(cache_trials())
+        .chain(algorithm_trials())
+        .chain(object_trials())
+        .chain(decoding_trials())
+        .chain(progress_trials())
+        .chain(exclude_trials())
+        .chain(directory_trials())
+        .chain(unpacking_trials())
+        .collect();
     libtest_mimic::run(&Arguments::from_args(), trials).exit()
 }
@@ -7,7 +7,7 @@ use arbitrary::Arbitrary;
 use bytes::{Bytes, BytesMut};
 use libtest_mimic::{Failed, Measurement};
 use tracing::debug;
-use xh_archive::{Event, Object, ObjectContent, decoding::Decoder, encoding::Encoder};
+use xh_archive::{Event, HashAlgorithm, Object, decoding::Decoder, encoding::Encoder};
 
 #[derive(Clone, Copy)]
 pub struct BenchmarkOptions {
@@ -94,17 +94,24 @@ impl Arbitrary<'_> for ArbitraryArchive {
                     Ok(Bytes::from_owner(u.arbitrary::<Vec<u8>>()?))
                 };
 
-                let object = Object {
-                    location: bytes(u)?.into(),
-                    permissions: u.arbitrary()?,
-                    content: match u.choose_index(3)? {
-                        0 => ObjectContent::File { data: bytes(u)? },
-                        1 => ObjectContent::Symlink {
-                            target: bytes(u)?.into(),
-                        },
-                        2 => ObjectContent::Directory,
-                        _ => unreachable!(),
-                    },
+                // objects must have a normalized relative location
+                let location = (0..u.int_in_range(1..=3)?)
+                    .map(|_| Ok(format!("{:x}", u.arbitrary::<u32>()?)))
+                    .collect::<arbitrary::Result<Vec<_>>>()?
+                    .join("/");
+                let location = Bytes::from(location);
+                let permissions = u.arbitrary::<u32>()? & 0o7777;
+
+                let object = match u.choose_index(3)? {
+                    0 => Object::file(location, permissions, bytes(u)?),
+                    1 => {
+                        // symlinks must have a non-empty target
+                        let mut target = u.arbitrary::<Vec<u8>>()?;
+                        target.push(b't');
+                        Object::symlink(location, permissions, Bytes::from(target))
+                    }
+                    2 => Object::directory(location, permissions),
+                    _ => unreachable!(),
                 };
 
                 Ok(Event::Object(object))
@@ -154,8 +161,14 @@ pub fn decode(contents: &mut Bytes) -> Vec<Event> {
 }
 
 pub fn encode(events: &Vec<Event>) -> Bytes {
+    encode_with(events, HashAlgorithm::default())
+}
+
+pub fn encode_with(events: &Vec<Event>, algorithm: HashAlgorithm) -> Bytes {
     let mut encoded = BytesMut::new();
-    Encoder::new().encode_iter(&mut encoded, events);
+    Encoder::with_algorithm(algorithm)
+        .encode_iter(&mut encoded, events)
+        .expect("encoding should not fail");
 
     debug!(?encoded, "encoded data");
     encoded.freeze()
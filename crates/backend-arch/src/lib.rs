@@ -1,12 +1,15 @@
 use std::{
     collections::{BTreeMap, HashMap, hash_map::Entry},
     fs::read_dir,
-    path::Path,
+    io::Read,
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
     str::FromStr,
     sync::LazyLock,
 };
 
 use alpm_repo_db::desc::RepoDescFile;
+use flate2::read::GzDecoder;
 use serde::Deserialize;
 use smol_str::{SmolStr, ToSmolStr};
 use xh_engine::{
@@ -18,21 +21,33 @@ use xh_engine::{
     package::{Dependency, DispatchRequest, LinkTime, Metadata, Package},
     planner::{Planner, Unfrozen},
 };
-use xh_executor_bubblewrap::BubblewrapExecutor;
-use xh_executor_compression::CompressionExecutor;
-use xh_executor_http::HttpExecutor;
-use xh_reports::{partition_results, prelude::*};
+use xh_executor_extract::ExtractExecutor;
+use xh_reports::{PartitionOptions, partition_results_with, prelude::*};
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Options {
     pub mirror: String,
     pub architecture: SmolStr,
+    /// Repos in priority order, packages from earlier repos win over later ones
     #[serde(default)]
     pub repos: Vec<SmolStr>,
+    /// Explicit per-package priorities, taking precedence over repo order
     #[serde(default)]
     pub priorities: BTreeMap<SmolStr, usize>,
+    /// Download repo databases from the mirror instead of scanning the project directory
+    #[serde(default)]
+    pub fetch_db: bool,
+    /// Lossily convert repo directory names that are not valid UTF-8 instead of failing
+    #[serde(default)]
+    pub lossy_repo_names: bool,
+    /// Fail on package directories with a missing or empty `desc` file, instead of skipping them
+    #[serde(default)]
+    pub strict_scan: bool,
 }
 
+/// Maximum amount of package errors reported by [`ArchBackend::plan`]
+const MAX_PLAN_ERRORS: usize = 32;
+
 pub struct ArchBackend {
     options: Options,
 }
@@ -49,6 +64,8 @@ impl ArchBackend {
         let transform_ref = |name, origin| Package {
             name: package_name(name),
             metadata: Metadata,
+            tags: Vec::new(),
+            build_cost: None,
             requests: vec![],
             dependencies: vec![Dependency {
                 name: package_name(origin),
@@ -56,48 +73,13 @@ impl ArchBackend {
             }],
         };
 
-        let transform_pkg = move |name, dependencies: Vec<_>, repo, file| {
+        let transform_pkg = move |name, dependencies: Vec<_>, repo: SmolStr, file: SmolStr| {
             let pkg = Package {
                 name: package_name(name),
                 metadata: Metadata,
-                requests: vec![
-                    DispatchRequest {
-                        executor: HttpExecutor::name().clone(),
-                        payload: to_value(xh_executor_http::Request {
-                            path: "download.pkg.tar.zst".into(),
-                            url: FromStr::from_str(&format!(
-                                "{}/{repo}/os/{}/{file}",
-                                self.options.mirror, self.options.architecture
-                            ))
-                            .erased()?,
-                            method: FromStr::from_str("GET").expect("GET should be a valid method"),
-                        })
-                        .erased()?,
-                    },
-                    DispatchRequest {
-                        executor: CompressionExecutor::name().clone(),
-                        payload: to_value(xh_executor_compression::Request {
-                            algorithm: xh_executor_compression::Algorithm::Zstd,
-                            action: xh_executor_compression::Action::Decompress,
-                            input: "download.pkg.tar.zst".into(),
-                            output: "download.pkg.tar".into(),
-                        })
-                        .erased()?,
-                    },
-                    DispatchRequest {
-                        executor: BubblewrapExecutor::name().clone(),
-                        payload: to_value(xh_executor_bubblewrap::Request {
-                            program: "/busybox".into(),
-                            working_dir: None,
-                            arguments: ["tar", "x", "-f", "download.pkg.tar", "-C", "output"]
-                                .into_iter()
-                                .map(Into::into)
-                                .collect(),
-                            environment: Vec::new(),
-                        })
-                        .erased()?,
-                    },
-                ],
+                tags: Vec::new(),
+                build_cost: None,
+                requests: vec![self.download_request(&repo, &file)?],
                 dependencies: dependencies
                     .into_iter()
                     .map(|dependency| Dependency {
@@ -120,16 +102,108 @@ impl ArchBackend {
         })
     }
 
+    /// Request downloading `file` from `repo` and extracting it into `output`.
+    fn download_request(&self, repo: &str, file: &str) -> Result<DispatchRequest, ()> {
+        Ok(DispatchRequest {
+            executor: ExtractExecutor::name().clone(),
+            payload: to_value(xh_executor_extract::Request {
+                url: FromStr::from_str(&format!(
+                    "{}/{repo}/os/{}/{file}",
+                    self.options.mirror, self.options.architecture
+                ))
+                .erased()?,
+                method: FromStr::from_str("GET").expect("GET should be a valid method"),
+                output_dir: "output".into(),
+            })
+            .erased()?,
+            after: None,
+        })
+    }
+
+    fn register(
+        &self,
+        planner: &mut Planner<Unfrozen>,
+        descriptions: Vec<Description>,
+    ) -> Result<(), Error> {
+        let index = self.resolve_index(descriptions);
+        let packages = self.index_to_packages(index);
+        let planner = packages.map(|result| planner.register(result?).erased().map(|_| ()));
+
+        let options = PartitionOptions::new().cap(MAX_PLAN_ERRORS);
+        partition_results_with::<_, (), _, Vec<_>>(planner, options)
+            .map_err(|errors| errors.wrap_with(Error))
+    }
+
+    fn database_url(&self, repo: &str) -> String {
+        format!(
+            "{}/{repo}/os/{}/{repo}.db",
+            self.options.mirror, self.options.architecture
+        )
+    }
+
+    /// Fetches the databases of every configured repo from the mirror.
+    fn fetch_repos_blocking(&self) -> Result<Vec<Description>, ()> {
+        let mut descriptions = Vec::new();
+        for repo in &self.options.repos {
+            let url = self.database_url(repo);
+            let fetched = fetch_database(&url, repo.clone())
+                .wrap_with_fn(|| RepoFetchError {
+                    repo: repo.clone(),
+                    url,
+                })
+                .erased()?;
+            descriptions.extend(fetched);
+        }
+
+        Ok(descriptions)
+    }
+
+    /// Fetches the databases of every configured repo from the mirror concurrently.
+    async fn fetch_repos(&self) -> Result<Vec<Description>, ()> {
+        let handles: Vec<_> = self
+            .options
+            .repos
+            .iter()
+            .map(|repo| {
+                let url = self.database_url(repo);
+                let repo = repo.clone();
+
+                tokio::task::spawn_blocking(move || {
+                    fetch_database(&url, repo.clone()).wrap_with_fn(|| RepoFetchError { repo, url })
+                })
+            })
+            .collect();
+
+        let mut descriptions = Vec::new();
+        for handle in handles {
+            descriptions.extend(handle.await.erased()?.erased()?);
+        }
+
+        Ok(descriptions)
+    }
+
+    /// Ranks `repo` by its position in [`Options::repos`], earlier repos rank higher.
+    ///
+    /// Repos not listed rank below every listed one.
+    fn repo_priority(&self, repo: &str) -> usize {
+        let repos = &self.options.repos;
+        repos
+            .iter()
+            .position(|candidate| candidate == repo)
+            .map_or(0, |position| repos.len() - position)
+    }
+
     fn resolve_index(&self, descriptions: Vec<Description>) -> HashMap<SmolStr, IndexEntry> {
         fn attempt_replacement(
             name: SmolStr,
-            priority: usize,
+            priority: (usize, usize),
             create_index_entry: impl FnOnce() -> IndexEntry,
             index: &mut HashMap<SmolStr, IndexEntry>,
         ) {
             match index.entry(name) {
                 Entry::Occupied(mut occupied) => {
-                    if priority > occupied.get().priority {
+                    let current = occupied.get();
+                    if priority > (current.priority, current.repo_priority) {
                         occupied.insert(create_index_entry());
                     }
                 }
@@ -154,12 +228,14 @@ impl ArchBackend {
                 .get(&name)
                 .copied()
                 .unwrap_or_default();
+            let repo_priority = self.repo_priority(&repo);
 
             attempt_replacement(
                 name.clone(),
-                priority,
+                (priority, repo_priority),
                 || IndexEntry {
                     priority,
+                    repo_priority,
                     ty: IndexEntryType::Package {
                         dependencies,
                         file,
@@ -172,9 +248,10 @@ impl ArchBackend {
             for provided in provides {
                 attempt_replacement(
                     provided,
-                    priority,
+                    (priority, repo_priority),
                     || IndexEntry {
                         priority,
+                        repo_priority,
                         ty: IndexEntryType::Reference {
                             origin: name.clone(),
                         },
@@ -197,17 +274,70 @@ impl Backend for ArchBackend {
     }
 
     fn plan(&self, planner: &mut Planner<Unfrozen>, project: &Path) -> Result<(), Error> {
-        let entries = scan_project(project).wrap()?;
-        let index = self.resolve_index(entries);
-        let packages = self.index_to_packages(index);
-        let planner = packages.map(|result| planner.register(result?).erased().map(|_| ()));
+        let entries = if self.options.fetch_db {
+            self.fetch_repos_blocking().wrap()?
+        } else {
+            let descs = scan_project(
+                project,
+                self.options.lossy_repo_names,
+                self.options.strict_scan,
+            )
+            .wrap()?;
+            let threads = std::thread::available_parallelism().unwrap_or(NonZeroUsize::MIN);
+            parse_descriptions(&descs, threads).wrap()?
+        };
 
-        partition_results::<_, (), _, Vec<_>>(planner)
-            .map_err(|reports| Error.into_report().with_children(reports))
+        self.register(planner, entries)
+    }
+
+    /// Same as [`Self::plan`], but fetches repo databases concurrently.
+    async fn plan_async(
+        &self,
+        planner: &mut Planner<Unfrozen>,
+        project: &Path,
+    ) -> Result<(), Error> {
+        if !self.options.fetch_db {
+            return self.plan(planner, project);
+        }
+
+        let entries = self.fetch_repos().await.wrap()?;
+        self.register(planner, entries)
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, IntoReport)]
+#[message("could not fetch repository database")]
+#[context(display: repo, url)]
+struct RepoFetchError {
+    repo: SmolStr,
+    url: String,
+}
+
+fn fetch_database(url: &str, repo: SmolStr) -> Result<Vec<Description>, ()> {
+    let response = ureq::get(url).call().erased()?;
+    parse_database(response.into_body().into_reader(), repo)
+}
+
+/// Parses the `desc` entries of a gzip compressed repo database.
+fn parse_database(reader: impl Read, repo: SmolStr) -> Result<Vec<Description>, ()> {
+    let mut archive = tar::Archive::new(GzDecoder::new(reader));
+
+    let mut descriptions = Vec::new();
+    for entry in archive.entries().erased()? {
+        let mut entry = entry.erased()?;
+        if entry.path().erased()?.file_name() != Some("desc".as_ref()) {
+            continue;
+        }
+
+        let mut content = String::new();
+        entry.read_to_string(&mut content).erased()?;
+        descriptions.push(content_to_description(&content, repo.clone())?);
+    }
+
+    Ok(descriptions)
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
 struct Description {
     name: SmolStr,
     repo: SmolStr,
@@ -246,23 +376,93 @@ fn content_to_description(content: &str, repo: SmolStr) -> Result<Description, (
 #[message("could not scan packages")]
 struct PackageScanError;
 
-fn scan_project(project: &Path) -> Result<Vec<Description>, PackageScanError> {
-    let mut entries = vec![];
+#[derive(Debug, IntoReport)]
+#[message("repository directory name is not valid UTF-8")]
+#[suggestion("rename the directory, or enable lossy repo names")]
+#[context(path)]
+struct NonUtf8RepoNameError {
+    path: String,
+}
+
+#[derive(Debug, IntoReport)]
+#[message("could not parse package description")]
+#[context(path)]
+struct DescriptionParseError {
+    path: PathBuf,
+}
+
+/// Lists the `desc` file of every package in the project, sorted by path.
+///
+/// Unless `strict`, package directories with a missing or empty `desc` are skipped with a warning.
+fn scan_project(
+    project: &Path,
+    lossy: bool,
+    strict: bool,
+) -> Result<Vec<(SmolStr, PathBuf)>, PackageScanError> {
+    let mut descs = vec![];
 
     for entry in read_dir(project).wrap()? {
         let entry = entry.wrap()?;
-        let repo = entry.file_name().into_encoded_bytes();
-        let repo = String::from_utf8(repo).wrap()?.to_smolstr();
+        let repo = match entry.file_name().into_string() {
+            Ok(repo) => repo.to_smolstr(),
+            Err(repo) if lossy => repo.to_string_lossy().to_smolstr(),
+            Err(_) => {
+                return Err(NonUtf8RepoNameError {
+                    path: entry.path().to_string_lossy().into_owned(),
+                }
+                .wrap());
+            }
+        };
 
         for entry in read_dir(entry.path()).wrap()? {
-            let entry = entry.wrap()?;
-            let content = std::fs::read_to_string(entry.path().join("desc")).wrap()?;
+            let package = entry.wrap()?.path();
+            let desc = package.join("desc");
+            if !strict && !std::fs::metadata(&desc).is_ok_and(|metadata| metadata.len() > 0) {
+                tracing::warn!(
+                    ?package,
+                    "package has a missing or empty desc file, skipping"
+                );
+                continue;
+            }
 
-            entries.push(content_to_description(&content, repo.clone()).wrap()?);
+            descs.push((repo.clone(), desc));
         }
     }
 
-    Ok(entries)
+    // directory iteration order is unspecified
+    descs.sort_unstable_by(|(_, a), (_, b)| a.cmp(b));
+    Ok(descs)
+}
+
+/// Reads and parses `descs` across `threads` threads, preserving their order.
+fn parse_descriptions(
+    descs: &[(SmolStr, PathBuf)],
+    threads: NonZeroUsize,
+) -> Result<Vec<Description>, PackageScanError> {
+    let parse = |(repo, path): &(SmolStr, PathBuf)| {
+        let content = std::fs::read_to_string(path)
+            .erased()
+            .and_then(|content| content_to_description(&content, repo.clone()));
+
+        content.wrap_with_fn(|| DescriptionParseError { path: path.clone() })
+    };
+
+    let chunk_size = descs.len().div_ceil(threads.get()).max(1);
+    let results: Vec<_> = std::thread::scope(|scope| {
+        let handles: Vec<_> = descs
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || chunk.iter().map(parse).collect::<Vec<_>>()))
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("parser thread should not panic"))
+            .collect()
+    });
+
+    let options = PartitionOptions::new().cap(MAX_PLAN_ERRORS);
+    partition_results_with::<_, _, _, Vec<_>>(results.into_iter(), options)
+        .map_err(|errors| errors.wrap_with(PackageScanError))
 }
 
 #[derive(Debug)]
@@ -280,6 +480,7 @@ enum IndexEntryType {
 #[derive(Debug)]
 struct IndexEntry {
     priority: usize,
+    repo_priority: usize,
     ty: IndexEntryType,
 }
 
@@ -289,9 +490,41 @@ fn package_name(identifier: impl Into<SmolStr>) -> PackageName {
 
 #[cfg(test)]
 mod tests {
-    use std::collections::BTreeMap;
+    use std::{collections::BTreeMap, num::NonZeroUsize};
 
-    use crate::{ArchBackend, Description, IndexEntry, IndexEntryType, Options};
+    use xh_engine::{
+        backend::Backend,
+        encoding::from_value,
+        executor::Executor,
+        planner::{Planner, Unfrozen},
+    };
+    use xh_executor_extract::ExtractExecutor;
+
+    use crate::{
+        ArchBackend, Description, IndexEntry, IndexEntryType, Options, package_name,
+        parse_database, parse_descriptions, scan_project,
+    };
+
+    #[test]
+    fn test_parse_database() {
+        let fixture = include_bytes!("../tests/fixtures/core.db");
+        let mut descriptions =
+            parse_database(fixture.as_slice(), "core".into()).expect("database should parse");
+        descriptions.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let [example, glibc] = descriptions.as_slice() else {
+            panic!("database should contain 2 descriptions, got {descriptions:?}");
+        };
+
+        assert_eq!(example.name, "example");
+        assert_eq!(example.repo, "core");
+        assert_eq!(example.file, "example-1.0.0-1-x86_64.pkg.tar.zst");
+        assert_eq!(example.dependencies, ["glibc"]);
+        assert_eq!(example.provides, ["example-component"]);
+
+        assert_eq!(glibc.name, "glibc");
+        assert!(glibc.dependencies.is_empty());
+    }
 
     #[test]
     fn test_index_resolution() {
@@ -299,8 +532,11 @@ mod tests {
             options: Options {
                 mirror: Default::default(),
                 architecture: Default::default(),
-                repos: Default::default(),
+                repos: vec!["core".into(), "extra".into()],
                 priorities: BTreeMap::from([("my-other-pkg".into(), 1), ("my-next-pkg".into(), 2)]),
+                fetch_db: false,
+                lossy_repo_names: false,
+                strict_scan: false,
             },
         };
 
@@ -308,6 +544,7 @@ mod tests {
             Description {
                 name: "my-pkg".into(),
                 provides: vec!["my-library".into()],
+                repo: "core".into(),
                 ..Default::default()
             },
             Description {
@@ -320,6 +557,22 @@ mod tests {
                 provides: vec!["my-other-pkg".into()],
                 ..Default::default()
             },
+            Description {
+                name: "my-tool".into(),
+                provides: vec!["my-shell".into()],
+                repo: "extra".into(),
+                ..Default::default()
+            },
+            Description {
+                name: "my-shell".into(),
+                repo: "core".into(),
+                ..Default::default()
+            },
+            Description {
+                name: "my-shell".into(),
+                repo: "my-unlisted-repo".into(),
+                ..Default::default()
+            },
         ];
 
         let index = backend.resolve_index(entries);
@@ -341,5 +594,143 @@ mod tests {
             }) if origin == "my-other-pkg" => (),
             _ => panic!("my-library did not resolve to the expected value"),
         }
+
+        match index.get("my-shell") {
+            Some(IndexEntry {
+                priority: 0,
+                repo_priority: 2,
+                ty: IndexEntryType::Package { repo, .. },
+            }) if repo == "core" => (),
+            _ => panic!("my-shell did not resolve to the expected value"),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_repo_name() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let temp = tempfile::tempdir().unwrap();
+        let repo = temp.path().join(OsStr::from_bytes(b"core\xff"));
+        std::fs::create_dir_all(repo.join("example-1.0.0-1")).unwrap();
+        std::fs::write(
+            repo.join("example-1.0.0-1/desc"),
+            include_str!("../tests/fixtures/example.desc"),
+        )
+        .unwrap();
+
+        let error = scan_project(temp.path(), false, false).unwrap_err();
+        assert_eq!(
+            error.root_cause().message,
+            "repository directory name is not valid UTF-8"
+        );
+
+        let descs = scan_project(temp.path(), true, false).unwrap();
+        assert_eq!(descs[0].0, "core\u{fffd}");
+    }
+
+    #[test]
+    fn test_empty_desc() {
+        let temp = tempfile::tempdir().unwrap();
+        for (package, desc) in [
+            (
+                "example-1.0.0-1",
+                include_str!("../tests/fixtures/example.desc"),
+            ),
+            ("empty-1.0.0-1", ""),
+        ] {
+            let package = temp.path().join("core").join(package);
+            std::fs::create_dir_all(&package).unwrap();
+            std::fs::write(package.join("desc"), desc).unwrap();
+        }
+        std::fs::create_dir(temp.path().join("core/missing-1.0.0-1")).unwrap();
+
+        let descs = scan_project(temp.path(), false, false).unwrap();
+        assert_eq!(descs.len(), 1);
+        assert!(descs[0].1.ends_with("example-1.0.0-1/desc"));
+
+        let options = |strict_scan| Options {
+            mirror: "https://mirror.example".to_string(),
+            architecture: "x86_64".into(),
+            repos: vec!["core".into()],
+            priorities: BTreeMap::new(),
+            fetch_db: false,
+            lossy_repo_names: false,
+            strict_scan,
+        };
+        let mut planner = Planner::<Unfrozen>::new();
+        ArchBackend::new(options(false))
+            .plan(&mut planner, temp.path())
+            .unwrap();
+        assert!(planner.resolve(&package_name("example")).is_some());
+
+        let mut planner = Planner::<Unfrozen>::new();
+        assert!(
+            ArchBackend::new(options(true))
+                .plan(&mut planner, temp.path())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_parallel_parsing() {
+        let desc = include_str!("../tests/fixtures/example.desc");
+        let temp = tempfile::tempdir().unwrap();
+        for repo in ["core", "extra"] {
+            for idx in 0..16 {
+                let package = temp.path().join(repo).join(format!("example-{idx}"));
+                std::fs::create_dir_all(&package).unwrap();
+                let desc = desc.replace("\nexample\n", &format!("\nexample-{idx}\n"));
+                std::fs::write(package.join("desc"), desc).unwrap();
+            }
+        }
+
+        let descs = scan_project(temp.path(), false, false).unwrap();
+        let serial = parse_descriptions(&descs, NonZeroUsize::MIN).unwrap();
+        let parallel = parse_descriptions(&descs, NonZeroUsize::new(5).unwrap()).unwrap();
+        assert_eq!(serial.len(), 32);
+        assert_eq!(serial, parallel);
+
+        // every malformed file is reported
+        std::fs::write(temp.path().join("core/example-3/desc"), "").unwrap();
+        std::fs::write(temp.path().join("extra/example-7/desc"), "").unwrap();
+        let error = parse_descriptions(&descs, NonZeroUsize::new(5).unwrap()).unwrap_err();
+        assert_eq!(error.into_payload().children.len(), 2);
+    }
+
+    #[test]
+    fn test_download_paths() {
+        let backend = ArchBackend::new(Options {
+            mirror: "https://mirror.example".to_string(),
+            architecture: "x86_64".into(),
+            repos: Vec::new(),
+            priorities: BTreeMap::new(),
+            fetch_db: false,
+            lossy_repo_names: false,
+            strict_scan: false,
+        });
+
+        let requests: Vec<xh_executor_extract::Request> = [
+            "first-1.0.0-1-x86_64.pkg.tar.zst",
+            "second-1.0.0-1-any.pkg.tar.zst",
+        ]
+        .into_iter()
+        .map(|file| backend.download_request("core", file).unwrap())
+        .inspect(|request| assert_eq!(&request.executor, ExtractExecutor::name()))
+        .map(|request| from_value(request.payload).unwrap())
+        .collect();
+
+        let [first, second] = requests.as_slice() else {
+            panic!("expected 2 downloads, got {requests:?}");
+        };
+        assert_eq!(
+            first.url.to_string(),
+            "https://mirror.example/core/os/x86_64/first-1.0.0-1-x86_64.pkg.tar.zst"
+        );
+        assert_eq!(
+            second.url.to_string(),
+            "https://mirror.example/core/os/x86_64/second-1.0.0-1-any.pkg.tar.zst"
+        );
+        assert_eq!(first.output_dir, second.output_dir);
     }
 }
@@ -1,4 +1,5 @@
 mod logger;
+mod requests;
 
 use std::{path::Path, str::FromStr, sync::LazyLock};
 
@@ -22,6 +23,19 @@ use xh_engine::{
 };
 use xh_reports::prelude::*;
 
+#[derive(Debug, IntoReport)]
+#[message("configured source is not a registered package")]
+#[suggestion("pass the node index of a registered package as `source`")]
+#[context(node)]
+pub struct UnregisteredSourceError {
+    pub node: NodeIndex,
+}
+
+#[derive(Debug, IntoReport)]
+#[message("request ordered after request 0")]
+#[suggestion("number requests from 1, like the `requests` table")]
+pub struct RequestIndexError;
+
 fn conv_dependency(table: &Table) -> StdResult<Dependency, mlua::Error> {
     Ok(Dependency {
         name: table.get::<AnyUserData>("package")?.take()?,
@@ -31,10 +45,23 @@ fn conv_dependency(table: &Table) -> StdResult<Dependency, mlua::Error> {
     })
 }
 
+/// Converts a request table, whose `after` indices are 1-based, like lua tables.
 fn conv_request(table: &Table) -> Result<DispatchRequest, Error> {
+    let after = table
+        .get::<Option<Vec<usize>>>("after")
+        .wrap()?
+        .map(|after| {
+            after
+                .into_iter()
+                .map(|index| index.checked_sub(1).ok_or_else(|| RequestIndexError.wrap()))
+                .collect::<Result<_, _>>()
+        })
+        .transpose()?;
+
     Ok(DispatchRequest {
         payload: to_value(table.get::<LuaValue>("payload").wrap()?).wrap()?,
         executor: ExecutorName::from_str(&table.get::<String>("executor").wrap()?).wrap()?,
+        after,
     })
 }
 
@@ -42,6 +69,14 @@ fn conv_package(table: &Table) -> Result<Package, Error> {
     Ok(Package {
         name: PackageName::default(),
         metadata: Metadata,
+        tags: table
+            .get::<Option<Vec<String>>>("tags")
+            .wrap()?
+            .unwrap_or_default()
+            .into_iter()
+            .map(SmolStr::from)
+            .collect(),
+        build_cost: table.get::<Option<u64>>("build_cost").wrap()?,
         requests: table
             .get::<Option<Vec<Table>>>("requests")
             .wrap()?
@@ -118,6 +153,8 @@ impl UserData for LuaConfigManager<'_> {
         fields.add_field_method_get("namespace", |_, this| {
             Ok(LuaNamespaceTracker(this.namespace.clone()))
         });
+        fields.add_field_function_get("http", |lua, _| requests::http(lua));
+        fields.add_field_function_get("compression", |lua, _| requests::compression(lua));
     }
 
     fn add_methods<M: mlua::UserDataMethods<Self>>(methods: &mut M) {
@@ -130,12 +167,13 @@ impl UserData for LuaConfigManager<'_> {
                 move |value| func.call(value).wrap()
             };
 
-            this.inner
-                .configure(&source, dest, modify)
-                .expect("source should be a registered node")
-                .map(AnyUserData::wrap)
-                .into_error()
-                .into_lua_err()
+            let Some(result) = this.inner.configure(&source, dest, modify) else {
+                return Err(UnregisteredSourceError { node: source })
+                    .into_error()
+                    .into_lua_err();
+            };
+
+            result.map(AnyUserData::wrap).into_error().into_lua_err()
         });
 
         methods.add_method_mut("package", |_, this, table: Table| {
@@ -147,6 +185,18 @@ impl UserData for LuaConfigManager<'_> {
                 .into_error()
                 .into_lua_err()
         });
+
+        methods.add_method_mut("package_batch", |_, this, list: Vec<Table>| {
+            let batch = list
+                .iter()
+                .map(|table| {
+                    let name = this.package_name(table.get::<String>("identifier")?);
+                    Ok((name, conv_config(table).into_lua_err()?))
+                })
+                .collect::<StdResult<Vec<_>, mlua::Error>>()?;
+
+            this.inner.register_many(batch).into_error().into_lua_err()
+        });
     }
 
     fn register(registry: &mut UserDataRegistry<Self>) {
@@ -238,3 +288,158 @@ fn with_module<'scope, 'env>(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use mlua::Lua;
+    use xh_engine::{
+        backend::Backend,
+        encoding::from_value,
+        executor::Executor,
+        name::PackageName,
+        planner::{Planner, Unfrozen},
+    };
+    use xh_executor_compression::{
+        Action, Algorithm, CompressionExecutor, Request as CompressionRequest,
+    };
+    use xh_executor_http::{HttpExecutor, Request as HttpRequest};
+
+    use crate::{LuaBackend, Options, conv_dependency, conv_package, conv_request};
+
+    fn plan(project: &Path, script: &str) -> bool {
+        std::fs::write(project.join("main.lua"), script).unwrap();
+        let backend = LuaBackend::new(Options { sandbox: false }).unwrap();
+        backend
+            .plan(&mut Planner::<Unfrozen>::new(), project)
+            .is_ok()
+    }
+
+    #[test]
+    fn test_invalid_link_time() {
+        let lua = Lua::new();
+        let package = lua.create_any_userdata(PackageName::new("curl", []));
+        let table = lua.create_table().unwrap();
+        table.set("package", package.unwrap()).unwrap();
+        table.set("time", "Runtime").unwrap();
+
+        // lua sees the valid spellings, not just that parsing failed
+        let message = conv_dependency(&table).unwrap_err().to_string();
+        assert!(
+            message.contains("\"runtime\" or \"buildtime\""),
+            "{message}"
+        );
+    }
+
+    #[test]
+    fn test_request_after() {
+        let lua = Lua::new();
+        let request = |after: &[usize]| {
+            let table = lua.create_table().unwrap();
+            table.set("executor", "http@xuehua/executors").unwrap();
+            table.set("after", after).unwrap();
+            conv_request(&table).map(|request| request.after)
+        };
+
+        assert_eq!(request(&[1, 3]).unwrap(), Some(vec![0, 2]));
+        assert_eq!(request(&[]).unwrap(), Some(vec![]));
+        assert!(request(&[0]).is_err());
+    }
+
+    #[test]
+    fn test_package_tags() {
+        let lua = Lua::new();
+        let table = lua.create_table().unwrap();
+        assert!(conv_package(&table).unwrap().tags.is_empty());
+
+        table.set("tags", ["network", "core"]).unwrap();
+        assert_eq!(conv_package(&table).unwrap().tags, ["network", "core"]);
+
+        table.set("tags", "network").unwrap();
+        assert!(conv_package(&table).is_err());
+    }
+
+    #[test]
+    fn test_unregistered_source() {
+        let temp = tempfile::tempdir().unwrap();
+
+        // the error is catchable from lua
+        assert!(plan(
+            temp.path(),
+            r#"
+            local planner = require("xuehua.planner")
+            local ok, err = pcall(planner.configure, planner, {
+                source = 4096,
+                identifier = "copy",
+                modify = function(value) return value end,
+            })
+            assert(not ok)
+            assert(string.find(tostring(err), "not a registered package", 1, true))
+            "#,
+        ));
+
+        // and fails planning when left uncaught
+        assert!(!plan(
+            temp.path(),
+            r#"
+            local planner = require("xuehua.planner")
+            planner:configure({
+                source = 4096,
+                identifier = "copy",
+                modify = function(value) return value end,
+            })
+            "#,
+        ));
+    }
+
+    #[test]
+    fn test_request_helpers() {
+        let temp = tempfile::tempdir().unwrap();
+        std::fs::write(
+            temp.path().join("main.lua"),
+            r#"
+            local planner = require("xuehua.planner")
+            planner:package({
+                identifier = "hello",
+                apply = function()
+                    return {
+                        requests = {
+                            planner.http.get("https://example.com/hello.zst", "hello.zst"),
+                            planner.compression.decompress("Auto", "hello.zst", "output/hello"),
+                        },
+                    }
+                end,
+            })
+
+            -- helper modules are built once, then reused
+            assert(rawequal(planner.http, planner.http))
+
+            -- misspelled algorithms are caught while planning
+            local ok = pcall(planner.compression.decompress, "Zsdt", "in", "out")
+            assert(not ok)
+            "#,
+        )
+        .unwrap();
+
+        let mut planner = Planner::<Unfrozen>::new();
+        let backend = LuaBackend::new(Options { sandbox: false }).unwrap();
+        backend.plan(&mut planner, temp.path()).unwrap();
+
+        let package = planner.packages().next().unwrap();
+        let [download, decompress] = package.requests.as_slice() else {
+            panic!("package should have two requests");
+        };
+
+        assert_eq!(&download.executor, HttpExecutor::name());
+        let download: HttpRequest = from_value(download.payload.clone()).unwrap();
+        assert_eq!(download.url, "https://example.com/hello.zst");
+        assert_eq!(download.path, Path::new("hello.zst"));
+
+        assert_eq!(&decompress.executor, CompressionExecutor::name());
+        let decompress: CompressionRequest = from_value(decompress.payload.clone()).unwrap();
+        assert_eq!(decompress.algorithm, Algorithm::Auto);
+        assert_eq!(decompress.action, Action::Decompress);
+        assert_eq!(decompress.output, Path::new("output/hello"));
+    }
+}
@@ -0,0 +1,81 @@
+use mlua::{ExternalResult, Lua, LuaSerdeExt, Table, Value as LuaValue};
+use serde::Serialize;
+use xh_engine::{executor::Executor, name::ExecutorName};
+use xh_executor_compression::{Action, CompressionExecutor};
+use xh_executor_http::{HttpExecutor, Method};
+
+/// Builds a request table in the shape read by `conv_request`.
+fn request(lua: &Lua, executor: &ExecutorName, payload: impl Serialize) -> mlua::Result<Table> {
+    let table = lua.create_table()?;
+    table.set("executor", executor.to_string())?;
+    table.set("payload", lua.to_value(&payload)?)?;
+
+    Ok(table)
+}
+
+/// Returns the module stored under `key` in the Lua registry, building it on first access.
+fn cached(lua: &Lua, key: &str, build: fn(&Lua) -> mlua::Result<Table>) -> mlua::Result<Table> {
+    if let Some(module) = lua.named_registry_value::<Option<Table>>(key)? {
+        return Ok(module);
+    }
+
+    let module = build(lua)?;
+    lua.set_named_registry_value(key, &module)?;
+    Ok(module)
+}
+
+/// Helpers for [`HttpExecutor`] requests, exposed as `planner.http`.
+pub fn http(lua: &Lua) -> mlua::Result<Table> {
+    cached(lua, "xuehua.planner.http", build_http)
+}
+
+fn build_http(lua: &Lua) -> mlua::Result<Table> {
+    let module = lua.create_table()?;
+    module.set(
+        "get",
+        lua.create_function(|lua, (url, path): (String, String)| {
+            let payload = xh_executor_http::Request {
+                path: path.into(),
+                url: url.parse().into_lua_err()?,
+                method: Method::GET,
+            };
+
+            request(lua, HttpExecutor::name(), payload)
+        })?,
+    )?;
+
+    Ok(module)
+}
+
+/// Helpers for [`CompressionExecutor`] requests, exposed as `planner.compression`.
+///
+/// Algorithms are spelled like their variants, such as `"Auto"`.
+pub fn compression(lua: &Lua) -> mlua::Result<Table> {
+    cached(lua, "xuehua.planner.compression", build_compression)
+}
+
+fn build_compression(lua: &Lua) -> mlua::Result<Table> {
+    let module = lua.create_table()?;
+    for (name, action) in [
+        ("compress", Action::Compress),
+        ("decompress", Action::Decompress),
+    ] {
+        module.set(
+            name,
+            lua.create_function(
+                move |lua, (algorithm, input, output): (LuaValue, String, String)| {
+                    let payload = xh_executor_compression::Request {
+                        algorithm: lua.from_value(algorithm)?,
+                        action: action.clone(),
+                        input: input.into(),
+                        output: output.into(),
+                    };
+
+                    request(lua, CompressionExecutor::name(), payload)
+                },
+            )?,
+        )?;
+    }
+
+    Ok(module)
+}
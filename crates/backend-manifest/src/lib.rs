@@ -0,0 +1,215 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::LazyLock,
+};
+
+use serde::Deserialize;
+use xh_engine::{
+    backend::{Backend, Error},
+    encoding::Value,
+    gen_name,
+    name::{BackendName, ExecutorName, PackageName},
+    package::{Dependency, DispatchRequest, LinkTime, Metadata, Package},
+    planner::{Planner, Unfrozen},
+};
+use xh_reports::{partition_results, prelude::*};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Options {
+    #[serde(default = "default_manifest")]
+    pub manifest: PathBuf,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            manifest: default_manifest(),
+        }
+    }
+}
+
+fn default_manifest() -> PathBuf {
+    "manifest.json".into()
+}
+
+#[derive(Debug, Deserialize)]
+struct ManifestRequest {
+    #[serde(with = "xh_common::serde_display")]
+    executor: ExecutorName,
+    #[serde(default)]
+    payload: Value,
+    #[serde(default)]
+    after: Option<Vec<usize>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ManifestDependency {
+    #[serde(with = "xh_common::serde_display")]
+    name: PackageName,
+    #[serde(with = "xh_common::serde_display")]
+    time: LinkTime,
+}
+
+#[derive(Debug, Deserialize)]
+struct ManifestPackage {
+    #[serde(with = "xh_common::serde_display")]
+    name: PackageName,
+    #[serde(default)]
+    requests: Vec<ManifestRequest>,
+    #[serde(default)]
+    dependencies: Vec<ManifestDependency>,
+}
+
+impl From<ManifestPackage> for Package {
+    fn from(value: ManifestPackage) -> Self {
+        Package {
+            name: value.name,
+            metadata: Metadata,
+            tags: Vec::new(),
+            build_cost: None,
+            requests: value
+                .requests
+                .into_iter()
+                .map(|request| DispatchRequest {
+                    executor: request.executor,
+                    payload: request.payload,
+                    after: request.after,
+                })
+                .collect(),
+            dependencies: value
+                .dependencies
+                .into_iter()
+                .map(|dependency| Dependency {
+                    name: dependency.name,
+                    time: dependency.time,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    packages: Vec<ManifestPackage>,
+}
+
+#[derive(Debug, IntoReport)]
+#[message("could not read manifest")]
+#[context(path)]
+pub struct ManifestReadError {
+    path: PathBuf,
+}
+
+/// A backend reading packages from a declarative JSON manifest, without any scripting.
+///
+/// The manifest is read from [`Options::manifest`], relative to the project directory:
+/// ```json
+/// {
+///   "packages": [
+///     {
+///       "name": "hello@example",
+///       "requests": [{ "executor": "bubblewrap@xuehua", "payload": { "program": "/busybox" } }],
+///       "dependencies": [{ "name": "busybox@example", "time": "buildtime" }]
+///     }
+///   ]
+/// }
+/// ```
+pub struct ManifestBackend {
+    options: Options,
+}
+
+impl ManifestBackend {
+    pub fn new(options: Options) -> Self {
+        Self { options }
+    }
+
+    fn register(&self, planner: &mut Planner<Unfrozen>, manifest: Manifest) -> Result<(), Error> {
+        let planner = manifest
+            .packages
+            .into_iter()
+            .map(|package| planner.register(package.into()).erased().map(|_| ()));
+
+        partition_results::<_, (), _, Vec<_>>(planner)
+            .map_err(|reports| Error.into_report().with_children(reports))
+    }
+}
+
+impl Backend for ManifestBackend {
+    type Value = ();
+
+    fn name() -> &'static BackendName {
+        static NAME: LazyLock<BackendName> = LazyLock::new(|| gen_name!(manifest@xuehua));
+        &NAME
+    }
+
+    #[tracing::instrument(level = "debug", skip(self, planner))]
+    fn plan(&self, planner: &mut Planner<Unfrozen>, project: &Path) -> Result<(), Error> {
+        let path = project.join(&self.options.manifest);
+        let manifest = std::fs::read(&path)
+            .wrap_with_fn(|| ManifestReadError { path: path.clone() })
+            .and_then(|content| {
+                serde_json::from_slice(&content)
+                    .wrap_with_fn(|| ManifestReadError { path: path.clone() })
+            })
+            .wrap()?;
+
+        self.register(planner, manifest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use xh_engine::{gen_name, package::LinkTime, planner::Planner};
+
+    use crate::{Manifest, ManifestBackend, Options};
+
+    #[test]
+    fn test_manifest_plan() {
+        let manifest: Manifest = serde_json::from_str(
+            r#"{
+                "packages": [
+                    {
+                        "name": "hello@example",
+                        "requests": [
+                            {
+                                "executor": "bubblewrap@xuehua",
+                                "payload": { "program": "/busybox", "arguments": ["true"] }
+                            }
+                        ],
+                        "dependencies": [{ "name": "busybox@example", "time": "buildtime" }]
+                    },
+                    { "name": "busybox@example" }
+                ]
+            }"#,
+        )
+        .expect("manifest should deserialize");
+
+        let mut planner = Planner::new();
+        ManifestBackend::new(Options::default())
+            .register(&mut planner, manifest)
+            .expect("manifest should register");
+        let planner = planner.freeze().expect("plan should freeze");
+
+        let hello = planner
+            .resolve(&gen_name!(hello@example))
+            .expect("hello should be registered");
+        let busybox = planner
+            .resolve(&gen_name!(busybox@example))
+            .expect("busybox should be registered");
+
+        let plan = planner.graph();
+        assert_eq!(plan.node_count(), 2);
+        assert_eq!(plan.edge_count(), 1);
+
+        let edge = plan
+            .find_edge(hello, busybox)
+            .expect("hello should depend on busybox");
+        assert_eq!(plan[edge], LinkTime::Buildtime);
+
+        let requests = &plan[hello].requests;
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].executor, gen_name!(bubblewrap@xuehua));
+        assert_eq!(requests[0].payload["program"], "/busybox");
+        assert!(plan[busybox].requests.is_empty());
+    }
+}
@@ -1,16 +1,27 @@
 use std::{
-    io::{Write, stdin, stdout},
+    fs,
+    io::{BufWriter, Read, Write, stdin, stdout},
     os::fd::AsRawFd,
     path::Path,
 };
 
+use blake3::Hash;
 use bytes::{Bytes, BytesMut};
 use tempfile::tempfile;
-use xh_archive::{decoding::Decoder, encoding::Encoder, packing::Packer, unpacking::Unpacker};
+use xh_archive::{
+    Digest, Event,
+    decoding::{DecodeStats, Decoder, DigestMismatchError},
+    encoding::Encoder,
+    packing::Packer,
+    unpacking::Unpacker,
+};
 use xh_reports::prelude::*;
 
 use crate::options::cli::ArchiveAction;
 
+/// Largest object [`digest`] buffers while streaming, so a corrupt length prefix can't exhaust memory.
+const MAX_OBJECT_SIZE: usize = 1 << 30;
+
 #[derive(Debug, IntoReport)]
 pub enum ArchiveActionError {
     #[message("could not execute pack action")]
@@ -21,14 +32,26 @@ pub enum ArchiveActionError {
     Decode,
     #[message("could not execute hash action")]
     Hash,
+    #[message("could not execute verify action")]
+    Verify,
+}
+
+#[derive(Debug, IntoReport)]
+#[message("could not verify object {position}")]
+pub struct CorruptObjectError {
+    #[format(message)]
+    position: usize,
 }
 
 pub fn handle(action: &ArchiveAction) -> Result<(), ArchiveActionError> {
     match action {
-        ArchiveAction::Pack { path } => pack(path).wrap_with(ArchiveActionError::Pack),
+        ArchiveAction::Pack { path, output } => {
+            pack(path, output.as_deref()).wrap_with(ArchiveActionError::Pack)
+        }
         ArchiveAction::Unpack { path } => unpack(path).wrap_with(ArchiveActionError::Unpack),
         ArchiveAction::Decode => decode().wrap_with(ArchiveActionError::Decode),
-        ArchiveAction::Hash => hash().wrap_with(ArchiveActionError::Hash),
+        ArchiveAction::Hash { expect } => hash(*expect).wrap_with(ArchiveActionError::Hash),
+        ArchiveAction::Verify { path } => verify(path).wrap_with(ArchiveActionError::Verify),
     }
 }
 
@@ -51,19 +74,54 @@ fn mmapped_stdin() -> StdResult<Bytes, std::io::Error> {
     }
 }
 
-fn hash() -> Result<(), ()> {
-    let mut decoder = Decoder::new();
-    let mut mmap = mmapped_stdin().erased()?;
+fn hash(expect: Option<Hash>) -> Result<(), ()> {
+    let digest = digest(stdin().lock())?;
+    println!("{digest}");
+
+    // expected digests are given as bare hex, so they take on the archive's algorithm
+    let expect = expect.map(|hash| Digest::new(digest.algorithm(), *hash.as_bytes()));
+    verify_digest(digest, expect).erased()
+}
 
+/// Streams `archive` through a [`Decoder`], only keeping the event being decoded in memory.
+fn digest(archive: impl Read) -> Result<Digest, ()> {
+    let mut decoder = Decoder::new().with_max_object_size(MAX_OBJECT_SIZE);
     decoder
-        .decode_iter(&mut mmap)
+        .decode_reader(archive)
         .try_for_each(|result| result.map(|_| ()))
         .erased()?;
-    println!("{}", decoder.digest());
+
+    Ok(decoder.digest())
+}
+
+fn verify_digest(found: Digest, expect: Option<Digest>) -> Result<(), DigestMismatchError> {
+    match expect {
+        Some(expected) if expected != found => Err(DigestMismatchError { expected, found }.into()),
+        _ => Ok(()),
+    }
+}
+
+fn verify(path: &Path) -> Result<(), ()> {
+    let mut archive = Bytes::from(fs::read(path).erased()?);
+    let stats = verify_objects(&mut archive).erased()?;
+    println!("verified {stats}");
 
     Ok(())
 }
 
+fn verify_objects(archive: &mut Bytes) -> Result<DecodeStats, CorruptObjectError> {
+    let mut decoder = Decoder::new().with_stats();
+    let mut objects = 0;
+    for event in decoder.decode_iter(archive) {
+        let event = event.wrap_with_fn(|| CorruptObjectError { position: objects })?;
+        if let Event::Object(_) = event {
+            objects += 1;
+        }
+    }
+
+    Ok(decoder.stats().copied().unwrap_or_default())
+}
+
 fn decode() -> Result<(), ()> {
     let mut stdout = stdout().lock();
     for event in Decoder::new().decode_iter(&mut mmapped_stdin().erased()?) {
@@ -73,25 +131,175 @@ fn decode() -> Result<(), ()> {
     Ok(())
 }
 
+/// Unpacks each event as soon as it's decoded, so progress is reported as the archive is read.
 fn unpack(path: &Path) -> Result<(), ()> {
     let mut unpacker = Unpacker::new(path);
+    let mut done = 0;
     for event in Decoder::new().decode_iter(&mut mmapped_stdin().erased()?) {
-        unpacker.unpack(event.erased()?).erased()?;
+        let event = event.erased()?;
+        unpacker.unpack(&event).erased()?;
+        if let Event::Object(object) = &event {
+            done += 1;
+            tracing::debug!(location = ?object.location, done, "unpacked object");
+        }
     }
 
     Ok(())
 }
 
-fn pack(path: &Path) -> Result<(), ()> {
+/// Packs `path` into `output`, or stdout if `None`.
+fn pack(path: &Path, output: Option<&Path>) -> Result<(), ()> {
+    match output {
+        Some(output) => {
+            let mut file = BufWriter::new(fs::File::create(output).erased()?);
+            pack_into(path, &mut file)?;
+            file.flush().erased()
+        }
+        None => pack_into(path, &mut stdout().lock()),
+    }
+}
+
+/// Streams the encoded archive of `path` into `writer`, one event at a time.
+fn pack_into(path: &Path, writer: &mut impl Write) -> Result<(), ()> {
     let mut encoder = Encoder::new();
     let mut buffer = BytesMut::with_capacity(8192);
-    let mut stdout = stdout().lock();
 
-    for event in Packer::new(path.to_path_buf()).pack_iter() {
+    let mut packer = Packer::new(path.to_path_buf()).with_progress(|location, done, total| {
+        tracing::debug!(?location, done, total, "packed object")
+    });
+    for event in packer.pack_iter() {
         buffer.clear();
-        encoder.encode(&mut buffer, event.erased()?);
-        stdout.write_all(&buffer).erased()?;
+        encoder.encode(&mut buffer, event.erased()?).erased()?;
+        writer.write_all(&buffer).erased()?;
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use bytes::{Bytes, BytesMut};
+    use xh_archive::{
+        Digest, HashAlgorithm, decoding::Decoder, encoding::Encoder, packing::Packer,
+    };
+    use xh_reports::render::{PrettyRenderer, Renderer};
+
+    use super::{digest, pack, verify_digest, verify_objects};
+
+    fn encode(root: &Path) -> (Bytes, Digest) {
+        let mut encoder = Encoder::new();
+        let mut buffer = BytesMut::new();
+        for event in Packer::new(root.to_path_buf()).pack_iter() {
+            let event = event.expect("should be able to pack fixture");
+            encoder
+                .encode(&mut buffer, event)
+                .expect("should be able to encode fixture");
+        }
+
+        (buffer.freeze(), encoder.digest())
+    }
+
+    fn fixture() -> (Bytes, Digest) {
+        let root = tempfile::tempdir().expect("should be able to create fixture directory");
+        fs::create_dir(root.path().join("dir")).expect("should be able to create fixture");
+        for (file, content) in [("file", "xuehua"), ("dir/corrupt", "snowflake")] {
+            fs::write(root.path().join(file), content).expect("should be able to write fixture");
+        }
+
+        encode(root.path())
+    }
+
+    #[test]
+    fn test_pack_output() {
+        let root = tempfile::tempdir().expect("should be able to create fixture directory");
+        fs::write(root.path().join("file"), "xuehua").expect("should be able to write fixture");
+
+        let output = tempfile::tempdir().expect("should be able to create output directory");
+        let output = output.path().join("archive");
+        pack(root.path(), Some(&output)).expect("should be able to pack directory");
+
+        let mut archive = Bytes::from(fs::read(&output).expect("archive should be written"));
+        let events = Decoder::new()
+            .decode_iter(&mut archive)
+            .collect::<Result<Vec<_>, _>>()
+            .expect("archive should decode");
+        let expected = Packer::new(root.path().to_path_buf())
+            .pack_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .expect("should be able to pack fixture");
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn test_hash_matching() {
+        let (archive, expected) = fixture();
+        let found = digest(archive.as_ref()).expect("should be able to hash archive");
+
+        assert_eq!(found, expected);
+        assert!(verify_digest(found, Some(expected)).is_ok());
+        assert!(verify_digest(found, None).is_ok());
+    }
+
+    #[test]
+    fn test_hash_large() {
+        let root = tempfile::tempdir().expect("should be able to create fixture directory");
+        for i in 0..16 {
+            let content = vec![i as u8; 256 * 1024];
+            fs::write(root.path().join(format!("blob-{i}")), content)
+                .expect("should be able to write fixture");
+        }
+
+        let (mut archive, expected) = encode(root.path());
+        let streamed = digest(archive.as_ref()).expect("should be able to hash archive");
+
+        let mut decoder = Decoder::new();
+        for event in decoder.decode_iter(&mut archive) {
+            event.expect("archive should decode");
+        }
+        assert_eq!(streamed, decoder.digest());
+        assert_eq!(streamed, expected);
+    }
+
+    #[test]
+    fn test_hash_mismatching() {
+        let (archive, _) = fixture();
+        let found = digest(archive.as_ref()).expect("should be able to hash archive");
+
+        let expected = Digest::new(HashAlgorithm::Blake3, [0; 32]);
+        assert!(verify_digest(found, Some(expected)).is_err());
+    }
+
+    #[test]
+    fn test_verify_clean() {
+        let (mut archive, _) = fixture();
+        let stats = verify_objects(&mut archive).expect("archive should verify");
+
+        // "dir", "dir/corrupt", and "file"
+        assert_eq!((stats.files, stats.directories, stats.symlinks), (2, 1, 0));
+        assert_eq!(stats.file_bytes, 15);
+    }
+
+    #[test]
+    fn test_verify_corrupt() {
+        let (archive, _) = fixture();
+        let mut archive = archive.to_vec();
+        let offset = archive
+            .windows(b"snowflake".len())
+            .position(|window| window == b"snowflake")
+            .expect("archive should contain file contents");
+        archive[offset] ^= 0xff;
+
+        let report = verify_objects(&mut archive.into())
+            .expect_err("corrupt archive should not verify")
+            .into_payload();
+        let rendered = PrettyRenderer::new().render(&report).to_string();
+
+        assert!(rendered.contains("could not verify object 1"), "{rendered}");
+        assert!(
+            rendered.contains(r#"location: b"dir/corrupt""#),
+            "{rendered}"
+        );
+    }
+}
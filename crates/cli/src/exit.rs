@@ -0,0 +1,125 @@
+use std::{any::type_name, process::ExitCode};
+
+use xh_reports::prelude::*;
+
+use crate::{
+    archive::ArchiveActionError,
+    package::{BuildActionError, PackageResolveError, PlannerInitError},
+    store::StoreActionError,
+};
+
+/// Stable failure categories, each with a distinct exit code.
+///
+/// These codes are part of the cli's interface, so existing values must not change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Category {
+    Other = 1,
+    Planning = 2,
+    Resolve = 3,
+    Build = 4,
+    Archive = 5,
+    Store = 6,
+}
+
+impl Category {
+    /// Categorizes a top-level action report by its type.
+    pub fn of<E>(report: &Report<E>) -> Self {
+        let categories = [
+            (type_name::<PlannerInitError>(), Self::Planning),
+            (type_name::<PackageResolveError>(), Self::Resolve),
+            (type_name::<BuildActionError>(), Self::Build),
+            (type_name::<ArchiveActionError>(), Self::Archive),
+            (type_name::<StoreActionError>(), Self::Store),
+        ];
+
+        let type_name = report.metadata().type_name.as_str();
+        categories
+            .into_iter()
+            .find_map(|(name, category)| (name == type_name).then_some(category))
+            .unwrap_or(Self::Other)
+    }
+
+    pub fn code(self) -> u8 {
+        self as u8
+    }
+}
+
+impl From<Category> for ExitCode {
+    fn from(category: Category) -> Self {
+        ExitCode::from(category.code())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use xh_engine::{builder::DEFAULT_CONCURRENCY, gen_name};
+    use xh_reports::prelude::*;
+
+    use super::Category;
+    use crate::{
+        archive::ArchiveActionError,
+        options::cli::{InspectAction, PackageAction, PackageFormat},
+        package::{self, BuildActionError},
+        store::StoreActionError,
+    };
+
+    async fn package_category(project: &Path, action: PackageAction) -> Category {
+        let report = package::handle(project, &action)
+            .await
+            .expect_err("action should fail");
+        Category::of(&report)
+    }
+
+    #[tokio::test]
+    async fn test_exit_categories() {
+        let temp = tempfile::tempdir().expect("should be able to create temp directory");
+        let missing = temp.path().join("missing-project");
+        let action = PackageAction::Build {
+            dry_run: true,
+            keep_going: true,
+            jobs: DEFAULT_CONCURRENCY,
+            format: PackageFormat::Human,
+            profile: None,
+            changed_only: false,
+            tags: Vec::new(),
+            packages: Vec::new(),
+        };
+        assert_eq!(package_category(&missing, action).await, Category::Planning);
+
+        let empty = temp.path().join("empty-project");
+        fs::create_dir_all(&empty).expect("should be able to create project");
+
+        let packages = vec![gen_name!(missing@xuehua)];
+        let action = PackageAction::Build {
+            dry_run: true,
+            keep_going: true,
+            jobs: DEFAULT_CONCURRENCY,
+            format: PackageFormat::Human,
+            profile: None,
+            changed_only: false,
+            tags: Vec::new(),
+            packages: packages.clone(),
+        };
+        assert_eq!(package_category(&empty, action).await, Category::Resolve);
+
+        let action = PackageAction::Inspect(InspectAction::Packages {
+            packages,
+            format: PackageFormat::Human,
+        });
+        assert_eq!(package_category(&empty, action).await, Category::Resolve);
+
+        let report = Report::from(BuildActionError).erased();
+        assert_eq!(Category::of(&report), Category::Build);
+
+        let report = Report::from(ArchiveActionError::Hash).erased();
+        assert_eq!(Category::of(&report), Category::Archive);
+
+        let report = Report::from(StoreActionError::Prune).erased();
+        assert_eq!(Category::of(&report), Category::Store);
+
+        assert_eq!(Category::of(&Report::new("unknown")), Category::Other);
+    }
+}
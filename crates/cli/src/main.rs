@@ -1,8 +1,10 @@
 pub mod archive;
+pub mod exit;
 pub mod options;
 pub mod package;
+pub mod store;
 
-use std::process::ExitCode;
+use std::{env, process::ExitCode};
 
 use smol_str::ToSmolStr;
 use tracing_subscriber::{
@@ -14,38 +16,54 @@ use xh_reports::{
     tracing::ReportLayer,
 };
 
-use crate::options::{OPTIONS, Options, cli::Action, get_opts};
+use crate::{
+    exit::Category,
+    options::{
+        OPTIONS, Options,
+        cli::{self, Action},
+        get_opts,
+    },
+};
 
 fn init() -> Result<(), ()> {
     // TODO: support json rendering via cli arg
     // TODO: add color flag to use with pretty renderer
-    GlobalRenderer::set(PrettyRenderer::default());
+    let cli = cli::Options::new().run();
+    GlobalRenderer::set(PrettyRenderer::new().with_verbosity(cli.verbosity.report_verbosity()));
 
     // TODO: accept directives via flag instead of environment variable
-    let env_layer = EnvFilter::builder()
-        .with_default_directive(LevelFilter::INFO.into())
-        .from_env()
-        .wrap_with_fn(|| {
-            let msg = format_args!(
-                "could not parse the {:?} environment variable",
-                EnvFilter::DEFAULT_ENV
-            );
-
-            Report::new(msg.to_smolstr())
-        })?;
+    let directives = env::var(EnvFilter::DEFAULT_ENV).unwrap_or_default();
+    let env_layer = env_filter(cli.verbosity.level(), &directives).wrap_with_fn(|| {
+        let msg = format_args!(
+            "could not parse the {:?} environment variable",
+            EnvFilter::DEFAULT_ENV
+        );
+
+        Report::new(msg.to_smolstr())
+    })?;
     tracing_subscriber::registry()
         .with(ReportLayer::new())
         .with(env_layer)
         .init();
 
     OPTIONS
-        .set(Options::run()?)
+        .set(Options::read(cli)?)
         .ok()
         .expect("options should not be set");
 
     Ok(())
 }
 
+/// Builds the log filter, with `directives` (usually from `RUST_LOG`) overriding `level`.
+fn env_filter(
+    level: LevelFilter,
+    directives: &str,
+) -> StdResult<EnvFilter, tracing_subscriber::filter::ParseError> {
+    EnvFilter::builder()
+        .with_default_directive(level.into())
+        .parse(directives)
+}
+
 #[tokio::main]
 async fn main() -> ExitCode {
     if let Err(report) = init() {
@@ -54,19 +72,44 @@ async fn main() -> ExitCode {
             "failure initializing cli"
         );
 
-        return ExitCode::FAILURE;
+        return Category::Other.into();
     }
 
     if let Err(report) = match &get_opts().cli.action {
         Action::Package { project, action } => package::handle(project, action).await.erased(),
         Action::Archive(action) => archive::handle(action).erased(),
+        Action::Store(action) => store::handle(action).await.erased(),
+        Action::ListExecutors => package::list_executors(),
     } {
+        let category = Category::of(&report);
         tracing::error!(
             error = &report.into_error() as &dyn StdError,
             "failure executing action"
         );
-        return ExitCode::FAILURE;
+        return category.into();
     }
 
     ExitCode::SUCCESS
 }
+
+#[cfg(test)]
+mod tests {
+    use tracing_subscriber::filter::LevelFilter;
+
+    use crate::env_filter;
+
+    #[test]
+    fn test_env_filter() {
+        let hint = |level, directives| {
+            env_filter(level, directives)
+                .expect("directives should parse")
+                .max_level_hint()
+        };
+
+        assert_eq!(hint(LevelFilter::INFO, ""), Some(LevelFilter::INFO));
+        assert_eq!(hint(LevelFilter::WARN, ""), Some(LevelFilter::WARN));
+        assert_eq!(hint(LevelFilter::TRACE, ""), Some(LevelFilter::TRACE));
+        assert_eq!(hint(LevelFilter::INFO, "debug"), Some(LevelFilter::DEBUG));
+        assert_eq!(hint(LevelFilter::TRACE, "error"), Some(LevelFilter::ERROR));
+    }
+}
@@ -17,9 +17,9 @@ pub struct Options {
 }
 
 impl Options {
-    pub fn run() -> Result<Self, ()> {
+    pub fn read(cli: cli::Options) -> Result<Self, ()> {
         Ok(Options {
-            cli: cli::Options::new().run(),
+            cli,
             base: base::BaseOptions::read().erased()?,
         })
     }
@@ -1,8 +1,12 @@
-use std::{env, fmt, path::PathBuf, str::FromStr};
+use std::{env, fmt, num::NonZeroUsize, path::PathBuf, str::FromStr};
 
-use bpaf::{OptionParser, Parser, construct, long, positional, pure};
+use blake3::Hash;
+use bpaf::{OptionParser, Parser, construct, long, positional, pure, short};
+use smol_str::SmolStr;
+use tracing::level_filters::LevelFilter;
 
-use xh_engine::name::PackageName;
+use xh_engine::{builder::DEFAULT_CONCURRENCY, name::PackageName};
+use xh_reports::render::pretty;
 
 #[derive(Debug, Clone, Copy)]
 pub struct FormatParseError;
@@ -17,6 +21,7 @@ impl fmt::Display for FormatParseError {
 pub enum ProjectFormat {
     Dot,
     Json,
+    Mermaid,
 }
 
 impl FromStr for ProjectFormat {
@@ -26,6 +31,7 @@ impl FromStr for ProjectFormat {
         match s {
             "dot" => Ok(Self::Dot),
             "json" => Ok(Self::Json),
+            "mermaid" => Ok(Self::Mermaid),
             _ => Err(FormatParseError),
         }
     }
@@ -118,12 +124,24 @@ impl LinkAction {
 pub enum PackageAction {
     Link {
         dry_run: bool,
+        /// Link packages shipping the same file anyway, the last one winning.
+        force: bool,
         root: PathBuf,
         action: LinkAction,
         packages: Vec<PackageName>,
     },
     Build {
         dry_run: bool,
+        keep_going: bool,
+        /// Maximum amount of packages building at once.
+        jobs: NonZeroUsize,
+        format: PackageFormat,
+        /// Chrome trace of the build is written here, if set.
+        profile: Option<PathBuf>,
+        /// Skip packages whose identity is already in the store.
+        changed_only: bool,
+        /// Packages with any of these tags are built alongside `packages`.
+        tags: Vec<SmolStr>,
         packages: Vec<PackageName>,
     },
     Inspect(InspectAction),
@@ -148,10 +166,14 @@ impl PackageAction {
                 .short('r')
                 .help("Root filesystem to operate on")
                 .argument("ROOT");
+            let force = long("force")
+                .help("Link packages shipping the same file anyway, letting the last one win")
+                .switch();
 
             construct!(Self::Link {
                 root,
                 dry_run(),
+                force,
                 action,
                 packages
             })
@@ -161,11 +183,49 @@ impl PackageAction {
         };
 
         let build = {
+            let keep_going = long("keep-going")
+                .help("Keep building packages unaffected by a failure (default)")
+                .req_flag(true);
+            let stop_on_error = long("stop-on-error")
+                .help("Stop starting new builds after the first failure")
+                .req_flag(false);
+            let keep_going = construct!([keep_going, stop_on_error]).fallback(true);
+            let jobs = long("jobs")
+                .short('j')
+                .help("Build at most N packages at once")
+                .argument("N")
+                .fallback(DEFAULT_CONCURRENCY);
+            let format = long("format")
+                .short('f')
+                .help("Build summary output format")
+                .argument("FORMAT")
+                .fallback(PackageFormat::Human);
+            let profile = long("profile")
+                .help("Write a Chrome trace of package build timings to FILE")
+                .argument("FILE")
+                .optional();
+            let changed_only = long("changed-only")
+                .help("Only build packages that aren't in the store yet")
+                .switch();
+            let tags = long("tag")
+                .short('t')
+                .help("Also build every package tagged with TAG")
+                .argument("TAG")
+                .many();
             let packages = Self::pkgs_parser();
-            construct!(Self::Build { dry_run(), packages })
-                .to_options()
-                .descr("Builds packages")
-                .command("build")
+            construct!(Self::Build {
+                dry_run(),
+                keep_going,
+                jobs,
+                format,
+                profile,
+                changed_only,
+                tags,
+                packages
+            })
+            .to_options()
+            .descr("Builds packages")
+            .command("build")
         };
 
         let inspect = {
@@ -183,10 +243,21 @@ impl PackageAction {
 
 #[derive(Debug, Clone)]
 pub enum ArchiveAction {
-    Pack { path: PathBuf },
-    Unpack { path: PathBuf },
+    /// `output` is `None` for stdout.
+    Pack {
+        path: PathBuf,
+        output: Option<PathBuf>,
+    },
+    Unpack {
+        path: PathBuf,
+    },
     Decode,
-    Hash,
+    Hash {
+        expect: Option<Hash>,
+    },
+    Verify {
+        path: PathBuf,
+    },
 }
 
 impl ArchiveAction {
@@ -201,7 +272,14 @@ impl ArchiveAction {
     fn parser() -> impl Parser<Self> {
         let pack = {
             let path = Self::path_parser();
-            construct!(Self::Pack { path })
+            let output = long("output")
+                .short('o')
+                .help("File to write the archive to, or - for stdout")
+                .argument::<PathBuf>("OUTPUT")
+                .optional()
+                .map(|output| output.filter(|output| output.as_os_str() != "-"));
+
+            construct!(Self::Pack { path, output })
                 .to_options()
                 .descr("Pack a directory into an archive")
                 .command("pack")
@@ -221,12 +299,73 @@ impl ArchiveAction {
             .descr("Decode an archive into events")
             .command("decode");
 
-        let hash = pure(Self::Hash)
+        let hash = {
+            let expect = long("expect")
+                .short('e')
+                .help("Fail unless the archive hashes to this digest")
+                .argument("DIGEST")
+                .optional();
+
+            construct!(Self::Hash { expect })
+                .to_options()
+                .descr("Hash an archive")
+                .command("hash")
+        };
+
+        let verify = {
+            let path = positional("ARCHIVE").help("Path to the archive");
+            construct!(Self::Verify { path })
+                .to_options()
+                .descr("Verify every object digest in an archive")
+                .command("verify")
+        };
+
+        construct!([pack, unpack, decode, hash, verify])
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum StoreAction {
+    Prune {
+        keep: usize,
+    },
+    Export {
+        artifact: blake3::Hash,
+        dest: PathBuf,
+    },
+    DiskUsage,
+}
+
+impl StoreAction {
+    fn parser() -> impl Parser<Self> {
+        let prune = {
+            let keep = long("keep")
+                .short('k')
+                .help("Amount of versions to keep per package")
+                .argument("AMOUNT");
+
+            construct!(Self::Prune { keep })
+                .to_options()
+                .descr("Remove old package versions and their unreferenced artifacts")
+                .command("prune")
+        };
+
+        let export = {
+            let artifact = positional("ARTIFACT").help("Hash of the artifact to export");
+            let dest = positional("DIRECTORY").help("Directory to unpack the artifact into");
+
+            construct!(Self::Export { artifact, dest })
+                .to_options()
+                .descr("Unpack a stored artifact into a directory")
+                .command("export")
+        };
+
+        let disk_usage = pure(Self::DiskUsage)
             .to_options()
-            .descr("Hash an archive")
-            .command("hash");
+            .descr("Show the size of stored artifacts per package")
+            .command("du");
 
-        construct!([pack, unpack, decode, hash])
+        construct!([prune, export, disk_usage])
     }
 }
 
@@ -237,6 +376,8 @@ pub enum Action {
         action: PackageAction,
     },
     Archive(ArchiveAction),
+    Store(StoreAction),
+    ListExecutors,
 }
 
 impl Action {
@@ -264,28 +405,203 @@ impl Action {
                 .command("archive")
         };
 
-        construct!([package, archive])
+        let store = {
+            let action = StoreAction::parser();
+
+            construct!(Self::Store(action))
+                .to_options()
+                .descr("Manage the store")
+                .command("store")
+        };
+
+        let list_executors = long("list-executors")
+            .help("List the executors available to packages as JSON")
+            .req_flag(Self::ListExecutors);
+
+        construct!([package, archive, store, list_executors])
+    }
+}
+
+const EXIT_CODES: &str = "\
+Exit codes:
+  1  unknown failure
+  2  planning failure
+  3  package resolution failure
+  4  build failure
+  5  archive failure
+  6  store failure";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Verbosity {
+    pub verbose: usize,
+    pub quiet: usize,
+}
+
+impl Verbosity {
+    pub fn parser() -> impl Parser<Self> {
+        let verbose = short('v')
+            .long("verbose")
+            .help("Increase logging verbosity, can be repeated")
+            .req_flag(())
+            .count();
+        let quiet = short('q')
+            .long("quiet")
+            .help("Decrease logging verbosity, can be repeated")
+            .req_flag(())
+            .count();
+
+        construct!(Self { verbose, quiet })
+    }
+
+    pub fn level(self) -> LevelFilter {
+        match self.verbose as isize - self.quiet as isize {
+            ..=-2 => LevelFilter::ERROR,
+            -1 => LevelFilter::WARN,
+            0 => LevelFilter::INFO,
+            1 => LevelFilter::DEBUG,
+            2.. => LevelFilter::TRACE,
+        }
+    }
+
+    /// Detail of rendered reports, only showing locations and frames when verbose.
+    pub fn report_verbosity(self) -> pretty::Verbosity {
+        match self.verbose {
+            0 => pretty::Verbosity::Low,
+            1 => pretty::Verbosity::Normal,
+            2.. => pretty::Verbosity::High,
+        }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct Options {
+    pub verbosity: Verbosity,
     pub action: Action,
 }
 
 impl Options {
     pub fn new() -> OptionParser<Self> {
+        let verbosity = Verbosity::parser();
         let action = Action::parser();
-        construct!(Self { action })
+        construct!(Self { verbosity, action })
             .to_options()
             .fallback_to_usage()
             .version(env!("CARGO_PKG_VERSION"))
+            .footer(EXIT_CODES)
     }
 }
 
+#[cfg(test)]
 mod tests {
+    use std::path::PathBuf;
+
+    use tracing::level_filters::LevelFilter;
+    use xh_engine::builder::DEFAULT_CONCURRENCY;
+
+    use crate::options::cli::{Action, ArchiveAction, PackageAction};
+
+    /// Parses the arguments, and returns a field of the build or pack action they describe
+    macro_rules! field {
+        ($args:expr, build.$field:ident) => {
+            match parse($args) {
+                Action::Package {
+                    action: PackageAction::Build { $field, .. },
+                    ..
+                } => $field,
+                action => panic!("expected a build action, got {action:?}"),
+            }
+        };
+        ($args:expr, pack.$field:ident) => {
+            match parse($args) {
+                Action::Archive(ArchiveAction::Pack { $field, .. }) => $field,
+                action => panic!("expected a pack action, got {action:?}"),
+            }
+        };
+    }
+
+    fn parse(args: &[&str]) -> Action {
+        super::Options::new()
+            .run_inner(args)
+            .expect("arguments should parse")
+            .action
+    }
+
     #[test]
     fn check_options() {
         super::Options::new().check_invariants(false)
     }
+
+    #[test]
+    fn test_verbosity_flags() {
+        let level = |args: &[&str]| {
+            super::Options::new()
+                .run_inner(args)
+                .expect("arguments should parse")
+                .verbosity
+                .level()
+        };
+
+        assert_eq!(level(&["archive", "hash"]), LevelFilter::INFO);
+        assert_eq!(level(&["-q", "archive", "hash"]), LevelFilter::WARN);
+        assert_eq!(level(&["-qq", "archive", "hash"]), LevelFilter::ERROR);
+        assert_eq!(level(&["-qqq", "archive", "hash"]), LevelFilter::ERROR);
+        assert_eq!(level(&["-v", "archive", "hash"]), LevelFilter::DEBUG);
+        assert_eq!(level(&["-vv", "archive", "hash"]), LevelFilter::TRACE);
+        assert_eq!(
+            level(&["--verbose", "--quiet", "archive", "hash"]),
+            LevelFilter::INFO
+        );
+    }
+
+    #[test]
+    fn test_keep_going_flags() {
+        assert!(field!(&["package", "build"], build.keep_going));
+        assert!(field!(
+            &["package", "build", "--keep-going"],
+            build.keep_going
+        ));
+        assert!(!field!(
+            &["package", "build", "--stop-on-error"],
+            build.keep_going
+        ));
+    }
+
+    #[test]
+    fn test_jobs_flag() {
+        assert_eq!(
+            field!(&["package", "build"], build.jobs).get(),
+            DEFAULT_CONCURRENCY.get()
+        );
+        assert_eq!(
+            field!(&["package", "build", "-j", "1"], build.jobs).get(),
+            1
+        );
+        assert!(
+            super::Options::new()
+                .run_inner(&["package", "build", "--jobs", "0"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_profile_flag() {
+        assert_eq!(field!(&["package", "build"], build.profile), None);
+        assert_eq!(
+            field!(
+                &["package", "build", "--profile", "trace.json"],
+                build.profile
+            ),
+            Some(PathBuf::from("trace.json"))
+        );
+    }
+
+    #[test]
+    fn test_pack_output() {
+        assert_eq!(field!(&["archive", "pack"], pack.output), None);
+        assert_eq!(field!(&["archive", "pack", "-o", "-"], pack.output), None);
+        assert_eq!(
+            field!(&["archive", "pack", "--output", "out.xar"], pack.output),
+            Some(PathBuf::from("out.xar"))
+        );
+    }
 }
@@ -1,70 +1,122 @@
 use std::{
-    collections::BTreeMap,
-    io::Write,
-    path::Path,
+    collections::{BTreeMap, HashMap},
+    error::Error as StdError,
+    fmt, fs,
+    io::{BufWriter, Write},
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
     sync::{Arc, mpsc},
+    time::{Duration, Instant},
 };
 
 use crate::options::{
+    OPTIONS,
     cli::{PackageAction, ProjectFormat},
     get_opts,
 };
 
-use petgraph::{Direction, dot, graph::NodeIndex, visit::EdgeRef};
+use bytes::BytesMut;
+use petgraph::{
+    Direction, dot,
+    graph::NodeIndex,
+    visit::{Dfs, EdgeRef},
+};
+use serde::Serialize;
 use tokio::task;
 use tracing::info;
-// use xh_backend_lua::LuaBackend;
+use xh_archive::{Event as ArchiveEvent, ObjectContent, encoding::Encoder, packing::Packer};
 use xh_backend_arch::ArchBackend;
+use xh_backend_lua::LuaBackend;
 use xh_engine::{
-    backend::Backend,
-    builder::Builder,
+    backend::{
+        Backend,
+        registry::{BackendRegistry, ProjectDescriptor},
+    },
+    builder::{Builder, Dispatch, Initialize},
     name::PackageName,
-    planner::{Frozen, Planner},
+    planner::{Frozen, Planner, Unfrozen},
     scheduler::{Event, Scheduler},
-    store::Store,
+    store::{ArtifactId, Error as StoreError, MissingArtifactError, Store},
 };
 use xh_executor_bubblewrap::{BubblewrapExecutor, Options as BubblewrapExecutorOptions};
 use xh_executor_compression::{CompressionExecutor, Options as CompressionExecutorOptions};
+use xh_executor_extract::ExtractExecutor;
 use xh_executor_http::{HttpExecutor, Options as HttpExecutorOptions};
+use xh_executor_patch::PatchExecutor;
+use xh_executor_tar::TarExecutor;
 use xh_reports::{partition_results, prelude::*};
 use xh_store_sqlite::SqliteStore;
 
-use crate::options::cli::{InspectAction, PackageFormat};
+use crate::options::cli::{InspectAction, LinkAction, PackageFormat};
 
 #[derive(Debug, IntoReport)]
 pub enum PackageActionError {
-    #[message("could not initialize planner")]
-    Initialize,
     #[message("could not execute link action")]
     Link,
     #[message("could not execute inspect action")]
     Inspect,
 }
 
-pub async fn handle(project: &Path, action: &PackageAction) -> Result<(), ()> {
-    let mut planner = Planner::new();
-    ArchBackend::new(xh_backend_arch::Options {
-        mirror: "http://mirrors.acm.wpi.edu/archlinux".to_string(),
-        architecture: "x86_64".into(),
-        repos: Vec::default(),
-        priorities: BTreeMap::default(),
-    })
-    .plan(&mut planner, project)
-    .wrap_with(PackageActionError::Initialize)
-    .erased()?;
+#[derive(Default, Debug, IntoReport)]
+#[message("could not initialize planner")]
+pub struct PlannerInitError;
 
-    let planner = planner
-        .freeze()
-        .wrap_with(PackageActionError::Initialize)
-        .erased()?;
+pub async fn handle(project: &Path, action: &PackageAction) -> Result<(), ()> {
+    let mut planner = cached_plan(project).await.erased()?;
 
     match action {
-        PackageAction::Build { packages, .. } => build(&planner, packages).await.erased()?,
-        PackageAction::Link { .. } => todo!("link action not implemented"),
+        PackageAction::Build {
+            packages,
+            keep_going,
+            jobs,
+            format,
+            profile,
+            changed_only,
+            tags,
+            ..
+        } => {
+            let mut nodes = resolve_many(&planner, packages).erased()?;
+            for node in tags.iter().flat_map(|tag| planner.by_tag(tag)) {
+                if !nodes.contains(&node) {
+                    nodes.push(node);
+                }
+            }
+
+            build(
+                &mut planner,
+                &nodes,
+                *keep_going,
+                *jobs,
+                *changed_only,
+                *format,
+                profile.as_deref(),
+            )
+            .await
+            .erased()?
+        }
+        PackageAction::Link {
+            dry_run,
+            force,
+            root,
+            action: LinkAction::Add,
+            packages,
+        } => {
+            let nodes = resolve_many(&planner, packages).erased()?;
+            let store = SqliteStore::new(get_opts().base.locations.store.clone()).erased()?;
+            let artifacts = stored_artifacts(&planner, &nodes, &store).await.erased()?;
+            link(&store, &artifacts, root, *force, *dry_run)
+                .await
+                .erased()?;
+        }
+        PackageAction::Link {
+            action: LinkAction::Delete,
+            ..
+        } => todo!("link delete action not implemented"),
         PackageAction::Inspect(action) => match action {
             InspectAction::Project { format } => inspect_project(&planner, *format),
             InspectAction::Packages { packages, format } => {
-                inspect_packages(&planner, packages, *format)
+                let nodes = resolve_many(&planner, packages).erased()?;
+                inspect_packages(&planner, &nodes, *format)
                     .wrap_with(PackageActionError::Inspect)
                     .erased()?;
             }
@@ -74,9 +126,116 @@ pub async fn handle(project: &Path, action: &PackageAction) -> Result<(), ()> {
     Ok(())
 }
 
+fn arch_backend() -> ArchBackend {
+    ArchBackend::new(xh_backend_arch::Options {
+        mirror: "http://mirrors.acm.wpi.edu/archlinux".to_string(),
+        architecture: "x86_64".into(),
+        repos: Vec::default(),
+        priorities: BTreeMap::default(),
+        fetch_db: false,
+        lossy_repo_names: false,
+        strict_scan: false,
+    })
+}
+
+fn backends() -> Result<BackendRegistry, PlannerInitError> {
+    let mut registry = BackendRegistry::new();
+    registry.register(arch_backend());
+    // TODO: enable sandboxing once the lua backend supports it
+    registry.register(LuaBackend::new(xh_backend_lua::Options { sandbox: false }).wrap()?);
+
+    Ok(registry)
+}
+
+/// Loads the project's plan from the plan cache, evaluating and caching it if the project changed.
+///
+/// The cache lives in the build directory, so it's skipped when options aren't initialized.
+async fn cached_plan(project: &Path) -> Result<Planner<Frozen>, PlannerInitError> {
+    let cache = OPTIONS.get().and_then(|options| {
+        let inputs = match project_digest(project) {
+            Ok(inputs) => inputs,
+            Err(report) => {
+                tracing::debug!(
+                    error = &report.into_error() as &dyn StdError,
+                    "could not hash project, skipping plan cache"
+                );
+                return None;
+            }
+        };
+        let key = blake3::hash(project.as_os_str().as_encoded_bytes());
+        let path = options
+            .base
+            .locations
+            .build
+            .join(format!("plan-{key}.json"));
+
+        Some((path, inputs))
+    });
+
+    if let Some((path, inputs)) = &cache {
+        match Planner::<Frozen>::load(path, inputs) {
+            Ok(Some(planner)) => {
+                tracing::debug!(?path, "loaded cached plan");
+                return Ok(planner);
+            }
+            Ok(None) => (),
+            Err(report) => tracing::warn!(
+                error = &report.into_error() as &dyn StdError,
+                "could not load cached plan"
+            ),
+        }
+    }
+
+    let mut planner = Planner::new();
+    planner.register_validator::<BubblewrapExecutor>();
+    planner.register_validator::<CompressionExecutor>();
+    planner.register_validator::<ExtractExecutor>();
+    planner.register_validator::<HttpExecutor>();
+    planner.register_validator::<PatchExecutor>();
+    planner.register_validator::<TarExecutor>();
+
+    plan(&mut planner, project).await?;
+    let planner = planner.freeze().wrap()?;
+
+    if let Some((path, inputs)) = &cache
+        && let Err(report) = planner.save(path, inputs)
+    {
+        tracing::warn!(
+            error = &report.into_error() as &dyn StdError,
+            "could not cache plan"
+        );
+    }
+
+    Ok(planner)
+}
+
+/// Hashes every file in the project, as the digest of its archive.
+fn project_digest(project: &Path) -> Result<blake3::Hash, ()> {
+    let mut encoder = Encoder::new();
+    for event in Packer::new(project.to_path_buf()).pack_iter() {
+        encoder
+            .encode(&mut BytesMut::new(), event.erased()?)
+            .erased()?;
+    }
+
+    Ok(blake3::Hash::from_bytes(*encoder.digest().as_bytes()))
+}
+
+/// Plans a project through its [`ProjectDescriptor`] if it has one,
+/// otherwise treating the whole project as an arch project.
+async fn plan(planner: &mut Planner<Unfrozen>, project: &Path) -> Result<(), PlannerInitError> {
+    if project.join(ProjectDescriptor::FILE).exists() {
+        backends()?.plan(planner, project).await.wrap()
+    } else {
+        let backend = arch_backend();
+        backend.plan_async(planner, project).await.wrap()?;
+        backend.finalize(planner).wrap()
+    }
+}
+
 fn inspect_packages(
     planner: &Planner<Frozen>,
-    packages: &[PackageName],
+    nodes: &[NodeIndex],
     format: PackageFormat,
 ) -> Result<(), ()> {
     match format {
@@ -84,11 +243,7 @@ fn inspect_packages(
         // TODO: output store artifacts for pkg
         PackageFormat::Human => {
             let mut stdout = std::io::stdout().lock();
-            for (i, node) in resolve_many(planner, packages)
-                .erased()?
-                .into_iter()
-                .enumerate()
-            {
+            for (i, &node) in nodes.iter().enumerate() {
                 let plan = planner.graph();
                 let pkg = &plan[node];
 
@@ -100,7 +255,7 @@ fn inspect_packages(
                 }
 
                 // .join would be less efficient here
-                if i + 1 != packages.len() {
+                if i + 1 != nodes.len() {
                     writeln!(stdout).erased()?;
                 }
             }
@@ -123,27 +278,215 @@ fn inspect_project(planner: &Planner<Frozen>, format: ProjectFormat) {
             )
         ),
         ProjectFormat::Json => todo!("json format not yet implemented"),
+        ProjectFormat::Mermaid => print!("{}", mermaid(planner)),
     }
 }
 
+/// Renders the plan as a Mermaid flowchart, with an edge from each package to its dependencies.
+fn mermaid(planner: &Planner<Frozen>) -> String {
+    use fmt::Write as _;
+
+    // `#` starts entity codes, and the rest would end the label or be read as HTML
+    let escape = |name: &PackageName| {
+        name.to_string()
+            .replace('#', "#35;")
+            .replace('"', "#quot;")
+            .replace('<', "#lt;")
+            .replace('>', "#gt;")
+    };
+
+    let plan = planner.graph();
+    let mut output = String::from("graph TD\n");
+    for node in plan.node_indices() {
+        let name = escape(&plan[node].name);
+        writeln!(output, r#"    n{}["{name}"]"#, node.index())
+            .expect("writing to a string should not fail");
+    }
+    for edge in plan.edge_references() {
+        writeln!(
+            output,
+            "    n{} -->|{}| n{}",
+            edge.source().index(),
+            edge.weight(),
+            edge.target().index()
+        )
+        .expect("writing to a string should not fail");
+    }
+
+    output
+}
+
 #[derive(Default, Debug, IntoReport)]
 #[message("could not execute build action")]
-struct BuildActionError;
+pub struct BuildActionError;
 
-async fn build(
-    planner: &Planner<Frozen>,
-    packages: &[PackageName],
-) -> StdResult<(), Report<BuildActionError>> {
-    let locations = &get_opts().base.locations;
-    let nodes = resolve_many(planner, packages).wrap()?;
-    let mut store = SqliteStore::new(locations.store.clone()).wrap()?;
-    let builder: Arc<_> = Builder::new(locations.build.clone())
-        .register(|ctx| {
-            Ok(BubblewrapExecutor::new(
-                ctx,
-                BubblewrapExecutorOptions::default(),
-            ))
-        })
+/// End-of-run counts for a build, derived from the scheduler's events.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct BuildSummary {
+    /// Packages the build needed, including dependencies of the requested packages.
+    pub total: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    /// Packages never built, because a dependency failed, the build stopped early,
+    /// or they were already in the store with `--changed-only`.
+    pub skipped: usize,
+    /// Wall time of the build, in seconds.
+    pub duration: f64,
+}
+
+impl BuildSummary {
+    fn new(planner: &Planner<Frozen>, nodes: &[NodeIndex]) -> Self {
+        let plan = planner.graph();
+        let mut visitor = Dfs::empty(plan);
+        let mut total = 0;
+        for &node in nodes {
+            visitor.move_to(node);
+            while visitor.next(plan).is_some() {
+                total += 1;
+            }
+        }
+
+        Self {
+            total,
+            ..Self::default()
+        }
+    }
+
+    fn record(&mut self, event: &Event) {
+        match event {
+            Event::Finished { result: Ok(()), .. } => self.succeeded += 1,
+            Event::Finished { result: Err(_), .. } => self.failed += 1,
+            Event::Started { .. } => (),
+        }
+    }
+
+    fn finish(&mut self, elapsed: Duration) {
+        self.skipped = self.total - self.succeeded - self.failed;
+        self.duration = elapsed.as_secs_f64();
+    }
+}
+
+impl fmt::Display for BuildSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} packages in {:.2}s: {} succeeded, {} failed, {} skipped",
+            self.total, self.duration, self.succeeded, self.failed, self.skipped
+        )
+    }
+}
+
+/// Chrome trace of a build, recorded from the scheduler's events, see `--profile`.
+///
+/// Packages are laid out on the lowest lane free when they started,
+/// so concurrent builds don't overlap within a lane.
+#[derive(Debug)]
+struct BuildProfile {
+    origin: Instant,
+    running: HashMap<PackageName, (Instant, usize)>,
+    lanes: Vec<bool>,
+    events: Vec<TraceEvent>,
+}
+
+/// A complete (`"X"`) event of the Chrome trace event format.
+#[derive(Debug, Serialize)]
+struct TraceEvent {
+    name: String,
+    cat: &'static str,
+    ph: &'static str,
+    /// Start of the build, in microseconds since the profile began.
+    ts: u64,
+    /// Duration of the build, in microseconds.
+    dur: u64,
+    pid: u32,
+    tid: usize,
+    args: TraceArgs,
+}
+
+#[derive(Debug, Serialize)]
+struct TraceArgs {
+    status: &'static str,
+}
+
+impl BuildProfile {
+    fn new(origin: Instant) -> Self {
+        Self {
+            origin,
+            running: HashMap::new(),
+            lanes: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+
+    fn record(&mut self, event: &Event, now: Instant) {
+        match event {
+            Event::Started { name, .. } => {
+                let lane = match self.lanes.iter().position(|busy| !busy) {
+                    Some(lane) => lane,
+                    None => {
+                        self.lanes.push(false);
+                        self.lanes.len() - 1
+                    }
+                };
+                self.lanes[lane] = true;
+                self.running.insert(name.clone(), (now, lane));
+            }
+            Event::Finished { name, result, .. } => {
+                let Some((start, lane)) = self.running.remove(name) else {
+                    return;
+                };
+                self.lanes[lane] = false;
+
+                let micros = |duration: Duration| duration.as_micros() as u64;
+                self.events.push(TraceEvent {
+                    name: name.to_string(),
+                    cat: "package",
+                    ph: "X",
+                    ts: micros(start.duration_since(self.origin)),
+                    dur: micros(now.duration_since(start)),
+                    pid: 1,
+                    tid: lane,
+                    args: TraceArgs {
+                        status: if result.is_ok() { "success" } else { "failure" },
+                    },
+                });
+            }
+        }
+    }
+
+    fn write(&self, writer: impl Write) -> StdResult<(), serde_json::Error> {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Trace<'a> {
+            trace_events: &'a [TraceEvent],
+            display_time_unit: &'static str,
+        }
+
+        serde_json::to_writer(
+            writer,
+            &Trace {
+                trace_events: &self.events,
+                display_time_unit: "ms",
+            },
+        )
+    }
+}
+
+fn print_summary(summary: &BuildSummary, format: PackageFormat) -> Result<(), ()> {
+    let mut stdout = std::io::stdout().lock();
+    match format {
+        PackageFormat::Human => writeln!(stdout, "{summary}").erased(),
+        PackageFormat::Json => {
+            serde_json::to_writer(&mut stdout, summary).erased()?;
+            writeln!(stdout).erased()
+        }
+    }
+}
+
+/// Builder with every executor available to packages.
+fn builder(root: PathBuf) -> Builder<impl Initialize<Output: Dispatch + Send> + Send + Sync> {
+    Builder::new(root)
+        .register(|ctx| BubblewrapExecutor::new(ctx, BubblewrapExecutorOptions::default()))
         .register(|ctx| Ok(HttpExecutor::new(ctx, HttpExecutorOptions::default())))
         .register(|ctx| {
             Ok(CompressionExecutor::new(
@@ -151,15 +494,62 @@ async fn build(
                 CompressionExecutorOptions::default(),
             ))
         })
-        .into();
+        .register(|ctx| Ok(ExtractExecutor::new(ctx, HttpExecutorOptions::default())))
+        .register(|ctx| Ok(PatchExecutor::new(ctx)))
+        .register(|ctx| Ok(TarExecutor::new(ctx)))
+}
+
+/// Prints the executors registered in the builder as JSON.
+pub fn list_executors() -> Result<(), ()> {
+    let builder = builder(get_opts().base.locations.build.clone());
+    let executors: Vec<_> = builder
+        .executor_names()
+        .into_iter()
+        .map(|name| serde_json::json!({ "name": name }))
+        .collect();
+
+    println!("{}", serde_json::to_string(&executors).erased()?);
+    Ok(())
+}
+
+async fn build(
+    planner: &mut Planner<Frozen>,
+    nodes: &[NodeIndex],
+    keep_going: bool,
+    jobs: NonZeroUsize,
+    changed_only: bool,
+    format: PackageFormat,
+    profile: Option<&Path>,
+) -> StdResult<(), Report<BuildActionError>> {
+    let start = Instant::now();
+    let mut summary = BuildSummary::new(planner, nodes);
+    let mut trace = profile.map(|_| BuildProfile::new(start));
+    let locations = &get_opts().base.locations;
+    let mut store = SqliteStore::new(locations.store.clone()).wrap()?;
+    estimate_costs(planner, nodes, &store).await.wrap()?;
+    let planner = &*planner;
+    let builder: Arc<_> = builder(locations.build.clone()).into();
 
-    let mut scheduler = Scheduler::new(planner, builder.as_ref());
+    let mut scheduler = Scheduler::new(planner, builder.as_ref())
+        .keep_going(keep_going)
+        .concurrency(jobs.get());
+    if changed_only {
+        scheduler = scheduler.prebuilt(up_to_date(planner, nodes, &store).await.wrap()?);
+    }
     let builder = builder.clone();
 
     let (results_tx, results_rx) = mpsc::channel();
     let handle = task::spawn(async move {
         let mut failures = Vec::new();
+        let mut started = HashMap::new();
         while let Ok(event) = results_rx.recv() {
+            summary.record(&event);
+            if let Some(trace) = &mut trace {
+                trace.record(&event, Instant::now());
+            }
+            if let Event::Started { request, .. } = &event {
+                started.insert(request.id, Instant::now());
+            }
             let Event::Finished {
                 name,
                 request,
@@ -177,33 +567,265 @@ async fn build(
 
             match result {
                 Ok(()) => {
-                    let archive = builder
-                        .fetch(&request.id)
-                        .expect("should be able to fetch package output")
+                    let artifact = builder
+                        .fetch_into_store(&request.package, &mut store)
+                        .await
+                        .expect("could not register artifact")
                         .expect("package should exist");
-
                     store
-                        .register_artifact(archive)
+                        .register_package(&name, &request.package, &artifact.id)
                         .await
-                        .expect("could not register artifact");
+                        .expect("could not register package");
+
+                    if let Some(start) = started.remove(&request.id)
+                        && let Err(report) =
+                            store.record_build_duration(&name, start.elapsed()).await
+                    {
+                        tracing::warn!(
+                            error = &report.into_error() as &dyn StdError,
+                            "could not record build duration"
+                        );
+                    }
                 }
                 Err(report) => failures.push(report),
             }
         }
 
-        failures
+        (failures, summary, trace)
     });
 
-    scheduler.schedule(&nodes, results_tx).await;
+    scheduler.schedule(nodes, results_tx).await;
+
+    let (failures, mut summary, trace) = handle.await.wrap()?;
+    summary.finish(start.elapsed());
+    print_summary(&summary, format).wrap()?;
+
+    if let (Some(path), Some(trace)) = (profile, trace) {
+        let mut file = BufWriter::new(fs::File::create(path).wrap()?);
+        trace.write(&mut file).wrap()?;
+        file.flush().wrap()?;
+    }
 
-    let failures = handle.await.wrap()?;
     if failures.is_empty() {
         Ok(())
     } else {
-        Err(BuildActionError.into_report().with_children(failures))
+        Err(BuildActionError
+            .into_report()
+            .aggregate(failures, summary.total, "packages"))
     }
 }
 
+/// Packages needed to build `nodes` whose identity is already registered in the store.
+async fn up_to_date(
+    planner: &Planner<Frozen>,
+    nodes: &[NodeIndex],
+    store: &impl Store,
+) -> Result<Vec<NodeIndex>, ()> {
+    let plan = planner.graph();
+    let mut visitor = Dfs::empty(plan);
+    let mut found = Vec::new();
+    for &node in nodes {
+        visitor.move_to(node);
+        while let Some(node) = visitor.next(plan) {
+            let identity = planner
+                .identity(node)
+                .expect("planned package should be registered");
+
+            if store.package(&identity).await.erased()?.is_some() {
+                info!(name = %plan[node].name, "package is up to date, skipping");
+                found.push(node);
+            }
+        }
+    }
+
+    Ok(found)
+}
+
+/// Sets the build cost of every package needed to build `nodes` to the duration of its last build.
+async fn estimate_costs(
+    planner: &mut Planner<Frozen>,
+    nodes: &[NodeIndex],
+    store: &SqliteStore,
+) -> Result<(), ()> {
+    let mut needed = Vec::new();
+    let plan = planner.graph();
+    let mut visitor = Dfs::empty(plan);
+    for &node in nodes {
+        visitor.move_to(node);
+        while let Some(node) = visitor.next(plan) {
+            needed.push((node, plan[node].name.clone()));
+        }
+    }
+
+    for (node, name) in needed {
+        if let Some(duration) = store.last_build_duration(&name).await.erased()? {
+            planner.set_build_cost(node, duration.as_secs());
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, IntoReport)]
+#[message("packages have not been built")]
+#[suggestion("build them with `package build` first")]
+#[context(packages)]
+pub struct UnbuiltPackageError {
+    packages: Vec<PackageName>,
+}
+
+/// Looks up the stored artifact of every package in `nodes`.
+async fn stored_artifacts(
+    planner: &Planner<Frozen>,
+    nodes: &[NodeIndex],
+    store: &impl Store,
+) -> Result<Vec<(PackageName, ArtifactId)>, ()> {
+    let plan = planner.graph();
+    let mut artifacts = Vec::with_capacity(nodes.len());
+    let mut unbuilt = Vec::new();
+    for &node in nodes {
+        let identity = planner
+            .identity(node)
+            .expect("planned package should be registered");
+        let name = plan[node].name.clone();
+
+        match store.package(&identity).await.erased()? {
+            Some(package) => artifacts.push((name, package.artifact)),
+            None => unbuilt.push(name),
+        }
+    }
+
+    if unbuilt.is_empty() {
+        Ok(artifacts)
+    } else {
+        Err(UnbuiltPackageError { packages: unbuilt }
+            .into_report()
+            .erased())
+    }
+}
+
+#[derive(Debug, IntoReport)]
+#[message("file is shipped by multiple packages")]
+#[suggestion("pass --force to let the last package win")]
+#[context(path, packages)]
+pub struct FileConflictError {
+    path: PathBuf,
+    packages: Vec<PackageName>,
+}
+
+/// Links the artifacts of `packages` into `root`, in order.
+///
+/// Paths shipped by more than one package, unless every one of them ships a directory there,
+/// are reported as [`FileConflictError`]s before anything is written.
+/// If `force` is set, the last package shipping a path wins instead.
+async fn link(
+    store: &impl Store,
+    packages: &[(PackageName, ArtifactId)],
+    root: &Path,
+    force: bool,
+    dry_run: bool,
+) -> Result<(), PackageActionError> {
+    let mut owners: BTreeMap<PathBuf, Vec<(&PackageName, bool)>> = BTreeMap::new();
+    for (name, artifact) in packages {
+        let objects = shipped_objects(store, artifact)
+            .await
+            .wrap_with(PackageActionError::Link)?;
+        for (path, directory) in objects {
+            owners.entry(path).or_default().push((name, directory));
+        }
+    }
+
+    let conflicts: Vec<_> = owners
+        .into_iter()
+        .filter(|(_, owners)| owners.len() > 1 && owners.iter().any(|(_, directory)| !directory))
+        .collect();
+    if !force && !conflicts.is_empty() {
+        return Err(PackageActionError::Link.into_report().with_children(
+            conflicts.into_iter().map(|(path, owners)| {
+                FileConflictError {
+                    path,
+                    packages: owners.into_iter().map(|(name, _)| name.clone()).collect(),
+                }
+                .into_report()
+            }),
+        ));
+    }
+
+    for (path, owners) in &conflicts {
+        let owners: Vec<_> = owners.iter().map(|(name, _)| name).collect();
+        tracing::warn!(
+            ?path,
+            ?owners,
+            "file is shipped by multiple packages, last one wins"
+        );
+    }
+
+    for (name, artifact) in packages {
+        if dry_run {
+            info!(%name, ?root, "would link package");
+            continue;
+        }
+
+        // symlinks can't be created over an existing path, and files and directories can't replace each other
+        for (path, directory) in conflicts.iter().filter_map(|(path, owners)| {
+            owners
+                .iter()
+                .find(|(owner, _)| *owner == name)
+                .map(|(_, directory)| (root.join(path), *directory))
+        }) {
+            let result = match fs::symlink_metadata(&path) {
+                Ok(metadata) if metadata.is_dir() && directory => continue,
+                Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(&path),
+                Ok(_) => fs::remove_file(&path),
+                Err(err) => Err(err),
+            };
+
+            match result {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(err).wrap_with(PackageActionError::Link);
+                }
+                _ => (),
+            }
+        }
+
+        store
+            .extract(artifact, root)
+            .await
+            .wrap_with(PackageActionError::Link)?;
+        info!(%name, ?root, "linked package");
+    }
+
+    Ok(())
+}
+
+/// Locations of every object in a stored artifact, and whether each is a directory.
+async fn shipped_objects(
+    store: &impl Store,
+    artifact: &ArtifactId,
+) -> Result<Vec<(PathBuf, bool)>, StoreError> {
+    store
+        .download_iter(artifact, |events| {
+            events
+                .filter_map(|event| match event {
+                    Ok(ArchiveEvent::Object(object)) => Some(Ok((
+                        PathBuf::from(object.location.as_ref()),
+                        matches!(object.content, ObjectContent::Directory),
+                    ))),
+                    Ok(_) => None,
+                    Err(err) => Some(Err(err)),
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .await?
+        .ok_or_else(|| {
+            MissingArtifactError {
+                artifact: *artifact,
+            }
+            .wrap()
+        })
+        .flatten()
+}
+
 #[derive(Debug, IntoReport)]
 #[message("could not resolve packages")]
 #[context(packages)]
@@ -222,3 +844,507 @@ fn resolve_many(
     )
     .map_err(|packages| PackageResolveError { packages }.into())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        time::{Duration, Instant},
+    };
+
+    use petgraph::graph::NodeIndex;
+    use xh_archive::packing::Packer;
+    use xh_engine::store::Store;
+    use xh_engine::{
+        builder::{BuildRequest, Error as BuildError},
+        executor::Executor,
+        gen_name,
+        name::PackageName,
+        package::{Dependency, LinkTime, Metadata, Package},
+        planner::{Frozen, Planner},
+        scheduler::{Event, Scheduler},
+    };
+    use xh_executor_bubblewrap::BubblewrapExecutor;
+    use xh_executor_http::HttpExecutor;
+    use xh_reports::prelude::*;
+    use xh_store_sqlite::SqliteStore;
+
+    use crate::package::{
+        BuildProfile, BuildSummary, builder, link, mermaid, plan, stored_artifacts, up_to_date,
+    };
+
+    const GLIBC_DESC: &str = "%FILENAME%
+glibc-2.42-1-x86_64.pkg.tar.zst
+
+%NAME%
+glibc
+
+%BASE%
+glibc
+
+%VERSION%
+2.42-1
+
+%CSIZE%
+1818463
+
+%ISIZE%
+18184634
+
+%SHA256SUM%
+b5bb9d8014a0f9b1d61e21e796d78dccdf1352f23cd32812f4850b878ae4944c
+
+%ARCH%
+x86_64
+
+%BUILDDATE%
+1729181726
+
+%PACKAGER%
+Foobar McFooface <foobar@mcfooface.org>
+";
+
+    #[tokio::test]
+    async fn test_plan_mixed_project() {
+        let temp = tempfile::tempdir().unwrap();
+        let project = temp.path();
+
+        fs::create_dir_all(project.join("arch/core/glibc-2.42-1")).unwrap();
+        fs::write(project.join("arch/core/glibc-2.42-1/desc"), GLIBC_DESC).unwrap();
+
+        fs::create_dir(project.join("lua")).unwrap();
+        fs::write(
+            project.join("lua/main.lua"),
+            r#"
+            local planner = require("xuehua.planner")
+            planner:package({
+                identifier = "hello",
+                defaults = {},
+                apply = function() return {} end,
+            })
+            "#,
+        )
+        .unwrap();
+
+        fs::write(
+            project.join("xuehua.json"),
+            r#"{ "projects": [
+                { "backend": "lua@xuehua", "path": "lua" },
+                { "backend": "arch@xuehua", "path": "arch" }
+            ] }"#,
+        )
+        .unwrap();
+
+        let mut planner = Planner::new();
+        plan(&mut planner, project).await.unwrap();
+
+        let hello = PackageName::new("hello", []);
+        let glibc = PackageName::new("glibc", ["xuehua".into(), "arch".into()]);
+        assert!(planner.resolve(&hello).is_some());
+        assert!(planner.resolve(&glibc).is_some());
+        assert!(planner.freeze().is_ok());
+    }
+
+    #[test]
+    fn test_build_summary() {
+        let mut planner = Planner::new();
+        for (name, dependencies) in [
+            (gen_name!(app@my), &["lib"][..]),
+            (gen_name!(lib@my), &[]),
+            (gen_name!(broken@my), &[]),
+            (gen_name!(unrelated@my), &[]),
+        ] {
+            planner
+                .register(Package {
+                    name,
+                    metadata: Metadata,
+                    tags: Vec::new(),
+                    build_cost: None,
+                    requests: Vec::new(),
+                    dependencies: dependencies
+                        .iter()
+                        .map(|dependency| Dependency {
+                            name: dependency.parse().unwrap(),
+                            time: LinkTime::Runtime,
+                        })
+                        .collect(),
+                })
+                .unwrap();
+        }
+        let planner = planner.freeze().unwrap();
+        let nodes =
+            [gen_name!(app@my), gen_name!(broken@my)].map(|name| planner.resolve(&name).unwrap());
+
+        let mut summary = BuildSummary::new(&planner, &nodes);
+        assert_eq!(summary.total, 3);
+
+        let finished = |name: PackageName, result| {
+            let target = planner.resolve(&name).unwrap();
+            let request = BuildRequest {
+                id: blake3::hash(name.to_string().as_bytes()),
+                target,
+                package: planner.identity(target).unwrap(),
+            };
+
+            [
+                Event::Started {
+                    name: name.clone(),
+                    request,
+                },
+                Event::Finished {
+                    name,
+                    request,
+                    result,
+                },
+            ]
+        };
+        let events = [
+            finished(gen_name!(lib@my), Ok(())),
+            finished(gen_name!(broken@my), Err(BuildError.into_report())),
+        ];
+        for event in events.iter().flatten() {
+            summary.record(event);
+        }
+        summary.finish(Duration::from_millis(1500));
+
+        assert_eq!(
+            summary,
+            BuildSummary {
+                total: 3,
+                succeeded: 1,
+                failed: 1,
+                skipped: 1,
+                duration: 1.5,
+            }
+        );
+        assert_eq!(
+            summary.to_string(),
+            "3 packages in 1.50s: 1 succeeded, 1 failed, 1 skipped"
+        );
+        assert_eq!(
+            serde_json::to_value(&summary).unwrap(),
+            serde_json::json!({
+                "total": 3,
+                "succeeded": 1,
+                "failed": 1,
+                "skipped": 1,
+                "duration": 1.5,
+            })
+        );
+    }
+
+    #[test]
+    fn test_mermaid() {
+        let mut planner = Planner::new();
+        let quoted = PackageName::new(r#"say "hi" <#>"#, ["my".into()]);
+        for (name, dependencies) in [
+            (
+                gen_name!(app@my),
+                vec![(gen_name!(lib@my), LinkTime::Runtime)],
+            ),
+            (
+                gen_name!(lib@my),
+                vec![(quoted.clone(), LinkTime::Buildtime)],
+            ),
+            (quoted, Vec::new()),
+        ] {
+            planner
+                .register(Package {
+                    name,
+                    metadata: Metadata,
+                    tags: Vec::new(),
+                    build_cost: None,
+                    requests: Vec::new(),
+                    dependencies: dependencies
+                        .into_iter()
+                        .map(|(name, time)| Dependency { name, time })
+                        .collect(),
+                })
+                .unwrap();
+        }
+        let planner = planner.freeze().unwrap();
+        let mermaid = mermaid(&planner);
+        let lines: Vec<_> = mermaid.lines().collect();
+
+        let node = |name: PackageName| format!("n{}", planner.resolve(&name).unwrap().index());
+        let (app, lib) = (node(gen_name!(app@my)), node(gen_name!(lib@my)));
+        let quoted = node(PackageName::new(r#"say "hi" <#>"#, ["my".into()]));
+
+        assert_eq!(lines[0], "graph TD");
+        assert_eq!(lines.len(), 6, "{mermaid}");
+        for line in [
+            format!(r#"    {app}["app@my(package)"]"#),
+            format!(r#"    {lib}["lib@my(package)"]"#),
+            format!(r#"    {quoted}["say #quot;hi#quot; #lt;#35;#gt;@my(package)"]"#),
+            format!("    {app} -->|runtime| {lib}"),
+            format!("    {lib} -->|buildtime| {quoted}"),
+        ] {
+            assert!(lines.contains(&line.as_str()), "{mermaid}");
+        }
+    }
+
+    #[test]
+    fn test_build_profile() {
+        let start = Instant::now();
+        let mut profile = BuildProfile::new(start);
+        let request = BuildRequest {
+            id: blake3::hash(b"request"),
+            target: NodeIndex::new(0),
+            package: blake3::hash(b"package"),
+        };
+        let started = |name: PackageName| Event::Started { name, request };
+        let finished = |name: PackageName, result| Event::Finished {
+            name,
+            request,
+            result,
+        };
+        let at = |millis| start + Duration::from_millis(millis);
+
+        // `lib` and `cli` overlap, `app` reuses the lane `lib` freed
+        profile.record(&started(gen_name!(lib@my)), at(0));
+        profile.record(&started(gen_name!(cli@my)), at(5));
+        profile.record(&finished(gen_name!(lib@my), Ok(())), at(10));
+        profile.record(&started(gen_name!(app@my)), at(10));
+        profile.record(
+            &finished(gen_name!(cli@my), Err(BuildError.into_report())),
+            at(15),
+        );
+        profile.record(&finished(gen_name!(app@my), Ok(())), at(30));
+
+        let mut written = Vec::new();
+        profile.write(&mut written).unwrap();
+        let trace: serde_json::Value = serde_json::from_slice(&written).unwrap();
+        let events = trace["traceEvents"].as_array().unwrap();
+        assert_eq!(events.len(), 3);
+
+        let event = |name: &str| {
+            events
+                .iter()
+                .find(|event| event["name"] == name)
+                .unwrap_or_else(|| panic!("{name} should be traced"))
+        };
+        let lib = event("lib@my(package)");
+        assert_eq!(
+            (&lib["ts"], &lib["dur"], &lib["tid"]),
+            (&0.into(), &10_000.into(), &0.into())
+        );
+        let cli = event("cli@my(package)");
+        assert_eq!(cli["tid"], 1);
+        assert_eq!(cli["args"]["status"], "failure");
+        let app = event("app@my(package)");
+        assert_eq!(
+            (&app["ts"], &app["dur"], &app["tid"]),
+            (&10_000.into(), &20_000.into(), &0.into())
+        );
+        assert!(events.iter().all(|event| event["ph"] == "X"));
+    }
+
+    #[test]
+    fn test_default_executors() {
+        let temp = tempfile::tempdir().unwrap();
+        let builder = builder(temp.path().to_path_buf());
+        let names = builder.executor_names();
+
+        assert!(names.contains(&BubblewrapExecutor::name()));
+        assert!(names.contains(&HttpExecutor::name()));
+    }
+
+    #[tokio::test]
+    async fn test_link_conflicts() {
+        let temp = tempfile::tempdir().unwrap();
+        let mut store = SqliteStore::new(temp.path().to_path_buf()).unwrap();
+
+        let mut packages = Vec::new();
+        for (name, content) in [
+            (gen_name!(foo@xuehua), "foo"),
+            (gen_name!(bar@xuehua), "bar"),
+        ] {
+            let source = temp.path().join(name.to_string());
+            fs::create_dir_all(source.join("bin")).unwrap();
+            fs::write(source.join("bin/tool"), content).unwrap();
+            fs::write(source.join(format!("bin/{content}")), content).unwrap();
+
+            let archive = Packer::new(source)
+                .pack_iter()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            let artifact = store.register_artifact(archive).await.unwrap();
+            packages.push((name, artifact.id));
+        }
+
+        let root = temp.path().join("root");
+        let report = link(&store, &packages, &root, false, false)
+            .await
+            .expect_err("conflicting packages should not link");
+        let conflicts = report.into_payload().children;
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].message, "file is shipped by multiple packages");
+        assert_eq!(conflicts[0].context_value("path"), Some("\"bin/tool\""));
+        assert!(!root.exists());
+
+        link(&store, &packages, &root, true, false)
+            .await
+            .expect("forced link should succeed");
+        assert_eq!(fs::read_to_string(root.join("bin/tool")).unwrap(), "bar");
+        assert_eq!(fs::read_to_string(root.join("bin/foo")).unwrap(), "foo");
+    }
+
+    #[tokio::test]
+    async fn test_link_directory_conflicts() {
+        let temp = tempfile::tempdir().unwrap();
+        let mut store = SqliteStore::new(temp.path().to_path_buf()).unwrap();
+
+        let mut packages = Vec::new();
+        for (name, directory) in [
+            (gen_name!(foo@xuehua), false),
+            (gen_name!(bar@xuehua), true),
+        ] {
+            let source = temp.path().join(name.to_string());
+            fs::create_dir_all(&source).unwrap();
+            if directory {
+                fs::create_dir(source.join("lib")).unwrap();
+                fs::write(source.join("lib/tool"), "bar").unwrap();
+            } else {
+                fs::write(source.join("lib"), "foo").unwrap();
+            }
+
+            let archive = Packer::new(source)
+                .pack_iter()
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+            let artifact = store.register_artifact(archive).await.unwrap();
+            packages.push((name, artifact.id));
+        }
+
+        let root = temp.path().join("root");
+        let report = link(&store, &packages, &root, false, false)
+            .await
+            .expect_err("a file and a directory at the same path should not link");
+        let conflicts = report.into_payload().children;
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].context_value("path"), Some("\"lib\""));
+
+        link(&store, &packages, &root, true, false)
+            .await
+            .expect("forced link should succeed");
+        assert_eq!(fs::read_to_string(root.join("lib/tool")).unwrap(), "bar");
+    }
+
+    /// Plans `app@my`, depending on `lib@my`, through to `base@my`, so closures have several members.
+    fn chain_planner() -> Planner<Frozen> {
+        let mut planner = Planner::new();
+        let chain = [
+            gen_name!(app@my),
+            gen_name!(lib@my),
+            gen_name!(util@my),
+            gen_name!(core@my),
+            gen_name!(sys@my),
+            gen_name!(base@my),
+        ];
+        for (index, name) in chain.iter().enumerate() {
+            planner
+                .register(Package {
+                    name: name.clone(),
+                    metadata: Metadata,
+                    tags: Vec::new(),
+                    build_cost: None,
+                    requests: Vec::new(),
+                    dependencies: chain
+                        .get(index + 1)
+                        .map(|name| Dependency {
+                            name: name.clone(),
+                            time: LinkTime::Runtime,
+                        })
+                        .into_iter()
+                        .collect(),
+                })
+                .unwrap();
+        }
+
+        planner.freeze().unwrap()
+    }
+
+    /// Builds `targets`, skipping `prebuilt`, and registers every built package like `build` does.
+    async fn build_chain(
+        planner: &Planner<Frozen>,
+        targets: &[NodeIndex],
+        prebuilt: Vec<NodeIndex>,
+        store: &mut SqliteStore,
+        root: &std::path::Path,
+    ) -> Vec<NodeIndex> {
+        let builder = builder(root.to_path_buf());
+        let (events, receiver) = std::sync::mpsc::channel();
+        let report = Scheduler::new(planner, &builder)
+            .prebuilt(prebuilt)
+            .schedule(targets, events)
+            .await;
+
+        for event in receiver.try_iter() {
+            if let Event::Finished {
+                name,
+                request,
+                result: Ok(()),
+            } = event
+            {
+                let artifact = builder
+                    .fetch_into_store(&request.package, store)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                store
+                    .register_package(&name, &request.package, &artifact.id)
+                    .await
+                    .unwrap();
+            }
+        }
+
+        report.built
+    }
+
+    #[tokio::test]
+    async fn test_changed_only_chain() {
+        let temp = tempfile::tempdir().unwrap();
+        let mut store = SqliteStore::new(temp.path().to_path_buf()).unwrap();
+        let root = temp.path().join("build");
+        fs::create_dir(&root).unwrap();
+
+        let planner = chain_planner();
+        let targets = [planner.resolve(&gen_name!(app@my)).unwrap()];
+        let built = build_chain(&planner, &targets, Vec::new(), &mut store, &root).await;
+        assert_eq!(built.len(), 6);
+
+        // a later run plans again from scratch
+        let planner = chain_planner();
+        let targets = [planner.resolve(&gen_name!(app@my)).unwrap()];
+        let prebuilt = up_to_date(&planner, &targets, &store).await.unwrap();
+        assert_eq!(prebuilt.len(), 6);
+
+        let built = build_chain(&planner, &targets, prebuilt, &mut store, &root).await;
+        assert!(built.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_link_built_chain() {
+        let temp = tempfile::tempdir().unwrap();
+        let mut store = SqliteStore::new(temp.path().to_path_buf()).unwrap();
+        let root = temp.path().join("build");
+        fs::create_dir(&root).unwrap();
+
+        let planner = chain_planner();
+        let targets = [planner.resolve(&gen_name!(app@my)).unwrap()];
+        build_chain(&planner, &targets, Vec::new(), &mut store, &root).await;
+
+        let planner = chain_planner();
+        let nodes: Vec<_> = planner.graph().node_indices().collect();
+        let artifacts = stored_artifacts(&planner, &nodes, &store)
+            .await
+            .expect("every package in the chain should be built");
+        assert_eq!(artifacts.len(), 6);
+
+        let linked = temp.path().join("root");
+        link(&store, &artifacts, &linked, false, false)
+            .await
+            .expect("packages without shared files should link");
+        assert!(linked.exists());
+    }
+}
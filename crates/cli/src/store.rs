@@ -0,0 +1,53 @@
+use std::path::Path;
+
+use tracing::info;
+use xh_engine::store::{ArtifactId, Store};
+use xh_reports::prelude::*;
+use xh_store_sqlite::SqliteStore;
+
+use crate::options::{cli::StoreAction, get_opts};
+
+#[derive(Debug, IntoReport)]
+pub enum StoreActionError {
+    #[message("could not execute prune action")]
+    Prune,
+    #[message("could not execute export action")]
+    Export,
+    #[message("could not execute disk usage action")]
+    DiskUsage,
+}
+
+pub async fn handle(action: &StoreAction) -> Result<(), StoreActionError> {
+    match action {
+        StoreAction::Prune { keep } => prune(*keep).await.wrap_with(StoreActionError::Prune),
+        StoreAction::Export { artifact, dest } => export(artifact, dest)
+            .await
+            .wrap_with(StoreActionError::Export),
+        StoreAction::DiskUsage => disk_usage().await.wrap_with(StoreActionError::DiskUsage),
+    }
+}
+
+async fn prune(keep: usize) -> Result<(), ()> {
+    let store = SqliteStore::new(get_opts().base.locations.store.clone()).erased()?;
+    let pruned = store.prune(keep).await.erased()?;
+    info!(pruned, "pruned store");
+
+    Ok(())
+}
+
+async fn disk_usage() -> Result<(), ()> {
+    let store = SqliteStore::new(get_opts().base.locations.store.clone()).erased()?;
+    for (name, size) in store.disk_usage().await.erased()? {
+        println!("{size}\t{name}");
+    }
+
+    Ok(())
+}
+
+async fn export(artifact: &ArtifactId, dest: &Path) -> Result<(), ()> {
+    let store = SqliteStore::new(get_opts().base.locations.store.clone()).erased()?;
+    store.extract(artifact, dest).await.erased()?;
+    info!(%artifact, dest = %dest.display(), "exported artifact");
+
+    Ok(())
+}
@@ -42,3 +42,24 @@ pub fn random_hash() -> blake3::Hash {
     fastrand::fill(&mut buffer);
     blake3::Hash::from_bytes(buffer)
 }
+
+/// Like [`random_hash`], but always returns the same hash for the same `seed`.
+///
+/// Intended for tests, which need reproducible placeholder hashes.
+pub fn random_hash_seeded(seed: u64) -> blake3::Hash {
+    let mut buffer = [0; blake3::OUT_LEN];
+    fastrand::Rng::with_seed(seed).fill(&mut buffer);
+    blake3::Hash::from_bytes(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::random_hash_seeded;
+
+    #[test]
+    fn test_random_hash_seeded() {
+        assert_eq!(random_hash_seeded(0), random_hash_seeded(0));
+        assert_eq!(random_hash_seeded(42), random_hash_seeded(42));
+        assert_ne!(random_hash_seeded(0), random_hash_seeded(1));
+    }
+}
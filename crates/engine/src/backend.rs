@@ -1,3 +1,5 @@
+pub mod registry;
+
 use std::path::Path;
 
 use xh_reports::prelude::*;
@@ -16,4 +18,110 @@ pub trait Backend {
 
     fn name() -> &'static BackendName;
     fn plan(&self, planner: &mut Planner<Unfrozen>, project: &Path) -> Result<(), Error>;
+
+    /// Asynchronous variant of [`Self::plan`], for backends that perform I/O while planning.
+    ///
+    /// Defaults to calling [`Self::plan`].
+    fn plan_async(
+        &self,
+        planner: &mut Planner<Unfrozen>,
+        project: &Path,
+    ) -> impl Future<Output = Result<(), Error>> {
+        async move { self.plan(planner, project) }
+    }
+
+    /// Adjusts the plan once every project has been planned, right before it's frozen.
+    ///
+    /// Packages can be registered or replaced here, for example to add
+    /// a dependency shared by every package. Defaults to doing nothing.
+    fn finalize(&self, planner: &mut Planner<Unfrozen>) -> Result<(), Error> {
+        let _ = planner;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, sync::LazyLock};
+
+    use xh_reports::prelude::*;
+
+    use crate::{
+        backend::{Backend, Error},
+        gen_name,
+        name::{BackendName, PackageName},
+        package::{Metadata, Package},
+        planner::{Planner, Unfrozen},
+    };
+
+    fn package(name: PackageName) -> Package {
+        Package {
+            name,
+            metadata: Metadata,
+            tags: Vec::new(),
+            build_cost: None,
+            requests: Vec::new(),
+            dependencies: Vec::new(),
+        }
+    }
+
+    struct SyncBackend;
+
+    impl Backend for SyncBackend {
+        type Value = ();
+
+        fn name() -> &'static BackendName {
+            static NAME: LazyLock<BackendName> = LazyLock::new(|| gen_name!(sync@test));
+            &NAME
+        }
+
+        fn plan(&self, planner: &mut Planner<Unfrozen>, _project: &Path) -> Result<(), Error> {
+            planner.register(package(gen_name!(sync@test))).wrap()?;
+            Ok(())
+        }
+    }
+
+    struct AsyncBackend;
+
+    impl Backend for AsyncBackend {
+        type Value = ();
+
+        fn name() -> &'static BackendName {
+            static NAME: LazyLock<BackendName> = LazyLock::new(|| gen_name!(async@test));
+            &NAME
+        }
+
+        fn plan(&self, _planner: &mut Planner<Unfrozen>, _project: &Path) -> Result<(), Error> {
+            unreachable!("async backend should only be planned asynchronously")
+        }
+
+        async fn plan_async(
+            &self,
+            planner: &mut Planner<Unfrozen>,
+            _project: &Path,
+        ) -> Result<(), Error> {
+            // simulate fetching the package from elsewhere
+            let package = tokio::spawn(async { package(gen_name!(fetched@test)) })
+                .await
+                .wrap()?;
+
+            planner.register(package).wrap()?;
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_plan_async() {
+        let mut planner = Planner::<Unfrozen>::new();
+        let project = Path::new(".");
+
+        SyncBackend.plan_async(&mut planner, project).await.unwrap();
+        AsyncBackend
+            .plan_async(&mut planner, project)
+            .await
+            .unwrap();
+
+        assert!(planner.resolve(&gen_name!(sync@test)).is_some());
+        assert!(planner.resolve(&gen_name!(fetched@test)).is_some());
+    }
 }
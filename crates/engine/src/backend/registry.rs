@@ -0,0 +1,329 @@
+use std::path::{Path, PathBuf};
+
+use futures_util::{FutureExt, future::LocalBoxFuture};
+use rapidhash::RapidHashMap;
+use serde::Deserialize;
+use xh_reports::prelude::*;
+
+use crate::{
+    backend::{Backend, Error},
+    name::BackendName,
+    planner::{Planner, Unfrozen},
+};
+
+#[derive(Debug, IntoReport)]
+#[message("backend not found")]
+#[suggestion("provide a registered backend")]
+#[context(name)]
+pub struct UnregisteredBackendError {
+    pub name: BackendName,
+}
+
+#[derive(Debug, IntoReport)]
+#[message("could not read project descriptor")]
+#[context(path)]
+pub struct DescriptorReadError {
+    path: PathBuf,
+}
+
+#[derive(Debug, IntoReport)]
+#[message("could not plan sub-project")]
+#[context(display: backend)]
+#[context(path)]
+pub struct SubProjectError {
+    backend: BackendName,
+    path: PathBuf,
+}
+
+#[derive(Debug, IntoReport)]
+#[message("could not finalize plan")]
+#[context(display: backend)]
+pub struct FinalizeError {
+    backend: BackendName,
+}
+
+/// A sub-project, planned by a single backend.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SubProject {
+    #[serde(with = "xh_common::serde_display")]
+    pub backend: BackendName,
+    /// Directory of the sub-project, relative to the project directory.
+    pub path: PathBuf,
+}
+
+/// Describes which backends plan which parts of a project.
+///
+/// Read from [`ProjectDescriptor::FILE`] within the project directory:
+/// ```json
+/// {
+///   "projects": [
+///     { "backend": "lua@xuehua", "path": "lua" },
+///     { "backend": "arch@xuehua", "path": "arch" }
+///   ]
+/// }
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProjectDescriptor {
+    pub projects: Vec<SubProject>,
+}
+
+impl ProjectDescriptor {
+    pub const FILE: &str = "xuehua.json";
+
+    pub fn read(project: &Path) -> Result<Self, DescriptorReadError> {
+        let path = project.join(Self::FILE);
+        let contents =
+            std::fs::read(&path).wrap_with_fn(|| DescriptorReadError { path: path.clone() })?;
+
+        serde_json::from_slice(&contents).wrap_with_fn(|| DescriptorReadError { path })
+    }
+}
+
+trait DynBackend {
+    fn plan_boxed<'a>(
+        &'a self,
+        planner: &'a mut Planner<Unfrozen>,
+        project: &'a Path,
+    ) -> LocalBoxFuture<'a, Result<(), Error>>;
+
+    fn finalize_dyn(&self, planner: &mut Planner<Unfrozen>) -> Result<(), Error>;
+}
+
+impl<B: Backend> DynBackend for B {
+    fn plan_boxed<'a>(
+        &'a self,
+        planner: &'a mut Planner<Unfrozen>,
+        project: &'a Path,
+    ) -> LocalBoxFuture<'a, Result<(), Error>> {
+        self.plan_async(planner, project).boxed_local()
+    }
+
+    fn finalize_dyn(&self, planner: &mut Planner<Unfrozen>) -> Result<(), Error> {
+        self.finalize(planner)
+    }
+}
+
+/// Backends keyed by [`Backend::name`], for projects mixing packages from several backends.
+///
+/// Every sub-project is planned into the same [`Planner`],
+/// so packages defined by more than one backend fail with a [`ConflictError`](crate::planner::ConflictError).
+#[derive(Default)]
+pub struct BackendRegistry {
+    backends: RapidHashMap<BackendName, Box<dyn DynBackend>>,
+}
+
+impl BackendRegistry {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register<B: Backend + 'static>(&mut self, backend: B) {
+        // later registrations shadow earlier ones with the same name
+        self.backends.insert(B::name().clone(), Box::new(backend));
+    }
+
+    /// Plans every sub-project listed in the project's [`ProjectDescriptor`].
+    pub async fn plan(&self, planner: &mut Planner<Unfrozen>, project: &Path) -> Result<(), Error> {
+        let descriptor = ProjectDescriptor::read(project).wrap()?;
+        self.plan_descriptor(planner, project, &descriptor).await
+    }
+
+    /// Plans every sub-project in `descriptor`, in order.
+    ///
+    /// Afterwards, every backend used by the descriptor is [finalized](Backend::finalize) once,
+    /// in the order of their first sub-project.
+    pub async fn plan_descriptor(
+        &self,
+        planner: &mut Planner<Unfrozen>,
+        project: &Path,
+        descriptor: &ProjectDescriptor,
+    ) -> Result<(), Error> {
+        for SubProject { backend, path } in &descriptor.projects {
+            let registered = self.backends.get(backend).ok_or_else(|| {
+                UnregisteredBackendError {
+                    name: backend.clone(),
+                }
+                .wrap()
+            })?;
+
+            let directory = xh_common::safe_path(project, path).wrap()?;
+            registered
+                .plan_boxed(planner, &directory)
+                .await
+                .wrap_with_fn(|| SubProjectError {
+                    backend: backend.clone(),
+                    path: path.clone(),
+                })
+                .wrap()?;
+        }
+
+        let mut finalized = Vec::new();
+        for SubProject { backend, .. } in &descriptor.projects {
+            if finalized.contains(&backend) {
+                continue;
+            }
+
+            finalized.push(backend);
+            self.backends[backend]
+                .finalize_dyn(planner)
+                .wrap_with_fn(|| FinalizeError {
+                    backend: backend.clone(),
+                })
+                .wrap()?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, sync::LazyLock};
+
+    use xh_reports::prelude::*;
+
+    use crate::{
+        backend::{
+            Backend, Error,
+            registry::{BackendRegistry, ProjectDescriptor},
+        },
+        gen_name,
+        name::{BackendName, PackageName},
+        package::{Dependency, LinkTime, Metadata, Package},
+        planner::{Planner, Unfrozen},
+    };
+
+    fn package(name: PackageName) -> Package {
+        Package {
+            name,
+            metadata: Metadata,
+            tags: Vec::new(),
+            build_cost: None,
+            requests: Vec::new(),
+            dependencies: Vec::new(),
+        }
+    }
+
+    /// Registers a package for every line of `packages.txt` in the project.
+    struct ListBackend;
+
+    impl Backend for ListBackend {
+        type Value = ();
+
+        fn name() -> &'static BackendName {
+            static NAME: LazyLock<BackendName> = LazyLock::new(|| gen_name!(list@test));
+            &NAME
+        }
+
+        fn plan(&self, planner: &mut Planner<Unfrozen>, project: &Path) -> Result<(), Error> {
+            let packages = std::fs::read_to_string(project.join("packages.txt")).wrap()?;
+            for name in packages.lines() {
+                planner.register(package(name.parse().wrap()?)).wrap()?;
+            }
+
+            Ok(())
+        }
+    }
+
+    /// Plans like [`ListBackend`], then makes every package depend on `base@test`.
+    struct BaseBackend;
+
+    impl Backend for BaseBackend {
+        type Value = ();
+
+        fn name() -> &'static BackendName {
+            static NAME: LazyLock<BackendName> = LazyLock::new(|| gen_name!(base@test));
+            &NAME
+        }
+
+        fn plan(&self, planner: &mut Planner<Unfrozen>, project: &Path) -> Result<(), Error> {
+            ListBackend.plan(planner, project)
+        }
+
+        fn finalize(&self, planner: &mut Planner<Unfrozen>) -> Result<(), Error> {
+            let packages: Vec<_> = planner.packages().cloned().collect();
+            planner.register(package(gen_name!(base@test))).wrap()?;
+
+            for mut package in packages {
+                package.dependencies.push(Dependency {
+                    name: gen_name!(base@test),
+                    time: LinkTime::Buildtime,
+                });
+                planner.replace(package).wrap()?;
+            }
+
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_plan_descriptor() {
+        let temp = tempfile::tempdir().unwrap();
+        let project = temp.path();
+        for (directory, packages) in [("first", "a@test\nb@test"), ("second", "c@test")] {
+            std::fs::create_dir(project.join(directory)).unwrap();
+            std::fs::write(project.join(directory).join("packages.txt"), packages).unwrap();
+        }
+        std::fs::write(
+            project.join(ProjectDescriptor::FILE),
+            r#"{ "projects": [
+                { "backend": "list@test", "path": "first" },
+                { "backend": "list@test", "path": "second" }
+            ] }"#,
+        )
+        .unwrap();
+
+        let mut registry = BackendRegistry::new();
+        registry.register(ListBackend);
+
+        let mut planner = Planner::<Unfrozen>::new();
+        registry.plan(&mut planner, project).await.unwrap();
+        for name in ["a@test", "b@test", "c@test"] {
+            let name: PackageName = name.parse().unwrap();
+            assert!(planner.resolve(&name).is_some());
+        }
+
+        // the same package from two sub-projects conflicts
+        std::fs::write(project.join("second/packages.txt"), "a@test").unwrap();
+        let mut planner = Planner::<Unfrozen>::new();
+        assert!(registry.plan(&mut planner, project).await.is_err());
+
+        // unregistered backend
+        let registry = BackendRegistry::new();
+        let mut planner = Planner::<Unfrozen>::new();
+        assert!(registry.plan(&mut planner, project).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_finalize() {
+        let temp = tempfile::tempdir().unwrap();
+        let project = temp.path();
+        std::fs::create_dir(project.join("first")).unwrap();
+        std::fs::write(project.join("first/packages.txt"), "a@test\nb@test").unwrap();
+        std::fs::create_dir(project.join("second")).unwrap();
+        std::fs::write(project.join("second/packages.txt"), "c@test").unwrap();
+        std::fs::write(
+            project.join(ProjectDescriptor::FILE),
+            r#"{ "projects": [
+                { "backend": "base@test", "path": "first" },
+                { "backend": "base@test", "path": "second" }
+            ] }"#,
+        )
+        .unwrap();
+
+        let mut registry = BackendRegistry::new();
+        registry.register(BaseBackend);
+
+        // finalizing twice would register `base@test` twice and conflict
+        let mut planner = Planner::<Unfrozen>::new();
+        registry.plan(&mut planner, project).await.unwrap();
+        let planner = planner.freeze().unwrap();
+
+        let base = planner.resolve(&gen_name!(base@test)).unwrap();
+        for name in ["a@test", "b@test", "c@test"] {
+            let node = planner.resolve(&name.parse().unwrap()).unwrap();
+            assert!(planner.graph().contains_edge(node, base), "{name}");
+        }
+    }
+}
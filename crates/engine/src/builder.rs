@@ -1,17 +1,26 @@
-use std::{fs::create_dir, path::PathBuf, sync::Arc};
+use std::{
+    fs::{File, create_dir, remove_dir_all},
+    io::{BufWriter, Write},
+    num::NonZeroUsize,
+    path::PathBuf,
+    sync::Arc,
+};
 
+use bytes::BytesMut;
 use futures_util::FutureExt;
-use futures_util::future::BoxFuture;
+use futures_util::{StreamExt, future::BoxFuture, stream::FuturesUnordered};
 use petgraph::graph::NodeIndex;
+use rapidhash::RapidHashMap;
 use serde::Deserialize;
-use xh_archive::{Event, packing::Packer};
+use xh_archive::{Event, encoding::Encoder, packing::Packer};
 use xh_reports::prelude::*;
 
 use crate::{
     executor::Executor,
     name::ExecutorName,
     package::DispatchRequest,
-    planner::{Frozen, Planner},
+    planner::{Frozen, PackageId, Planner},
+    store::{Store, StoreArtifact},
 };
 
 #[derive(Debug, IntoReport)]
@@ -22,11 +31,20 @@ pub struct UnregisteredExecutorError {
     pub name: ExecutorName,
 }
 
+#[derive(Debug, IntoReport)]
+#[message("request ordered after a request that doesn't precede it")]
+#[suggestion("only order requests after earlier requests")]
+#[context(request, after)]
+pub struct RequestOrderError {
+    pub request: usize,
+    pub after: usize,
+}
+
 #[derive(Default, Debug, IntoReport)]
 #[message("could not initialize executor")]
 pub struct InitializationError;
 
-#[derive(Default, Debug, IntoReport)]
+#[derive(Default, Debug, Clone, IntoReport)]
 #[message("could not build package")]
 pub struct Error;
 
@@ -36,6 +54,8 @@ pub type BuildId = blake3::Hash;
 pub struct BuildRequest {
     pub id: BuildId,
     pub target: NodeIndex,
+    /// Identity of the target, keying its build environment.
+    pub package: PackageId,
 }
 
 #[derive(Debug, Clone)]
@@ -46,6 +66,22 @@ pub struct InitializeContext {
 #[derive(Clone)]
 pub struct ExecutorPair<E>(E);
 
+/// Length of an [`ExecutorPair`] chain.
+///
+/// Each registered executor is indexed by the length of the chain it was pushed onto,
+/// so indices stay stable as more executors are registered.
+pub trait Chain {
+    const LEN: usize;
+}
+
+impl Chain for ExecutorPair<()> {
+    const LEN: usize = 0;
+}
+
+impl<H, T: Chain> Chain for ExecutorPair<(H, T)> {
+    const LEN: usize = T::LEN + 1;
+}
+
 pub trait Initialize
 where
     Self: Sized,
@@ -82,34 +118,41 @@ where
 type DispatchResult<'a> = Option<BoxFuture<'a, Result<(), Error>>>;
 
 pub trait Dispatch {
-    fn dispatch(&mut self, request: &DispatchRequest) -> DispatchResult<'_>;
+    /// Dispatches `request` to the executor at `index`, as assigned by [`Chain::LEN`].
+    fn dispatch(&mut self, index: usize, request: &DispatchRequest) -> DispatchResult<'_>;
 }
 
 impl Dispatch for ExecutorPair<()> {
-    fn dispatch(&mut self, _request: &DispatchRequest) -> DispatchResult<'_> {
+    fn dispatch(&mut self, _index: usize, _request: &DispatchRequest) -> DispatchResult<'_> {
         None
     }
 }
 
 impl<E, T> Dispatch for ExecutorPair<(E, T)>
 where
-    T: Dispatch + Send,
+    T: Dispatch + Chain + Send,
     E: Executor,
     E::Request: Send,
 {
-    fn dispatch(&mut self, request: &DispatchRequest) -> DispatchResult<'_> {
-        if *E::name() == request.executor {
+    fn dispatch(&mut self, index: usize, request: &DispatchRequest) -> DispatchResult<'_> {
+        if index == T::LEN {
             let payload = E::Request::deserialize(request.payload.clone());
             Some(async { self.0.0.execute(payload.wrap()?).await.wrap() }.boxed())
         } else {
-            self.0.1.dispatch(request)
+            self.0.1.dispatch(index, request)
         }
     }
 }
 
+/// Default for [`Builder::with_concurrency`].
+pub const DEFAULT_CONCURRENCY: NonZeroUsize = NonZeroUsize::new(4).unwrap();
+
 pub struct Builder<T> {
     pub root: PathBuf,
     pub executors: T,
+    indices: RapidHashMap<ExecutorName, usize>,
+    concurrency: NonZeroUsize,
+    snapshot_failures: bool,
 }
 
 impl Builder<ExecutorPair<()>> {
@@ -118,32 +161,118 @@ impl Builder<ExecutorPair<()>> {
         Self {
             root,
             executors: ExecutorPair(()),
+            indices: RapidHashMap::default(),
+            concurrency: DEFAULT_CONCURRENCY,
+            snapshot_failures: false,
         }
     }
 }
 
 impl<T> Builder<T>
 where
-    T: Initialize,
+    T: Initialize + Chain,
     T::Output: Dispatch,
 {
-    pub fn register<E, F>(self, init: F) -> Builder<ExecutorPair<(F, T)>>
+    pub fn register<E, F>(mut self, init: F) -> Builder<ExecutorPair<(F, T)>>
     where
         E: Executor,
         F: Fn(Arc<InitializeContext>) -> Result<E, InitializationError>,
     {
+        // later registrations shadow earlier ones with the same name
+        self.indices.insert(E::name().clone(), T::LEN);
+
         Builder {
             root: self.root,
             executors: ExecutorPair((init, self.executors)),
+            indices: self.indices,
+            concurrency: self.concurrency,
+            snapshot_failures: self.snapshot_failures,
         }
     }
+}
+
+impl<T> Builder<T>
+where
+    T: Initialize,
+    T::Output: Dispatch,
+{
+    /// Maximum amount of a package's requests running at once.
+    ///
+    /// Each running request gets its own initialized executors.
+    /// Defaults to [`DEFAULT_CONCURRENCY`].
+    #[inline]
+    pub fn with_concurrency(mut self, concurrency: NonZeroUsize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Packs the environment of every failed build into an archive next to it,
+    /// so it outlives the package's next build. Its path is added to the failure's report.
+    ///
+    /// Snapshots are xuehua archives rather than tarballs, and can be restored with `xh archive unpack`.
+    ///
+    /// Disabled by default.
+    #[inline]
+    pub fn with_failure_snapshots(mut self, enabled: bool) -> Self {
+        self.snapshot_failures = enabled;
+        self
+    }
+
+    /// Names of the registered executors, in registration order.
+    ///
+    /// Executors shadowed by a later registration are listed once, at their latest position.
+    pub fn executor_names(&self) -> Vec<&ExecutorName> {
+        let mut names: Vec<_> = self.indices.iter().collect();
+        names.sort_unstable_by_key(|(_, index)| **index);
+        names.into_iter().map(|(name, _)| name).collect()
+    }
 
-    fn environment_path(&self, id: &BuildId) -> PathBuf {
-        self.root.join(id.to_string())
+    #[inline]
+    fn index_of(&self, name: &ExecutorName) -> Option<usize> {
+        self.indices.get(name).copied()
     }
 
-    pub fn fetch(&self, build: &BuildId) -> Result<Option<Vec<Event>>, Error> {
-        let output = self.environment_path(build).join("output");
+    /// Environments are keyed by package identity,
+    /// so rebuilding an unchanged package reuses the same location.
+    fn environment_path(&self, package: &PackageId) -> PathBuf {
+        self.root.join(package.to_string())
+    }
+
+    /// Holds an exclusive lock on `<package>.lock`, next to the environment,
+    /// so concurrent builds of the same package don't clear each other's environment.
+    async fn lock(&self, package: &PackageId) -> Result<File, Error> {
+        let file = File::create(self.root.join(format!("{package}.lock"))).wrap()?;
+        tokio::task::spawn_blocking(move || file.lock().map(|()| file).erased())
+            .await
+            .erased()
+            .flatten()
+            .wrap()
+    }
+
+    /// Packs a failed build's environment into `<package>.failed`, next to the environment.
+    async fn snapshot(&self, package: &PackageId) -> Result<PathBuf, Error> {
+        let path = self.root.join(format!("{package}.failed"));
+        let environment = self.environment_path(package);
+        tokio::task::spawn_blocking(move || {
+            let mut file = BufWriter::new(File::create(&path).wrap()?);
+            let mut encoder = Encoder::new();
+            let mut buffer = BytesMut::new();
+            for event in Packer::new(environment).pack_iter() {
+                buffer.clear();
+                encoder.encode(&mut buffer, event.wrap()?).wrap()?;
+                file.write_all(&buffer).wrap()?;
+            }
+
+            file.flush().wrap()?;
+            Ok(path)
+        })
+        .await
+        .wrap()
+        .flatten()
+    }
+
+    pub fn fetch(&self, package: &PackageId) -> Result<Option<Vec<Event>>, Error> {
+        let output = self.environment_path(package).join("output");
         if !std::fs::exists(&output).wrap()? {
             return Ok(None);
         }
@@ -156,13 +285,44 @@ where
         Ok(Some(archive))
     }
 
+    /// Packs a build's output straight into `store`, without collecting it in memory first.
+    pub async fn fetch_into_store<S: Store>(
+        &self,
+        package: &PackageId,
+        store: &mut S,
+    ) -> Result<Option<StoreArtifact>, Error> {
+        let output = self.environment_path(package).join("output");
+        if !std::fs::exists(&output).wrap()? {
+            return Ok(None);
+        }
+
+        let archive = unsafe { Packer::new(output).into_pack_mmap_iter() };
+        let artifact = store
+            .register_artifact_iter(archive.map(|event| event.wrap()))
+            .await
+            .wrap()?;
+
+        Ok(Some(artifact))
+    }
+
+    /// Runs every request of the targeted package in a fresh environment.
+    ///
+    /// Failure reports carry the environment's path, and how many requests finished,
+    /// along with the path of its snapshot if enabled with [`Self::with_failure_snapshots`].
     #[tracing::instrument(level = "debug", skip(self, planner))]
     pub async fn build(
         &self,
         planner: &Planner<Frozen>,
         request: BuildRequest,
     ) -> Result<(), Error> {
-        let environment = self.environment_path(&request.id);
+        let environment = self.environment_path(&request.package);
+        // released once the build, and its snapshot, finish
+        let _lock = self.lock(&request.package).await?;
+
+        // start from a clean environment, even if this package was built before
+        if std::fs::exists(&environment).wrap()? {
+            remove_dir_all(&environment).wrap()?;
+        }
 
         create_dir(&environment)
             .and_then(|()| create_dir(environment.join("output")))
@@ -171,23 +331,537 @@ where
         // TODO: link closure
         // planner.closure(request.target);
 
-        let mut executors = self
-            .executors
-            .initialize(InitializeContext { environment }.into())
-            .wrap()?;
+        let requests = &planner.graph()[request.target].requests;
+        let mut finished = vec![false; requests.len()];
+        let ctx = Arc::new(InitializeContext {
+            environment: environment.clone(),
+        });
+
+        let Err(report) = self.run(requests, ctx, &mut finished).await else {
+            return Ok(());
+        };
+
+        let done = finished.iter().filter(|finished| **finished).count();
+        let report = report.with_frames([
+            Frame::context("environment", environment.display()),
+            Frame::context(
+                "finished",
+                format_args!("{done} of {} requests", requests.len()),
+            ),
+        ]);
+        if !self.snapshot_failures {
+            return Err(report);
+        }
+
+        match self.snapshot(&request.package).await {
+            Ok(path) => Err(report.with_frame(Frame::context("snapshot", path.display()))),
+            Err(snapshot) => {
+                tracing::warn!(
+                    error = &snapshot.into_error() as &dyn StdError,
+                    "could not snapshot failed build"
+                );
+                Err(report)
+            }
+        }
+    }
 
-        for request in &planner.graph()[request.target].requests {
-            executors
-                .dispatch(request)
-                .ok_or_else(|| {
+    /// Dispatches `requests` in dependency order, marking each one as it finishes.
+    async fn run(
+        &self,
+        requests: &[DispatchRequest],
+        ctx: Arc<InitializeContext>,
+        finished: &mut [bool],
+    ) -> Result<(), Error> {
+        let dependencies = |index: usize| match &requests[index].after {
+            Some(after) => after.clone(),
+            None => index.checked_sub(1).into_iter().collect(),
+        };
+        for index in 0..requests.len() {
+            if let Some(after) = dependencies(index)
+                .into_iter()
+                .find(|after| *after >= index)
+            {
+                return Err(RequestOrderError {
+                    request: index,
+                    after,
+                }
+                .wrap());
+            }
+        }
+
+        // executors are reused once their request finishes,
+        // so strictly ordered requests keep sharing a single instance
+        let mut idle = Vec::new();
+        let mut started = vec![false; requests.len()];
+        let mut running = FuturesUnordered::new();
+        let result = 'dispatch: loop {
+            for (index, request) in requests.iter().enumerate() {
+                let ready =
+                    !started[index] && dependencies(index).into_iter().all(|after| finished[after]);
+                if !ready || running.len() >= self.concurrency.get() {
+                    continue;
+                }
+
+                let unregistered = || {
                     UnregisteredExecutorError {
                         name: request.executor.clone(),
                     }
                     .wrap()
-                })?
-                .await?;
+                };
+                let Some(executor) = self.index_of(&request.executor) else {
+                    break 'dispatch Err(unregistered());
+                };
+                let mut executors = match idle.pop() {
+                    Some(executors) => executors,
+                    None => match self.executors.initialize(ctx.clone()) {
+                        Ok(executors) => executors,
+                        Err(report) => break 'dispatch Err(report.wrap()),
+                    },
+                };
+
+                started[index] = true;
+                running.push(async move {
+                    let result = match executors.dispatch(executor, request) {
+                        Some(future) => future.await,
+                        None => Err(unregistered()),
+                    };
+
+                    (index, executors, result)
+                });
+            }
+
+            let Some((index, executors, result)) = running.next().await else {
+                break Ok(());
+            };
+
+            if let Err(report) = result {
+                break Err(report.with_frame(Frame::context("request", index)));
+            }
+            finished[index] = true;
+            idle.push(executors);
+        };
+
+        // let requests that already started finish, instead of dropping them midway
+        while let Some((index, _, result)) = running.next().await {
+            finished[index] = result.is_ok();
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        num::NonZeroUsize,
+        path::PathBuf,
+        sync::{
+            Arc, LazyLock, Mutex,
+            atomic::{AtomicUsize, Ordering},
+        },
+    };
+
+    use xh_archive::{Event, decoding::Decoder};
+    use xh_reports::prelude::*;
+
+    use crate::{
+        builder::{
+            BuildRequest, Builder, Dispatch, InitializationError, Initialize, InitializeContext,
+        },
+        encoding::Value,
+        executor::{Error, Executor},
+        gen_name,
+        name::ExecutorName,
+        package::{DispatchRequest, Metadata, Package},
+        planner::{Planner, Unfrozen},
+    };
+
+    static NAMES: LazyLock<[ExecutorName; 4]> = LazyLock::new(|| {
+        [
+            gen_name!(zero@tests),
+            gen_name!(one@tests),
+            gen_name!(two@tests),
+            gen_name!(unregistered@tests),
+        ]
+    });
+
+    type Log = Arc<Mutex<Vec<&'static str>>>;
+
+    struct Recorder<const N: usize> {
+        tag: &'static str,
+        log: Log,
+    }
+
+    impl<const N: usize> Executor for Recorder<N> {
+        type Request = ();
+
+        fn name() -> &'static ExecutorName {
+            &NAMES[N]
+        }
+
+        async fn execute(&mut self, _request: Self::Request) -> Result<(), Error> {
+            self.log.lock().unwrap().push(self.tag);
+            Ok(())
+        }
+    }
+
+    fn recorder<const N: usize>(
+        tag: &'static str,
+        log: &Log,
+    ) -> impl Fn(Arc<InitializeContext>) -> Result<Recorder<N>, InitializationError> {
+        let log = log.clone();
+        move |_| {
+            Ok(Recorder {
+                tag,
+                log: log.clone(),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn bench_dispatch_equivalence() {
+        let log = Log::default();
+
+        // registration order, with `zero` registered twice to exercise shadowing
+        let registered = [(0, "zero"), (1, "one"), (2, "two"), (0, "zero-shadow")];
+        let builder = Builder::new(Default::default())
+            .register(recorder::<0>("zero", &log))
+            .register(recorder::<1>("one", &log))
+            .register(recorder::<2>("two", &log))
+            .register(recorder::<0>("zero-shadow", &log));
+
+        let ctx = Arc::new(InitializeContext {
+            environment: Default::default(),
+        });
+        let mut executors = builder.executors.initialize(ctx).unwrap();
+
+        let mut expected = Vec::new();
+        for i in 0..4096 {
+            let n = i % NAMES.len();
+            let request = DispatchRequest {
+                executor: NAMES[n].clone(),
+                payload: Value::Null,
+                after: None,
+            };
+
+            // previous behavior: walk the chain from the newest registration, comparing names
+            let reference = registered.iter().rev().find(|(m, _)| *m == n);
+            let dispatched = builder
+                .index_of(&request.executor)
+                .and_then(|index| executors.dispatch(index, &request));
+
+            match (reference, dispatched) {
+                (Some((_, tag)), Some(future)) => {
+                    future.await.unwrap();
+                    expected.push(*tag);
+                }
+                (None, None) => (),
+                (reference, dispatched) => panic!(
+                    "dispatch diverged for {}: expected {reference:?}, got {:?}",
+                    request.executor,
+                    dispatched.is_some()
+                ),
+            }
+        }
+
+        assert_eq!(*log.lock().unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn test_environment_identity() {
+        struct Environment {
+            path: PathBuf,
+            log: Arc<Mutex<Vec<PathBuf>>>,
         }
 
-        Ok(())
+        impl Executor for Environment {
+            type Request = ();
+
+            fn name() -> &'static ExecutorName {
+                &NAMES[0]
+            }
+
+            async fn execute(&mut self, _request: Self::Request) -> Result<(), Error> {
+                std::fs::write(self.path.join("output/marker"), "").wrap()?;
+                self.log.lock().unwrap().push(self.path.clone());
+                Ok(())
+            }
+        }
+
+        let temp = tempfile::tempdir().unwrap();
+        let log = Arc::default();
+        let builder = Builder::new(temp.path().to_path_buf()).register(|ctx| {
+            Ok(Environment {
+                path: ctx.environment.clone(),
+                log: Arc::clone(&log),
+            })
+        });
+
+        // identical packages, planned separately
+        for _ in 0..2 {
+            let mut planner = Planner::<Unfrozen>::new();
+            let target = planner
+                .register(Package {
+                    name: gen_name!(package@tests),
+                    metadata: Metadata,
+                    tags: Vec::new(),
+                    build_cost: None,
+                    requests: vec![DispatchRequest {
+                        executor: NAMES[0].clone(),
+                        payload: Value::Null,
+                        after: None,
+                    }],
+                    dependencies: Vec::new(),
+                })
+                .unwrap();
+            let planner = planner.freeze().unwrap();
+
+            let request = BuildRequest {
+                id: xh_common::random_hash(),
+                target,
+                package: planner.identity(target).unwrap(),
+            };
+            builder.build(&planner, request).await.unwrap();
+            assert!(builder.fetch(&request.package).unwrap().is_some());
+        }
+
+        let log = log.lock().unwrap();
+        assert_eq!(log.len(), 2);
+        assert_eq!(log[0], log[1]);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_builds() {
+        /// Fails if its environment is cleared while it runs.
+        struct Marker(PathBuf);
+
+        impl Executor for Marker {
+            type Request = ();
+
+            fn name() -> &'static ExecutorName {
+                &NAMES[0]
+            }
+
+            async fn execute(&mut self, _request: Self::Request) -> Result<(), Error> {
+                let marker = self.0.join(format!("output/{}", xh_common::random_hash()));
+                std::fs::write(&marker, "").wrap()?;
+                for _ in 0..8 {
+                    tokio::task::yield_now().await;
+                }
+
+                std::fs::metadata(&marker).wrap()?;
+                Ok(())
+            }
+        }
+
+        let mut planner = Planner::<Unfrozen>::new();
+        let target = planner
+            .register(Package {
+                name: gen_name!(package@tests),
+                metadata: Metadata,
+                tags: Vec::new(),
+                build_cost: None,
+                requests: vec![DispatchRequest {
+                    executor: NAMES[0].clone(),
+                    payload: Value::Null,
+                    after: None,
+                }],
+                dependencies: Vec::new(),
+            })
+            .unwrap();
+        let planner = planner.freeze().unwrap();
+
+        let temp = tempfile::tempdir().unwrap();
+        let builder = Builder::new(temp.path().to_path_buf())
+            .register(|ctx| Ok(Marker(ctx.environment.clone())));
+        let request = || BuildRequest {
+            id: xh_common::random_hash(),
+            target,
+            package: planner.identity(target).unwrap(),
+        };
+
+        // both builds share an environment, so the second waits for the first
+        let (first, second) = tokio::join!(
+            builder.build(&planner, request()),
+            builder.build(&planner, request())
+        );
+        first.unwrap();
+        second.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_requests() {
+        #[derive(Default)]
+        struct Tracker {
+            active: AtomicUsize,
+            peak: AtomicUsize,
+            log: Mutex<Vec<String>>,
+        }
+
+        struct Overlap(Arc<Tracker>);
+
+        impl Executor for Overlap {
+            type Request = String;
+
+            fn name() -> &'static ExecutorName {
+                &NAMES[0]
+            }
+
+            async fn execute(&mut self, request: Self::Request) -> Result<(), Error> {
+                let active = self.0.active.fetch_add(1, Ordering::SeqCst) + 1;
+                self.0.peak.fetch_max(active, Ordering::SeqCst);
+                // failures happen right away, while other requests are still running
+                let failed = request == "fail";
+                let yields = if failed { 0 } else { 8 };
+                for _ in 0..yields {
+                    tokio::task::yield_now().await;
+                }
+
+                self.0.log.lock().unwrap().push(request);
+                self.0.active.fetch_sub(1, Ordering::SeqCst);
+                if failed {
+                    return Err(Error.into_report());
+                }
+
+                Ok(())
+            }
+        }
+
+        let request = |tag: &str, after: Option<Vec<usize>>| DispatchRequest {
+            executor: NAMES[0].clone(),
+            payload: Value::String(tag.into()),
+            after,
+        };
+
+        let build = async |requests, concurrency| {
+            let mut planner = Planner::<Unfrozen>::new();
+            let target = planner
+                .register(Package {
+                    name: gen_name!(package@tests),
+                    metadata: Metadata,
+                    tags: Vec::new(),
+                    build_cost: None,
+                    requests,
+                    dependencies: Vec::new(),
+                })
+                .unwrap();
+            let planner = planner.freeze().unwrap();
+
+            let temp = tempfile::tempdir().unwrap();
+            let tracker = Arc::new(Tracker::default());
+            let builder = Builder::new(temp.path().to_path_buf())
+                .with_concurrency(NonZeroUsize::new(concurrency).unwrap())
+                .register(|_| Ok(Overlap(tracker.clone())));
+
+            let request = BuildRequest {
+                id: xh_common::random_hash(),
+                target,
+                package: planner.identity(target).unwrap(),
+            };
+            let result = builder.build(&planner, request).await;
+            (result, Arc::into_inner(tracker).unwrap())
+        };
+
+        // two independent downloads, then extracting both
+        let requests = vec![
+            request("first", Some(vec![])),
+            request("second", Some(vec![])),
+            request("extract", Some(vec![0, 1])),
+            request("cleanup", None),
+        ];
+
+        let (result, tracker) = build(requests.clone(), 2).await;
+        result.unwrap();
+        assert_eq!(tracker.peak.into_inner(), 2);
+        let log = tracker.log.into_inner().unwrap();
+        assert_eq!(log[2..], ["extract", "cleanup"]);
+
+        let (result, tracker) = build(requests, 1).await;
+        result.unwrap();
+        assert_eq!(tracker.peak.into_inner(), 1);
+
+        // ordered after a later request
+        let requests = vec![request("first", Some(vec![1])), request("second", None)];
+        assert!(build(requests, 2).await.0.is_err());
+
+        // requests already running finish when another one fails
+        let requests = vec![
+            request("fail", Some(vec![])),
+            request("running", Some(vec![])),
+            request("never", None),
+        ];
+        let (result, tracker) = build(requests, 2).await;
+        assert!(result.is_err());
+        assert_eq!(tracker.active.into_inner(), 0);
+        assert_eq!(tracker.log.into_inner().unwrap(), ["fail", "running"]);
+    }
+
+    #[tokio::test]
+    async fn test_failure_report() {
+        /// Leaves a file behind, failing if requested to.
+        struct Failing(PathBuf);
+
+        impl Executor for Failing {
+            type Request = bool;
+
+            fn name() -> &'static ExecutorName {
+                &NAMES[0]
+            }
+
+            async fn execute(&mut self, fail: Self::Request) -> Result<(), Error> {
+                std::fs::write(self.0.join("log"), "failed").wrap()?;
+                if fail {
+                    Err(Error.into_report())
+                } else {
+                    Ok(())
+                }
+            }
+        }
+
+        let mut planner = Planner::<Unfrozen>::new();
+        let target = planner
+            .register(Package {
+                name: gen_name!(package@tests),
+                metadata: Metadata,
+                tags: Vec::new(),
+                build_cost: None,
+                requests: [false, true, false]
+                    .map(|fail| DispatchRequest {
+                        executor: NAMES[0].clone(),
+                        payload: Value::Bool(fail),
+                        after: None,
+                    })
+                    .into(),
+                dependencies: Vec::new(),
+            })
+            .unwrap();
+        let planner = planner.freeze().unwrap();
+
+        let temp = tempfile::tempdir().unwrap();
+        let builder = Builder::new(temp.path().to_path_buf())
+            .with_failure_snapshots(true)
+            .register(|ctx| Ok(Failing(ctx.environment.clone())));
+        let request = BuildRequest {
+            id: xh_common::random_hash(),
+            target,
+            package: planner.identity(target).unwrap(),
+        };
+
+        let report = builder.build(&planner, request).await.unwrap_err();
+        let environment = builder.environment_path(&request.package);
+        let context = |key| report.context_value(key).unwrap();
+        assert_eq!(context("environment"), environment.display().to_string());
+        assert_eq!(context("finished"), "1 of 3 requests");
+        assert_eq!(context("request"), "1");
+
+        let snapshot = std::fs::read(context("snapshot")).unwrap();
+        let events = Decoder::new()
+            .decode_iter(&mut snapshot.into())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert!(events.iter().any(|event| matches!(
+            event,
+            Event::Object(object) if object.location.as_ref() == std::path::Path::new("log")
+        )));
     }
 }
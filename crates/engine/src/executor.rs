@@ -1,3 +1,5 @@
+pub mod timeout;
+
 use xh_reports::prelude::*;
 
 use crate::name::ExecutorName;
@@ -0,0 +1,106 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+use xh_reports::prelude::*;
+
+use crate::{
+    executor::{Error, Executor},
+    name::ExecutorName,
+};
+
+#[derive(Debug, IntoReport)]
+#[message("executor request timed out")]
+#[suggestion("increase the executor's timeout")]
+#[context(timeout)]
+pub struct TimeoutError {
+    pub timeout: Duration,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Options {
+    pub timeout: Duration,
+}
+
+/// Wraps an [`Executor`], failing any request that takes longer than [`Options::timeout`].
+#[derive(Debug)]
+pub struct TimeoutExecutor<E> {
+    inner: E,
+    options: Options,
+}
+
+impl<E: Executor> TimeoutExecutor<E> {
+    #[inline]
+    pub fn new(inner: E, options: Options) -> Self {
+        Self { inner, options }
+    }
+}
+
+impl<E: Executor> Executor for TimeoutExecutor<E> {
+    type Request = E::Request;
+
+    fn name() -> &'static ExecutorName {
+        E::name()
+    }
+
+    fn execute(
+        &mut self,
+        request: Self::Request,
+    ) -> impl Future<Output = Result<(), Error>> + Send {
+        let timeout = self.options.timeout;
+        let execution = self.inner.execute(request);
+
+        async move {
+            tokio::time::timeout(timeout, execution)
+                .await
+                .map_err(|_| TimeoutError { timeout }.wrap())?
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::LazyLock, time::Duration};
+
+    use xh_reports::prelude::*;
+
+    use crate::{
+        executor::{
+            Error, Executor,
+            timeout::{Options, TimeoutExecutor},
+        },
+        gen_name,
+        name::ExecutorName,
+    };
+
+    static NAME: LazyLock<ExecutorName> = LazyLock::new(|| gen_name!(sleep@tests));
+
+    /// Sleeps for the requested amount of milliseconds.
+    struct Sleeper;
+
+    impl Executor for Sleeper {
+        type Request = u64;
+
+        fn name() -> &'static ExecutorName {
+            &NAME
+        }
+
+        async fn execute(&mut self, request: Self::Request) -> Result<(), Error> {
+            tokio::time::sleep(Duration::from_millis(request)).await;
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let mut executor = TimeoutExecutor::new(
+            Sleeper,
+            Options {
+                timeout: Duration::from_millis(50),
+            },
+        );
+
+        assert_eq!(TimeoutExecutor::<Sleeper>::name(), &*NAME);
+        assert!(executor.execute(0).await.is_ok());
+        assert!(executor.execute(10_000).await.is_err());
+    }
+}
@@ -1,5 +1,11 @@
-use std::{fmt, str::FromStr, sync::Arc};
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    str::FromStr,
+    sync::Arc,
+};
 
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de, ser::SerializeStruct};
 use smol_str::SmolStr;
 use xh_reports::prelude::*;
 
@@ -11,6 +17,10 @@ pub struct ParseError;
 pub struct Name<T: NameType> {
     pub identifier: SmolStr,
     pub namespace: Arc<[SmolStr]>,
+    /// What the name refers to, see [`NameKind`].
+    ///
+    /// The kind takes part in equality, hashing, and [`Display`](fmt::Display),
+    /// so names of different kinds never collide, even with the same identifier and namespace.
     pub ty: T,
 }
 
@@ -24,6 +34,11 @@ impl<T: NameType> Name<T> {
         }
     }
 
+    #[inline]
+    pub fn kind(&self) -> NameKind {
+        T::KIND
+    }
+
     pub fn with_type<U: NameType>(self, ty: U) -> Name<U> {
         Name {
             identifier: self.identifier,
@@ -63,6 +78,52 @@ impl<T: NameType> FromStr for Name<T> {
     }
 }
 
+impl<T: NameType> Serialize for Name<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> StdResult<S::Ok, S::Error> {
+        let namespace: Vec<_> = self.namespace.iter().map(SmolStr::as_str).collect();
+
+        let mut state = serializer.serialize_struct("Name", 2)?;
+        state.serialize_field("identifier", self.identifier.as_str())?;
+        state.serialize_field("namespace", &namespace)?;
+        state.end()
+    }
+}
+
+/// Accepts either the [`FromStr`] shorthand, or the fields [`Name`] serializes as
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NameRepr {
+    Shorthand(String),
+    Fields {
+        identifier: String,
+        #[serde(default)]
+        namespace: Vec<String>,
+    },
+}
+
+impl<'de, T: NameType> Deserialize<'de> for Name<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> StdResult<Self, D::Error> {
+        match NameRepr::deserialize(deserializer)? {
+            NameRepr::Shorthand(name) => name.parse().map_err(|report| {
+                // serde errors are plain messages, so the whole tree is rendered into one
+                let report = Report::new(format!("could not parse name {name:?}"))
+                    .with_child::<ParseError>(report);
+                de::Error::custom(format_args!("{report:?}"))
+            }),
+            NameRepr::Fields {
+                identifier,
+                namespace,
+            } => Ok(Self::new(
+                identifier,
+                namespace.into_iter().map(Into::into).collect::<Arc<_>>(),
+            )),
+        }
+    }
+}
+
+/// Constructs a [`Name`] from an `identifier@namespace/path` literal.
+///
+/// The [`NameKind`] is taken from the name type the expansion is inferred as.
 #[macro_export]
 macro_rules! gen_name {
     ($ident:ident @ $($namespace:ident) / *) => {
@@ -74,54 +135,172 @@ macro_rules! gen_name {
     };
 }
 
-pub trait NameType: Default + fmt::Display + FromStr<Err = ParseError> {}
+/// Discriminates what a [`Name`] refers to.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum NameKind {
+    Package,
+    Executor,
+    Backend,
+    Store,
+}
+
+impl NameKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NameKind::Package => "package",
+            NameKind::Executor => "executor",
+            NameKind::Backend => "backend",
+            NameKind::Store => "store",
+        }
+    }
+}
+
+impl FromStr for NameKind {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> StdResult<Self, Self::Err> {
+        [
+            NameKind::Package,
+            NameKind::Executor,
+            NameKind::Backend,
+            NameKind::Store,
+        ]
+        .into_iter()
+        .find(|kind| kind.as_str() == s)
+        .ok_or(ParseError)
+    }
+}
+
+impl fmt::Display for NameKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Type-level [`NameKind`], so names of different kinds are also different types.
+pub trait NameType: Default + fmt::Display + FromStr<Err = ParseError> {
+    const KIND: NameKind;
+}
 
 macro_rules! impl_name_type {
-    ($(($name:ident, $alias:ident, $type:expr)),*) => {$(
-        #[derive(Default, Debug, Clone, Hash, PartialEq, Eq)]
+    ($(($name:ident, $alias:ident)),*) => {$(
+        #[derive(Default, Debug, Clone, PartialEq, Eq)]
         pub struct $name;
 
         pub type $alias = Name<$crate::name::$name>;
 
+        impl Hash for $name {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                Self::KIND.hash(state);
+            }
+        }
+
         impl FromStr for $name {
             type Err = ParseError;
 
             fn from_str(s: &str) -> StdResult<Self, Self::Err> {
-               if s == $type {
-                   Ok(Self)
-               } else {
-                   Err(ParseError)
-               }
+                match s.parse()? {
+                    NameKind::$name => Ok(Self),
+                    _ => Err(ParseError),
+                }
             }
         }
 
         impl fmt::Display for $name {
             fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-                f.write_str($type)
+                Self::KIND.fmt(f)
             }
         }
 
-        impl NameType for $name {}
+        impl NameType for $name {
+            const KIND: NameKind = NameKind::$name;
+        }
     )*};
 }
 
-impl_name_type!((Package, PackageName, "package"));
-impl_name_type!((Executor, ExecutorName, "executor"));
-impl_name_type!((Backend, BackendName, "backend"));
-impl_name_type!((Store, StoreName, "store"));
+impl_name_type!((Package, PackageName));
+impl_name_type!((Executor, ExecutorName));
+impl_name_type!((Backend, BackendName));
+impl_name_type!((Store, StoreName));
 
 impl<T: NameType> fmt::Display for Name<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.namespace.is_empty() {
-            self.identifier.fmt(f)
-        } else {
-            write!(
-                f,
-                "{}@{}({})",
-                self.identifier,
-                self.namespace.join("/"),
-                T::default()
-            )
+        self.identifier.fmt(f)?;
+        if !self.namespace.is_empty() {
+            write!(f, "@{}", self.namespace.join("/"))?;
         }
+
+        write!(f, "({})", T::KIND)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::{DefaultHasher, Hash, Hasher};
+
+    use serde_json::json;
+
+    use crate::name::{ExecutorName, NameKind, PackageName};
+
+    #[test]
+    fn test_serde_fields() {
+        let name: PackageName = gen_name!(curl@xuehua/arch);
+        let value = serde_json::to_value(&name).unwrap();
+        assert_eq!(
+            value,
+            json!({ "identifier": "curl", "namespace": ["xuehua", "arch"] })
+        );
+
+        assert_eq!(serde_json::from_value::<PackageName>(value).unwrap(), name);
+        assert_eq!(
+            serde_json::from_value::<PackageName>(json!({ "identifier": "curl" })).unwrap(),
+            PackageName::new("curl", [])
+        );
+    }
+
+    #[test]
+    fn test_serde_shorthand() {
+        let name: PackageName = gen_name!(curl@xuehua/arch);
+        for shorthand in ["curl@xuehua/arch", "curl@xuehua/arch(package)"] {
+            let parsed: PackageName = serde_json::from_value(json!(shorthand)).unwrap();
+            assert_eq!(parsed, name);
+        }
+
+        // the shorthand agrees with the `Display` grammar
+        let displayed: PackageName = serde_json::from_value(json!(name.to_string())).unwrap();
+        assert_eq!(displayed, name);
+
+        assert!(serde_json::from_value::<PackageName>(json!("curl@xuehua(executor)")).is_err());
+    }
+
+    #[test]
+    fn test_kinds_do_not_collide() {
+        let package: PackageName = gen_name!(curl@xuehua);
+        let executor: ExecutorName = gen_name!(curl@xuehua);
+        assert_eq!(package.kind(), NameKind::Package);
+        assert_eq!(executor.kind(), NameKind::Executor);
+
+        let hash = |value: &dyn Fn(&mut DefaultHasher)| {
+            let mut hasher = DefaultHasher::new();
+            value(&mut hasher);
+            hasher.finish()
+        };
+        assert_ne!(
+            hash(&|hasher| package.hash(hasher)),
+            hash(&|hasher| executor.hash(hasher))
+        );
+        assert_ne!(package.to_string(), executor.to_string());
+
+        // an executor's name never resolves to a package
+        assert!(executor.to_string().parse::<PackageName>().is_err());
+        assert!(package.to_string().parse::<ExecutorName>().is_err());
+        assert_eq!(
+            executor.to_string().parse::<ExecutorName>().unwrap(),
+            executor
+        );
+
+        let unnamespaced: PackageName = "curl".parse().unwrap();
+        assert_eq!(unnamespaced.to_string(), "curl(package)");
+        assert!("curl(package)".parse::<ExecutorName>().is_err());
     }
 }
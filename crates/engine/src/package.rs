@@ -2,35 +2,48 @@ pub mod manifest;
 
 use std::{fmt, result::Result as StdResult, str::FromStr};
 
+use serde::{Deserialize, Serialize};
 use smol_str::SmolStr;
 use xh_reports::prelude::*;
 
-use crate::{encoding::Value, name::{ExecutorName, PackageName}};
+use crate::{
+    encoding::Value,
+    name::{ExecutorName, PackageName},
+};
 
-#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+/// When a dependency needs to be linked.
+///
+/// Spelled `"runtime"` or `"buildtime"`, both by serde and by [`FromStr`]/[`Display`](fmt::Display).
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum LinkTime {
     Runtime,
     Buildtime,
 }
 
+impl LinkTime {
+    pub const ALL: [LinkTime; 2] = [LinkTime::Runtime, LinkTime::Buildtime];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LinkTime::Runtime => "runtime",
+            LinkTime::Buildtime => "buildtime",
+        }
+    }
+}
+
 impl fmt::Display for LinkTime {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{}",
-            match self {
-                LinkTime::Runtime => "runtime",
-                LinkTime::Buildtime => "buildtime",
-            }
-        )
+        f.write_str(self.as_str())
     }
 }
 
 #[derive(Debug, IntoReport)]
-#[message("could not parse link time")]
-#[suggestion("provide \"buildtime\" or \"runtime\"")]
+#[message("invalid link time {found:?}, expected \"runtime\" or \"buildtime\"")]
+#[suggestion("provide \"runtime\" or \"buildtime\"")]
 #[context(found)]
 pub struct LinkTimeParseError {
+    #[format(message)]
     found: SmolStr,
 }
 
@@ -38,33 +51,93 @@ impl FromStr for LinkTime {
     type Err = Report<LinkTimeParseError>;
 
     fn from_str(s: &str) -> StdResult<Self, Self::Err> {
-        match s {
-            "buildtime" => Ok(LinkTime::Buildtime),
-            "runtime" => Ok(LinkTime::Runtime),
-            _ => Err(LinkTimeParseError { found: s.into() }.into_report()),
-        }
+        LinkTime::ALL
+            .into_iter()
+            .find(|time| time.as_str() == s)
+            .ok_or_else(|| LinkTimeParseError { found: s.into() }.into_report())
     }
 }
 
-#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Metadata;
 
-#[derive(Debug, Hash, PartialEq, Eq, Clone)]
+#[derive(Debug, Hash, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct DispatchRequest {
     pub executor: ExecutorName,
     pub payload: Value,
+    /// Indices of earlier requests within the same package that must finish before this one starts.
+    ///
+    /// `None` runs after the previous request, while `Some(vec![])` may start right away.
+    pub after: Option<Vec<usize>>,
 }
 
-#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Dependency {
     pub name: PackageName,
     pub time: LinkTime,
 }
 
-#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Package {
     pub name: PackageName,
     pub metadata: Metadata,
+    /// Free-form labels for selecting groups of packages, see [`Planner::by_tag`](crate::planner::Planner::by_tag).
+    #[serde(default)]
+    pub tags: Vec<SmolStr>,
+    /// Estimated cost of building this package, in seconds,
+    /// which the [`Scheduler`](crate::scheduler::Scheduler) uses to start long builds first.
+    ///
+    /// Not part of the package's identity, so it can be refined from prior builds.
+    #[serde(default)]
+    pub build_cost: Option<u64>,
     pub requests: Vec<DispatchRequest>,
     pub dependencies: Vec<Dependency>,
 }
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use serde_json::json;
+
+    use crate::package::{LinkTime, Package};
+
+    #[test]
+    fn test_link_time_roundtrip() {
+        for time in LinkTime::ALL {
+            let value = serde_json::to_value(time).unwrap();
+            assert_eq!(value, json!(time.to_string()));
+            assert_eq!(serde_json::from_value::<LinkTime>(value).unwrap(), time);
+            assert_eq!(LinkTime::from_str(&time.to_string()).unwrap(), time);
+        }
+
+        assert_eq!(json!(LinkTime::Runtime), json!("runtime"));
+        assert_eq!(json!(LinkTime::Buildtime), json!("buildtime"));
+    }
+
+    #[test]
+    fn test_link_time_invalid() {
+        let report = LinkTime::from_str("Runtime").unwrap_err();
+        let message = report.into_error().to_string();
+        assert!(message.contains("\"Runtime\""), "{message}");
+        assert!(
+            message.contains("\"runtime\" or \"buildtime\""),
+            "{message}"
+        );
+
+        assert!(serde_json::from_value::<LinkTime>(json!("linktime")).is_err());
+    }
+
+    #[test]
+    fn test_untagged_package() {
+        // as saved by plan snapshots from before packages had tags
+        let package: Package = serde_json::from_value(json!({
+            "name": "curl@xuehua",
+            "metadata": null,
+            "requests": [],
+            "dependencies": [],
+        }))
+        .unwrap();
+        assert!(package.tags.is_empty());
+    }
+}
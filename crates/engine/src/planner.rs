@@ -1,4 +1,5 @@
 pub mod config;
+pub mod snapshot;
 
 use std::{
     collections::HashMap,
@@ -15,11 +16,14 @@ use petgraph::{
     visit::{Dfs, EdgeRef},
 };
 use rapidhash::RapidHashSet;
+use serde::Deserialize;
 use smol_str::SmolStr;
 use xh_reports::{partition_results, prelude::*};
 
 use crate::{
-    name::PackageName,
+    encoding::{self, Value},
+    executor::Executor,
+    name::{ExecutorName, PackageName},
     package::{LinkTime, Package},
 };
 
@@ -49,6 +53,33 @@ pub struct UnregisteredDependency {
     dependency: PackageName,
 }
 
+#[derive(Debug, IntoReport)]
+#[message("package has an invalid request payload")]
+#[suggestion("provide a payload {executor} accepts")]
+#[context(display: package, executor)]
+pub struct InvalidPayloadError {
+    package: PackageName,
+    #[format(suggestion)]
+    executor: ExecutorName,
+}
+
+#[derive(Debug, IntoReport)]
+#[message("package is not registered")]
+#[context(display: package)]
+pub struct UnregisteredPackage {
+    package: PackageName,
+}
+
+#[derive(Debug, IntoReport)]
+#[message("package is depended upon")]
+#[suggestion("unregister {dependent} first")]
+#[context(display: package, dependent)]
+pub struct DependedUponError {
+    package: PackageName,
+    #[format(suggestion)]
+    dependent: PackageName,
+}
+
 #[derive(Default, Debug, IntoReport)]
 #[message("could not evaluate plan")]
 pub struct Error;
@@ -67,13 +98,69 @@ impl NamespaceTracker {
         self.0.read().unwrap().clone()
     }
 
+    /// Pushes `segment` onto the namespace until the returned guard is dropped.
+    #[inline]
+    pub fn enter<S: Into<SmolStr>>(&self, segment: S) -> NamespaceGuard<'_> {
+        let mut namespace = self.0.write().unwrap();
+        namespace.push(segment.into());
+
+        NamespaceGuard {
+            tracker: self,
+            depth: namespace.len(),
+            _marker: PhantomData,
+        }
+    }
+
     #[inline]
     pub fn scope<R, S: Into<SmolStr>>(&self, segment: S, func: impl FnOnce() -> R) -> R {
-        let get = || self.0.write().unwrap();
-        get().push(segment.into());
-        let rval = func();
-        get().pop();
-        rval
+        let _guard = self.enter(segment);
+        func()
+    }
+
+    /// Replaces the whole namespace with `namespace` while `func` runs,
+    /// restoring the previous namespace afterwards, even if `func` panics.
+    ///
+    /// Segments entered within `func` are pushed onto `namespace`, and discarded along with it.
+    pub fn with_namespace<R>(&self, namespace: Vec<SmolStr>, func: impl FnOnce() -> R) -> R {
+        let previous = std::mem::replace(&mut *self.0.write().unwrap(), namespace);
+        let _guard = RestoreGuard {
+            tracker: self,
+            previous,
+        };
+
+        func()
+    }
+}
+
+/// Restores the namespace replaced by [`NamespaceTracker::with_namespace`] when dropped.
+struct RestoreGuard<'a> {
+    tracker: &'a NamespaceTracker,
+    previous: Vec<SmolStr>,
+}
+
+impl Drop for RestoreGuard<'_> {
+    fn drop(&mut self) {
+        *self.tracker.0.write().unwrap() = std::mem::take(&mut self.previous);
+    }
+}
+
+/// Guard returned by [`NamespaceTracker::enter`], which pops its segment when dropped.
+///
+/// Guards should be dropped in the reverse order they were entered,
+/// dropping an outer guard also pops every segment entered after it.
+/// Since clones of a tracker share the same namespace, guards are `!Send`,
+/// to keep them on the thread that entered them.
+#[must_use]
+#[derive(Debug)]
+pub struct NamespaceGuard<'a> {
+    tracker: &'a NamespaceTracker,
+    depth: usize,
+    _marker: PhantomData<*const ()>,
+}
+
+impl Drop for NamespaceGuard<'_> {
+    fn drop(&mut self) {
+        self.tracker.0.write().unwrap().truncate(self.depth - 1);
     }
 }
 
@@ -83,9 +170,25 @@ pub struct DependencyClosure {
     buildtime: RapidHashSet<NodeIndex>,
 }
 
+/// Packages that differ between two frozen plans, see [`Planner::diff`].
+#[derive(Default, Debug, Clone)]
+pub struct PlanDiff {
+    pub added: RapidHashSet<PackageName>,
+    pub removed: RapidHashSet<PackageName>,
+    /// Packages whose identity changed, either through their own requests or their closure.
+    pub changed: RapidHashSet<PackageName>,
+}
+
 pub type Plan = Acyclic<DiGraph<Package, LinkTime>>;
 pub type PackageId = blake3::Hash;
 
+/// Checks that a request payload deserializes into an executor's request type
+type Validator = fn(&Value) -> StdResult<(), encoding::Error>;
+
+fn validate_payload<E: Executor>(payload: &Value) -> StdResult<(), encoding::Error> {
+    E::Request::deserialize(payload).map(drop)
+}
+
 #[derive(Debug)]
 pub struct Frozen;
 
@@ -96,6 +199,11 @@ pub struct Unfrozen;
 pub struct Planner<State> {
     graph: Plan,
     packages: HashMap<PackageName, NodeIndex>,
+    /// Packages depending on a name, keyed by its identifier, recorded as they're registered.
+    ///
+    /// Lets [`Planner::unregister`] find dependents without scanning every package.
+    dependents: HashMap<SmolStr, Vec<PackageName>>,
+    validators: HashMap<ExecutorName, Validator>,
     _marker: PhantomData<State>,
 }
 
@@ -104,6 +212,8 @@ impl Default for Planner<Unfrozen> {
         Self {
             graph: Acyclic::default(),
             packages: HashMap::default(),
+            dependents: HashMap::default(),
+            validators: HashMap::default(),
             _marker: PhantomData,
         }
     }
@@ -120,6 +230,14 @@ impl Planner<Unfrozen> {
         Planner::<Frozen>::new(self)
     }
 
+    /// Validates request payloads dispatched to `E` when the planner is frozen.
+    ///
+    /// Payloads for executors without a validator are only checked during the build.
+    pub fn register_validator<E: Executor>(&mut self) {
+        self.validators
+            .insert(E::name().clone(), validate_payload::<E>);
+    }
+
     pub fn register(&mut self, package: Package) -> Result<NodeIndex, Error> {
         if self.packages.contains_key(&package.name) {
             return Err(ConflictError {
@@ -128,12 +246,161 @@ impl Planner<Unfrozen> {
             .wrap());
         }
 
+        index_dependents(&mut self.dependents, &package);
         let name = package.name.clone();
         let node = self.graph.add_node(package);
         self.packages.insert(name, node);
 
         Ok(node)
     }
+
+    /// Removes a package from the planner.
+    ///
+    /// Fails if the package isn't registered, or if any other registered package depends on it.
+    /// This invalidates the [`NodeIndex`] of the most recently registered package,
+    /// so packages should be re-resolved by name afterwards.
+    pub fn unregister(&mut self, name: &PackageName) -> Result<Package, Error> {
+        let node = self.resolve(name).ok_or_else(|| {
+            UnregisteredPackage {
+                package: name.clone(),
+            }
+            .wrap()
+        })?;
+
+        let dependent = self
+            .dependents
+            .get(&name.identifier)
+            .into_iter()
+            .flatten()
+            .map(|dependent| &self.graph[self.packages[dependent]])
+            .find(|package| {
+                package.dependencies.iter().any(|dependency| {
+                    self.resolve_from(&package.name, &dependency.name) == Some(node)
+                })
+            });
+
+        if let Some(dependent) = dependent {
+            return Err(DependedUponError {
+                package: name.clone(),
+                dependent: dependent.name.clone(),
+            }
+            .wrap());
+        }
+
+        let package = self
+            .graph
+            .remove_node(node)
+            .expect("registered package should exist");
+        self.packages.remove(name);
+        unindex_dependents(&mut self.dependents, &package);
+
+        // the last node is swapped into the removed node's index
+        if let Some(moved) = self.graph.node_weight(node) {
+            self.packages.insert(moved.name.clone(), node);
+        }
+
+        Ok(package)
+    }
+
+    /// Removes every package registered after the planner held `count` packages.
+    ///
+    /// Packages are removed newest first, so no other [`NodeIndex`] is invalidated.
+    pub(crate) fn truncate(&mut self, count: usize) {
+        while self.graph.node_count() > count {
+            let node = NodeIndex::new(self.graph.node_count() - 1);
+            let package = self
+                .graph
+                .remove_node(node)
+                .expect("registered package should exist");
+            self.packages.remove(&package.name);
+            unindex_dependents(&mut self.dependents, &package);
+        }
+    }
+
+    /// Every registered package, in registration order.
+    ///
+    /// Useful to adjust the plan from [`Backend::finalize`](crate::backend::Backend::finalize).
+    pub fn packages(&self) -> impl Iterator<Item = &Package> {
+        self.graph.node_weights()
+    }
+
+    /// Replaces a registered package with a new definition of the same name,
+    /// or registers it if it isn't registered yet.
+    ///
+    /// Unlike [`Self::unregister`], this succeeds even if the package is depended upon.
+    pub fn replace(&mut self, package: Package) -> Result<NodeIndex, Error> {
+        match self.resolve(&package.name) {
+            Some(node) => {
+                // both definitions share a name, so the previous one is forgotten first
+                let slot = self
+                    .graph
+                    .node_weight_mut(node)
+                    .expect("registered package should exist");
+                unindex_dependents(&mut self.dependents, slot);
+                index_dependents(&mut self.dependents, &package);
+                *slot = package;
+                Ok(node)
+            }
+            None => self.register(package),
+        }
+    }
+}
+
+impl<State> Planner<State> {
+    #[inline]
+    pub fn resolve(&self, id: &PackageName) -> Option<NodeIndex> {
+        self.packages.get(id).copied()
+    }
+
+    /// Lists every registered package whose namespace starts with `prefix`, sorted by full name.
+    pub fn resolve_prefix(&self, prefix: &[SmolStr]) -> Vec<(PackageName, NodeIndex)> {
+        let mut packages: Vec<_> = self
+            .packages
+            .iter()
+            .filter(|(name, _)| name.namespace.starts_with(prefix))
+            .map(|(name, node)| (name.clone(), *node))
+            .collect();
+
+        packages.sort_by_cached_key(|(name, _)| name.to_string());
+        packages
+    }
+
+    /// Resolves `id` as referenced from the package `from`.
+    ///
+    /// If there is no exact match, `id` is treated as relative to `from`'s namespace,
+    /// and each of its prefixes is searched from the innermost outwards.
+    pub fn resolve_from(&self, from: &PackageName, id: &PackageName) -> Option<NodeIndex> {
+        self.resolve(id).or_else(|| {
+            (1..=from.namespace.len()).rev().find_map(|len| {
+                let namespace: Vec<_> = from.namespace[..len]
+                    .iter()
+                    .chain(id.namespace.iter())
+                    .cloned()
+                    .collect();
+
+                self.resolve(&PackageName::new(id.identifier.clone(), namespace))
+            })
+        })
+    }
+}
+
+/// Records `package` as a dependent of every name it depends on, see [`Planner::unregister`].
+fn index_dependents(dependents: &mut HashMap<SmolStr, Vec<PackageName>>, package: &Package) {
+    for dependency in &package.dependencies {
+        dependents
+            .entry(dependency.name.identifier.clone())
+            .or_default()
+            .push(package.name.clone());
+    }
+}
+
+/// Forgets every dependency recorded for `package` by [`index_dependents`].
+fn unindex_dependents(dependents: &mut HashMap<SmolStr, Vec<PackageName>>, package: &Package) {
+    for dependency in &package.dependencies {
+        if let Some(names) = dependents.get_mut(&dependency.name.identifier) {
+            names.retain(|name| *name != package.name);
+        }
+    }
 }
 
 impl Planner<Frozen> {
@@ -141,9 +408,26 @@ impl Planner<Frozen> {
         let mut planner = Planner {
             graph: unfrozen.graph,
             packages: unfrozen.packages,
+            dependents: unfrozen.dependents,
+            validators: unfrozen.validators,
             _marker: PhantomData,
         };
 
+        let payloads = planner.graph.node_weights().flat_map(|package| {
+            package.requests.iter().filter_map(|request| {
+                let validator = planner.validators.get(&request.executor)?;
+                Some(
+                    validator(&request.payload).wrap_with_fn(|| InvalidPayloadError {
+                        package: package.name.clone(),
+                        executor: request.executor.clone(),
+                    }),
+                )
+            })
+        });
+        if let Err(reports) = partition_results::<_, (), _, Vec<_>>(payloads) {
+            return Err(Error.into_report().with_children(reports));
+        }
+
         let results = planner.graph.node_indices().flat_map(|node| {
             // take dependencies so we don't hold a reference to the graph
             let dependencies = std::mem::take(
@@ -158,7 +442,7 @@ impl Planner<Frozen> {
                 .into_iter()
                 .map(|dependency| {
                     let dependency_node = planner
-                        .resolve(&dependency.name)
+                        .resolve_from(&planner.graph[node].name, &dependency.name)
                         .ok_or_else(|| UnregisteredDependency {
                             dependency: dependency.name.clone(),
                         })
@@ -222,6 +506,79 @@ impl Planner<Frozen> {
         })
     }
 
+    /// Finds the packages tagged with `tag`, in plan order.
+    pub fn by_tag(&self, tag: &str) -> Vec<NodeIndex> {
+        self.graph
+            .node_indices()
+            .filter(|node| self.graph[*node].tags.iter().any(|other| other == tag))
+            .collect()
+    }
+
+    /// Overrides the [`build_cost`](Package::build_cost) of a package, like with the duration of its last build.
+    ///
+    /// Returns `false` if `node` isn't in the plan.
+    pub fn set_build_cost(&mut self, node: NodeIndex, cost: u64) -> bool {
+        let Some(package) = self.graph.node_weight_mut(node) else {
+            return false;
+        };
+
+        package.build_cost = Some(cost);
+        true
+    }
+
+    /// Finds the packages outside of the dependency closure of every target, sorted by name.
+    ///
+    /// Targets themselves are always referenced. Both runtime and buildtime dependencies count as references.
+    pub fn unreferenced(&self, targets: &[NodeIndex]) -> Vec<(PackageName, NodeIndex)> {
+        let mut referenced = RapidHashSet::default();
+        let mut visitor = Dfs::empty(&self.graph);
+        for &target in targets {
+            visitor.move_to(target);
+            while let Some(node) = visitor.next(&self.graph) {
+                referenced.insert(node);
+            }
+        }
+
+        let mut unreferenced: Vec<_> = self
+            .packages
+            .iter()
+            .filter(|(_, node)| !referenced.contains(*node))
+            .map(|(name, node)| (name.clone(), *node))
+            .collect();
+        unreferenced.sort_by_cached_key(|(name, _)| name.to_string());
+
+        unreferenced
+    }
+
+    /// Compares this plan against `other`, matching packages by name.
+    ///
+    /// Packages only present in `other` are added, and packages only present in `self` are removed.
+    pub fn diff(&self, other: &Self) -> PlanDiff {
+        let mut diff = PlanDiff::default();
+
+        for (name, node) in &self.packages {
+            match other.resolve(name) {
+                Some(other_node) if self.identity(*node) != other.identity(other_node) => {
+                    diff.changed.insert(name.clone());
+                }
+                Some(_) => (),
+                None => {
+                    diff.removed.insert(name.clone());
+                }
+            }
+        }
+
+        diff.added.extend(
+            other
+                .packages
+                .keys()
+                .filter(|name| !self.packages.contains_key(*name))
+                .cloned(),
+        );
+
+        diff
+    }
+
     // TODO: cache identity
     pub fn identity(&self, node: NodeIndex) -> Option<PackageId> {
         let mut hasher = blake3::Hasher::new();
@@ -237,20 +594,464 @@ impl Planner<Frozen> {
                 let mut payload_hasher = std::hash::DefaultHasher::new();
                 request.payload.hash(&mut payload_hasher);
                 hasher.update(&payload_hasher.finish().to_le_bytes());
+
+                // `None` and `Some(vec![])` order requests differently, so they hash differently too
+                let after = request.after.as_deref();
+                hasher.update(&[u8::from(after.is_some())]);
+                hasher.update(&(after.map_or(0, <[usize]>::len) as u64).to_le_bytes());
+                for index in after.unwrap_or_default() {
+                    hasher.update(&(*index as u64).to_le_bytes());
+                }
             }
         };
 
+        // closures iterate in a per-set random order, so members are hashed in name order instead
+        let sorted = |members: RapidHashSet<NodeIndex>| {
+            let mut members: Vec<_> = members.into_iter().collect();
+            members.sort_by_cached_key(|node| self.graph[*node].name.to_string());
+            members
+        };
+
         let closure = self.closure(node)?;
-        std::iter::once(&node)
-            .chain(closure.runtime.iter())
-            .chain(closure.buildtime.iter())
-            .for_each(|node| hash_pkg(&self.graph[*node]));
+        std::iter::once(node)
+            .chain(sorted(closure.runtime))
+            .chain(sorted(closure.buildtime))
+            .for_each(|node| hash_pkg(&self.graph[node]));
 
         Some(hasher.finalize())
     }
+}
 
-    #[inline]
-    pub fn resolve(&self, id: &PackageName) -> Option<NodeIndex> {
-        self.packages.get(id).copied()
+#[cfg(test)]
+mod tests {
+    use crate::{
+        gen_name,
+        name::PackageName,
+        package::{Dependency, DispatchRequest, LinkTime, Metadata, Package},
+        planner::{NamespaceTracker, Planner, Unfrozen},
+    };
+
+    fn package(name: PackageName, dependencies: &[&str]) -> Package {
+        Package {
+            name,
+            metadata: Metadata,
+            tags: Vec::new(),
+            build_cost: None,
+            requests: Vec::new(),
+            dependencies: dependencies
+                .iter()
+                .map(|dependency| Dependency {
+                    name: dependency.parse().unwrap(),
+                    time: LinkTime::Runtime,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_namespaced_resolution() {
+        let mut planner = Planner::<Unfrozen>::new();
+        planner
+            .register(package(gen_name!(a@my/scope), &["b"]))
+            .unwrap();
+        planner
+            .register(package(gen_name!(b@my/scope), &["c", "nested@inner"]))
+            .unwrap();
+        planner
+            .register(package(gen_name!(nested@my/scope/inner), &[]))
+            .unwrap();
+        // resolved from a parent namespace of the dependent
+        planner.register(package(gen_name!(c@my), &[])).unwrap();
+        // exact matches take precedence over namespaced ones
+        planner
+            .register(package(gen_name!(d@my/scope), &["c@my"]))
+            .unwrap();
+
+        let planner = planner.freeze().expect("plan should freeze");
+        let plan = planner.graph();
+        let node = |name| planner.resolve(&name).unwrap();
+
+        let edges = [
+            (gen_name!(a@my/scope), gen_name!(b@my/scope)),
+            (gen_name!(b@my/scope), gen_name!(c@my)),
+            (gen_name!(b@my/scope), gen_name!(nested@my/scope/inner)),
+            (gen_name!(d@my/scope), gen_name!(c@my)),
+        ];
+
+        assert_eq!(plan.edge_count(), edges.len());
+        for (from, to) in edges {
+            assert!(plan.contains_edge(node(from), node(to)));
+        }
+    }
+
+    #[test]
+    fn test_resolve_prefix() {
+        let mut planner = Planner::<Unfrozen>::new();
+        let names = [
+            gen_name!(b@my/scope),
+            gen_name!(a@my/scope),
+            gen_name!(nested@my/scope/inner),
+            gen_name!(c@my),
+            gen_name!(d@my/scoped),
+            gen_name!(e@other),
+        ];
+        for name in names {
+            planner.register(package(name, &[])).unwrap();
+        }
+
+        let prefixed = |prefix: &[&str]| {
+            let prefix: Vec<_> = prefix.iter().map(|segment| (*segment).into()).collect();
+            planner
+                .resolve_prefix(&prefix)
+                .into_iter()
+                .map(|(name, node)| {
+                    assert_eq!(planner.resolve(&name), Some(node));
+                    name
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            prefixed(&["my", "scope"]),
+            [
+                gen_name!(a@my/scope),
+                gen_name!(b@my/scope),
+                gen_name!(nested@my/scope/inner)
+            ]
+        );
+        assert_eq!(
+            prefixed(&["my", "scope", "inner"]),
+            [gen_name!(nested@my/scope/inner)]
+        );
+        assert_eq!(prefixed(&["my"]).len(), 5);
+        assert_eq!(prefixed(&[]).len(), 6);
+        assert!(prefixed(&["missing"]).is_empty());
+    }
+
+    #[test]
+    fn test_unresolved_dependency() {
+        let mut planner = Planner::<Unfrozen>::new();
+        planner
+            .register(package(gen_name!(a@my/scope), &["missing"]))
+            .unwrap();
+        planner
+            .register(package(gen_name!(missing@other), &[]))
+            .unwrap();
+
+        assert!(planner.freeze().is_err());
+    }
+
+    #[test]
+    fn test_unregister() {
+        let mut planner = Planner::<Unfrozen>::new();
+        planner
+            .register(package(gen_name!(app@my), &["lib"]))
+            .unwrap();
+        planner.register(package(gen_name!(lib@my), &[])).unwrap();
+        planner.register(package(gen_name!(leaf@my), &[])).unwrap();
+
+        // "lib" is depended upon by "app"
+        assert!(planner.unregister(&gen_name!(lib@my)).is_err());
+        assert!(planner.resolve(&gen_name!(lib@my)).is_some());
+
+        let leaf = planner.unregister(&gen_name!(leaf@my)).unwrap();
+        assert_eq!(leaf.name, gen_name!(leaf@my));
+        assert!(planner.resolve(&gen_name!(leaf@my)).is_none());
+        assert!(planner.unregister(&gen_name!(leaf@my)).is_err());
+
+        // once "app" is gone, "lib" can be unregistered
+        planner.unregister(&gen_name!(app@my)).unwrap();
+        planner.unregister(&gen_name!(lib@my)).unwrap();
+        assert_eq!(planner.freeze().unwrap().graph().node_count(), 0);
+    }
+
+    #[test]
+    fn test_unregister_dependents() {
+        let mut planner = Planner::<Unfrozen>::new();
+        planner
+            .register(package(gen_name!(app@my), &["lib"]))
+            .unwrap();
+        planner.register(package(gen_name!(lib@my), &[])).unwrap();
+        planner
+            .register(package(gen_name!(lib@other), &[]))
+            .unwrap();
+        planner.register(package(gen_name!(leaf@my), &[])).unwrap();
+
+        // "app" resolves "lib" to "lib@my", not to every package with that identifier
+        planner.unregister(&gen_name!(lib@other)).unwrap();
+        assert!(planner.unregister(&gen_name!(lib@my)).is_err());
+
+        // dependencies are tracked through replacements
+        planner
+            .replace(package(gen_name!(app@my), &["leaf"]))
+            .unwrap();
+        assert!(planner.unregister(&gen_name!(leaf@my)).is_err());
+        planner.unregister(&gen_name!(lib@my)).unwrap();
+    }
+
+    #[test]
+    fn test_namespace_guard() {
+        let tracker = NamespaceTracker::new();
+        let outer = tracker.enter("my");
+        {
+            let _inner = tracker.enter("scope");
+            assert_eq!(tracker.current(), ["my", "scope"]);
+            tracker.scope("nested", || {
+                assert_eq!(tracker.current(), ["my", "scope", "nested"]);
+            });
+        }
+
+        assert_eq!(tracker.current(), ["my"]);
+        drop(outer);
+        assert!(tracker.current().is_empty());
+    }
+
+    #[test]
+    fn test_with_namespace() {
+        let tracker = NamespaceTracker::new();
+        let _outer = tracker.enter("my");
+
+        tracker.with_namespace(vec!["deeply".into(), "nested".into()], || {
+            assert_eq!(tracker.current(), ["deeply", "nested"]);
+            tracker.scope("scope", || {
+                assert_eq!(tracker.current(), ["deeply", "nested", "scope"]);
+                tracker.with_namespace(vec!["other".into()], || {
+                    assert_eq!(tracker.current(), ["other"]);
+                });
+                assert_eq!(tracker.current(), ["deeply", "nested", "scope"]);
+            });
+            assert_eq!(tracker.current(), ["deeply", "nested"]);
+        });
+        assert_eq!(tracker.current(), ["my"]);
+
+        // restored even when unwinding
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            tracker.with_namespace(Vec::new(), || panic!("replaying failed"));
+        }));
+        assert!(result.is_err());
+        assert_eq!(tracker.current(), ["my"]);
+    }
+
+    #[test]
+    fn test_diff() {
+        let plan = |extra: bool, payload: i32| {
+            let mut planner = Planner::<Unfrozen>::new();
+            planner
+                .register(package(gen_name!(app@my), &["lib"]))
+                .unwrap();
+            planner.register(package(gen_name!(lib@my), &[])).unwrap();
+            planner.register(package(gen_name!(cli@my), &[])).unwrap();
+
+            let mut fetch = package(gen_name!(fetch@my), &[]);
+            fetch.requests.push(DispatchRequest {
+                executor: gen_name!(http@xuehua),
+                payload: payload.into(),
+                after: None,
+            });
+            planner.register(fetch).unwrap();
+
+            if extra {
+                planner.register(package(gen_name!(new@my), &[])).unwrap();
+            }
+
+            planner.freeze().unwrap()
+        };
+
+        let diff = plan(false, 1).diff(&plan(true, 2));
+        assert_eq!(diff.added, [gen_name!(new@my)].into_iter().collect());
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.changed, [gen_name!(fetch@my)].into_iter().collect());
+
+        let diff = plan(true, 1).diff(&plan(false, 1));
+        assert_eq!(diff.removed, [gen_name!(new@my)].into_iter().collect());
+        assert!(diff.added.is_empty() && diff.changed.is_empty());
+    }
+
+    #[test]
+    fn test_diff_identical() {
+        let plan = || {
+            let mut planner = Planner::<Unfrozen>::new();
+            for (name, dependencies) in [
+                (gen_name!(app@my), &["lib", "cli"][..]),
+                (gen_name!(lib@my), &["util"]),
+                (gen_name!(cli@my), &["util", "core"]),
+                (gen_name!(util@my), &["core"]),
+                (gen_name!(core@my), &["base"]),
+                (gen_name!(base@my), &[]),
+            ] {
+                planner.register(package(name, dependencies)).unwrap();
+            }
+
+            planner.freeze().unwrap()
+        };
+
+        let diff = plan().diff(&plan());
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+        assert!(diff.changed.is_empty());
+    }
+
+    #[test]
+    fn test_identity_ordering() {
+        let identity = |after: [Option<Vec<usize>>; 3]| {
+            let mut planner = Planner::<Unfrozen>::new();
+            let mut package = package(gen_name!(app@my), &[]);
+            package.requests = after
+                .into_iter()
+                .map(|after| DispatchRequest {
+                    executor: gen_name!(http@xuehua),
+                    payload: 0.into(),
+                    after,
+                })
+                .collect();
+            let node = planner.register(package).unwrap();
+
+            planner.freeze().unwrap().identity(node).unwrap()
+        };
+
+        let sequential = identity([None, None, None]);
+        assert_eq!(sequential, identity([None, None, None]));
+        assert_ne!(sequential, identity([None, Some(vec![]), None]));
+        assert_ne!(sequential, identity([None, None, Some(vec![0])]));
+        assert_ne!(
+            identity([None, Some(vec![0]), Some(vec![0])]),
+            identity([None, Some(vec![0]), Some(vec![1])])
+        );
+    }
+
+    #[test]
+    fn test_identity_stable() {
+        let plan = || {
+            let mut planner = Planner::<Unfrozen>::new();
+            for (name, dependencies) in [
+                (gen_name!(a@my), &["b"][..]),
+                (gen_name!(b@my), &["c"]),
+                (gen_name!(c@my), &["d"]),
+                (gen_name!(d@my), &["e"]),
+                (gen_name!(e@my), &[]),
+            ] {
+                planner.register(package(name, dependencies)).unwrap();
+            }
+
+            planner.freeze().unwrap()
+        };
+
+        let planner = plan();
+        let node = planner.resolve(&gen_name!(a@my)).unwrap();
+        assert!(planner.closure(node).unwrap().runtime.len() > 1);
+
+        let identity = planner.identity(node).unwrap();
+        for _ in 0..16 {
+            assert_eq!(planner.identity(node), Some(identity));
+        }
+
+        let fresh = plan();
+        let node = fresh.resolve(&gen_name!(a@my)).unwrap();
+        assert_eq!(fresh.identity(node), Some(identity));
+    }
+
+    #[test]
+    fn test_unreferenced() {
+        let mut planner = Planner::<Unfrozen>::new();
+        planner
+            .register(package(gen_name!(app@my), &["lib"]))
+            .unwrap();
+        let mut lib = package(gen_name!(lib@my), &[]);
+        lib.dependencies.push(Dependency {
+            name: gen_name!(compiler@my),
+            time: LinkTime::Buildtime,
+        });
+        planner.register(lib).unwrap();
+        planner
+            .register(package(gen_name!(compiler@my), &[]))
+            .unwrap();
+        planner
+            .register(package(gen_name!(orphan@my), &["lib"]))
+            .unwrap();
+        planner.register(package(gen_name!(tool@my), &[])).unwrap();
+
+        let planner = planner.freeze().unwrap();
+        let resolve = |name| planner.resolve(&name).unwrap();
+        let names = |targets: &[_]| -> Vec<_> {
+            planner
+                .unreferenced(targets)
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect()
+        };
+
+        assert_eq!(
+            names(&[resolve(gen_name!(app@my))]),
+            [gen_name!(orphan@my), gen_name!(tool@my)]
+        );
+        assert_eq!(
+            names(&[resolve(gen_name!(app@my)), resolve(gen_name!(tool@my))]),
+            [gen_name!(orphan@my)]
+        );
+        assert_eq!(names(&[]).len(), 5);
+    }
+
+    #[test]
+    fn test_by_tag() {
+        let mut planner = Planner::<Unfrozen>::new();
+        let tagged = |name, tags: &[&str]| {
+            let mut package = package(name, &[]);
+            package.tags = tags.iter().map(|tag| (*tag).into()).collect();
+            package
+        };
+        planner
+            .register(tagged(gen_name!(editor@my), &["gui", "text"]))
+            .unwrap();
+        planner
+            .register(tagged(gen_name!(shell@my), &["text"]))
+            .unwrap();
+        planner
+            .register(tagged(gen_name!(viewer@my), &["gui"]))
+            .unwrap();
+        planner.register(tagged(gen_name!(lib@my), &[])).unwrap();
+
+        let planner = planner.freeze().unwrap();
+        let names = |tag| -> Vec<_> {
+            planner
+                .by_tag(tag)
+                .into_iter()
+                .map(|node| planner.graph()[node].name.clone())
+                .collect()
+        };
+
+        assert_eq!(names("gui"), [gen_name!(editor@my), gen_name!(viewer@my)]);
+        assert_eq!(names("text"), [gen_name!(editor@my), gen_name!(shell@my)]);
+        assert!(names("Gui").is_empty());
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut planner = Planner::<Unfrozen>::new();
+        planner
+            .register(package(gen_name!(app@my), &["lib"]))
+            .unwrap();
+        planner.register(package(gen_name!(lib@my), &[])).unwrap();
+        planner.register(package(gen_name!(c@my), &[])).unwrap();
+
+        // the removed node's index is reused by the last registered package
+        planner.unregister(&gen_name!(app@my)).unwrap();
+        let node = planner
+            .replace(package(gen_name!(app@my), &["lib", "c"]))
+            .unwrap();
+        let replaced = planner.replace(package(gen_name!(app@my), &["c"])).unwrap();
+        assert_eq!(node, replaced);
+
+        let planner = planner.freeze().expect("plan should freeze");
+        let resolve = |name| planner.resolve(&name).unwrap();
+        assert_eq!(planner.graph().node_count(), 3);
+        assert_eq!(
+            planner.graph()[resolve(gen_name!(c@my))].name,
+            gen_name!(c@my)
+        );
+        assert!(
+            planner
+                .graph()
+                .contains_edge(resolve(gen_name!(app@my)), resolve(gen_name!(c@my)))
+        );
+        assert_eq!(planner.graph().edge_count(), 1);
     }
 }
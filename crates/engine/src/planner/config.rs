@@ -67,6 +67,36 @@ impl<'a, B: Backend> ConfigManager<'a, B> {
         Ok(())
     }
 
+    /// Registers a batch of packages in one pass.
+    ///
+    /// If any package fails to register, the packages already registered by this batch
+    /// are removed again and the first error is returned.
+    pub fn register_many(
+        &mut self,
+        batch: impl IntoIterator<Item = (PackageName, Config<B>)>,
+    ) -> Result<(), Error> {
+        let count = self.planner.graph.node_count();
+        let mut registered = Vec::new();
+
+        for (name, config) in batch {
+            let result = config.clone().apply().wrap().and_then(|mut package| {
+                package.name = name;
+                self.planner.register(package).wrap()
+            });
+
+            match result {
+                Ok(node) => registered.push((node, config)),
+                Err(err) => {
+                    self.planner.truncate(count);
+                    return Err(err);
+                }
+            }
+        }
+
+        self.configs.extend(registered);
+        Ok(())
+    }
+
     #[inline]
     pub fn configure(
         &mut self,
@@ -85,3 +115,72 @@ impl<'a, B: Backend> ConfigManager<'a, B> {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, sync::LazyLock};
+
+    use xh_reports::prelude::*;
+
+    use crate::{
+        backend::{Backend, Error},
+        gen_name,
+        name::{BackendName, PackageName},
+        package::{Metadata, Package},
+        planner::{Planner, Unfrozen, config::Config},
+    };
+
+    use super::ConfigManager;
+
+    struct TestBackend;
+
+    impl Backend for TestBackend {
+        type Value = ();
+
+        fn name() -> &'static BackendName {
+            static NAME: LazyLock<BackendName> = LazyLock::new(|| gen_name!(test@xuehua));
+            &NAME
+        }
+
+        fn plan(&self, _planner: &mut Planner<Unfrozen>, _project: &Path) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    fn config() -> Config<TestBackend> {
+        Config::new((), |_| {
+            Ok(Package {
+                name: PackageName::default(),
+                metadata: Metadata,
+                tags: Vec::new(),
+                build_cost: None,
+                requests: Vec::new(),
+                dependencies: Vec::new(),
+            })
+        })
+    }
+
+    #[test]
+    fn test_register_many_rollback() {
+        let mut planner = Planner::<Unfrozen>::new();
+        let mut manager = ConfigManager::new(&mut planner);
+        manager.register(gen_name!(a@my), config()).unwrap();
+
+        let batch = [gen_name!(b@my), gen_name!(c@my), gen_name!(a@my)];
+        assert!(
+            manager
+                .register_many(batch.map(|name| (name, config())))
+                .is_err()
+        );
+        assert!(manager.planner.resolve(&gen_name!(a@my)).is_some());
+        assert!(manager.planner.resolve(&gen_name!(b@my)).is_none());
+        assert!(manager.planner.resolve(&gen_name!(c@my)).is_none());
+
+        let batch = [gen_name!(b@my), gen_name!(c@my)];
+        manager
+            .register_many(batch.map(|name| (name, config())))
+            .unwrap();
+        assert_eq!(manager.configs.len(), 3);
+        assert_eq!(planner.graph.node_count(), 3);
+    }
+}
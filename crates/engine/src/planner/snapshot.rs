@@ -0,0 +1,249 @@
+use std::{
+    collections::HashMap,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+};
+
+use petgraph::{acyclic::Acyclic, data::Build, graph::NodeIndex, visit::EdgeRef};
+use serde::{Deserialize, Serialize};
+use xh_reports::prelude::*;
+
+use crate::{
+    package::{LinkTime, Package},
+    planner::{Error, Frozen, Planner},
+};
+
+#[derive(Debug, IntoReport)]
+#[message("could not save plan")]
+#[context(path)]
+pub struct SaveError {
+    path: PathBuf,
+}
+
+#[derive(Debug, IntoReport)]
+#[message("could not load plan")]
+#[context(path)]
+pub struct LoadError {
+    path: PathBuf,
+}
+
+#[derive(Debug, IntoReport)]
+#[message("plan snapshot is inconsistent")]
+#[suggestion("delete the snapshot, so the plan is evaluated again")]
+#[context(reason)]
+pub struct InconsistentError {
+    reason: &'static str,
+}
+
+/// Bumped whenever the snapshot layout, or the meaning of its contents, changes.
+const VERSION: u32 = 1;
+
+/// A frozen plan, as written by [`Planner::save`].
+///
+/// Dependencies are stored as edges between package indices,
+/// since freezing moves them out of their packages.
+#[derive(Serialize, Deserialize)]
+struct Snapshot<P> {
+    version: u32,
+    /// Hash of whatever the plan was evaluated from, see [`Planner::load`].
+    inputs: String,
+    packages: Vec<P>,
+    edges: Vec<(usize, usize, LinkTime)>,
+}
+
+impl Planner<Frozen> {
+    /// Writes this plan to `path`, tagged with the hash of the `inputs` it was evaluated from.
+    pub fn save(&self, path: &Path, inputs: &blake3::Hash) -> Result<(), Error> {
+        let snapshot = Snapshot {
+            version: VERSION,
+            inputs: inputs.to_hex().to_string(),
+            packages: self.graph.node_weights().collect(),
+            edges: self
+                .graph
+                .edge_references()
+                .map(|edge| (edge.source().index(), edge.target().index(), *edge.weight()))
+                .collect(),
+        };
+
+        let error = || SaveError {
+            path: path.to_path_buf(),
+        };
+        let contents = serde_json::to_vec(&snapshot).wrap_with_fn(error).wrap()?;
+        std::fs::write(path, contents).wrap_with_fn(error).wrap()
+    }
+
+    /// Reads a plan written by [`Self::save`].
+    ///
+    /// Returns `None` if there is no plan at `path`, or if it was evaluated from different `inputs`,
+    /// in which case the plan should be evaluated again.
+    /// Loaded plans have no validators, as their payloads were validated when first frozen.
+    pub fn load(path: &Path, inputs: &blake3::Hash) -> Result<Option<Self>, Error> {
+        let contents = match std::fs::read(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err)
+                    .wrap_with_fn(|| LoadError {
+                        path: path.to_path_buf(),
+                    })
+                    .wrap();
+            }
+        };
+
+        let snapshot: Snapshot<Package> = serde_json::from_slice(&contents)
+            .wrap_with_fn(|| LoadError {
+                path: path.to_path_buf(),
+            })
+            .wrap()?;
+        if snapshot.version != VERSION || snapshot.inputs != inputs.to_hex().as_str() {
+            tracing::debug!(?path, "plan snapshot is stale");
+            return Ok(None);
+        }
+
+        Self::from_snapshot(snapshot)
+            .wrap_with_fn(|| LoadError {
+                path: path.to_path_buf(),
+            })
+            .wrap()
+            .map(Some)
+    }
+
+    fn from_snapshot(snapshot: Snapshot<Package>) -> Result<Self, InconsistentError> {
+        let mut planner = Planner {
+            graph: Acyclic::default(),
+            packages: HashMap::default(),
+            // only unfrozen planners unregister packages
+            dependents: HashMap::default(),
+            validators: HashMap::default(),
+            _marker: PhantomData,
+        };
+
+        for package in snapshot.packages {
+            let name = package.name.clone();
+            let node = planner.graph.add_node(package);
+            if planner.packages.insert(name, node).is_some() {
+                return Err(InconsistentError {
+                    reason: "duplicate package",
+                }
+                .into_report());
+            }
+        }
+
+        let count = planner.graph.node_count();
+        for (from, to, time) in snapshot.edges {
+            if from >= count || to >= count {
+                return Err(InconsistentError {
+                    reason: "dependency on a missing package",
+                }
+                .into_report());
+            }
+
+            planner
+                .graph
+                .try_add_edge(NodeIndex::new(from), NodeIndex::new(to), time)
+                .map_err(|_| {
+                    InconsistentError {
+                        reason: "dependency cycle",
+                    }
+                    .into_report()
+                })?;
+        }
+
+        Ok(planner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use petgraph::visit::EdgeRef;
+
+    use crate::{
+        gen_name,
+        package::{Dependency, DispatchRequest, LinkTime, Metadata, Package},
+        planner::{Frozen, Planner, Unfrozen},
+    };
+
+    fn plan() -> Planner<Frozen> {
+        let mut planner = Planner::<Unfrozen>::new();
+        planner
+            .register(Package {
+                name: gen_name!(app@my),
+                metadata: Metadata,
+                tags: Vec::new(),
+                build_cost: None,
+                requests: vec![DispatchRequest {
+                    executor: gen_name!(http@xuehua),
+                    payload: serde_json::json!({ "url": "https://example.com" }),
+                    after: Some(Vec::new()),
+                }],
+                dependencies: vec![
+                    Dependency {
+                        name: gen_name!(lib@my),
+                        time: LinkTime::Runtime,
+                    },
+                    Dependency {
+                        name: gen_name!(compiler@my),
+                        time: LinkTime::Buildtime,
+                    },
+                ],
+            })
+            .unwrap();
+        for name in [gen_name!(lib@my), gen_name!(compiler@my)] {
+            planner
+                .register(Package {
+                    name,
+                    metadata: Metadata,
+                    tags: Vec::new(),
+                    build_cost: None,
+                    requests: Vec::new(),
+                    dependencies: Vec::new(),
+                })
+                .unwrap();
+        }
+
+        planner.freeze().unwrap()
+    }
+
+    fn contents(planner: &Planner<Frozen>) -> (Vec<Package>, Vec<(usize, usize, LinkTime)>) {
+        let graph = planner.graph();
+        let mut edges: Vec<_> = graph
+            .edge_references()
+            .map(|edge| (edge.source().index(), edge.target().index(), *edge.weight()))
+            .collect();
+        edges.sort_by_key(|(from, to, _)| (*from, *to));
+
+        (graph.node_weights().cloned().collect(), edges)
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("plan.json");
+        let inputs = blake3::hash(b"inputs");
+
+        let planner = plan();
+        planner.save(&path, &inputs).unwrap();
+        let loaded = Planner::<Frozen>::load(&path, &inputs).unwrap().unwrap();
+
+        assert_eq!(contents(&loaded), contents(&planner));
+        let app = gen_name!(app@my);
+        assert_eq!(loaded.resolve(&app), planner.resolve(&app));
+        assert_eq!(
+            loaded.identity(loaded.resolve(&app).unwrap()),
+            planner.identity(planner.resolve(&app).unwrap())
+        );
+
+        // stale or missing snapshots are re-evaluated
+        let changed = blake3::hash(b"changed");
+        assert!(Planner::<Frozen>::load(&path, &changed).unwrap().is_none());
+        let missing = temp.path().join("missing.json");
+        assert!(
+            Planner::<Frozen>::load(&missing, &inputs)
+                .unwrap()
+                .is_none()
+        );
+
+        std::fs::write(&path, "not a plan").unwrap();
+        assert!(Planner::<Frozen>::load(&path, &inputs).is_err());
+    }
+}
@@ -1,14 +1,14 @@
-use std::sync::mpsc;
+use std::{cell::Cell, cmp::Reverse, collections::BinaryHeap, sync::mpsc};
 
 use futures_util::{StreamExt, stream::FuturesUnordered};
 use petgraph::{Direction, graph::NodeIndex, visit::Dfs};
 use rapidhash::{RapidHashMap, RapidHashSet};
-use xh_reports::Result;
+use xh_reports::{Report, Result};
 
 use crate::{
     builder::{BuildRequest, Builder, Dispatch, Error as BuilderError, Initialize},
     name::PackageName,
-    planner::{Frozen, Planner},
+    planner::{Frozen, Plan, Planner},
 };
 
 #[derive(Debug)]
@@ -31,10 +31,70 @@ pub enum Event {
     },
 }
 
+/// Outcome of [`Scheduler::schedule`], for callers not interested in streaming [`Event`]s.
+#[derive(Debug, Default)]
+pub struct ScheduleReport {
+    /// Packages built successfully, in completion order.
+    pub built: Vec<NodeIndex>,
+    /// Packages that failed to build, in completion order.
+    pub failed: Vec<(NodeIndex, Report<BuilderError>)>,
+    /// Packages never built, because a dependency failed or scheduling stopped early, sorted by index.
+    pub skipped: Vec<NodeIndex>,
+}
+
 pub struct Scheduler<'a, E> {
     state: RapidHashMap<NodeIndex, PackageState>,
     planner: &'a Planner<Frozen>,
     builder: &'a Builder<E>,
+    ordered: bool,
+    keep_going: bool,
+    concurrency: Option<usize>,
+}
+
+/// Holds back [`Event::Finished`] until every package before it in the plan's order has finished.
+struct OrderedEvents {
+    order: Vec<NodeIndex>,
+    cursor: usize,
+    buffered: RapidHashMap<NodeIndex, Event>,
+    failed: RapidHashSet<NodeIndex>,
+}
+
+impl OrderedEvents {
+    fn push(&mut self, plan: &Plan, node: NodeIndex, event: Event, events: &mpsc::Sender<Event>) {
+        self.buffered.insert(node, event);
+
+        while let Some(&node) = self.order.get(self.cursor) {
+            // dependents of failed packages are never built
+            let skipped = plan
+                .neighbors_directed(node, Direction::Outgoing)
+                .any(|dependency| self.failed.contains(&dependency));
+
+            if skipped {
+                self.failed.insert(node);
+            } else {
+                let Some(event) = self.buffered.remove(&node) else {
+                    break;
+                };
+
+                if let Event::Finished { result: Err(_), .. } = event {
+                    self.failed.insert(node);
+                }
+
+                let _ = events.send(event);
+            }
+
+            self.cursor += 1;
+        }
+    }
+
+    /// Sends every remaining buffered event in order, skipping packages that never finished.
+    fn flush(mut self, events: &mpsc::Sender<Event>) {
+        for node in &self.order[self.cursor..] {
+            if let Some(event) = self.buffered.remove(node) {
+                let _ = events.send(event);
+            }
+        }
+    }
 }
 
 impl<'a, E> Scheduler<'a, E>
@@ -60,18 +120,96 @@ where
             state,
             planner,
             builder,
+            ordered: false,
+            keep_going: true,
+            concurrency: None,
         }
     }
 
+    /// Whether to keep building packages that don't depend on a failed package.
+    ///
+    /// When disabled, the first failure stops any new builds from starting,
+    /// and scheduling returns once the builds already in progress finish.
+    /// Defaults to `true`.
+    pub fn keep_going(mut self, keep_going: bool) -> Self {
+        self.keep_going = keep_going;
+        self
+    }
+
+    /// Treats `prebuilt` packages as already built, so they're never scheduled,
+    /// and don't hold back their dependents.
+    ///
+    /// Packages are only treated as built if every one of their dependencies is too,
+    /// since their build would otherwise be outdated.
+    ///
+    /// Passing the packages found in a store resumes an interrupted build,
+    /// only scheduling the packages it didn't get to.
+    pub fn prebuilt(mut self, prebuilt: impl IntoIterator<Item = NodeIndex>) -> Self {
+        let prebuilt: RapidHashSet<_> = prebuilt.into_iter().collect();
+        let plan = self.planner.graph();
+
+        // dependencies come after their dependents in the plan's order
+        let order: Vec<_> = plan.nodes_iter().collect();
+        for node in order.into_iter().rev() {
+            let ready = matches!(self.state[&node], PackageState::Unbuilt { remaining: 0 });
+            if !ready || !prebuilt.contains(&node) {
+                continue;
+            }
+
+            self.state.insert(node, PackageState::Built);
+            for parent in plan.neighbors_directed(node, Direction::Incoming) {
+                if let Some(PackageState::Unbuilt { remaining }) = self.state.get_mut(&parent) {
+                    *remaining -= 1;
+                }
+            }
+        }
+
+        self
+    }
+
+    /// Builds at most `limit` packages at once, instead of every package that's ready.
+    ///
+    /// Ready packages with the highest [`build_cost`](crate::package::Package::build_cost) start first,
+    /// so long builds don't end up holding back the rest of the schedule.
+    pub fn concurrency(mut self, limit: usize) -> Self {
+        self.concurrency = Some(limit.max(1));
+        self
+    }
+
+    /// Emits [`Event::Finished`] in the plan's topological order instead of completion order,
+    /// so identical builds produce identical event streams.
+    ///
+    /// Packages are still built concurrently, but their events are held back until every
+    /// package ordered before them has finished.
+    pub fn ordered(mut self) -> Self {
+        self.ordered = true;
+        self
+    }
+
     #[tracing::instrument(skip(self, events))]
-    pub async fn schedule(&mut self, targets: &[NodeIndex], events: mpsc::Sender<Event>) {
+    pub async fn schedule(
+        &mut self,
+        targets: &[NodeIndex],
+        events: mpsc::Sender<Event>,
+    ) -> ScheduleReport {
+        let mut report = ScheduleReport::default();
+        let stopped = Cell::new(false);
         let mut futures = FuturesUnordered::new();
         let plan = self.planner.graph();
 
         let build = async |events: &mpsc::Sender<_>, node| {
+            // builds queued before a failure, but not yet started
+            if stopped.get() {
+                return None;
+            }
+
             let request = BuildRequest {
                 id: xh_common::random_hash(),
                 target: node,
+                package: self
+                    .planner
+                    .identity(node)
+                    .expect("scheduled package should be registered"),
             };
 
             let name = &plan[node].name;
@@ -80,10 +218,15 @@ where
                 name: name.clone(),
             });
 
-            (request, self.builder.build(self.planner, request).await)
+            Some((request, self.builder.build(self.planner, request).await))
         };
 
-        // compute subset and build leaf packages
+        // ready packages, most expensive first, then in plan order
+        let mut ready = BinaryHeap::new();
+        let priority = |node: NodeIndex| (plan[node].build_cost.unwrap_or_default(), Reverse(node));
+        let limit = self.concurrency.unwrap_or(usize::MAX);
+
+        // compute subset and queue leaf packages
         let mut subset = RapidHashSet::default();
         let mut visitor = Dfs::empty(&plan);
         for target in targets {
@@ -92,20 +235,69 @@ where
                 subset.insert(node);
                 if let PackageState::Unbuilt { remaining: 0, .. } = self.state[&node] {
                     tracing::trace!(name = ?plan[node].name, "scheduling leaf package");
-                    futures.push(build(&events, node));
+                    ready.push(priority(node));
                 }
             }
         }
 
+        let mut ordered = self.ordered.then(|| {
+            // dependencies come after their dependents in the plan's order
+            let mut order: Vec<_> = plan
+                .nodes_iter()
+                .filter(|node| {
+                    subset.contains(node)
+                        && matches!(self.state[node], PackageState::Unbuilt { .. })
+                })
+                .collect();
+            order.reverse();
+
+            OrderedEvents {
+                order,
+                cursor: 0,
+                buffered: RapidHashMap::default(),
+                failed: RapidHashSet::default(),
+            }
+        });
+
         // main build loop
-        while let Some((request, result)) = futures.next().await {
+        loop {
+            while futures.len() < limit
+                && let Some((_, Reverse(node))) = ready.pop()
+            {
+                futures.push(build(&events, node));
+            }
+
+            let Some(finished) = futures.next().await else {
+                break;
+            };
+            let Some((request, result)) = finished else {
+                continue;
+            };
+
             let errored = result.is_err();
-            let _ = events.send(Event::Finished {
+            match &result {
+                Ok(()) => report.built.push(request.target),
+                Err(error) => report.failed.push((request.target, error.clone())),
+            }
+
+            let event = Event::Finished {
                 request,
                 result,
                 name: plan[request.target].name.clone(),
-            });
+            };
+
+            match &mut ordered {
+                Some(ordered) => ordered.push(plan, request.target, event, &events),
+                None => {
+                    let _ = events.send(event);
+                }
+            }
             if errored {
+                if !self.keep_going {
+                    tracing::debug!(name = ?plan[request.target].name, "stopping after failure");
+                    stopped.set(true);
+                }
+
                 continue;
             }
 
@@ -119,10 +311,355 @@ where
                 };
 
                 *remaining -= 1;
-                if *remaining == 0 && subset.contains(&parent) {
-                    futures.push(build(&events, parent));
+                if *remaining == 0 && subset.contains(&parent) && !stopped.get() {
+                    ready.push(priority(parent));
                 }
             }
         }
+
+        if let Some(ordered) = ordered {
+            ordered.flush(&events);
+        }
+
+        report.skipped = subset
+            .into_iter()
+            .filter(|node| {
+                matches!(self.state[node], PackageState::Unbuilt { .. })
+                    && !report.failed.iter().any(|(failed, _)| failed == node)
+            })
+            .collect();
+        report.skipped.sort_unstable();
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{LazyLock, mpsc};
+
+    use rapidhash::RapidHashSet;
+    use serde_json::json;
+    use xh_reports::prelude::*;
+
+    use crate::{
+        builder::Builder,
+        executor::{Error, Executor},
+        gen_name,
+        name::{ExecutorName, PackageName},
+        package::{Dependency, DispatchRequest, LinkTime, Metadata, Package},
+        planner::{Frozen, PackageId, Planner, Unfrozen},
+        scheduler::{Event, Scheduler},
+    };
+
+    static NAME: LazyLock<ExecutorName> = LazyLock::new(|| gen_name!(yield@tests));
+
+    /// Yields to the scheduler as many times as requested, to shuffle completion order.
+    struct Yielder;
+
+    impl Executor for Yielder {
+        type Request = usize;
+
+        fn name() -> &'static ExecutorName {
+            &NAME
+        }
+
+        async fn execute(&mut self, request: Self::Request) -> Result<(), Error> {
+            for _ in 0..request {
+                tokio::task::yield_now().await;
+            }
+
+            Ok(())
+        }
+    }
+
+    fn package(
+        name: PackageName,
+        executor: ExecutorName,
+        yields: usize,
+        dependencies: &[&str],
+    ) -> Package {
+        Package {
+            name,
+            metadata: Metadata,
+            tags: Vec::new(),
+            build_cost: None,
+            requests: vec![DispatchRequest {
+                executor,
+                payload: json!(yields),
+                after: None,
+            }],
+            dependencies: dependencies
+                .iter()
+                .map(|dependency| Dependency {
+                    name: dependency.parse().unwrap(),
+                    time: LinkTime::Runtime,
+                })
+                .collect(),
+        }
+    }
+
+    /// Builds a plan where `broken` fails, and `dependent` depends on it.
+    fn mixed_planner(yields: [usize; 4]) -> Planner<Frozen> {
+        let mut planner = Planner::<Unfrozen>::new();
+        let packages = [
+            package(
+                gen_name!(app@my),
+                NAME.clone(),
+                yields[0],
+                &["left", "right"],
+            ),
+            package(gen_name!(left@my), NAME.clone(), yields[1], &["base"]),
+            package(gen_name!(right@my), NAME.clone(), yields[2], &[]),
+            package(gen_name!(base@my), NAME.clone(), yields[3], &[]),
+            package(gen_name!(broken@my), gen_name!(missing@tests), 0, &[]),
+            package(gen_name!(dependent@my), NAME.clone(), 0, &["broken"]),
+        ];
+        for package in packages {
+            planner.register(package).unwrap();
+        }
+
+        planner.freeze().unwrap()
+    }
+
+    async fn finished(yields: [usize; 4]) -> Vec<(PackageName, bool)> {
+        let planner = mixed_planner(yields);
+        let temp = tempfile::tempdir().unwrap();
+        let builder = Builder::new(temp.path().to_path_buf()).register(|_| Ok(Yielder));
+        let targets = [gen_name!(app@my), gen_name!(dependent@my)]
+            .map(|name| planner.resolve(&name).unwrap());
+
+        let (events, receiver) = mpsc::channel();
+        Scheduler::new(&planner, &builder)
+            .ordered()
+            .schedule(&targets, events)
+            .await;
+
+        receiver
+            .try_iter()
+            .filter_map(|event| match event {
+                Event::Finished { name, result, .. } => Some((name, result.is_ok())),
+                Event::Started { .. } => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_ordered_events() {
+        let first = finished([0, 0, 16, 8]).await;
+        let second = finished([16, 8, 0, 0]).await;
+        assert_eq!(first, second);
+
+        let position = |name| first.iter().position(|(other, _)| *other == name).unwrap();
+        assert!(position(gen_name!(base@my)) < position(gen_name!(left@my)));
+        assert!(position(gen_name!(left@my)) < position(gen_name!(app@my)));
+        assert!(position(gen_name!(right@my)) < position(gen_name!(app@my)));
+        assert!(first.contains(&(gen_name!(broken@my), false)));
+        assert_eq!(first.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_keep_going() {
+        let mut planner = Planner::<Unfrozen>::new();
+        for name in [gen_name!(first@my), gen_name!(second@my)] {
+            planner
+                .register(package(name, gen_name!(missing@tests), 0, &[]))
+                .unwrap();
+        }
+        let planner = planner.freeze().unwrap();
+
+        let temp = tempfile::tempdir().unwrap();
+        let builder = Builder::new(temp.path().to_path_buf()).register(|_| Ok(Yielder));
+        let targets =
+            [gen_name!(first@my), gen_name!(second@my)].map(|name| planner.resolve(&name).unwrap());
+
+        // failed builds never stop each other when keeping going, and a single build
+        // at a time stops at the first failure, regardless of poll order
+        let started = async |keep_going, concurrency| {
+            let (events, receiver) = mpsc::channel();
+            Scheduler::new(&planner, &builder)
+                .keep_going(keep_going)
+                .concurrency(concurrency)
+                .schedule(&targets, events)
+                .await;
+
+            let (mut started, mut failed) = (RapidHashSet::default(), RapidHashSet::default());
+            for event in receiver.try_iter() {
+                match event {
+                    Event::Started { request, .. } => started.insert(request.target),
+                    Event::Finished {
+                        request,
+                        result: Err(_),
+                        ..
+                    } => failed.insert(request.target),
+                    Event::Finished { .. } => panic!("build should fail"),
+                };
+            }
+
+            assert_eq!(started, failed);
+            started
+        };
+
+        let all: RapidHashSet<_> = targets.into_iter().collect();
+        assert_eq!(started(true, 1).await, all);
+
+        let stopped = started(false, 1).await;
+        assert_eq!(stopped.len(), 1);
+        assert!(stopped.is_subset(&all));
+    }
+
+    #[tokio::test]
+    async fn test_schedule_report() {
+        let planner = mixed_planner([0; 4]);
+        let temp = tempfile::tempdir().unwrap();
+        let builder = Builder::new(temp.path().to_path_buf()).register(|_| Ok(Yielder));
+        let targets = [gen_name!(app@my), gen_name!(dependent@my)]
+            .map(|name| planner.resolve(&name).unwrap());
+
+        let (events, receiver) = mpsc::channel();
+        let report = Scheduler::new(&planner, &builder)
+            .schedule(&targets, events)
+            .await;
+
+        let (mut built, mut failed) = (Vec::new(), Vec::new());
+        for event in receiver.try_iter() {
+            if let Event::Finished {
+                request, result, ..
+            } = event
+            {
+                match result {
+                    Ok(()) => built.push(request.target),
+                    Err(_) => failed.push(request.target),
+                }
+            }
+        }
+
+        assert_eq!(report.built, built);
+        assert_eq!(report.built.len(), 4);
+        let report_failed: Vec<_> = report.failed.iter().map(|(node, _)| *node).collect();
+        assert_eq!(report_failed, failed);
+        assert_eq!(failed, [planner.resolve(&gen_name!(broken@my)).unwrap()]);
+        assert_eq!(
+            report.skipped,
+            [planner.resolve(&gen_name!(dependent@my)).unwrap()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_prebuilt() {
+        let temp = tempfile::tempdir().unwrap();
+        let builder = Builder::new(temp.path().to_path_buf()).register(|_| Ok(Yielder));
+        let schedule = async |planner: &Planner<Frozen>, prebuilt: &[PackageId]| {
+            let target = planner.resolve(&gen_name!(app@my)).unwrap();
+            let prebuilt = planner
+                .graph()
+                .node_indices()
+                .filter(|node| prebuilt.contains(&planner.identity(*node).unwrap()));
+
+            let (events, _receiver) = mpsc::channel();
+            let report = Scheduler::new(planner, &builder)
+                .prebuilt(prebuilt)
+                .schedule(&[target], events)
+                .await;
+
+            let mut built: Vec<_> = report
+                .built
+                .iter()
+                .map(|node| planner.graph()[*node].name.clone())
+                .collect();
+            built.sort_unstable_by_key(ToString::to_string);
+            let identities: Vec<_> = report
+                .built
+                .iter()
+                .map(|node| planner.identity(*node).unwrap())
+                .collect();
+
+            (built, identities)
+        };
+
+        let planner = mixed_planner([0; 4]);
+        let (built, store) = schedule(&planner, &[]).await;
+        assert_eq!(built.len(), 4);
+
+        // nothing changed
+        let (built, _) = schedule(&planner, &store).await;
+        assert!(built.is_empty());
+
+        // changing `base` rebuilds it and its dependents, but not `right`
+        let planner = mixed_planner([0, 0, 0, 1]);
+        let (built, _) = schedule(&planner, &store).await;
+        assert_eq!(
+            built,
+            [gen_name!(app@my), gen_name!(base@my), gen_name!(left@my)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resume() {
+        let planner = mixed_planner([0; 4]);
+        let temp = tempfile::tempdir().unwrap();
+        let builder = Builder::new(temp.path().to_path_buf()).register(|_| Ok(Yielder));
+        let resolve = |name| planner.resolve(&name).unwrap();
+
+        let (events, receiver) = mpsc::channel();
+        let report = Scheduler::new(&planner, &builder)
+            .prebuilt([resolve(gen_name!(base@my)), resolve(gen_name!(right@my))])
+            .schedule(&[resolve(gen_name!(app@my))], events)
+            .await;
+
+        let mut started: Vec<_> = receiver
+            .try_iter()
+            .filter_map(|event| match event {
+                Event::Started { name, .. } => Some(name),
+                Event::Finished { .. } => None,
+            })
+            .collect();
+        started.sort_unstable_by_key(ToString::to_string);
+        assert_eq!(started, [gen_name!(app@my), gen_name!(left@my)]);
+        assert!(report.failed.is_empty() && report.skipped.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_build_cost() {
+        let mut planner = Planner::<Unfrozen>::new();
+        let costs = [
+            (gen_name!(cheap@my), Some(1)),
+            (gen_name!(unknown@my), None),
+            (gen_name!(expensive@my), Some(60)),
+        ];
+        for (name, cost) in costs {
+            let mut package = package(name, NAME.clone(), 0, &[]);
+            package.build_cost = cost;
+            planner.register(package).unwrap();
+        }
+        let mut planner = planner.freeze().unwrap();
+        let unknown = planner.resolve(&gen_name!(unknown@my)).unwrap();
+        assert!(planner.set_build_cost(unknown, 30));
+
+        let temp = tempfile::tempdir().unwrap();
+        let builder = Builder::new(temp.path().to_path_buf()).register(|_| Ok(Yielder));
+        let targets: Vec<_> = planner.graph().node_indices().collect();
+
+        let (events, receiver) = mpsc::channel();
+        Scheduler::new(&planner, &builder)
+            .concurrency(1)
+            .schedule(&targets, events)
+            .await;
+
+        let started: Vec<_> = receiver
+            .try_iter()
+            .filter_map(|event| match event {
+                Event::Started { name, .. } => Some(name),
+                Event::Finished { .. } => None,
+            })
+            .collect();
+        assert_eq!(
+            started,
+            [
+                gen_name!(expensive@my),
+                gen_name!(unknown@my),
+                gen_name!(cheap@my)
+            ]
+        );
     }
 }
@@ -2,20 +2,33 @@ pub mod empty;
 
 pub use empty::EmptyStore;
 
+use std::path::Path;
+
 use jiff::Timestamp;
 use xh_archive::Event;
 use xh_reports::prelude::*;
 
-use crate::{name::StoreName, planner::PackageId};
+use crate::{
+    name::{PackageName, StoreName},
+    planner::PackageId,
+};
 
 #[derive(Default, Debug, IntoReport)]
 #[message("could not execute store action")]
 pub struct Error;
 
+#[derive(Debug, IntoReport)]
+#[message("artifact is not in the store")]
+#[context(display: artifact)]
+pub struct MissingArtifactError {
+    pub artifact: ArtifactId,
+}
+
 pub type ArtifactId = blake3::Hash;
 
 #[derive(Debug)]
 pub struct StorePackage {
+    pub name: PackageName,
     pub id: PackageId,
     pub artifact: ArtifactId,
     pub created_at: Timestamp,
@@ -25,6 +38,10 @@ pub struct StorePackage {
 pub struct StoreArtifact {
     pub id: ArtifactId,
     pub created_at: Timestamp,
+    /// Size of the encoded archive in bytes, if recorded when it was registered.
+    pub size: Option<u64>,
+    /// Amount of objects in the archive, if recorded when it was registered.
+    pub objects: Option<u64>,
 }
 
 pub trait Store {
@@ -32,20 +49,38 @@ pub trait Store {
 
     fn register_package(
         &mut self,
+        name: &PackageName,
         package: &PackageId,
         artifact: &ArtifactId,
     ) -> impl Future<Output = Result<StorePackage, Error>> + Send;
 
+    /// Removes a package from the store, returning whether it was registered.
+    fn unregister_package(
+        &mut self,
+        package: &PackageId,
+    ) -> impl Future<Output = Result<bool, Error>> + Send;
+
     fn package(
         &self,
         package: &PackageId,
     ) -> impl Future<Output = Result<Option<StorePackage>, Error>> + Send;
 
+    /// Lists every package in the store, ordered by name, then most recent first.
+    fn list_packages(&self) -> impl Future<Output = Result<Vec<StorePackage>, Error>> + Send;
+
     fn register_artifact(
         &mut self,
         archive: Vec<Event>,
     ) -> impl Future<Output = Result<StoreArtifact, Error>> + Send;
 
+    /// Registers an artifact from a stream of events, without collecting them first.
+    fn register_artifact_iter<I>(
+        &mut self,
+        archive: I,
+    ) -> impl Future<Output = Result<StoreArtifact, Error>> + Send
+    where
+        I: Iterator<Item = Result<Event, Error>> + Send + 'static;
+
     fn artifact(
         &self,
         artifact: &ArtifactId,
@@ -57,4 +92,21 @@ pub trait Store {
     ) -> impl Future<
         Output = Result<Option<Vec<Event>>, Error>,
     > + Send;
+
+    /// Streams an artifact's events through `f`, without collecting them first.
+    fn download_iter<F, T>(
+        &self,
+        artifact: &ArtifactId,
+        f: F,
+    ) -> impl Future<Output = Result<Option<T>, Error>> + Send
+    where
+        F: FnOnce(&mut dyn Iterator<Item = Result<Event, Error>>) -> T + Send + 'static,
+        T: Send + 'static;
+
+    /// Unpacks an artifact into `dest`, failing with a [`MissingArtifactError`] if it isn't stored.
+    fn extract(
+        &self,
+        artifact: &ArtifactId,
+        dest: &Path,
+    ) -> impl Future<Output = Result<(), Error>> + Send;
 }
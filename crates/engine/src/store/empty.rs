@@ -1,13 +1,13 @@
-use std::sync::LazyLock;
+use std::{path::Path, sync::LazyLock};
 
 use xh_archive::Event;
 use xh_reports::prelude::*;
 
 use crate::{
     gen_name,
-    name::StoreName,
+    name::{PackageName, StoreName},
     planner::PackageId,
-    store::{ArtifactId, Error, Store, StoreArtifact, StorePackage},
+    store::{ArtifactId, Error, MissingArtifactError, Store, StoreArtifact, StorePackage},
 };
 
 #[derive(Debug, IntoReport)]
@@ -25,20 +25,36 @@ impl Store for EmptyStore {
 
     async fn register_package(
         &mut self,
+        _name: &PackageName,
         _package: &PackageId,
         _artifact: &ArtifactId,
     ) -> Result<StorePackage, Error> {
         Err(CannotRegister.wrap())
     }
 
+    async fn unregister_package(&mut self, _package: &PackageId) -> Result<bool, Error> {
+        Ok(false)
+    }
+
     async fn package(&self, _package: &PackageId) -> Result<Option<StorePackage>, Error> {
         Ok(None)
     }
 
+    async fn list_packages(&self) -> Result<Vec<StorePackage>, Error> {
+        Ok(Vec::new())
+    }
+
     async fn register_artifact(&mut self, _archive: Vec<Event>) -> Result<StoreArtifact, Error> {
         Err(CannotRegister.wrap())
     }
 
+    async fn register_artifact_iter<I>(&mut self, _archive: I) -> Result<StoreArtifact, Error>
+    where
+        I: Iterator<Item = Result<Event, Error>> + Send + 'static,
+    {
+        Err(CannotRegister.wrap())
+    }
+
     async fn artifact(&self, _artifact: &ArtifactId) -> Result<Option<StoreArtifact>, Error> {
         Ok(None)
     }
@@ -46,4 +62,19 @@ impl Store for EmptyStore {
     async fn download(&self, _artifact: &ArtifactId) -> Result<Option<Vec<Event>>, Error> {
         Ok(None)
     }
+
+    async fn download_iter<F, T>(&self, _artifact: &ArtifactId, _f: F) -> Result<Option<T>, Error>
+    where
+        F: FnOnce(&mut dyn Iterator<Item = Result<Event, Error>>) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        Ok(None)
+    }
+
+    async fn extract(&self, artifact: &ArtifactId, _dest: &Path) -> Result<(), Error> {
+        Err(MissingArtifactError {
+            artifact: *artifact,
+        }
+        .wrap())
+    }
 }
@@ -1,12 +1,17 @@
 use std::{
+    fs,
+    os::unix::fs::PermissionsExt,
+    path::{Component, Path, PathBuf},
     process::{ExitStatus, Output, Stdio},
     sync::{Arc, LazyLock},
+    time::Instant,
 };
 
 use serde::{Deserialize, Serialize};
 use smol_str::SmolStr;
+use tokio::process::Command;
 use xh_engine::{
-    builder::InitializeContext,
+    builder::{InitializationError, InitializeContext},
     executor::{Error, Executor},
     gen_name,
     name::ExecutorName,
@@ -14,14 +19,95 @@ use xh_engine::{
 use xh_reports::prelude::*;
 
 #[derive(Debug, IntoReport)]
-#[message("external command failed")]
-#[context(display: status)]
-#[attachment(display: stderr)]
-pub struct CommandError {
-    status: ExitStatus,
-    stderr: String,
+pub enum CommandError {
+    #[message("external command failed")]
+    #[context(display: status)]
+    #[attachment(display: stderr)]
+    Failed { status: ExitStatus, stderr: String },
+    #[message("external command ran out of memory")]
+    #[suggestion("raise the memory_limit option")]
+    #[context(limit)]
+    #[attachment(display: stderr)]
+    OutOfMemory { limit: u64, stderr: String },
 }
 
+#[derive(Debug, IntoReport)]
+#[message("busybox bootstrap is not an executable file")]
+#[suggestion("point the busybox option at a static busybox binary")]
+#[context(path)]
+pub struct BusyboxError {
+    path: PathBuf,
+}
+
+#[derive(Debug, IntoReport)]
+#[message("sandbox is not isolated from the host")]
+#[suggestion("check that bubblewrap supports --unshare-all and --clearenv")]
+#[context(leak)]
+pub struct IsolationError {
+    leak: &'static str,
+}
+
+#[derive(Debug, IntoReport)]
+#[message("cpu quota is not a positive number")]
+#[suggestion("set cpu_quota to a positive amount of CPUs, such as 1.5")]
+#[context(quota)]
+pub struct CpuQuotaError {
+    quota: f64,
+}
+
+#[derive(Debug, IntoReport)]
+#[message("bind mount destinations overlap")]
+#[suggestion(
+    "give every bind mount its own destination, outside of /busybox, /proc, /dev, and other binds"
+)]
+#[context(destination, first, second)]
+pub struct ConflictingBindError {
+    destination: PathBuf,
+    first: PathBuf,
+    second: PathBuf,
+}
+
+#[derive(Debug, IntoReport)]
+#[message("bind mount destination is not an absolute path")]
+#[suggestion("use an absolute destination without `..` components")]
+#[context(destination)]
+pub struct InvalidBindError {
+    destination: PathBuf,
+}
+
+/// Destination of the busybox bootstrap inside the sandbox.
+const BUSYBOX_DESTINATION: &str = "/busybox";
+
+/// Runs `bwrap` inside the transient scope, then prints the scope's `oom_kill` count.
+///
+/// The shell stays in the scope after `bwrap` exits, so its cgroup can still be read.
+const SCOPE_SCRIPT: &str = r#""$@" >/dev/null
+status=$?
+while IFS= read -r line; do
+    case $line in 0::*) cgroup=${line#0::} ;; esac
+done </proc/self/cgroup
+while read -r key value; do
+    [ "$key" = oom_kill ] && echo "$value"
+done 2>/dev/null <"/sys/fs/cgroup$cgroup/memory.events"
+exit $status
+"#;
+
+/// Exit code of an isolation probe which found host state.
+const LEAKED: i32 = 100;
+
+/// Environment variable set on the host when probing, which must not reach the sandbox.
+const PROBE_VARIABLE: &str = "XUEHUA_ISOLATION_PROBE";
+
+/// Probes run by [`BubblewrapExecutor::verify_isolation`], as a description of the leak,
+/// and a shell condition holding only while isolated.
+const PROBES: &[(&str, &str)] = &[
+    ("host file /etc/hostname", "test ! -e /etc/hostname"),
+    (
+        "host environment variable",
+        "test -z \"${XUEHUA_ISOLATION_PROBE+set}\"",
+    ),
+];
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(default)]
 pub struct Request {
@@ -31,11 +117,43 @@ pub struct Request {
     pub environment: Vec<(SmolStr, SmolStr)>,
 }
 
+/// Host path made available inside the sandbox.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct BindMount {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    #[serde(default)]
+    pub read_only: bool,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct Options {
     pub network: bool,
     pub add_capabilities: Vec<String>,
     pub drop_capabilities: Vec<String>,
+    /// Static shell bound read-only to `/busybox` inside the sandbox.
+    #[serde(default = "default_busybox")]
+    pub busybox: PathBuf,
+    /// Maximum memory in bytes the sandbox may use, swap included.
+    #[serde(default)]
+    pub memory_limit: Option<u64>,
+    /// Maximum CPU time the sandbox may use, as a number of CPUs (`1.5` is one and a half).
+    #[serde(default)]
+    pub cpu_quota: Option<f64>,
+    /// User id the command runs as inside the sandbox's user namespace.
+    #[serde(default)]
+    pub uid: Option<u32>,
+    /// Group id the command runs as inside the sandbox's user namespace.
+    #[serde(default)]
+    pub gid: Option<u32>,
+    /// Additional host paths bound into the sandbox, after the environment and busybox.
+    #[serde(default)]
+    pub binds: Vec<BindMount>,
+}
+
+// TODO: move busybox bootstrap to its own package
+fn default_busybox() -> PathBuf {
+    "busybox-bootstrap".into()
 }
 
 impl Default for Options {
@@ -44,6 +162,12 @@ impl Default for Options {
             network: true,
             add_capabilities: Vec::default(),
             drop_capabilities: Vec::default(),
+            busybox: default_busybox(),
+            memory_limit: None,
+            cpu_quota: None,
+            uid: None,
+            gid: None,
+            binds: Vec::new(),
         }
     }
 }
@@ -61,6 +185,17 @@ impl Default for Options {
 /// - `--unshare-all`
 /// - `--clearenv`
 ///
+/// Setting [`Options::uid`] or [`Options::gid`] additionally requires a user namespace (`--unshare-user`),
+/// so the build sees the same identity regardless of the invoking user.
+///
+/// Working directories are resolved within the environment, and requests escaping it are rejected.
+///
+/// # Resource Limits
+///
+/// When [`Options::memory_limit`] or [`Options::cpu_quota`] are set, the sandbox is started
+/// in a transient cgroup through `systemd-run --user --scope`, which requires a systemd user session.
+/// A failed command is reported as out of memory if the OOM killer killed anything in that cgroup.
+///
 /// # Command Runner
 ///
 /// To execute multiple commands within the sandbox, this executor bundles a command runner.
@@ -71,25 +206,122 @@ pub struct BubblewrapExecutor {
 }
 
 impl BubblewrapExecutor {
-    #[inline]
-    pub fn new(ctx: Arc<InitializeContext>, options: Options) -> Self {
-        Self { ctx, options }
+    /// Constructs a new executor, checking that [`Options::cpu_quota`] is a positive number,
+    /// and that [`Options::binds`] have absolute destinations that don't overlap.
+    ///
+    /// [`Options::busybox`] is only checked once a request is executed,
+    /// so builds without bubblewrap requests don't need it.
+    pub fn new(ctx: Arc<InitializeContext>, options: Options) -> Result<Self, InitializationError> {
+        if let Some(quota) = options.cpu_quota
+            && !(quota.is_finite() && quota > 0.0)
+        {
+            return Err(CpuQuotaError { quota }.wrap());
+        }
+
+        check_binds(&ctx.environment, &options)?;
+        Ok(Self { ctx, options })
     }
-}
 
-impl Executor for BubblewrapExecutor {
-    type Request = Request;
+    /// Checks that [`Options::busybox`] is an executable file.
+    fn check_busybox(&self) -> Result<(), Error> {
+        let executable = fs::metadata(&self.options.busybox)
+            .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0);
 
-    fn name() -> &'static ExecutorName {
-        static NAME: LazyLock<ExecutorName> = LazyLock::new(|| gen_name!(bubblewrap@xuehua));
-        &NAME
+        if !executable {
+            return Err(BusyboxError {
+                path: self.options.busybox.clone(),
+            }
+            .wrap());
+        }
+
+        Ok(())
     }
 
-    #[tracing::instrument(level = "debug", skip(self))]
-    async fn execute(&mut self, request: Self::Request) -> Result<(), Error> {
-        let mut sandboxed = tokio::process::Command::new("bwrap");
+    /// Checks that sandboxed commands can't observe host files or environment variables.
+    ///
+    /// Each probe runs `/busybox sh` inside the sandbox, so the environment should be empty.
+    pub async fn verify_isolation(&self) -> Result<(), Error> {
+        self.check_busybox()?;
+        for (leak, condition) in PROBES {
+            let mut command = self.command(Request {
+                program: "/busybox".into(),
+                arguments: vec![
+                    "sh".into(),
+                    "-c".into(),
+                    format!("{condition} || exit {LEAKED}").into(),
+                ],
+                ..Request::default()
+            })?;
+            command.env(PROBE_VARIABLE, "leaked");
+
+            let Output { status, stderr, .. } = command.output().await.wrap()?;
+            match status.code() {
+                Some(0) => (),
+                Some(LEAKED) => return Err(IsolationError { leak }.wrap()),
+                _ => {
+                    let stderr = String::from_utf8_lossy(&stderr).to_string();
+                    return Err(CommandError::Failed { status, stderr }.wrap());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Resolves a working directory to its path inside the sandbox.
+    ///
+    /// Defaults to the root of the environment, and rejects paths that escape it.
+    fn working_dir(&self, working_dir: Option<&str>) -> Result<PathBuf, Error> {
+        let environment = &self.ctx.environment;
+        let resolved =
+            xh_common::safe_path(environment, Path::new(working_dir.unwrap_or_default())).wrap()?;
+        let relative = resolved
+            .strip_prefix(environment)
+            .expect("path should be a child of the environment");
+
+        Ok(Path::new("/").join(relative))
+    }
+
+    /// Resource control properties applied to the sandbox's cgroup.
+    fn properties(&self) -> Vec<String> {
+        let mut properties = Vec::new();
+
+        if let Some(limit) = self.options.memory_limit {
+            properties.push(format!("MemoryMax={limit}"));
+            properties.push("MemorySwapMax=0".to_string());
+        }
+
+        if let Some(quota) = self.options.cpu_quota {
+            properties.push(format!("CPUQuota={}%", (quota * 100.0).round() as u64));
+        }
+
+        properties
+    }
+
+    fn command(&self, request: Request) -> Result<Command, Error> {
+        let working_dir = self.working_dir(request.working_dir.as_deref())?;
+
+        let properties = self.properties();
+        let mut sandboxed = if properties.is_empty() {
+            Command::new("bwrap")
+        } else {
+            let mut scoped = Command::new("systemd-run");
+            scoped.args(["--user", "--scope", "--quiet", "--collect"]);
+            scoped.args(
+                properties
+                    .iter()
+                    .flat_map(|property| ["--property", property]),
+            );
+            scoped.args(["--", "sh", "-c", SCOPE_SCRIPT, "sh", "bwrap"]);
+            scoped
+        };
         sandboxed.stdin(Stdio::null());
-        sandboxed.stdout(Stdio::null());
+        // the scope script discards the sandbox's stdout, and only writes its oom_kill count there
+        sandboxed.stdout(if properties.is_empty() {
+            Stdio::null()
+        } else {
+            Stdio::piped()
+        });
         sandboxed.stderr(Stdio::piped());
 
         // essentials
@@ -97,16 +329,21 @@ impl Executor for BubblewrapExecutor {
             .arg("--bind")
             .arg(&self.ctx.environment)
             .arg("/")
-            .args([
-                // TODO: move busybox bootstrap to its own package
-                "--ro-bind",
-                "busybox-bootstrap",
-                "/busybox",
-                "--proc",
-                "/proc",
-                "--dev",
-                "/dev",
-            ]);
+            .arg("--ro-bind")
+            .arg(&self.options.busybox)
+            .arg(BUSYBOX_DESTINATION)
+            .args(["--proc", "/proc", "--dev", "/dev"]);
+
+        for bind in &self.options.binds {
+            sandboxed
+                .arg(if bind.read_only {
+                    "--ro-bind"
+                } else {
+                    "--bind"
+                })
+                .arg(&bind.source)
+                .arg(&bind.destination);
+        }
 
         // restrictions
         sandboxed.args([
@@ -116,6 +353,19 @@ impl Executor for BubblewrapExecutor {
             "--unshare-all",
         ]);
 
+        // identity
+        if self.options.uid.is_some() || self.options.gid.is_some() {
+            sandboxed.arg("--unshare-user");
+        }
+
+        if let Some(uid) = self.options.uid {
+            sandboxed.arg("--uid").arg(uid.to_string());
+        }
+
+        if let Some(gid) = self.options.gid {
+            sandboxed.arg("--gid").arg(gid.to_string());
+        }
+
         sandboxed.args(
             self.options
                 .add_capabilities
@@ -135,9 +385,7 @@ impl Executor for BubblewrapExecutor {
         }
 
         // command payload
-        if let Some(working_dir) = request.working_dir {
-            sandboxed.arg("--chdir").arg(working_dir);
-        }
+        sandboxed.arg("--chdir").arg(working_dir);
 
         for (key, value) in request.environment {
             sandboxed.args(["--setenv", &key, &value]);
@@ -148,18 +396,391 @@ impl Executor for BubblewrapExecutor {
             .arg(request.program)
             .args(request.arguments);
 
+        Ok(sandboxed)
+    }
+}
+
+impl Executor for BubblewrapExecutor {
+    type Request = Request;
+
+    fn name() -> &'static ExecutorName {
+        static NAME: LazyLock<ExecutorName> = LazyLock::new(|| gen_name!(bubblewrap@xuehua));
+        &NAME
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn execute(&mut self, request: Self::Request) -> Result<(), Error> {
+        self.check_busybox()?;
+
+        let start = Instant::now();
         let Output {
             status,
             stderr,
-            stdout: _,
-        } = sandboxed.output().await.wrap()?;
-        status
-            .success()
-            .then_some(())
-            .ok_or(CommandError {
-                status,
-                stderr: String::from_utf8_lossy(&stderr).to_string(),
-            })
+            stdout,
+        } = self.command(request)?.output().await.wrap()?;
+
+        let duration = start.elapsed();
+        tracing::debug!(?duration, "sandboxed command exited");
+        if status.success() {
+            return Ok(());
+        }
+
+        let stderr = String::from_utf8_lossy(&stderr).to_string();
+        let error = match self.options.memory_limit {
+            Some(limit) if oom_kills(&stdout) > 0 => CommandError::OutOfMemory { limit, stderr },
+            _ => CommandError::Failed { status, stderr },
+        };
+
+        Err(error.wrap().with_frame(Frame::timing("execute", duration)))
+    }
+}
+
+/// Rejects bind mounts whose destination is within, or contains, the destination of an earlier mount,
+/// be it another bind mount or one of the sandbox's own.
+///
+/// `bwrap` applies binds in order, so a later bind silently shadows an earlier one, or part of it.
+/// Every bind is within the environment mounted at `/` though, which only conflicts with binds over `/` itself.
+fn check_binds(environment: &Path, options: &Options) -> Result<(), InitializationError> {
+    let mut destinations = vec![
+        (PathBuf::from("/"), environment),
+        (
+            PathBuf::from(BUSYBOX_DESTINATION),
+            options.busybox.as_path(),
+        ),
+        (PathBuf::from("/proc"), Path::new("proc")),
+        (PathBuf::from("/dev"), Path::new("dev")),
+    ];
+    for bind in &options.binds {
+        let destination = normalize(&bind.destination).ok_or_else(|| {
+            InvalidBindError {
+                destination: bind.destination.clone(),
+            }
             .wrap()
+        })?;
+
+        let overlapping = destinations.iter().find(|(other, _)| {
+            let root = other.parent().is_none();
+            (destination.starts_with(other) && !root) || other.starts_with(&destination)
+        });
+        if let Some((_, first)) = overlapping {
+            return Err(ConflictingBindError {
+                destination: bind.destination.clone(),
+                first: first.to_path_buf(),
+                second: bind.source.clone(),
+            }
+            .wrap());
+        }
+
+        destinations.push((destination, &bind.source));
+    }
+
+    Ok(())
+}
+
+/// Normalizes an absolute path without `..` components, like `/usr//share/` into `/usr/share`.
+fn normalize(path: &Path) -> Option<PathBuf> {
+    if !path.is_absolute() {
+        return None;
+    }
+
+    path.components().try_fold(
+        PathBuf::new(),
+        |mut normalized, component| match component {
+            Component::ParentDir => None,
+            component => {
+                normalized.push(component);
+                Some(normalized)
+            }
+        },
+    )
+}
+
+/// Parses the amount of processes the OOM killer killed in the sandbox's scope, see [`SCOPE_SCRIPT`].
+///
+/// Unscoped sandboxes have no memory limit, and print nothing.
+fn oom_kills(stdout: &[u8]) -> u64 {
+    String::from_utf8_lossy(stdout).trim().parse().unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsStr, fs, os::unix::fs::PermissionsExt, path::Path, sync::Arc};
+
+    use xh_engine::builder::InitializeContext;
+
+    use crate::{BindMount, BubblewrapExecutor, Options, Request, SCOPE_SCRIPT, oom_kills};
+
+    #[test]
+    fn test_working_dir() {
+        let ctx = InitializeContext {
+            environment: "/var/lib/xuehua/builds/0".into(),
+        };
+        let executor = BubblewrapExecutor {
+            ctx: Arc::new(ctx),
+            options: Options::default(),
+        };
+
+        assert_eq!(executor.working_dir(None).unwrap(), Path::new("/"));
+        assert_eq!(
+            executor.working_dir(Some("build/./src")).unwrap(),
+            Path::new("/build/src")
+        );
+        assert_eq!(
+            executor.working_dir(Some("build/../src")).unwrap(),
+            Path::new("/src")
+        );
+        assert!(executor.working_dir(Some("../..")).is_err());
+        assert!(executor.working_dir(Some("build/../../1")).is_err());
+    }
+
+    #[test]
+    fn test_busybox() {
+        let temp = tempfile::tempdir().unwrap();
+        let busybox = temp.path().join("busybox");
+        let ctx = Arc::new(InitializeContext {
+            environment: temp.path().join("environment"),
+        });
+        let options = || Options {
+            busybox: busybox.clone(),
+            ..Options::default()
+        };
+
+        // only checked once a request is executed
+        let executor = BubblewrapExecutor::new(ctx, options()).unwrap();
+        assert!(executor.check_busybox().is_err());
+
+        // not executable
+        fs::write(&busybox, "#!/bin/sh").unwrap();
+        assert!(executor.check_busybox().is_err());
+
+        fs::set_permissions(&busybox, fs::Permissions::from_mode(0o755)).unwrap();
+        executor.check_busybox().unwrap();
+        let command = executor.command(Request::default()).unwrap();
+        let arguments: Vec<_> = command.as_std().get_args().collect();
+        assert!(arguments.windows(3).any(|window| window
+            == [
+                OsStr::new("--ro-bind"),
+                busybox.as_os_str(),
+                OsStr::new("/busybox")
+            ]));
+    }
+
+    #[test]
+    fn test_conflicting_binds() {
+        let temp = tempfile::tempdir().unwrap();
+        let busybox = temp.path().join("busybox");
+        fs::write(&busybox, "#!/bin/sh").unwrap();
+        fs::set_permissions(&busybox, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let ctx = Arc::new(InitializeContext {
+            environment: temp.path().join("environment"),
+        });
+        let bind = |source: &str, destination: &str| BindMount {
+            source: source.into(),
+            destination: destination.into(),
+            read_only: true,
+        };
+        let options = |binds| Options {
+            busybox: busybox.clone(),
+            binds,
+            ..Options::default()
+        };
+
+        let executor = BubblewrapExecutor::new(
+            ctx.clone(),
+            options(vec![bind("/usr/share", "/share"), bind("/etc/ssl", "/ssl")]),
+        )
+        .unwrap();
+        let command = executor.command(Request::default()).unwrap();
+        let arguments: Vec<_> = command.as_std().get_args().collect();
+        assert!(
+            arguments
+                .windows(3)
+                .any(|window| window == ["--ro-bind", "/etc/ssl", "/ssl"])
+        );
+
+        for binds in [
+            vec![bind("/usr/share", "/share"), bind("/opt/share", "/share/")],
+            vec![
+                bind("/usr/share", "/share"),
+                bind("/opt/share", "/share/./doc"),
+            ],
+            vec![
+                bind("/usr/share", "/share//doc"),
+                bind("/opt/share", "/share"),
+            ],
+            vec![bind("/bin/busybox", "/busybox")],
+            vec![bind("/tmp/proc", "/proc/self")],
+            vec![bind("/dev/shm", "/dev/shm")],
+            vec![bind("/", "/")],
+        ] {
+            let Err(error) = BubblewrapExecutor::new(ctx.clone(), options(binds.clone())) else {
+                panic!("overlapping binds should conflict: {binds:?}");
+            };
+            assert_eq!(
+                error.root_cause().message,
+                "bind mount destinations overlap"
+            );
+        }
+
+        for destination in ["share", "/share/../etc"] {
+            let Err(error) = BubblewrapExecutor::new(
+                ctx.clone(),
+                options(vec![bind("/usr/share", destination)]),
+            ) else {
+                panic!("{destination} should be rejected");
+            };
+            assert_eq!(
+                error.root_cause().message,
+                "bind mount destination is not an absolute path"
+            );
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        let ctx = Arc::new(InitializeContext {
+            environment: "/var/lib/xuehua/builds/0".into(),
+        });
+        let executor = BubblewrapExecutor {
+            ctx: ctx.clone(),
+            options: Options::default(),
+        };
+        let command = executor.command(Request::default()).unwrap();
+        assert_eq!(command.as_std().get_program(), "bwrap");
+
+        let executor = BubblewrapExecutor {
+            ctx: ctx.clone(),
+            options: Options {
+                memory_limit: Some(64 * 1024 * 1024),
+                cpu_quota: Some(1.5),
+                ..Options::default()
+            },
+        };
+        assert_eq!(
+            executor.properties(),
+            ["MemoryMax=67108864", "MemorySwapMax=0", "CPUQuota=150%"]
+        );
+
+        let command = executor.command(Request::default()).unwrap();
+        let arguments: Vec<_> = command.as_std().get_args().collect();
+        assert_eq!(command.as_std().get_program(), "systemd-run");
+        assert!(
+            arguments
+                .windows(6)
+                .any(|window| window == ["--", "sh", "-c", SCOPE_SCRIPT, "sh", "bwrap"])
+        );
+
+        for quota in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let options = Options {
+                cpu_quota: Some(quota),
+                ..Options::default()
+            };
+            assert!(BubblewrapExecutor::new(ctx.clone(), options).is_err());
+        }
+    }
+
+    #[test]
+    fn test_scope_script() {
+        // the command's own output is hidden, and its status is kept
+        let output = std::process::Command::new("sh")
+            .args(["-c", SCOPE_SCRIPT, "sh", "sh", "-c", "echo 5; exit 3"])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(3));
+        assert!(!String::from_utf8_lossy(&output.stdout).contains('5'));
+
+        assert_eq!(oom_kills(b"2\n"), 2);
+        assert_eq!(oom_kills(b""), 0);
+    }
+
+    #[test]
+    fn test_identity() {
+        let ctx = Arc::new(InitializeContext {
+            environment: "/var/lib/xuehua/builds/0".into(),
+        });
+        let arguments = |options| {
+            let executor = BubblewrapExecutor {
+                ctx: ctx.clone(),
+                options,
+            };
+            let command = executor.command(Request::default()).unwrap();
+            command
+                .as_std()
+                .get_args()
+                .map(|argument| argument.to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        };
+
+        let unmapped = arguments(Options::default());
+        assert!(!unmapped.iter().any(|argument| argument == "--unshare-user"));
+        assert!(!unmapped.iter().any(|argument| argument == "--uid"));
+
+        let mapped = arguments(Options {
+            uid: Some(0),
+            gid: Some(0),
+            ..Options::default()
+        });
+        let position = |flag: &str| mapped.iter().position(|argument| argument == flag);
+        assert!(position("--unshare-all").unwrap() < position("--unshare-user").unwrap());
+        assert!(mapped.windows(2).any(|window| window == ["--uid", "0"]));
+        assert!(mapped.windows(2).any(|window| window == ["--gid", "0"]));
+        assert!(position("--gid").unwrap() < position("--").unwrap());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    #[ignore = "requires bwrap and busybox-bootstrap"]
+    async fn test_isolation() {
+        let temp = tempfile::tempdir().unwrap();
+        let ctx = Arc::new(InitializeContext {
+            environment: temp.path().to_path_buf(),
+        });
+        let executor = BubblewrapExecutor::new(ctx, Options::default()).unwrap();
+        executor.verify_isolation().await.unwrap();
+
+        // host files only leak when they are placed in the environment
+        fs::create_dir(temp.path().join("etc")).unwrap();
+        fs::write(temp.path().join("etc/hostname"), "host").unwrap();
+        let error = executor.verify_isolation().await.unwrap_err();
+        assert_eq!(
+            error.root_cause().message,
+            "sandbox is not isolated from the host"
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    #[ignore = "requires bwrap, busybox-bootstrap, and a systemd user session"]
+    async fn test_out_of_memory() {
+        use xh_engine::executor::Executor;
+
+        let temp = tempfile::tempdir().unwrap();
+        let ctx = Arc::new(InitializeContext {
+            environment: temp.path().to_path_buf(),
+        });
+        let options = Options {
+            memory_limit: Some(16 * 1024 * 1024),
+            ..Options::default()
+        };
+        let mut executor = BubblewrapExecutor::new(ctx, options).unwrap();
+
+        let error = executor
+            .execute(Request {
+                program: "/busybox".into(),
+                arguments: vec![
+                    "sh".into(),
+                    "-c".into(),
+                    "x=$(/busybox head -c 268435456 /dev/zero | /busybox tr '\\0' a)".into(),
+                ],
+                ..Request::default()
+            })
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            error.root_cause().message,
+            "external command ran out of memory"
+        );
     }
 }
@@ -1,8 +1,13 @@
 #[cfg(feature = "zstd")]
 mod zstd;
 
+#[cfg(feature = "zstd")]
+pub use zstd::Decoder as ZstdDecoder;
+
 use std::{
-    path::PathBuf,
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
     sync::{Arc, LazyLock},
 };
 
@@ -12,12 +17,75 @@ use xh_engine::{builder::InitializeContext, executor::{Error, Executor}, gen_nam
 
 use xh_reports::prelude::*;
 
+#[derive(Debug, IntoReport)]
+#[message("could not detect compression format")]
+#[suggestion("provide an explicit algorithm")]
+#[context(path)]
+pub struct UnrecognizedFormatError {
+    path: PathBuf,
+}
+
+#[derive(Debug, IntoReport)]
+#[message("compression format is not supported")]
+#[context(format)]
+pub struct UnsupportedFormatError {
+    format: &'static str,
+}
+
+#[derive(Debug, IntoReport)]
+#[message("compression algorithm cannot be detected")]
+#[suggestion("provide an explicit algorithm when compressing")]
+pub struct AutoCompressError;
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Algorithm {
+    /// Detects the algorithm from the input's magic bytes, only valid when decompressing.
+    Auto,
     #[cfg(feature = "zstd")]
     Zstd,
 }
 
+/// Formats recognized by [`Algorithm::Auto`], along with the algorithm decompressing them, if any.
+const FORMATS: &[(&str, &[u8], Option<Algorithm>)] = &[
+    #[cfg(feature = "zstd")]
+    ("zstd", &[0x28, 0xb5, 0x2f, 0xfd], Some(Algorithm::Zstd)),
+    #[cfg(not(feature = "zstd"))]
+    ("zstd", &[0x28, 0xb5, 0x2f, 0xfd], None),
+    ("gzip", &[0x1f, 0x8b], None),
+    ("xz", &[0xfd, b'7', b'z', b'X', b'Z', 0x00], None),
+];
+
+fn format(magic: &[u8]) -> Option<&'static (&'static str, &'static [u8], Option<Algorithm>)> {
+    FORMATS
+        .iter()
+        .find(|(_, prefix, _)| magic.starts_with(prefix))
+}
+
+/// Detects the algorithm `input` was compressed with.
+pub fn detect(input: &Path) -> Result<Algorithm, ()> {
+    let mut magic = Vec::with_capacity(8);
+    File::open(input)
+        .and_then(|file| file.take(8).read_to_end(&mut magic))
+        .erased()?;
+
+    match format(&magic) {
+        Some((_, _, Some(algorithm))) => Ok(algorithm.clone()),
+        Some((format, _, None)) => Err(UnsupportedFormatError { format }).erased(),
+        None => Err(UnrecognizedFormatError {
+            path: input.to_path_buf(),
+        })
+        .erased(),
+    }
+}
+
+/// Returns the algorithm decompressing a stream starting with `magic`, if any.
+///
+/// Unlike [`detect`], unrecognized and unsupported formats aren't errors,
+/// so callers can handle them on their own.
+pub fn recognize(magic: &[u8]) -> Option<Algorithm> {
+    format(magic).and_then(|(_, _, algorithm)| algorithm.clone())
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Action {
     Compress,
@@ -64,7 +132,14 @@ impl Executor for CompressionExecutor {
         let options = self.options.clone();
 
         tokio::task::spawn_blocking(move || {
-            match request.algorithm {
+            let algorithm = match (request.algorithm, &request.action) {
+                (Algorithm::Auto, Action::Compress) => return Err(AutoCompressError).erased(),
+                (Algorithm::Auto, Action::Decompress) => detect(&input)?,
+                (algorithm, _) => algorithm,
+            };
+
+            match algorithm {
+                Algorithm::Auto => unreachable!("algorithm should be detected"),
                 #[cfg(feature = "zstd")]
                 Algorithm::Zstd => match request.action {
                     Action::Compress => zstd::compress(&options, &input, &output),
@@ -78,3 +153,93 @@ impl Executor for CompressionExecutor {
         .wrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::{FORMATS, detect};
+
+    #[test]
+    fn test_detect() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("input");
+
+        for (format, magic, algorithm) in FORMATS {
+            std::fs::write(&path, [*magic, b"data"].concat()).unwrap();
+            match algorithm {
+                Some(algorithm) => assert_eq!(&detect(&path).unwrap(), algorithm),
+                None => {
+                    let report = detect(&path).unwrap_err();
+                    assert_eq!(
+                        report.root_cause().message,
+                        "compression format is not supported",
+                        "{format} should be unsupported"
+                    );
+                }
+            }
+        }
+
+        std::fs::write(&path, b"plain text").unwrap();
+        let report = detect(&path).unwrap_err();
+        assert_eq!(
+            report.root_cause().message,
+            "could not detect compression format"
+        );
+
+        // too short for any magic
+        std::fs::write(&path, [0x28]).unwrap();
+        assert!(detect(&path).is_err());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[tokio::test]
+    async fn test_auto_decompress() {
+        use std::sync::Arc;
+
+        use xh_engine::{builder::InitializeContext, executor::Executor};
+
+        use crate::{Action, Algorithm, CompressionExecutor, Options, Request};
+
+        let temp = tempfile::tempdir().unwrap();
+        let ctx = Arc::new(InitializeContext {
+            environment: temp.path().to_path_buf(),
+        });
+        let mut executor = CompressionExecutor::new(ctx, Options::default());
+        std::fs::write(temp.path().join("plain"), b"xuehua".repeat(64)).unwrap();
+
+        let request = |algorithm, action, input: &str, output: &str| Request {
+            algorithm,
+            action,
+            input: input.into(),
+            output: output.into(),
+        };
+        executor
+            .execute(request(
+                Algorithm::Zstd,
+                Action::Compress,
+                "plain",
+                "compressed",
+            ))
+            .await
+            .unwrap();
+        executor
+            .execute(request(
+                Algorithm::Auto,
+                Action::Decompress,
+                "compressed",
+                "roundtrip",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(temp.path().join("roundtrip")).unwrap(),
+            b"xuehua".repeat(64)
+        );
+
+        assert!(
+            executor
+                .execute(request(Algorithm::Auto, Action::Compress, "plain", "auto"))
+                .await
+                .is_err()
+        );
+    }
+}
@@ -1,7 +1,12 @@
-use std::{fs::File, path::Path};
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::Path,
+};
 
 use memmap2::{Mmap, MmapMut};
 use xh_reports::prelude::*;
+use zstd_safe::{InBuffer, OutBuffer};
 
 use crate::Options;
 
@@ -16,12 +21,12 @@ fn mmap_input(input: &Path) -> Result<Mmap, std::io::Error> {
     Ok(map)
 }
 
-fn mmap_output(output: &Path, size: usize) -> Result<MmapMut, std::io::Error> {
+fn mmap_output(output: &Path, size: usize) -> Result<(File, MmapMut), std::io::Error> {
     let file = File::create_new(output)?;
     file.set_len(size.try_into().expect("cannot resize file past u64::MAX"))?;
     let map = unsafe { memmap2::MmapOptions::new().len(size).map_mut(&file) }?;
 
-    Ok(map)
+    Ok((file, map))
 }
 
 #[tracing::instrument(level = "trace", skip(options))]
@@ -29,14 +34,18 @@ pub fn compress(options: &Options, input: &Path, output: &Path) -> Result<(), ()
     let input = mmap_input(input).erased()?;
 
     let size = zstd_safe::compress_bound(input.len());
-    let mut output = mmap_output(output, size).erased()?;
+    let (file, mut output) = mmap_output(output, size).erased()?;
 
-    map_result(zstd_safe::compress(
+    let written = map_result(zstd_safe::compress(
         output.as_mut(),
         input.as_ref(),
         options.zstd_level,
     ))?;
 
+    // the bound is only an upper limit, trailing space would be read as another frame
+    drop(output);
+    file.set_len(written as u64).erased()?;
+
     Ok(())
 }
 
@@ -51,7 +60,7 @@ pub fn decompress(_options: &Options, input: &Path, output: &Path) -> Result<(),
         1024 * 1024 * 256
     });
 
-    let mut output = mmap_output(
+    let (_, mut output) = mmap_output(
         output,
         size.try_into()
             .expect("cannot mmap region larger than usize::MAX"),
@@ -61,3 +70,65 @@ pub fn decompress(_options: &Options, input: &Path, output: &Path) -> Result<(),
 
     Ok(())
 }
+
+/// Streaming zstd decompression of a reader, for inputs that aren't files on disk.
+pub struct Decoder<R> {
+    reader: R,
+    context: zstd_safe::DCtx<'static>,
+    buffer: Box<[u8]>,
+    position: usize,
+    filled: usize,
+    finished: bool,
+}
+
+impl<R: Read> Decoder<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            context: zstd_safe::DCtx::create(),
+            buffer: vec![0; zstd_safe::DCtx::in_size()].into_boxed_slice(),
+            position: 0,
+            filled: 0,
+            finished: false,
+        }
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            let mut input = InBuffer::around(&self.buffer[self.position..self.filled]);
+            let mut output = OutBuffer::around(buf);
+            let hint = self
+                .context
+                .decompress_stream(&mut output, &mut input)
+                .map_err(|code| io::Error::other(zstd_safe::get_error_name(code)))?;
+            self.position += input.pos();
+            // a hint of 0 means the current frame was fully decoded and flushed
+            self.finished = hint == 0;
+
+            if output.pos() > 0 {
+                return Ok(output.pos());
+            }
+
+            if self.position == self.filled {
+                self.position = 0;
+                self.filled = self.reader.read(&mut self.buffer)?;
+                if self.filled == 0 {
+                    return if self.finished {
+                        Ok(0)
+                    } else {
+                        Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "zstd stream ended mid-frame",
+                        ))
+                    };
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use std::{
+    io::{BufRead, BufReader},
+    path::PathBuf,
+    sync::{Arc, LazyLock},
+    time::Instant,
+};
+
+use serde::{Deserialize, Serialize};
+use ureq::{
+    Agent,
+    config::Config,
+    http::{Method, Request as HttpRequest, Uri},
+};
+use xh_engine::{
+    builder::InitializeContext,
+    executor::{Error, Executor},
+    gen_name,
+    name::ExecutorName,
+};
+use xh_executor_compression::{Algorithm, ZstdDecoder};
+use xh_executor_http::Options;
+use xh_reports::prelude::*;
+
+#[derive(Debug, IntoReport)]
+#[message("could not extract downloaded archive")]
+#[context(url)]
+pub struct ExtractError {
+    url: Uri,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Request {
+    /// Tar archive to download.
+    #[serde(with = "xh_common::serde_display")]
+    pub url: Uri,
+    #[serde(with = "xh_common::serde_display")]
+    pub method: Method,
+    /// Directory the archive is extracted into, relative to the environment.
+    pub output_dir: PathBuf,
+}
+
+/// An executor downloading, decompressing and extracting tar archives in one step
+///
+/// The response body is streamed through the decompressor into the archive,
+/// so no intermediate files are written to the environment.
+/// Zstd and gzip compressed archives are detected from their magic bytes.
+///
+/// Entries are checked the same way as in [`xh_executor_tar::TarExecutor`],
+/// the granular http, compression and tar executors remain available for other pipelines.
+#[derive(Debug)]
+pub struct ExtractExecutor {
+    ctx: Arc<InitializeContext>,
+    agent: Agent,
+}
+
+impl ExtractExecutor {
+    #[inline]
+    pub fn new(ctx: Arc<InitializeContext>, options: Options) -> Self {
+        Self {
+            ctx,
+            agent: Config::builder()
+                .user_agent(options.user_agent)
+                .build()
+                .new_agent(),
+        }
+    }
+}
+
+impl Executor for ExtractExecutor {
+    type Request = Request;
+
+    fn name() -> &'static ExecutorName {
+        static NAME: LazyLock<ExecutorName> = LazyLock::new(|| gen_name!(extract@xuehua));
+        &NAME
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn execute(&mut self, request: Self::Request) -> Result<(), Error> {
+        let output = xh_common::safe_path(&self.ctx.environment, &request.output_dir).wrap()?;
+        let agent = self.agent.clone();
+
+        let start = Instant::now();
+        let span = tracing::Span::current();
+        let result = tokio::task::spawn_blocking(move || {
+            let _guard = span.enter();
+            let url = request.url.clone();
+
+            let http = HttpRequest::builder()
+                .method(request.method)
+                .uri(request.url)
+                .body(())
+                .erased()?;
+            let response = agent.run(http).erased()?;
+            let mut reader = BufReader::new(response.into_body().into_reader());
+
+            let algorithm = xh_executor_compression::recognize(reader.fill_buf().erased()?);
+            tracing::trace!(?algorithm, "detected compression");
+
+            match algorithm {
+                Some(Algorithm::Zstd) => xh_executor_tar::unpack_stream(
+                    BufReader::new(ZstdDecoder::new(reader)),
+                    &output,
+                ),
+                // gzip and uncompressed archives are handled while unpacking
+                _ => xh_executor_tar::unpack_stream(reader, &output),
+            }
+            .wrap_with_fn(|| ExtractError { url })
+            .erased()
+        })
+        .await
+        .erased()
+        .flatten()
+        .wrap();
+
+        let duration = start.elapsed();
+        tracing::debug!(?duration, "request finished");
+        result.with_frame(|| Frame::timing("execute", duration))
+    }
+}
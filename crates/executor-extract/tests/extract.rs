@@ -0,0 +1,88 @@
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+    path::Path,
+    sync::Arc,
+};
+
+use xh_engine::{builder::InitializeContext, executor::Executor};
+use xh_executor_extract::{ExtractExecutor, Request};
+use xh_executor_http::Options;
+
+const FIXTURE: &[u8] = include_bytes!("fixtures/hello.tar.zst");
+
+/// Serves `body` to a single connection.
+fn server(body: &'static [u8]) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let _ = stream.read(&mut [0; 1024]);
+        let header = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len());
+        let _ = stream.write_all(header.as_bytes());
+        let _ = stream.write_all(body);
+    });
+
+    format!("http://{address}/hello-1.0.0-1-any.pkg.tar.zst")
+}
+
+fn executor(environment: &Path) -> ExtractExecutor {
+    ExtractExecutor::new(
+        Arc::new(InitializeContext {
+            environment: environment.to_path_buf(),
+        }),
+        Options::default(),
+    )
+}
+
+fn request(url: String) -> Request {
+    Request {
+        url: url.parse().unwrap(),
+        method: "GET".parse().unwrap(),
+        output_dir: "output".into(),
+    }
+}
+
+#[tokio::test]
+async fn test_extract_fixture() {
+    let temp = tempfile::tempdir().unwrap();
+    executor(temp.path())
+        .execute(request(server(FIXTURE)))
+        .await
+        .unwrap();
+
+    let output = temp.path().join("output");
+    assert_eq!(
+        std::fs::read_to_string(output.join("usr/share/doc/hello/README")).unwrap(),
+        "hello, xuehua\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(output.join("usr/bin/hello")).unwrap(),
+        "#!/bin/sh\necho hello\n"
+    );
+    assert_eq!(
+        std::fs::read_link(output.join("usr/bin/hi")).unwrap(),
+        Path::new("hello")
+    );
+
+    // nothing but the extracted archive is left behind
+    let entries: Vec<_> = std::fs::read_dir(temp.path())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect();
+    assert_eq!(entries, ["output"]);
+}
+
+#[tokio::test]
+async fn test_truncated_fixture() {
+    let temp = tempfile::tempdir().unwrap();
+    let report = executor(temp.path())
+        .execute(request(server(&FIXTURE[..FIXTURE.len() / 2])))
+        .await
+        .unwrap_err();
+
+    assert_eq!(
+        report.into_payload().children[0].message,
+        "could not extract downloaded archive"
+    );
+}
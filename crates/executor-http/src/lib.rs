@@ -1,13 +1,21 @@
+pub use ureq::http::Method;
+
 use std::{
+    io::Read,
     path::{Component, PathBuf},
-    sync::{Arc, LazyLock},
+    sync::{
+        Arc, LazyLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Instant,
 };
 
 use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
 use ureq::{
     Agent,
     config::Config,
-    http::{Method, Request as HttpRequest, Uri},
+    http::{Request as HttpRequest, Uri},
 };
 use xh_engine::{builder::InitializeContext, executor::{Error, Executor}, gen_name, name::ExecutorName};
 use xh_reports::prelude::*;
@@ -39,10 +47,73 @@ fn default_user_agent() -> String {
     concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")).to_string()
 }
 
+#[derive(Debug, Default)]
+struct CancellationState {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+/// Flag aborting in-progress downloads once set.
+#[derive(Debug, Clone, Default)]
+pub struct Cancellation(Arc<CancellationState>);
+
+impl Cancellation {
+    #[inline]
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::Relaxed);
+        self.0.notify.notify_waiters();
+    }
+
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Waits until the flag is set.
+    pub async fn cancelled(&self) {
+        let notified = self.0.notify.notified();
+        let mut notified = std::pin::pin!(notified);
+        notified.as_mut().enable();
+        if !self.is_cancelled() {
+            notified.await;
+        }
+    }
+}
+
+/// Cancels the download of a request whose future was dropped before it finished.
+struct CancelOnDrop(Cancellation);
+
+impl Drop for CancelOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+/// Checks for cancellation before every read of the response body.
+///
+/// A read blocked on a stalled connection can't be interrupted, so [`HttpExecutor::execute`]
+/// also stops waiting on the download once cancelled.
+struct CancellableReader<R> {
+    inner: R,
+    executor: Cancellation,
+    request: Cancellation,
+}
+
+impl<R: Read> Read for CancellableReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.executor.is_cancelled() || self.request.is_cancelled() {
+            return Err(std::io::Error::other("download was cancelled"));
+        }
+
+        self.inner.read(buf)
+    }
+}
+
 #[derive(Debug)]
 pub struct HttpExecutor {
     ctx: Arc<InitializeContext>,
     agent: Agent,
+    cancellation: Cancellation,
 }
 
 impl HttpExecutor {
@@ -54,14 +125,30 @@ impl HttpExecutor {
                 .user_agent(options.user_agent)
                 .build()
                 .new_agent(),
+            cancellation: Cancellation::default(),
         }
     }
+
+    /// Handle aborting every download of this executor, current and future.
+    ///
+    /// Downloads are also aborted when their [`Executor::execute`] future is dropped.
+    #[inline]
+    pub fn cancellation(&self) -> Cancellation {
+        self.cancellation.clone()
+    }
 }
 
 #[derive(Debug, IntoReport)]
 #[message("paths referencing parent directories are not allowed")]
 pub struct InvalidPathError;
 
+#[derive(Debug, IntoReport)]
+#[message("download was cancelled")]
+#[context(path)]
+pub struct CancelledError {
+    path: PathBuf,
+}
+
 impl Executor for HttpExecutor {
     type Request = Request;
 
@@ -83,14 +170,18 @@ impl Executor for HttpExecutor {
             return Err(InvalidPathError.wrap());
         }
 
-        let path = self.ctx.environment.join(request.path);
+        let path = self.ctx.environment.join(&request.path);
         let agent = self.agent.clone();
+        let executor = self.cancellation.clone();
+        let guard = CancelOnDrop(Cancellation::default());
+        let cancelled = guard.0.clone();
 
+        let start = Instant::now();
         let span = tracing::Span::current();
-        tokio::task::spawn_blocking(move || {
+        let download = tokio::task::spawn_blocking(move || {
             let _guard = span.enter();
 
-            let mut file = std::fs::File::create(path).wrap()?;
+            let mut file = std::fs::File::create(&path).wrap()?;
             let request = HttpRequest::builder()
                 .method(request.method)
                 .uri(request.url)
@@ -98,12 +189,206 @@ impl Executor for HttpExecutor {
                 .wrap()?;
 
             let response = agent.run(request).wrap()?;
-            std::io::copy(&mut response.into_body().as_reader(), &mut file).wrap()?;
+            let mut reader = CancellableReader {
+                inner: response.into_body().into_reader(),
+                executor,
+                request: cancelled,
+            };
+
+            if let Err(err) = std::io::copy(&mut reader, &mut file) {
+                drop(file);
+                if let Err(err) = std::fs::remove_file(&path) {
+                    tracing::warn!(
+                        error = &err as &dyn std::error::Error,
+                        "could not remove partial download"
+                    );
+                }
+
+                return if reader.executor.is_cancelled() || reader.request.is_cancelled() {
+                    Err(CancelledError { path }.wrap())
+                } else {
+                    Err(err).wrap()
+                };
+            }
 
             Ok(())
-        })
-        .await
-        .wrap()
-        .flatten()
+        });
+
+        // the download removes its partial file once its current read returns
+        let result = tokio::select! {
+            biased;
+            result = download => result.wrap().flatten(),
+            () = self.cancellation.cancelled() => Err(CancelledError {
+                path: self.ctx.environment.join(request.path),
+            }
+            .wrap()),
+        };
+
+        drop(guard);
+
+        let duration = start.elapsed();
+        tracing::debug!(?duration, "request finished");
+        result.with_frame(|| Frame::timing("execute", duration))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        sync::Arc,
+        time::{Duration, Instant},
+    };
+
+    use serde_json::json;
+    use xh_engine::{
+        builder::InitializeContext,
+        encoding::Value,
+        executor::Executor,
+        gen_name,
+        package::{DispatchRequest, Metadata, Package},
+        planner::{Planner, Unfrozen},
+    };
+
+    use crate::{HttpExecutor, Options, Request};
+
+    /// Serves an endless response body, a few bytes at a time, or stops sending it if `stalled`.
+    fn trickle_server(stalled: bool) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = stream.read(&mut [0; 1024]);
+            let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 1000000000\r\n\r\n");
+            let _ = stream.write_all(&[0; 16]);
+            if stalled {
+                // closed eventually, so the blocked read doesn't hold up the runtime's shutdown
+                std::thread::sleep(Duration::from_secs(2));
+                return;
+            }
+
+            while stream.write_all(&[0; 16]).is_ok() {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        });
+
+        format!("http://{address}/slow")
+    }
+
+    fn planner(payload: Value) -> Planner<Unfrozen> {
+        let mut planner = Planner::new();
+        planner.register_validator::<HttpExecutor>();
+        planner
+            .register(Package {
+                name: gen_name!(fetch@tests),
+                metadata: Metadata,
+                tags: Vec::new(),
+                build_cost: None,
+                requests: vec![DispatchRequest {
+                    executor: HttpExecutor::name().clone(),
+                    payload,
+                    after: None,
+                }],
+                dependencies: Vec::new(),
+            })
+            .unwrap();
+
+        planner
+    }
+
+    #[test]
+    fn test_payload_validation() {
+        let valid = json!({
+            "path": "source.tar.gz",
+            "url": "https://example.com/source.tar.gz",
+            "method": "GET",
+        });
+        assert!(planner(valid).freeze().is_ok());
+
+        // "uri" instead of "url"
+        let malformed = json!({
+            "path": "source.tar.gz",
+            "uri": "https://example.com/source.tar.gz",
+            "method": "GET",
+        });
+        assert!(planner(malformed).freeze().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_user_agent() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let read = stream.read(&mut buffer).unwrap();
+                request.extend_from_slice(&buffer[..read]);
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+
+            String::from_utf8(request).unwrap()
+        });
+
+        let temp = tempfile::tempdir().unwrap();
+        let ctx = Arc::new(InitializeContext {
+            environment: temp.path().to_path_buf(),
+        });
+        let options = Options {
+            user_agent: "mirror-friendly/1.0".to_string(),
+        };
+        HttpExecutor::new(ctx, options)
+            .execute(Request {
+                path: "download".into(),
+                url: format!("http://{address}/").parse().unwrap(),
+                method: "GET".parse().unwrap(),
+            })
+            .await
+            .unwrap();
+
+        let request = server.join().unwrap().to_lowercase();
+        assert!(request.contains("user-agent: mirror-friendly/1.0\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_download() {
+        for stalled in [false, true] {
+            let temp = tempfile::tempdir().unwrap();
+            let ctx = Arc::new(InitializeContext {
+                environment: temp.path().to_path_buf(),
+            });
+            let mut executor = HttpExecutor::new(ctx, Options::default());
+            let cancellation = executor.cancellation();
+
+            let request = Request {
+                path: "download".into(),
+                url: trickle_server(stalled).parse().unwrap(),
+                method: "GET".parse().unwrap(),
+            };
+            let download = tokio::spawn(async move { executor.execute(request).await });
+
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            assert!(temp.path().join("download").exists());
+            let start = Instant::now();
+            cancellation.cancel();
+
+            let report = tokio::time::timeout(Duration::from_secs(5), download)
+                .await
+                .expect("download should stop after cancellation")
+                .unwrap()
+                .unwrap_err();
+            assert!(start.elapsed() < Duration::from_secs(1), "{stalled}");
+            assert_eq!(report.root_cause().message, "download was cancelled");
+
+            // a stalled download can't remove its partial file until its read returns
+            if !stalled {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                assert!(!temp.path().join("download").exists());
+            }
+        }
     }
 }
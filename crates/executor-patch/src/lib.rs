@@ -0,0 +1,248 @@
+pub mod unified;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock},
+};
+
+use serde::{Deserialize, Serialize};
+use xh_engine::{
+    builder::InitializeContext,
+    executor::{Error, Executor},
+    gen_name,
+    name::ExecutorName,
+};
+use xh_reports::prelude::*;
+
+#[derive(Debug, IntoReport)]
+#[message("could not apply patch")]
+#[context(patch)]
+pub struct ApplyError {
+    patch: PathBuf,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Request {
+    /// Unified diff to apply, relative to the environment.
+    pub patch: PathBuf,
+    /// Directory the patch's paths are relative to, itself relative to the environment.
+    #[serde(default)]
+    pub target_dir: PathBuf,
+    /// Amount of leading components removed from the patch's paths, like `patch -p`.
+    #[serde(default)]
+    pub strip: usize,
+}
+
+/// An executor applying unified diffs to files within the environment
+///
+/// Every file is patched in memory first, so a failed hunk leaves the environment untouched.
+#[derive(Debug)]
+pub struct PatchExecutor {
+    ctx: Arc<InitializeContext>,
+}
+
+impl PatchExecutor {
+    #[inline]
+    pub fn new(ctx: Arc<InitializeContext>) -> Self {
+        Self { ctx }
+    }
+}
+
+impl Executor for PatchExecutor {
+    type Request = Request;
+
+    fn name() -> &'static ExecutorName {
+        static NAME: LazyLock<ExecutorName> = LazyLock::new(|| gen_name!(patch@xuehua));
+        &NAME
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn execute(&mut self, request: Self::Request) -> Result<(), Error> {
+        let patch = xh_common::safe_path(&self.ctx.environment, &request.patch).wrap()?;
+        let target = xh_common::safe_path(&self.ctx.environment, &request.target_dir).wrap()?;
+
+        tokio::task::spawn_blocking(move || {
+            apply(&patch, &target, request.strip)
+                .wrap_with_fn(|| ApplyError {
+                    patch: request.patch,
+                })
+                .erased()
+        })
+        .await
+        .erased()
+        .flatten()
+        .wrap()
+    }
+}
+
+fn apply(patch: &Path, target: &Path, strip: usize) -> Result<(), ()> {
+    let contents = fs::read_to_string(patch).erased()?;
+    let files = unified::parse(&contents, strip).erased()?;
+
+    // sections for the same file apply to the same buffer, in order
+    let mut buffers: Vec<(PathBuf, unified::Buffer)> = Vec::new();
+    for file in &files {
+        let path = xh_common::safe_path(target, file.path()).erased()?;
+        let buffer = match buffers.iter().position(|(other, _)| *other == path) {
+            Some(index) => &mut buffers[index].1,
+            None => {
+                let original = match &file.old {
+                    Some(_) => Some(fs::read_to_string(&path).erased()?),
+                    None => None,
+                };
+
+                buffers.push((path, unified::Buffer::new(original.as_deref())));
+                &mut buffers.last_mut().unwrap().1
+            }
+        };
+
+        unified::apply(file, buffer).erased()?;
+    }
+
+    for (path, buffer) in buffers {
+        let contents = buffer.into_contents();
+        tracing::trace!(?path, removed = contents.is_none(), "patching file");
+        match contents {
+            Some(contents) => {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent).erased()?;
+                }
+                fs::write(&path, contents).erased()?;
+            }
+            None => fs::remove_file(&path).erased()?,
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, sync::Arc};
+
+    use xh_engine::{builder::InitializeContext, executor::Executor};
+
+    use crate::{PatchExecutor, Request};
+
+    const PATCH: &str = "\
+diff --git a/src/main.c b/src/main.c
+--- a/src/main.c\t2025-01-01 00:00:00
++++ b/src/main.c\t2025-01-01 00:00:00
+@@ -1,4 +1,4 @@
+ #include <stdio.h>
+ int main() {
+-    printf(\"hello\\n\");
++    printf(\"hello, world\\n\");
+     return 0;
+@@ -6,2 +6,3 @@
+ 
+ // end
++// patched
+--- /dev/null
++++ b/README
+@@ -0,0 +1,2 @@
++patched
++sources
+\\ No newline at end of file
+";
+
+    const MAIN: &str = "\
+// shifted by a line
+#include <stdio.h>
+int main() {
+    printf(\"hello\\n\");
+    return 0;
+}
+
+// end
+";
+
+    fn executor(temp: &tempfile::TempDir) -> PatchExecutor {
+        PatchExecutor::new(Arc::new(InitializeContext {
+            environment: temp.path().to_path_buf(),
+        }))
+    }
+
+    fn request() -> Request {
+        Request {
+            patch: "fix.patch".into(),
+            target_dir: "source".into(),
+            strip: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_clean_apply() {
+        let temp = tempfile::tempdir().unwrap();
+        fs::create_dir_all(temp.path().join("source/src")).unwrap();
+        fs::write(temp.path().join("source/src/main.c"), MAIN).unwrap();
+        fs::write(temp.path().join("fix.patch"), PATCH).unwrap();
+
+        executor(&temp).execute(request()).await.unwrap();
+        assert_eq!(
+            fs::read_to_string(temp.path().join("source/src/main.c")).unwrap(),
+            MAIN.replace("hello\\n", "hello, world\\n") + "// patched\n"
+        );
+        assert_eq!(
+            fs::read_to_string(temp.path().join("source/README")).unwrap(),
+            "patched\nsources"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_conflicting_hunk() {
+        let temp = tempfile::tempdir().unwrap();
+        let main = MAIN.replace("hello", "goodbye");
+        fs::create_dir_all(temp.path().join("source/src")).unwrap();
+        fs::write(temp.path().join("source/src/main.c"), &main).unwrap();
+        fs::write(temp.path().join("fix.patch"), PATCH).unwrap();
+
+        let report = executor(&temp).execute(request()).await.unwrap_err();
+        assert_eq!(report.root_cause().message, "hunk does not apply");
+
+        // nothing is written when any hunk fails
+        assert_eq!(
+            fs::read_to_string(temp.path().join("source/src/main.c")).unwrap(),
+            main
+        );
+        assert!(!temp.path().join("source/README").exists());
+
+        // patches can't reach outside of the environment
+        let escaping = Request {
+            target_dir: "..".into(),
+            ..request()
+        };
+        assert!(executor(&temp).execute(escaping).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_repeated_sections() {
+        let temp = tempfile::tempdir().unwrap();
+        fs::create_dir_all(temp.path().join("source")).unwrap();
+        fs::write(temp.path().join("source/dos.txt"), "one\r\ntwo\r\nthree").unwrap();
+
+        // each section sees the changes of the ones before it
+        let patch = "\
+--- a/dos.txt
++++ b/dos.txt
+@@ -1,2 +1,2 @@
+-one
++ONE
+ two
+--- a/dos.txt
++++ b/dos.txt
+@@ -1,2 +1,2 @@
+ ONE
+-two
++TWO
+";
+        fs::write(temp.path().join("fix.patch"), patch).unwrap();
+
+        executor(&temp).execute(request()).await.unwrap();
+        assert_eq!(
+            fs::read_to_string(temp.path().join("source/dos.txt")).unwrap(),
+            "ONE\r\nTWO\r\nthree"
+        );
+    }
+}
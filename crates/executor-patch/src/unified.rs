@@ -0,0 +1,315 @@
+//! Parsing and applying unified diffs.
+
+use std::path::{Path, PathBuf};
+
+use xh_reports::prelude::*;
+
+#[derive(Debug, IntoReport)]
+#[message("could not parse patch: {reason}")]
+#[context(line)]
+pub struct ParseError {
+    #[format(message)]
+    reason: &'static str,
+    line: usize,
+}
+
+#[derive(Debug, IntoReport)]
+#[message("hunk does not apply")]
+#[suggestion("check that the patch was made against these sources")]
+#[context(file, hunk, line)]
+pub struct HunkError {
+    pub file: PathBuf,
+    /// Position of the hunk within the file's patch, starting at 1.
+    pub hunk: usize,
+    /// Line the hunk expected to start at in the original file.
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Line {
+    Context(String),
+    Removed(String),
+    Added(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    old_start: usize,
+    lines: Vec<Line>,
+    /// Whether the original file ends without a newline within this hunk.
+    old_missing_newline: bool,
+    /// Whether the patched file ends without a newline within this hunk.
+    new_missing_newline: bool,
+}
+
+impl Hunk {
+    fn old_lines(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Context(line) | Line::Removed(line) => Some(line.as_str()),
+            Line::Added(_) => None,
+        })
+    }
+
+    fn new_lines(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Context(line) | Line::Added(line) => Some(line.as_str()),
+            Line::Removed(_) => None,
+        })
+    }
+}
+
+/// Changes to a single file, `None` paths being `/dev/null`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilePatch {
+    pub old: Option<PathBuf>,
+    pub new: Option<PathBuf>,
+    pub hunks: Vec<Hunk>,
+}
+
+impl FilePatch {
+    /// The file this patch applies to.
+    pub fn path(&self) -> &Path {
+        self.new
+            .as_deref()
+            .or(self.old.as_deref())
+            .expect("patch should have at least one path")
+    }
+}
+
+/// Parses a unified diff, removing `strip` leading components from every path.
+///
+/// Anything outside of file headers and hunks, like `diff --git` lines, is ignored.
+pub fn parse(patch: &str, strip: usize) -> Result<Vec<FilePatch>, ParseError> {
+    let mut lines = patch.lines().enumerate().peekable();
+    let mut files: Vec<FilePatch> = Vec::new();
+
+    while let Some((index, line)) = lines.next() {
+        let error = |reason| ParseError {
+            reason,
+            line: index + 1,
+        };
+
+        if let Some(old) = line.strip_prefix("--- ") {
+            let Some((_, new)) = lines.next_if(|(_, line)| line.starts_with("+++ ")) else {
+                return Err(error("missing +++ header").into());
+            };
+
+            let old = header_path(old, strip).ok_or_else(|| error("invalid --- path"))?;
+            let new = header_path(&new[4..], strip).ok_or_else(|| error("invalid +++ path"))?;
+            if old.is_none() && new.is_none() {
+                return Err(error("both paths are /dev/null").into());
+            }
+
+            files.push(FilePatch {
+                old,
+                new,
+                hunks: Vec::new(),
+            });
+        } else if line.starts_with("@@ ") {
+            let file = files
+                .last_mut()
+                .ok_or_else(|| error("hunk before a file header"))?;
+            let (old_start, mut old_count, mut new_count) =
+                hunk_header(line).ok_or_else(|| error("invalid hunk header"))?;
+
+            let mut hunk = Hunk {
+                old_start,
+                lines: Vec::new(),
+                old_missing_newline: false,
+                new_missing_newline: false,
+            };
+            while old_count > 0 || new_count > 0 {
+                let Some((index, line)) = lines.next() else {
+                    return Err(error("hunk ends early").into());
+                };
+                let error = || ParseError {
+                    reason: "hunk is longer than its header",
+                    line: index + 1,
+                };
+
+                // some tools strip the space of empty context lines
+                let (kind, content) = match line.split_at_checked(1) {
+                    Some((kind, content)) => (kind, content),
+                    None => (" ", ""),
+                };
+                let parsed = match kind {
+                    " " => Line::Context(content.to_string()),
+                    "-" => Line::Removed(content.to_string()),
+                    "+" => Line::Added(content.to_string()),
+                    "\\" => {
+                        mark_missing_newline(&mut hunk);
+                        continue;
+                    }
+                    _ => return Err(error().into()),
+                };
+
+                let (old, new) = match parsed {
+                    Line::Context(_) => (1, 1),
+                    Line::Removed(_) => (1, 0),
+                    Line::Added(_) => (0, 1),
+                };
+                old_count = old_count.checked_sub(old).ok_or_else(error)?;
+                new_count = new_count.checked_sub(new).ok_or_else(error)?;
+                hunk.lines.push(parsed);
+            }
+
+            if lines.next_if(|(_, line)| line.starts_with('\\')).is_some() {
+                mark_missing_newline(&mut hunk);
+            }
+
+            file.hunks.push(hunk);
+        }
+    }
+
+    Ok(files)
+}
+
+fn mark_missing_newline(hunk: &mut Hunk) {
+    match hunk.lines.last() {
+        Some(Line::Context(_)) => {
+            hunk.old_missing_newline = true;
+            hunk.new_missing_newline = true;
+        }
+        Some(Line::Removed(_)) => hunk.old_missing_newline = true,
+        Some(Line::Added(_)) => hunk.new_missing_newline = true,
+        None => (),
+    }
+}
+
+/// Parses the path of a `---`/`+++` header, `Some(None)` being `/dev/null`.
+fn header_path(header: &str, strip: usize) -> Option<Option<PathBuf>> {
+    // timestamps are separated by a tab
+    let path = header.split('\t').next()?.trim_end();
+    if path == "/dev/null" {
+        return Some(None);
+    }
+
+    let stripped: PathBuf = Path::new(path).components().skip(strip).collect();
+    (!stripped.as_os_str().is_empty()).then_some(Some(stripped))
+}
+
+/// Parses `@@ -start,count +start,count @@`, with counts defaulting to 1.
+fn hunk_header(header: &str) -> Option<(usize, usize, usize)> {
+    let mut ranges = header.strip_prefix("@@ ")?.split(' ');
+    let range = |range: &str| -> Option<(usize, usize)> {
+        match range.split_once(',') {
+            Some((start, count)) => Some((start.parse().ok()?, count.parse().ok()?)),
+            None => Some((range.parse().ok()?, 1)),
+        }
+    };
+
+    let (old_start, old_count) = range(ranges.next()?.strip_prefix('-')?)?;
+    let (_, new_count) = range(ranges.next()?.strip_prefix('+')?)?;
+    Some((old_start, old_count, new_count))
+}
+
+/// Contents of a file being patched, kept in memory so several patches can apply to it in turn.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Buffer {
+    lines: Vec<String>,
+    /// Line ending of the original file, either `\n` or `\r\n`.
+    newline: &'static str,
+    trailing_newline: bool,
+    exists: bool,
+}
+
+impl Buffer {
+    /// Splits `original` into lines, `None` being a missing file.
+    pub fn new(original: Option<&str>) -> Self {
+        let contents = original.unwrap_or_default();
+        let newline = match contents.find('\n') {
+            Some(end) if contents[..end].ends_with('\r') => "\r\n",
+            _ => "\n",
+        };
+
+        Self {
+            lines: contents.lines().map(str::to_string).collect(),
+            newline,
+            trailing_newline: contents.is_empty() || contents.ends_with('\n'),
+            exists: original.is_some(),
+        }
+    }
+
+    /// Joins the patched lines back together, with the original line endings,
+    /// `None` if the file was removed.
+    pub fn into_contents(self) -> Option<String> {
+        if !self.exists {
+            return None;
+        }
+
+        let mut contents = self.lines.join(self.newline);
+        if self.trailing_newline && !contents.is_empty() {
+            contents.push_str(self.newline);
+        }
+
+        Some(contents)
+    }
+}
+
+/// Applies `patch` to `buffer`, holding the contents of its file.
+///
+/// Hunks are searched for around their recorded position, so patches still apply to shifted sources,
+/// but every context and removed line must match exactly.
+pub fn apply(patch: &FilePatch, buffer: &mut Buffer) -> Result<(), HunkError> {
+    // creating a file replaces whatever was there before
+    if patch.old.is_none() {
+        buffer.lines.clear();
+        buffer.trailing_newline = true;
+    }
+
+    let lines = &mut buffer.lines;
+    let mut offset = 0isize;
+    let mut floor = 0;
+    for (index, hunk) in patch.hunks.iter().enumerate() {
+        let old: Vec<_> = hunk.old_lines().collect();
+        let new: Vec<_> = hunk.new_lines().collect();
+
+        // an empty range starts after its line, rather than at it
+        let recorded = if old.is_empty() {
+            hunk.old_start
+        } else {
+            hunk.old_start.saturating_sub(1)
+        };
+        let expected = recorded.saturating_add_signed(offset);
+        let position = find(lines, &old, expected, floor).ok_or_else(|| HunkError {
+            file: patch.path().to_path_buf(),
+            hunk: index + 1,
+            line: hunk.old_start,
+        })?;
+
+        let ends_file = position + old.len() == lines.len();
+        lines.splice(
+            position..position + old.len(),
+            new.iter().map(|line| line.to_string()),
+        );
+        if ends_file && hunk.new_missing_newline {
+            buffer.trailing_newline = false;
+        } else if ends_file && hunk.old_missing_newline {
+            buffer.trailing_newline = true;
+        }
+
+        offset = position as isize - recorded as isize + new.len() as isize - old.len() as isize;
+        floor = position + new.len();
+    }
+
+    buffer.exists = patch.new.is_some();
+    Ok(())
+}
+
+/// Finds `needle` in `lines`, starting at `expected` and moving outwards, but never before `floor`.
+fn find(lines: &[String], needle: &[&str], expected: usize, floor: usize) -> Option<usize> {
+    let last = lines.len().checked_sub(needle.len())?;
+    let matches = |position: usize| {
+        (floor..=last).contains(&position) && lines[position..position + needle.len()] == *needle
+    };
+
+    (0..=lines.len()).find_map(|distance| {
+        [
+            expected.checked_add(distance),
+            expected.checked_sub(distance),
+        ]
+        .into_iter()
+        .flatten()
+        .find(|position| matches(*position))
+    })
+}
@@ -0,0 +1,205 @@
+use std::{
+    fs::{self, File},
+    io::{BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock},
+};
+
+use flate2::read::GzDecoder;
+use serde::{Deserialize, Serialize};
+use xh_engine::{
+    builder::InitializeContext,
+    executor::{Error, Executor},
+    gen_name,
+    name::ExecutorName,
+};
+use xh_reports::prelude::*;
+
+#[derive(Debug, IntoReport)]
+#[message("could not extract archive")]
+#[context(input)]
+pub struct ExtractError {
+    input: PathBuf,
+}
+
+#[derive(Debug, IntoReport)]
+#[message("archive entry escapes the output directory")]
+#[context(entry)]
+pub struct TraversalError {
+    entry: PathBuf,
+}
+
+/// Magic bytes of gzip streams, which are decompressed while extracting.
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Request {
+    /// Tar archive to extract, relative to the environment.
+    pub input: PathBuf,
+    /// Directory the archive is extracted into, relative to the environment.
+    pub output_dir: PathBuf,
+}
+
+/// An executor extracting tar archives within the environment
+///
+/// Gzip compressed archives are detected and decompressed while extracting,
+/// other compression formats should go through the compression executor first.
+///
+/// Entries resolving outside of the output directory fail the whole request,
+/// though entries extracted before them are kept.
+#[derive(Debug)]
+pub struct TarExecutor {
+    ctx: Arc<InitializeContext>,
+}
+
+impl TarExecutor {
+    #[inline]
+    pub fn new(ctx: Arc<InitializeContext>) -> Self {
+        Self { ctx }
+    }
+}
+
+impl Executor for TarExecutor {
+    type Request = Request;
+
+    fn name() -> &'static ExecutorName {
+        static NAME: LazyLock<ExecutorName> = LazyLock::new(|| gen_name!(tar@xuehua));
+        &NAME
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
+    async fn execute(&mut self, request: Self::Request) -> Result<(), Error> {
+        let input = xh_common::safe_path(&self.ctx.environment, &request.input).wrap()?;
+        let output = xh_common::safe_path(&self.ctx.environment, &request.output_dir).wrap()?;
+
+        tokio::task::spawn_blocking(move || {
+            extract(&input, &output)
+                .wrap_with_fn(|| ExtractError {
+                    input: request.input,
+                })
+                .erased()
+        })
+        .await
+        .erased()
+        .flatten()
+        .wrap()
+    }
+}
+
+fn extract(input: &Path, output: &Path) -> Result<(), ()> {
+    unpack_stream(BufReader::new(File::open(input).erased()?), output)
+}
+
+/// Extracts the tar archive read from `reader` into `output`, decompressing gzip streams.
+///
+/// Entries are checked the same way as in [`TarExecutor`],
+/// so other executors can extract archives without writing them to disk first.
+pub fn unpack_stream(mut reader: impl BufRead, output: &Path) -> Result<(), ()> {
+    let gzipped = reader.fill_buf().erased()?.starts_with(GZIP_MAGIC);
+    tracing::trace!(gzipped, "detected compression");
+
+    if gzipped {
+        unpack(GzDecoder::new(reader), output)
+    } else {
+        unpack(reader, output)
+    }
+}
+
+fn unpack(reader: impl Read, output: &Path) -> Result<(), ()> {
+    fs::create_dir_all(output).erased()?;
+
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries().erased()? {
+        let mut entry = entry.erased()?;
+        let path = entry.path().erased()?.into_owned();
+
+        // `unpack_in` also refuses entries written through symlinks pointing outside of `output`
+        if xh_common::safe_path(output, &path).is_err() || !entry.unpack_in(output).erased()? {
+            return Err(TraversalError { entry: path }).erased();
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Write, sync::Arc};
+
+    use flate2::{Compression, write::GzEncoder};
+    use xh_engine::{builder::InitializeContext, executor::Executor};
+
+    use crate::{Request, TarExecutor};
+
+    fn executor(temp: &tempfile::TempDir) -> TarExecutor {
+        TarExecutor::new(Arc::new(InitializeContext {
+            environment: temp.path().to_path_buf(),
+        }))
+    }
+
+    fn request() -> Request {
+        Request {
+            input: "source.tar".into(),
+            output_dir: "output".into(),
+        }
+    }
+
+    fn archive(entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, contents) in entries {
+            let mut header = tar::Header::new_gnu();
+            // bypasses `Header::set_path`, which refuses traversing paths
+            header.as_old_mut().name[..path.len()].copy_from_slice(path.as_bytes());
+            header.set_mode(0o644);
+            header.set_size(contents.len() as u64);
+            header.set_cksum();
+            builder.append(&header, contents.as_bytes()).unwrap();
+        }
+
+        builder.into_inner().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_extract() {
+        let temp = tempfile::tempdir().unwrap();
+        let tar = archive(&[("src/main.c", "int main() {}"), ("README", "tar")]);
+
+        fs::write(temp.path().join("source.tar"), &tar).unwrap();
+        executor(&temp).execute(request()).await.unwrap();
+        assert_eq!(
+            fs::read_to_string(temp.path().join("output/src/main.c")).unwrap(),
+            "int main() {}"
+        );
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::fast());
+        encoder.write_all(&tar).unwrap();
+        fs::write(temp.path().join("source.tar"), encoder.finish().unwrap()).unwrap();
+        let gzipped = Request {
+            output_dir: "gzipped".into(),
+            ..request()
+        };
+        executor(&temp).execute(gzipped).await.unwrap();
+        assert_eq!(
+            fs::read_to_string(temp.path().join("gzipped/README")).unwrap(),
+            "tar"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_traversal() {
+        let temp = tempfile::tempdir().unwrap();
+        fs::create_dir(temp.path().join("environment")).unwrap();
+        let mut executor = TarExecutor::new(Arc::new(InitializeContext {
+            environment: temp.path().join("environment"),
+        }));
+
+        let tar = archive(&[("../../escaped", "oops")]);
+        fs::write(temp.path().join("environment/source.tar"), tar).unwrap();
+        let report = executor.execute(request()).await.unwrap_err();
+        assert_eq!(
+            report.root_cause().message,
+            "archive entry escapes the output directory"
+        );
+        assert!(!temp.path().join("escaped").exists());
+    }
+}
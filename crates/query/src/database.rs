@@ -4,13 +4,17 @@ pub mod evict;
 mod fallible;
 mod in_memory;
 pub mod persist;
+#[cfg(feature = "postcard")]
+mod persistent;
 
-use std::{any::Any, fmt, hash::BuildHasherDefault, sync::atomic::Ordering};
+use std::{any::Any, fmt, hash::BuildHasherDefault, io, sync::atomic::Ordering};
 
 use futures_util::{FutureExt, future::BoxFuture};
 
 pub use fallible::Fallible;
 pub use in_memory::InMemory;
+#[cfg(feature = "postcard")]
+pub use persistent::Persistent;
 use rapidhash::quality::RapidHasher;
 
 use crate::{
@@ -62,6 +66,13 @@ mod erased {
 pub use erased::*;
 
 pub(crate) trait DynDatabase: Any + Send + Sync {
+    #[cfg(feature = "postcard")]
+    fn name(&self) -> &'static str;
+    #[cfg(feature = "postcard")]
+    fn snapshot(&self) -> io::Result<Option<Vec<u8>>>;
+    #[cfg(feature = "postcard")]
+    fn restore(&mut self, snapshot: &[u8]) -> bool;
+    fn contains(&self, idx: KeyIndex) -> bool;
     fn evict_garbage(&mut self) -> Vec<KeyIndex>;
     fn recompute<'a>(&'a self, idx: KeyIndex, qcx: Context<'a>) -> BoxFuture<'a, Difference>;
 }
@@ -86,6 +97,25 @@ where
 }
 
 impl<D: EdgeDatabase> DynDatabase for D {
+    #[cfg(feature = "postcard")]
+    fn name(&self) -> &'static str {
+        std::any::type_name::<D>()
+    }
+
+    #[cfg(feature = "postcard")]
+    fn snapshot(&self) -> io::Result<Option<Vec<u8>>> {
+        Database::snapshot(self)
+    }
+
+    #[cfg(feature = "postcard")]
+    fn restore(&mut self, snapshot: &[u8]) -> bool {
+        Database::restore(self, snapshot)
+    }
+
+    fn contains(&self, idx: KeyIndex) -> bool {
+        self.key(idx).is_some()
+    }
+
     fn evict_garbage(&mut self) -> Vec<KeyIndex> {
         self.eviction().evict_garbage()
     }
@@ -151,4 +181,18 @@ pub trait Database: Send + Sync + 'static {
 
     /// Returns the eviction database extension.
     fn eviction(&mut self) -> &mut Self::EvictionExtension<'_>;
+
+    /// Serializes all keys and values, to be loaded with [`Self::restore`].
+    ///
+    /// Returns `None` if the database does not support persistence.
+    fn snapshot(&self) -> io::Result<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    /// Replaces all keys and values with a snapshot taken by [`Self::snapshot`].
+    ///
+    /// Returns whether the snapshot was restored.
+    fn restore(&mut self, _snapshot: &[u8]) -> bool {
+        false
+    }
 }
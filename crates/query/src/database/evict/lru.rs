@@ -1,4 +1,7 @@
-use std::sync::{Mutex, atomic::AtomicUsize};
+use std::{
+    io,
+    sync::{Mutex, atomic::AtomicUsize},
+};
 
 use educe::Educe;
 use rapidhash::RapidHashMap;
@@ -77,6 +80,14 @@ impl<D: Database> Database for LRU<D> {
     fn persistence(&self) -> &Self::PersistExtension<'_> {
         self.inner.persistence()
     }
+
+    fn snapshot(&self) -> io::Result<Option<Vec<u8>>> {
+        self.inner.snapshot()
+    }
+
+    fn restore(&mut self, snapshot: &[u8]) -> bool {
+        self.inner.restore(snapshot)
+    }
 }
 
 impl<D: Database> Evict for LRU<D> {
@@ -150,4 +161,48 @@ mod tests {
 
         assert_eq!(LRU_COMPUTES.load(Ordering::Relaxed), DEFAULT_CAPACITY * 3);
     }
+
+    #[tokio::test]
+    async fn test_evicted_dependency() {
+        static LEAF_COMPUTES: AtomicUsize = AtomicUsize::new(0);
+        static PARENT_COMPUTES: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Query, Debug, Clone, Hash, PartialEq, Eq)]
+        #[database(LRU<database::Default<LeafQuery, usize>>)]
+        #[compute(Self::inner)]
+        struct LeafQuery(usize);
+        impl LeafQuery {
+            async fn inner(self, _qcx: &Context<'_>) -> <Self as Query>::Value {
+                LEAF_COMPUTES.fetch_add(1, Ordering::Relaxed);
+                self.0
+            }
+        }
+
+        #[derive(Query, Debug, Clone, Hash, PartialEq, Eq)]
+        #[database(database::Default<ParentQuery, usize>)]
+        #[compute(Self::inner)]
+        struct ParentQuery;
+        impl ParentQuery {
+            async fn inner(self, qcx: &Context<'_>) -> <Self as Query>::Value {
+                PARENT_COMPUTES.fetch_add(1, Ordering::Relaxed);
+                qcx.query(LeafQuery(usize::MAX)).await
+            }
+        }
+
+        let mut engine = Engine::new();
+        assert_eq!(engine.context().query(ParentQuery).await, usize::MAX);
+
+        // push the parent's dependency out of the cache
+        for i in 0..DEFAULT_CAPACITY * 2 {
+            engine.context().query(LeafQuery(i)).await;
+        }
+        engine.upcoming();
+
+        assert_eq!(engine.context().query(ParentQuery).await, usize::MAX);
+        assert_eq!(PARENT_COMPUTES.load(Ordering::Relaxed), 2);
+        assert_eq!(
+            LEAF_COMPUTES.load(Ordering::Relaxed),
+            DEFAULT_CAPACITY * 2 + 2
+        );
+    }
 }
@@ -1,4 +1,4 @@
-use std::marker::PhantomData;
+use std::{io, marker::PhantomData};
 
 use bytes::Bytes;
 use educe::Educe;
@@ -88,6 +88,14 @@ where
     fn eviction(&mut self) -> &mut Self::EvictionExtension<'_> {
         self.inner.eviction()
     }
+
+    fn snapshot(&self) -> io::Result<Option<Vec<u8>>> {
+        self.inner.snapshot()
+    }
+
+    fn restore(&mut self, snapshot: &[u8]) -> bool {
+        self.inner.restore(snapshot)
+    }
 }
 
 #[cfg(all(test, feature = "inventory"))]
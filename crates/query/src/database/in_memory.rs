@@ -89,6 +89,26 @@ where
     }
 }
 
+#[cfg(feature = "postcard")]
+impl<K: Eq + Hash + Clone, V: Clone, S: BuildHasher> InMemory<K, V, S> {
+    pub(crate) fn entries(&self) -> Vec<(KeyIndex, K, Option<V>)> {
+        let keys = self.keys.lock().unwrap();
+        let values = self.values.lock().unwrap();
+
+        keys.iter()
+            .map(|(idx, key)| (*idx, key.clone(), values.get(idx).cloned()))
+            .collect()
+    }
+
+    pub(crate) fn insert(&mut self, idx: KeyIndex, key: K, value: Option<V>) {
+        self.lookup.get_mut().unwrap().insert(key.clone(), idx);
+        self.keys.get_mut().unwrap().insert(idx, key);
+        if let Some(value) = value {
+            self.values.get_mut().unwrap().insert(idx, value);
+        }
+    }
+}
+
 impl<K: Eq + Hash, V, S: BuildHasher> Evict for InMemory<K, V, S> {
     fn evict_garbage(&mut self) -> Vec<KeyIndex> {
         vec![]
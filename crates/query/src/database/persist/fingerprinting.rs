@@ -1,4 +1,7 @@
-use std::hash::{BuildHasher, Hash, Hasher};
+use std::{
+    hash::{BuildHasher, Hash, Hasher},
+    io,
+};
 
 use bytes::Bytes;
 
@@ -86,4 +89,12 @@ where
     fn eviction(&mut self) -> &mut Self::EvictionExtension<'_> {
         self.inner.eviction()
     }
+
+    fn snapshot(&self) -> io::Result<Option<Vec<u8>>> {
+        self.inner.snapshot()
+    }
+
+    fn restore(&mut self, snapshot: &[u8]) -> bool {
+        self.inner.restore(snapshot)
+    }
 }
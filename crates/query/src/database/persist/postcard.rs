@@ -1,3 +1,5 @@
+use std::io;
+
 use bytes::Bytes;
 use serde::{Deserialize, Serialize};
 
@@ -72,4 +74,12 @@ where
     fn eviction(&mut self) -> &mut Self::EvictionExtension<'_> {
         self.inner.eviction()
     }
+
+    fn snapshot(&self) -> io::Result<Option<Vec<u8>>> {
+        self.inner.snapshot()
+    }
+
+    fn restore(&mut self, snapshot: &[u8]) -> bool {
+        self.inner.restore(snapshot)
+    }
 }
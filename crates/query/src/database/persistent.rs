@@ -0,0 +1,147 @@
+use std::{
+    hash::{BuildHasher, Hash},
+    io,
+};
+
+use educe::Educe;
+use rapidhash::fast::RandomState;
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::{
+    KeyIndex,
+    database::{Database, Difference, InMemory, persist},
+};
+
+/// In-memory database whose keys and values survive restarts.
+///
+/// Snapshots are written and loaded alongside the engine's memos,
+/// see [`Engine::save`](crate::engine::Engine::save) and [`Engine::load`](crate::engine::Engine::load).
+#[derive(Educe, Debug)]
+#[educe(Default(new, bound(S: Default)))]
+pub struct Persistent<K, V, S = RandomState> {
+    inner: InMemory<K, V, S>,
+}
+
+impl<K, V, S> Database for Persistent<K, V, S>
+where
+    K: Eq + Hash + Clone + Serialize + DeserializeOwned + Send + Sync + 'static,
+    V: Eq + Clone + Serialize + DeserializeOwned + Send + Sync + 'static,
+    S: BuildHasher + Default + Send + Sync + 'static,
+{
+    type Key = K;
+    type InputValue = V;
+    type OutputValue<'a> = V;
+    type PersistExtension<'a> = persist::NoOp<V>;
+    type EvictionExtension<'a> = InMemory<K, V, S>;
+
+    fn index(&self, key: &Self::Key, new: impl FnOnce() -> KeyIndex) -> KeyIndex {
+        self.inner.index(key, new)
+    }
+
+    fn key(&self, idx: KeyIndex) -> Option<Self::Key> {
+        self.inner.key(idx)
+    }
+
+    fn value(&self, idx: KeyIndex) -> Option<Self::OutputValue<'_>> {
+        self.inner.value(idx)
+    }
+
+    fn pass_value(
+        &self,
+        idx: KeyIndex,
+        value: Self::InputValue,
+    ) -> (Self::OutputValue<'_>, Difference) {
+        self.inner.pass_value(idx, value)
+    }
+
+    fn persistence(&self) -> &Self::PersistExtension<'_> {
+        self.inner.persistence()
+    }
+
+    fn eviction(&mut self) -> &mut Self::EvictionExtension<'_> {
+        self.inner.eviction()
+    }
+
+    fn snapshot(&self) -> io::Result<Option<Vec<u8>>> {
+        let entries: Vec<_> = self
+            .inner
+            .entries()
+            .into_iter()
+            .map(|(idx, key, value)| (idx.0, key, value))
+            .collect();
+
+        postcard::to_allocvec(&entries)
+            .map(Some)
+            .map_err(io::Error::other)
+    }
+
+    fn restore(&mut self, snapshot: &[u8]) -> bool {
+        let Ok(entries) = postcard::from_bytes::<Vec<(usize, K, Option<V>)>>(snapshot) else {
+            return false;
+        };
+
+        self.inner = InMemory::new();
+        for (idx, key, value) in entries {
+            self.inner.insert(KeyIndex(idx), key, value);
+        }
+
+        true
+    }
+}
+
+#[cfg(all(test, feature = "inventory"))]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use serde::{Deserialize, Serialize};
+
+    use crate::{
+        Query,
+        database::Persistent,
+        engine::{Context, Engine},
+        input_query,
+    };
+
+    #[tokio::test]
+    async fn test_restore() {
+        static LENGTH_COMPUTES: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Query, Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+        #[database(Persistent<TextInput, String>)]
+        #[compute(input_query)]
+        struct TextInput;
+
+        #[derive(Query, Debug, Clone, Hash, PartialEq, Eq, Serialize, Deserialize)]
+        #[database(Persistent<LengthQuery, usize>)]
+        #[compute(Self::inner)]
+        struct LengthQuery;
+        impl LengthQuery {
+            async fn inner(self, qcx: &Context<'_>) -> <Self as Query>::Value {
+                LENGTH_COMPUTES.fetch_add(1, Ordering::Relaxed);
+                qcx.query(TextInput).await.len()
+            }
+        }
+
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("engine");
+
+        let mut engine = Engine::new();
+        engine.upcoming().update(&TextInput, "hello".to_string());
+        assert_eq!(engine.context().query(LengthQuery).await, 5);
+        engine.save(&path).unwrap();
+        drop(engine);
+
+        let mut engine = Engine::new().load(&path).unwrap();
+        assert_eq!(engine.context().query(LengthQuery).await, 5);
+        engine.upcoming();
+        assert_eq!(engine.context().query(LengthQuery).await, 5);
+        assert_eq!(LENGTH_COMPUTES.load(Ordering::Relaxed), 1);
+
+        // restored inputs still invalidate their dependents
+        engine
+            .upcoming()
+            .update(&TextInput, "hello world".to_string());
+        assert_eq!(engine.context().query(LengthQuery).await, 11);
+        assert_eq!(LENGTH_COMPUTES.load(Ordering::Relaxed), 2);
+    }
+}
@@ -2,10 +2,14 @@
 
 use std::{
     any::Any,
-    fmt::Debug,
+    fmt::{self, Debug},
     sync::{Arc, Mutex, atomic::Ordering},
+    time::Duration,
 };
+#[cfg(feature = "postcard")]
+use std::{io, path::Path};
 
+use futures_util::{StreamExt, stream::FuturesOrdered};
 use rapidhash::RapidHashSet;
 
 use crate::{
@@ -62,6 +66,30 @@ impl Engine {
         }
     }
 
+    /// Saves the engine's memos and the contents of persistent databases to `path`
+    #[cfg(feature = "postcard")]
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let snapshot = postcard::to_allocvec(&self.store.snapshot()?).map_err(io::Error::other)?;
+        std::fs::write(path, snapshot)
+    }
+
+    /// Loads memos and persistent databases saved by [`Self::save`] from `path`
+    ///
+    /// Databases must be registered, and nothing may be queried, before loading.
+    /// If `path` doesn't exist, the engine is returned unchanged.
+    #[cfg(feature = "postcard")]
+    pub fn load(mut self, path: &Path) -> io::Result<Self> {
+        let data = match std::fs::read(path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(self),
+            Err(err) => return Err(err),
+        };
+
+        let snapshot = postcard::from_bytes(&data).map_err(io::Error::other)?;
+        self.store_mut().restore(snapshot);
+        Ok(self)
+    }
+
     /// Loan out an [`Upcoming`] to mutate the engine
     pub fn upcoming(&mut self) -> Upcoming<'_> {
         let store = self.store_mut();
@@ -125,9 +153,48 @@ impl Upcoming<'_> {
         *memo.verified_at.get_mut() = revision;
         *memo.changed_at.get_mut() = revision;
         memo.dependencies = Mutex::default();
+        memo.input = true;
+    }
+
+    /// Forces the value for any given key to be recomputed with [`Query::compute`] when it's next queried
+    ///
+    /// Dependents are only recomputed if the new value differs from the old one.
+    /// Keys set with [`Self::update`] are computed from then on too,
+    /// so keys without a real computation, such as ones using [`input_query`](crate::input_query), should be updated instead.
+    ///
+    /// This lives here rather than on [`Context`], since only a new revision makes
+    /// dependents that were already verified check their dependencies again.
+    pub fn invalidate<K: Query>(&mut self, key: &K) {
+        let database = self.store.database_of::<K>();
+        let idx = self.store.index_of(database, key);
+
+        let memo = self
+            .store
+            .memos
+            .get_mut(idx.0)
+            .expect("memo should be valid for any KeyIndex");
+
+        // revision 0 is untracked, so the memo is never considered verified
+        *memo.verified_at.get_mut() = 0;
+        memo.input = false;
     }
 }
 
+/// Returned by [`Context::query_timeout`] when a query isn't computed in time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueryTimeoutError {
+    /// The timeout that elapsed
+    pub timeout: Duration,
+}
+
+impl fmt::Display for QueryTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "query was not computed within {:?}", self.timeout)
+    }
+}
+
+impl std::error::Error for QueryTimeoutError {}
+
 /// Handle to the current revision
 #[derive(Debug)]
 pub struct Context<'a> {
@@ -148,6 +215,10 @@ enum Frame<'a> {
     ComputeMemo(ComputeFrame<'a>),
 }
 
+/// Returns whether `memo` needs to be recomputed, given it was last verified at `verified_at`.
+///
+/// A memo is recomputed if it was never verified, if any of its dependencies changed since then,
+/// or if any of its dependencies were evicted from their database.
 async fn should_recompute(store: &Store, memo: &Memo, verified_at: usize) -> bool {
     if verified_at == 0 {
         return true;
@@ -156,6 +227,11 @@ async fn should_recompute(store: &Store, memo: &Memo, verified_at: usize) -> boo
     let dependencies = memo.dependencies.lock().unwrap().clone();
     for dep_idx in dependencies {
         let dep_memo = &store.memos[dep_idx.0];
+        // evicted keys get a new index once queried again, so this memo is orphaned
+        if !store.databases[&dep_memo.database].contains(dep_idx) {
+            return true;
+        }
+
         // FIX: somehow check that verified_at updated in case the task panicked
         //      we need some form of communication between parents and dependencies
         let _ = dep_memo.flight.takeoff().await;
@@ -209,6 +285,36 @@ impl<'a> Context<'a> {
         }
     }
 
+    /// Queries the engine like [`Self::query`], giving up once `timeout` elapses
+    ///
+    /// A computation that times out is cancelled before its value is stored,
+    /// so the key is computed again the next time it's queried.
+    /// Dependencies already being computed in the background are unaffected.
+    pub async fn query_timeout<K: Query>(
+        &self,
+        key: K,
+        timeout: Duration,
+    ) -> Result<OutputValue<'a, K>, QueryTimeoutError> {
+        tokio::select! {
+            value = self.query(key) => Ok(value),
+            () = tokio::time::sleep(timeout) => Err(QueryTimeoutError { timeout }),
+        }
+    }
+
+    /// Queries the engine for the memoized values computed from each of `keys` concurrently
+    ///
+    /// Values are returned in the same order as `keys`.
+    pub async fn query_all<K: Query>(
+        &self,
+        keys: impl IntoIterator<Item = K>,
+    ) -> Vec<OutputValue<'a, K>> {
+        keys.into_iter()
+            .map(|key| self.query(key))
+            .collect::<FuturesOrdered<_>>()
+            .collect()
+            .await
+    }
+
     async fn verify<K: Query>(
         &self,
         idx: KeyIndex,
@@ -258,7 +364,13 @@ impl<'a> Context<'a> {
         });
 
         let dependencies = memo.dependencies.lock().unwrap();
-        let dependencies = dependencies.iter().map(|&idx| Frame::Verify { idx });
+        let dependencies = dependencies
+            .iter()
+            .filter(|idx| {
+                let database = self.store.memos[idx.0].database;
+                self.store.databases[&database].contains(**idx)
+            })
+            .map(|&idx| Frame::Verify { idx });
         queue.extend(dependencies);
 
         None
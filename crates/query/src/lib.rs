@@ -66,15 +66,16 @@ mod tests {
         ops::Range,
         sync::{
             Arc,
-            atomic::{AtomicUsize, Ordering},
+            atomic::{AtomicBool, AtomicUsize, Ordering},
         },
+        time::Duration,
     };
 
     use tokio::{runtime::Runtime, task::JoinSet};
 
     use crate::{
         Query, database,
-        engine::{Context, Engine},
+        engine::{Context, Engine, QueryTimeoutError},
         input_query,
     };
 
@@ -124,6 +125,137 @@ mod tests {
         assert_eq!(EVEN_COMPUTES.load(Ordering::Relaxed), 1);
     }
 
+    #[tokio::test]
+    async fn test_invalidate() {
+        static FILE: AtomicUsize = AtomicUsize::new(0);
+        static READ_COMPUTES: AtomicUsize = AtomicUsize::new(0);
+        static DOUBLE_COMPUTES: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Query, Debug, Clone, Hash, PartialEq, Eq)]
+        #[database(database::Default<ReadQuery, usize>)]
+        #[compute(Self::inner)]
+        struct ReadQuery;
+        impl ReadQuery {
+            async fn inner(self, _qcx: &Context<'_>) -> <Self as Query>::Value {
+                READ_COMPUTES.fetch_add(1, Ordering::Relaxed);
+                FILE.load(Ordering::Relaxed)
+            }
+        }
+
+        #[derive(Query, Debug, Clone, Hash, PartialEq, Eq)]
+        #[database(database::Default<DoubleQuery, usize>)]
+        #[compute(Self::inner)]
+        struct DoubleQuery;
+        impl DoubleQuery {
+            async fn inner(self, qcx: &Context<'_>) -> <Self as Query>::Value {
+                DOUBLE_COMPUTES.fetch_add(1, Ordering::Relaxed);
+                qcx.query(ReadQuery).await * 2
+            }
+        }
+
+        let mut root = Engine::new();
+        assert_eq!(root.context().query(DoubleQuery).await, 0);
+
+        // external changes go unnoticed until the memo is invalidated
+        FILE.store(1, Ordering::Relaxed);
+        root.upcoming();
+        assert_eq!(root.context().query(DoubleQuery).await, 0);
+        assert_eq!(READ_COMPUTES.load(Ordering::Relaxed), 1);
+
+        root.upcoming().invalidate(&ReadQuery);
+        assert_eq!(root.context().query(DoubleQuery).await, 2);
+        assert_eq!(READ_COMPUTES.load(Ordering::Relaxed), 2);
+        assert_eq!(DOUBLE_COMPUTES.load(Ordering::Relaxed), 2);
+
+        // unchanged values are cut off early
+        root.upcoming().invalidate(&ReadQuery);
+        assert_eq!(root.context().query(DoubleQuery).await, 2);
+        assert_eq!(READ_COMPUTES.load(Ordering::Relaxed), 3);
+        assert_eq!(DOUBLE_COMPUTES.load(Ordering::Relaxed), 2);
+
+        // an input's memo is dropped too, and recomputed from the key
+        root.upcoming().update(&ReadQuery, 5);
+        assert_eq!(root.context().query(DoubleQuery).await, 10);
+        FILE.store(3, Ordering::Relaxed);
+        root.upcoming().invalidate(&ReadQuery);
+        assert_eq!(root.context().query(DoubleQuery).await, 6);
+        assert_eq!(READ_COMPUTES.load(Ordering::Relaxed), 4);
+    }
+
+    #[tokio::test]
+    async fn test_query_all() {
+        #[derive(Query, Debug, Clone, Hash, PartialEq, Eq)]
+        #[database(database::Default<BaseInput, usize>)]
+        #[compute(input_query)]
+        struct BaseInput;
+
+        #[derive(Query, Debug, Clone, Hash, PartialEq, Eq)]
+        #[database(database::Default<OffsetQuery, usize>)]
+        #[compute(Self::inner)]
+        struct OffsetQuery(usize);
+        impl OffsetQuery {
+            async fn inner(self, qcx: &Context<'_>) -> <Self as Query>::Value {
+                for _ in 0..self.0 {
+                    tokio::task::yield_now().await;
+                }
+
+                qcx.query(BaseInput).await + self.0
+            }
+        }
+
+        let mut root = Engine::new();
+        root.upcoming().update(&BaseInput, 100);
+        let keys = || (0..16).rev().map(OffsetQuery);
+
+        let serial = root.context();
+        let mut expected = Vec::new();
+        for key in keys() {
+            expected.push(serial.query(key).await);
+        }
+
+        let batched = root.context();
+        assert_eq!(batched.query_all(keys()).await, expected);
+        assert_eq!(
+            *batched.dependencies.lock().unwrap(),
+            *serial.dependencies.lock().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_timeout() {
+        static SLOW: AtomicBool = AtomicBool::new(true);
+
+        #[derive(Query, Debug, Clone, Hash, PartialEq, Eq)]
+        #[database(database::Default<StuckQuery, usize>)]
+        #[compute(Self::inner)]
+        struct StuckQuery;
+        impl StuckQuery {
+            async fn inner(self, _qcx: &Context<'_>) -> <Self as Query>::Value {
+                if SLOW.load(Ordering::Relaxed) {
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                }
+
+                7
+            }
+        }
+
+        let root = Engine::new();
+        let timeout = Duration::from_millis(20);
+        let error = root
+            .context()
+            .query_timeout(StuckQuery, timeout)
+            .await
+            .unwrap_err();
+        assert_eq!(error, QueryTimeoutError { timeout });
+
+        // nothing was memoized, and the flight was released
+        SLOW.store(false, Ordering::Relaxed);
+        assert_eq!(
+            root.context().query_timeout(StuckQuery, timeout).await,
+            Ok(7)
+        );
+    }
+
     #[tokio::test]
     async fn test_dynamic_dependencies() {
         static BRANCH_COMPUTES: AtomicUsize = AtomicUsize::new(0);
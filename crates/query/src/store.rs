@@ -1,3 +1,5 @@
+#[cfg(feature = "postcard")]
+use std::io;
 use std::{
     any::{Any, TypeId},
     num::NonZeroUsize,
@@ -21,6 +23,8 @@ pub struct Memo {
     pub changed_at: AtomicUsize,
     pub flight: SingleFlight,
     pub database: TypeId,
+    /// Whether the value was set with [`Upcoming::update`](crate::engine::Upcoming::update), rather than computed
+    pub input: bool,
     fingerprint: AtomicU64,
 }
 
@@ -83,10 +87,172 @@ impl Store {
                 dependencies: Mutex::default(),
                 flight: SingleFlight::default(),
                 database: database.type_id(),
+                input: false,
                 fingerprint: AtomicU64::new(0),
             });
 
             KeyIndex(idx)
         })
     }
+
+    /// Captures all memos, and the contents of databases that support persistence.
+    ///
+    /// Memos are kept in order, even those whose database is no longer registered,
+    /// since their position is their [`KeyIndex`].
+    #[cfg(feature = "postcard")]
+    pub fn snapshot(&self) -> io::Result<Snapshot> {
+        let name_of = |type_id| {
+            self.databases
+                .get(&type_id)
+                .map(|database| database.name().to_string())
+        };
+
+        let memos = self
+            .memos
+            .iter()
+            .map(|(_, memo)| MemoSnapshot {
+                database: name_of(memo.database),
+                dependencies: memo
+                    .dependencies
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .map(|idx| idx.0)
+                    .collect(),
+                verified_at: memo.verified_at.load(Ordering::Acquire),
+                changed_at: memo.changed_at.load(Ordering::Acquire),
+                input: memo.input,
+                fingerprint: memo.fingerprint.load(Ordering::Acquire),
+            })
+            .collect();
+
+        let databases = self
+            .databases
+            .values()
+            .filter_map(|database| {
+                let snapshot = database.snapshot().transpose()?;
+                Some(snapshot.map(|snapshot| (database.name().to_string(), snapshot)))
+            })
+            .collect::<io::Result<_>>()?;
+
+        Ok(Snapshot {
+            revision: self.revision,
+            memos,
+            databases,
+        })
+    }
+
+    /// Restores memos and databases from a snapshot taken by [`Self::snapshot`].
+    ///
+    /// Memos of databases that weren't restored can't be queried again,
+    /// so their dependents are marked as unverified.
+    #[cfg(feature = "postcard")]
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        assert!(
+            self.memos.is_empty(),
+            "snapshot should be restored before querying"
+        );
+
+        let type_ids: RapidHashMap<_, _> = self
+            .databases
+            .iter()
+            .map(|(type_id, database)| (database.name(), *type_id))
+            .collect();
+
+        let mut restored = RapidHashSet::default();
+        for (name, data) in snapshot.databases {
+            if let Some(type_id) = type_ids.get(name.as_str())
+                && self.databases.get_mut(type_id).unwrap().restore(&data)
+            {
+                restored.insert(*type_id);
+            }
+        }
+
+        let restored: Vec<_> = snapshot
+            .memos
+            .iter()
+            .map(|memo| {
+                memo.database
+                    .as_deref()
+                    .and_then(|name| type_ids.get(name))
+                    .filter(|type_id| restored.contains(*type_id))
+                    .copied()
+            })
+            .collect();
+
+        for (memo, database) in snapshot.memos.into_iter().zip(&restored) {
+            let orphaned = database.is_none()
+                || memo
+                    .dependencies
+                    .iter()
+                    .any(|idx| restored.get(*idx).is_none_or(Option::is_none));
+
+            self.memos.push(Memo {
+                dependencies: Mutex::new(match orphaned {
+                    true => RapidHashSet::default(),
+                    false => memo.dependencies.into_iter().map(KeyIndex).collect(),
+                }),
+                verified_at: if orphaned { 0 } else { memo.verified_at }.into(),
+                changed_at: if orphaned { 0 } else { memo.changed_at }.into(),
+                flight: SingleFlight::default(),
+                database: database.unwrap_or_else(TypeId::of::<()>),
+                input: memo.input,
+                fingerprint: AtomicU64::new(memo.fingerprint),
+            });
+        }
+
+        self.revision = snapshot.revision;
+    }
+}
+
+/// Serializable state of a [`Store`]
+#[cfg(feature = "postcard")]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Snapshot {
+    revision: NonZeroUsize,
+    memos: Vec<MemoSnapshot>,
+    databases: Vec<(String, Vec<u8>)>,
+}
+
+#[cfg(feature = "postcard")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct MemoSnapshot {
+    /// `None` if the memo's database wasn't registered
+    database: Option<String>,
+    dependencies: Vec<usize>,
+    verified_at: usize,
+    changed_at: usize,
+    input: bool,
+    fingerprint: u64,
+}
+
+#[cfg(all(test, feature = "postcard"))]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use crate::store::{MemoSnapshot, Snapshot, Store};
+
+    #[test]
+    fn test_unregistered_database() {
+        let memo = |database: Option<&str>| MemoSnapshot {
+            database: database.map(str::to_string),
+            dependencies: Vec::new(),
+            verified_at: 1,
+            changed_at: 1,
+            input: false,
+            fingerprint: 0,
+        };
+
+        let mut store = Store::default();
+        store.restore(Snapshot {
+            revision: NonZeroUsize::MIN,
+            memos: vec![memo(Some("removed")), memo(None)],
+            databases: Vec::new(),
+        });
+
+        // memos keep their position, but forget the database they came from
+        let snapshot = store.snapshot().unwrap();
+        assert_eq!(snapshot.memos.len(), 2);
+        assert!(snapshot.memos.iter().all(|memo| memo.database.is_none()));
+    }
 }
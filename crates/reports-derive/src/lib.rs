@@ -12,6 +12,7 @@ enum Mode {
     #[default]
     Debug,
     Display,
+    Bytes,
 }
 
 impl Mode {
@@ -19,6 +20,7 @@ impl Mode {
         match self {
             Mode::Debug => quote!(format_args!("{:?}", #value)),
             Mode::Display => quote!(#value),
+            Mode::Bytes => quote!(::std::convert::AsRef::<[u8]>::as_ref(#value)),
         }
     }
 }
@@ -28,6 +30,7 @@ impl Parse for Mode {
         mod kw {
             syn::custom_keyword!(display);
             syn::custom_keyword!(debug);
+            syn::custom_keyword!(bytes);
         }
 
         let lookahead = input.lookahead1();
@@ -35,6 +38,8 @@ impl Parse for Mode {
             input.parse::<kw::debug>().map(|_| Mode::Debug)
         } else if lookahead.peek(kw::display) {
             input.parse::<kw::display>().map(|_| Mode::Display)
+        } else if lookahead.peek(kw::bytes) {
+            input.parse::<kw::bytes>().map(|_| Mode::Bytes)
         } else {
             Err(lookahead.error())
         }
@@ -156,7 +161,7 @@ fn build_suggestion(fields: &Fields, attr: &Attribute) -> Result<TokenStream, Er
 }
 
 fn build_attachment(attr: &Attribute) -> Result<TokenStream, Error> {
-    let value = attr.parse_args_with(|input: ParseStream| {
+    let (mode, value) = attr.parse_args_with(|input: ParseStream| {
         let mode = match Mode::parse(input) {
             Ok(mode) => {
                 input.parse::<Token![:]>()?;
@@ -166,10 +171,13 @@ fn build_attachment(attr: &Attribute) -> Result<TokenStream, Error> {
         };
 
         let value = mode.format(Member::parse(input)?);
-        Ok(value)
+        Ok((mode, value))
     })?;
 
-    Ok(quote! ( ::xh_reports::Frame::attachment(#value) ))
+    Ok(match mode {
+        Mode::Bytes => quote! ( ::xh_reports::Frame::attachment_bytes(#value) ),
+        _ => quote! ( ::xh_reports::Frame::attachment(#value) ),
+    })
 }
 
 fn build_context(attr: &Attribute) -> Result<impl Iterator<Item = TokenStream>, Error> {
@@ -196,6 +204,9 @@ fn build_context(attr: &Attribute) -> Result<impl Iterator<Item = TokenStream>,
         }
 
         let mode = match Mode::parse(stream) {
+            Ok(Mode::Bytes) => {
+                return Err(unsupported_error(stream.span(), "bytes mode in #[context]"));
+            }
             Ok(mode) => {
                 stream.parse::<Token![:]>()?;
                 mode
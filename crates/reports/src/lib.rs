@@ -11,16 +11,18 @@ pub use xh_reports_derive::IntoReport;
 
 use std::{
     any::type_name,
+    collections::HashSet,
     error::Error,
     fmt::{self, Display},
     iter::once,
     marker::PhantomData,
     panic::Location as StdLocation,
     result::Result as StdResult,
+    time::Duration,
 };
 
 use educe::Educe;
-use smol_str::{SmolStr, ToSmolStr};
+use smol_str::{SmolStr, ToSmolStr, format_smolstr};
 
 use crate::render::{GlobalRenderer, Renderer, SimpleRenderer};
 
@@ -32,7 +34,7 @@ pub type Result<T, E> = StdResult<T, Report<E>>;
 
 /// A single piece of information inside of a [`Report`].
 ///
-/// `Frame`s can be created via the [`Self::suggestion`], [`Self::context`], or [`Self::attachment`] methods.
+/// `Frame`s can be created via the [`Self::suggestion`], [`Self::context`], [`Self::attachment`], or [`Self::attachment_bytes`] methods.
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Frame {
@@ -51,10 +53,25 @@ pub enum Frame {
     /// This can be used to attach more detailed data such as:
     /// stderr, build logs, files, etc.
     Attachment(SmolStr),
+    /// A long-form piece of binary information.
+    ///
+    /// This can be used to attach data that isn't valid UTF-8 such as:
+    /// tokens, file headers, packets, etc.
+    AttachmentBytes(Vec<u8>),
     /// An inline suggestion
     ///
     /// This can be used to suggest actions to users to resolve issues.
     Suggestion(SmolStr),
+    /// How long a labelled phase took.
+    ///
+    /// This can be used to show where time went, such as:
+    /// executing requests, downloads, decoding, etc.
+    Timing {
+        /// Name of the timed phase
+        label: SmolStr,
+        /// Time the phase took
+        duration: Duration,
+    },
 }
 
 impl Frame {
@@ -79,6 +96,19 @@ impl Frame {
     pub fn attachment(attachment: impl fmt::Display) -> Frame {
         Self::Attachment(attachment.to_smolstr())
     }
+
+    /// Helper function to create [`Self::Timing`]s.
+    pub fn timing(label: impl Into<SmolStr>, duration: Duration) -> Frame {
+        Self::Timing {
+            label: label.into(),
+            duration,
+        }
+    }
+
+    /// Helper function to create [`Self::AttachmentBytes`]s.
+    pub fn attachment_bytes(attachment: impl Into<Vec<u8>>) -> Frame {
+        Self::AttachmentBytes(attachment.into())
+    }
 }
 
 /// Location data associated with a [`Report`].
@@ -102,7 +132,9 @@ pub enum Location {
 }
 
 /// Various error levels associated with a [`Report`].
-#[derive(Clone, Debug)]
+///
+/// Reports default to [`Level::Error`], every other level is considered non-fatal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[allow(missing_docs)]
 pub enum Level {
@@ -168,6 +200,43 @@ impl ReportPayload {
             frames: Vec::default(),
         }
     }
+
+    /// Removes exact duplicate [`Frame::Context`]s from this payload and its children.
+    ///
+    /// Only the first occurrence of each key/value pair is kept,
+    /// so distinct values for the same key are preserved.
+    pub fn dedup_context(&mut self) {
+        let mut seen = HashSet::new();
+        self.frames.retain(|frame| match frame {
+            Frame::Context { key, value } => seen.insert((key.clone(), value.clone())),
+            _ => true,
+        });
+
+        self.children
+            .iter_mut()
+            .for_each(ReportPayload::dedup_context);
+    }
+
+    /// Returns the deepest payload reachable by following only children.
+    ///
+    /// Like [`ReportError`]'s [`Error::source`], this stops at the first payload
+    /// that doesn't have exactly one child.
+    pub fn root_cause(&self) -> &ReportPayload {
+        match self.children.as_slice() {
+            [child] => child.root_cause(),
+            _ => self,
+        }
+    }
+
+    /// Returns the value of the first [`Frame::Context`] with `key`.
+    ///
+    /// Only this payload's frames are searched, not its children's.
+    pub fn context_value(&self, key: &str) -> Option<&str> {
+        self.frames.iter().find_map(|frame| match frame {
+            Frame::Context { key: other, value } if other == key => Some(value.as_str()),
+            _ => None,
+        })
+    }
 }
 
 /// Type representing a [`Report`], but implementing [`Error`].
@@ -265,6 +334,11 @@ impl<E> Report<E> {
         *self.inner
     }
 
+    /// Returns the [`Metadata`] associated with this `Report`.
+    pub fn metadata(&self) -> &Metadata {
+        &self.inner.metadata
+    }
+
     /// Converts this `Report` into a [`ReportError`]
     pub fn into_error(self) -> ReportError {
         ReportError(*self.inner)
@@ -279,6 +353,16 @@ impl<E> Report<E> {
         }
     }
 
+    /// Returns the level associated with this `Report`.
+    pub fn level(&self) -> Level {
+        self.inner.metadata.level
+    }
+
+    /// Whether this `Report` is at [`Level::Error`], as opposed to a non-fatal warning or note.
+    pub fn is_fatal(&self) -> bool {
+        self.level() == Level::Error
+    }
+
     /// Sets the level associated with the `Report`.
     pub fn with_level(mut self, level: Level) -> Self {
         self.inner.metadata.level = level;
@@ -307,6 +391,35 @@ impl<E> Report<E> {
         children.into_iter().fold(self, Report::with_child)
     }
 
+    /// Appends `children` as failed siblings, summarizing how many of `total` failed in the message.
+    ///
+    /// For example, a report with the message `could not build`, aggregating 3 reports out of 5 `packages`,
+    /// ends up with the message `could not build: 3 of 5 packages failed`.
+    pub fn aggregate<F>(
+        self,
+        children: impl IntoIterator<Item = Report<F>>,
+        total: usize,
+        noun: &str,
+    ) -> Self {
+        let existing = self.inner.children.len();
+        let mut report = self.with_children(children);
+        report.inner.message = format_smolstr!(
+            "{}: {} of {total} {noun} failed",
+            report.inner.message,
+            report.inner.children.len() - existing
+        );
+
+        report
+    }
+
+    /// Removes exact duplicate [`Frame::Context`]s from every node of this `Report`.
+    ///
+    /// See [`ReportPayload::dedup_context`] for more information.
+    pub fn dedup_context(mut self) -> Self {
+        self.inner.dedup_context();
+        self
+    }
+
     /// "Casts" the reports generic parameter to `F`.
     pub fn cast<F>(mut self) -> Report<F> {
         self.inner.metadata.type_name = SmolStr::new_static(type_name::<F>());
@@ -491,24 +604,292 @@ macro_rules! impl_compat {
 }
 
 /// Helper function to partition an [`Iterator`] based on its [`Result`]
+///
+/// See [`partition_results_with`] to cap the amount of errors collected.
 pub fn partition_results<T, U, E, F>(
     iterator: impl Iterator<Item = StdResult<T, E>>,
 ) -> StdResult<U, F>
+where
+    U: Extend<T> + Default,
+    F: Extend<E> + Default,
+{
+    partition_results_with(iterator, PartitionOptions::default()).map_err(|err| err.errors)
+}
+
+/// Options for [`partition_results_with`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PartitionOptions {
+    /// Maximum amount of errors to collect.
+    ///
+    /// Errors past the cap are still consumed, but only counted.
+    pub cap: Option<usize>,
+    /// Whether to stop consuming the iterator at the first error.
+    pub fail_fast: bool,
+}
+
+impl PartitionOptions {
+    /// Constructs new `PartitionOptions` which collect every error.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets [`Self::cap`].
+    #[inline]
+    pub fn cap(mut self, cap: usize) -> Self {
+        self.cap = Some(cap);
+        self
+    }
+
+    /// Sets [`Self::fail_fast`].
+    #[inline]
+    pub fn fail_fast(mut self, fail_fast: bool) -> Self {
+        self.fail_fast = fail_fast;
+        self
+    }
+}
+
+/// Errors collected by [`partition_results_with`].
+#[derive(Debug, Clone, Default)]
+pub struct PartitionedErrors<F> {
+    /// The collected errors.
+    pub errors: F,
+    /// The amount of errors omitted due to [`PartitionOptions::cap`].
+    pub omitted: usize,
+}
+
+impl<F> PartitionedErrors<F> {
+    /// Appends the collected errors as children of `parent`.
+    ///
+    /// If any errors were omitted, a summary [`Frame`] is appended as well.
+    #[track_caller]
+    pub fn wrap_with<E, G>(self, parent: impl Into<Report<E>>) -> Report<E>
+    where
+        F: IntoIterator<Item = Report<G>>,
+    {
+        let report = parent.into().with_children(self.errors);
+        match self.omitted {
+            0 => report,
+            omitted => report.with_frame(Frame::attachment(format_args!("...and {omitted} more"))),
+        }
+    }
+}
+
+/// Helper function to partition an [`Iterator`] based on its [`Result`], with [`PartitionOptions`].
+pub fn partition_results_with<T, U, E, F>(
+    iterator: impl Iterator<Item = StdResult<T, E>>,
+    options: PartitionOptions,
+) -> StdResult<U, PartitionedErrors<F>>
 where
     U: Extend<T> + Default,
     F: Extend<E> + Default,
 {
     let mut ok = U::default();
-    let mut err = F::default();
-
-    let mut has_error = false;
-    iterator.for_each(|result| match result {
-        Ok(v) => ok.extend(once(v)),
-        Err(v) => {
-            has_error = true;
-            err.extend(once(v));
+    let mut err = PartitionedErrors::<F>::default();
+
+    let mut collected = 0;
+    for result in iterator {
+        match result {
+            Ok(v) => ok.extend(once(v)),
+            Err(v) => {
+                if options.cap.is_some_and(|cap| collected >= cap) {
+                    err.omitted += 1;
+                } else {
+                    collected += 1;
+                    err.errors.extend(once(v));
+                }
+
+                if options.fail_fast {
+                    break;
+                }
+            }
         }
-    });
+    }
+
+    if collected + err.omitted > 0 {
+        Err(err)
+    } else {
+        Ok(ok)
+    }
+}
 
-    if has_error { Err(err) } else { Ok(ok) }
+/// Helper function to partition an [`Iterator`] of [`Result`]s, tolerating non-fatal reports.
+///
+/// Reports below [`Level::Error`] are collected as warnings alongside the values.
+/// If any report is fatal, every report is returned in order, warnings included.
+pub fn partition_warnings<T, U, E, F>(
+    iterator: impl Iterator<Item = Result<T, E>>,
+) -> StdResult<(U, F), F>
+where
+    U: Extend<T> + Default,
+    F: Extend<Report<E>> + Default,
+{
+    let mut ok = U::default();
+    let mut reports = F::default();
+    let mut fatal = false;
+
+    for result in iterator {
+        match result {
+            Ok(v) => ok.extend(once(v)),
+            Err(report) => {
+                fatal |= report.is_fatal();
+                reports.extend(once(report));
+            }
+        }
+    }
+
+    if fatal {
+        Err(reports)
+    } else {
+        Ok((ok, reports))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        Frame, Level, PartitionOptions, Report, partition_results, partition_results_with,
+        partition_warnings,
+        render::{PrettyRenderer, Renderer},
+    };
+
+    fn results() -> impl Iterator<Item = Result<usize, usize>> {
+        (0..10).map(|i| if i % 2 == 0 { Ok(i) } else { Err(i) })
+    }
+
+    #[test]
+    fn test_partition_uncapped() {
+        let partitioned = partition_results::<_, Vec<_>, _, Vec<_>>(results());
+        assert_eq!(partitioned, Err(vec![1, 3, 5, 7, 9]));
+
+        let partitioned = partition_results::<_, Vec<_>, _, Vec<_>>((0..4).map(Ok::<_, ()>));
+        assert_eq!(partitioned, Ok(vec![0, 1, 2, 3]));
+    }
+
+    #[test]
+    fn test_partition_cap() {
+        let options = PartitionOptions::new().cap(2);
+        let partitioned = partition_results_with::<_, Vec<_>, _, Vec<_>>(results(), options)
+            .expect_err("results should contain errors");
+
+        assert_eq!(partitioned.errors, vec![1, 3]);
+        assert_eq!(partitioned.omitted, 3);
+    }
+
+    #[test]
+    fn test_partition_cap_summary() {
+        let reports =
+            results().map(|result| result.map(|_| ()).map_err(|i| Report::new(i.to_string())));
+
+        let options = PartitionOptions::new().cap(4);
+        let report = partition_results_with::<_, (), _, Vec<_>>(reports, options)
+            .expect_err("results should contain errors")
+            .wrap_with(Report::new("parent"))
+            .into_payload();
+
+        let children: Vec<_> = report
+            .children
+            .iter()
+            .map(|child| child.message.as_str())
+            .collect();
+        assert_eq!(children, ["1", "3", "5", "7"]);
+        assert_eq!(report.frames, vec![Frame::attachment("...and 1 more")]);
+    }
+
+    #[test]
+    fn test_partition_fail_fast() {
+        let mut consumed = 0;
+        let iterator = results().inspect(|_| consumed += 1);
+        let options = PartitionOptions::new().fail_fast(true);
+        let partitioned = partition_results_with::<_, Vec<_>, _, Vec<_>>(iterator, options)
+            .expect_err("results should contain errors");
+
+        assert_eq!(partitioned.errors, vec![1]);
+        assert_eq!(partitioned.omitted, 0);
+        assert_eq!(consumed, 2);
+    }
+
+    #[test]
+    fn test_partition_warnings() {
+        let deprecated = || Err(Report::new("deprecated option").with_level(Level::Warn));
+
+        let results = [Ok(1), deprecated(), Ok(2)].into_iter();
+        let (values, warnings) = partition_warnings::<_, Vec<_>, (), Vec<_>>(results).unwrap();
+        assert_eq!(values, [1, 2]);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].level(), Level::Warn);
+
+        let results = [deprecated(), Err(Report::new("missing file")), Ok(1)].into_iter();
+        let reports = partition_warnings::<_, Vec<_>, (), Vec<_>>(results).unwrap_err();
+        let fatal: Vec<_> = reports.iter().map(Report::is_fatal).collect();
+        assert_eq!(fatal, [false, true]);
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let failures = ["a", "b", "c"].map(Report::new);
+        let report = Report::new("could not build").aggregate(failures, 5, "packages");
+
+        assert_eq!(report.message, "could not build: 3 of 5 packages failed");
+        assert_eq!(report.children.len(), 3);
+
+        // only the aggregated children count as failed
+        let report = Report::new("could not build")
+            .with_child(Report::new("cycle detected"))
+            .aggregate([Report::new("a")], 2, "packages");
+        assert_eq!(report.message, "could not build: 1 of 2 packages failed");
+    }
+
+    #[test]
+    fn test_root_cause() {
+        let linear = Report::new("could not build package")
+            .with_child(Report::new("could not fetch source").with_child(Report::new("timed out")));
+        assert_eq!(linear.root_cause().message, "timed out");
+
+        let branching = Report::new("could not build package").with_child(
+            Report::new("could not fetch sources")
+                .with_children([Report::new("timed out"), Report::new("not found")]),
+        );
+        assert_eq!(branching.root_cause().message, "could not fetch sources");
+
+        assert_eq!(Report::new("leaf").root_cause().message, "leaf");
+    }
+
+    #[test]
+    fn test_context_value() {
+        let report = Report::new("could not read file")
+            .with_frames([
+                Frame::context("path", "/etc/xuehua"),
+                Frame::suggestion("check the path"),
+                Frame::context("path", "/var/xuehua"),
+            ])
+            .with_child(
+                Report::new("permission denied").with_frame(Frame::context("mode", "0600")),
+            );
+
+        assert_eq!(report.context_value("path"), Some("/etc/xuehua"));
+        assert_eq!(report.context_value("mode"), None);
+        assert_eq!(report.context_value("missing"), None);
+    }
+
+    #[test]
+    fn test_dedup_context() {
+        let report = Report::new("failed to read file")
+            .with_frames([
+                Frame::context("path", "/etc/xuehua"),
+                Frame::context("path", "/etc/xuehua"),
+                Frame::context("path", "/var/xuehua"),
+            ])
+            .with_child(Report::new("permission denied").with_frames([
+                Frame::context("mode", "0600"),
+                Frame::context("mode", "0600"),
+            ]))
+            .dedup_context()
+            .into_payload();
+
+        let rendered = PrettyRenderer::new().render(&report).to_string();
+        assert_eq!(rendered.matches("path: /etc/xuehua").count(), 1);
+        assert_eq!(rendered.matches("path: /var/xuehua").count(), 1);
+        assert_eq!(rendered.matches("mode: 0600").count(), 1);
+    }
 }
@@ -35,7 +35,9 @@ pub struct Styles {
     context: Style,
     suggestion: Style,
     attachment: Style,
+    timing: Style,
     location: Style,
+    type_name: Style,
     distracting: Style,
     log: LogStyles,
 }
@@ -47,7 +49,9 @@ impl Default for Styles {
             suggestion: Style::new().green(),
             context: Style::new().cyan(),
             attachment: Style::new().yellow(),
+            timing: Style::new().blue(),
             location: Style::new().purple(),
+            type_name: Style::new().purple(),
             distracting: Style::new(),
             log: LogStyles::default(),
         }
@@ -102,7 +106,9 @@ pub struct Headers {
     context: &'static str,
     suggestion: &'static str,
     attachment: &'static str,
+    timing: &'static str,
     location: &'static str,
+    type_name: &'static str,
     log: LogHeaders,
 }
 
@@ -112,18 +118,33 @@ impl Default for Headers {
             context: "(context)",
             suggestion: "(suggestion)",
             attachment: "(attachment)",
+            timing: "(timing)",
             location: "(location)",
+            type_name: "(type)",
             log: LogHeaders::default(),
         }
     }
 }
 
+/// How much of each report is rendered.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Only messages, suggestions, and children.
+    Low,
+    /// Additionally context, timings, attachments, and locations.
+    #[default]
+    Normal,
+    /// Everything, including type names.
+    High,
+}
+
 /// Configuration for [`PrettyRenderer`].
 #[derive(Default, Debug, Copy, Clone)]
 pub struct Config {
     guides: Guides,
     headers: Headers,
     styles: Styles,
+    verbosity: Verbosity,
 }
 
 /// Pretty renderer for [`Report`]s.
@@ -142,6 +163,13 @@ impl PrettyRenderer {
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// Sets the [`Verbosity`] reports are rendered with.
+    #[inline]
+    pub fn with_verbosity(mut self, verbosity: Verbosity) -> Self {
+        self.config.verbosity = verbosity;
+        self
+    }
 }
 
 impl Renderer for PrettyRenderer {
@@ -233,6 +261,20 @@ impl PrettyDisplayer<'_> {
     ) -> fmt::Result {
         let headers = &self.inner.config.headers;
         let styles = &self.inner.config.styles;
+        let verbosity = self.inner.config.verbosity;
+        if verbosity < Verbosity::Normal {
+            return Ok(());
+        }
+
+        if verbosity >= Verbosity::High {
+            write!(
+                printer,
+                "{prefix}{} {}",
+                headers.type_name.style(styles.type_name),
+                metadata.type_name.style(styles.distracting)
+            )?;
+        }
+
         let mut write_location = |value: fmt::Arguments<'_>| {
             write!(
                 printer,
@@ -280,6 +322,10 @@ impl PrettyDisplayer<'_> {
             )?;
         }
 
+        if self.inner.config.verbosity < Verbosity::Normal {
+            return Ok(());
+        }
+
         // context pass
         let mut first = true;
         for frame in frames {
@@ -299,20 +345,46 @@ impl PrettyDisplayer<'_> {
             )?;
         }
 
-        // attachment pass
+        // timing pass
         for frame in frames {
-            let Frame::Attachment(attachment) = frame else {
+            let Frame::Timing { label, duration } = frame else {
                 continue;
             };
 
             write!(
                 printer,
-                "{prefix}{}",
-                headers.attachment.style(styles.attachment)
+                "{prefix}{} {}",
+                headers.timing.style(styles.timing),
+                format_args!("{label} took {duration:.2?}").style(styles.distracting)
             )?;
+        }
 
-            for line in attachment.lines() {
-                write!(printer, "{prefix}  {}", line.style(styles.distracting))?;
+        // attachment pass
+        for frame in frames {
+            match frame {
+                Frame::Attachment(attachment) => {
+                    write!(
+                        printer,
+                        "{prefix}{}",
+                        headers.attachment.style(styles.attachment)
+                    )?;
+
+                    for line in attachment.lines() {
+                        write!(printer, "{prefix}  {}", line.style(styles.distracting))?;
+                    }
+                }
+                Frame::AttachmentBytes(bytes) => {
+                    write!(
+                        printer,
+                        "{prefix}{}",
+                        headers.attachment.style(styles.attachment)
+                    )?;
+
+                    for line in hex_lines(bytes) {
+                        write!(printer, "{prefix}  {}", line.style(styles.distracting))?;
+                    }
+                }
+                _ => continue,
             }
         }
 
@@ -348,3 +420,155 @@ impl PrettyDisplayer<'_> {
         Ok(())
     }
 }
+
+const HEX_LINE_WIDTH: usize = 16;
+
+/// A single line of a hex+ASCII dump, in the style of `hexdump -C`.
+struct HexLine<'a> {
+    offset: usize,
+    chunk: &'a [u8],
+}
+
+impl fmt::Display for HexLine<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:08x} ", self.offset)?;
+        for i in 0..HEX_LINE_WIDTH {
+            match self.chunk.get(i) {
+                Some(byte) => write!(f, " {byte:02x}")?,
+                None => f.write_str("   ")?,
+            }
+        }
+
+        f.write_str("  |")?;
+        for byte in self.chunk {
+            let char = if byte.is_ascii_graphic() || *byte == b' ' {
+                *byte as char
+            } else {
+                '.'
+            };
+
+            write!(f, "{char}")?;
+        }
+
+        f.write_str("|")
+    }
+}
+
+fn hex_lines(bytes: &[u8]) -> impl Iterator<Item = HexLine<'_>> {
+    bytes
+        .chunks(HEX_LINE_WIDTH)
+        .enumerate()
+        .map(|(i, chunk)| HexLine {
+            offset: i * HEX_LINE_WIDTH,
+            chunk,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use owo_colors::{OwoColorize, Style};
+
+    use crate::{
+        Frame, Level, Report,
+        render::{PrettyRenderer, Renderer, pretty::Verbosity},
+    };
+
+    #[test]
+    fn test_render_level() {
+        let render = |report: Report<()>| {
+            PrettyRenderer::new()
+                .render(&report.into_payload())
+                .to_string()
+        };
+
+        let warn = "(warn)".style(Style::new().yellow()).to_string();
+        let error = "(error)".style(Style::new().red()).to_string();
+
+        let rendered = render(Report::new("deprecated option").with_level(Level::Warn));
+        assert!(rendered.starts_with(&warn), "{rendered:?}");
+        assert!(!rendered.contains(&error));
+
+        let rendered = render(Report::new("missing file"));
+        assert!(rendered.starts_with(&error), "{rendered:?}");
+    }
+
+    #[test]
+    fn test_render_timing() {
+        let report = Report::new("could not build package")
+            .with_frame(Frame::timing("execute", Duration::from_millis(1250)))
+            .into_payload();
+
+        let rendered = PrettyRenderer::new().render(&report).to_string();
+        let timing = "(timing)".style(Style::new().blue()).to_string();
+        let line = rendered
+            .lines()
+            .find(|line| line.contains(&timing))
+            .expect("rendered report should contain a timing frame");
+        assert!(line.contains("execute took 1.25s"), "{line:?}");
+    }
+
+    #[test]
+    fn test_render_attachment_bytes() {
+        let report = Report::new("bad token")
+            .with_frame(Frame::attachment_bytes(
+                b"xuehua\x00\xff archive@hd\n".as_slice(),
+            ))
+            .into_payload();
+
+        let rendered = PrettyRenderer::new().render(&report).to_string();
+        let expected = [
+            "00000000  78 75 65 68 75 61 00 ff 20 61 72 63 68 69 76 65  |xuehua.. archive|",
+            "00000010  40 68 64 0a                                      |@hd.|",
+        ];
+
+        for line in expected {
+            assert!(
+                rendered.contains(line),
+                "rendered report should contain {line:?}:\n{rendered}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_render_verbosity() {
+        let report = Report::new("could not build package")
+            .with_frame(Frame::context("package", "example@core"))
+            .with_frame(Frame::suggestion("rerun with --keep-going"))
+            .with_frame(Frame::timing("execute", Duration::from_millis(10)))
+            .with_child(Report::new("download failed"))
+            .into_payload();
+        let render = |verbosity| {
+            PrettyRenderer::new()
+                .with_verbosity(verbosity)
+                .render(&report)
+                .to_string()
+        };
+
+        let low = render(Verbosity::Low);
+        for expected in [
+            "could not build package",
+            "rerun with --keep-going",
+            "download failed",
+        ] {
+            assert!(low.contains(expected), "{expected:?} missing:\n{low}");
+        }
+        for hidden in ["(context)", "(timing)", "(location)", "(type)"] {
+            assert!(!low.contains(hidden), "{hidden:?} rendered:\n{low}");
+        }
+
+        let high = render(Verbosity::High);
+        for expected in [
+            "rerun with --keep-going",
+            "(context)",
+            "package: example@core",
+            "(timing)",
+            "(location)",
+            "(type)",
+            "download failed",
+        ] {
+            assert!(high.contains(expected), "{expected:?} missing:\n{high}");
+        }
+    }
+}
@@ -0,0 +1,7 @@
+BEGIN;
+CREATE TABLE IF NOT EXISTS build_durations(
+    name TEXT PRIMARY KEY NOT NULL,
+    milliseconds INTEGER NOT NULL
+) WITHOUT ROWID;
+PRAGMA user_version = 4;
+COMMIT;
@@ -1,3 +1,5 @@
+-- lets readers continue while another connection writes, persisted in the database file
+PRAGMA journal_mode = WAL;
 BEGIN;
 CREATE TABLE IF NOT EXISTS artifacts(
     id BLOB PRIMARY KEY NOT NULL,
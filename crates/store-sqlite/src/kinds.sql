@@ -0,0 +1,7 @@
+BEGIN;
+-- names are stored like they're displayed, which now ends with their kind
+UPDATE packages SET name = name || '(package)' WHERE name != '' AND name NOT LIKE '%)';
+DELETE FROM build_durations WHERE name NOT LIKE '%)' AND name || '(package)' IN (SELECT name FROM build_durations);
+UPDATE build_durations SET name = name || '(package)' WHERE name NOT LIKE '%)';
+PRAGMA user_version = 5;
+COMMIT;
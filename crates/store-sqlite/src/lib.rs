@@ -1,55 +1,243 @@
 use std::{
     fs::File,
     io::{BufWriter, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::LazyLock,
+    time::Duration,
 };
 
 use bytes::Bytes;
 use educe::Educe;
 use jiff::Timestamp;
-use rusqlite::{Connection, OptionalExtension, named_params};
+use rusqlite::{Connection, ErrorCode, OptionalExtension, Row, named_params, types::Type};
 use tokio::sync::{mpsc, oneshot};
 use tracing::instrument;
-use xh_archive::{Event, decoding::Decoder};
+use xh_archive::{Event, decoding::Decoder, unpacking::Unpacker};
 use xh_engine::{
     gen_name,
-    name::StoreName,
+    name::{PackageName, StoreName},
     planner::PackageId,
-    store::{ArtifactId, Error, Store, StoreArtifact, StorePackage},
+    store::{ArtifactId, Error, MissingArtifactError, Store, StoreArtifact, StorePackage},
     utils::ensure_dir,
 };
 use xh_reports::prelude::*;
 
+#[derive(Debug, IntoReport)]
+#[message("store database is newer than this version of xuehua supports")]
+#[suggestion("upgrade xuehua, or point it at a different store")]
+#[context(found, supported)]
+pub struct SchemaVersionError {
+    found: u32,
+    supported: u32,
+}
+
+/// How artifacts are laid out within the store directory.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// Every artifact is a single file named by its hash, like `abcdef...`.
+    Flat,
+    /// Artifacts are grouped into directories by the first byte of their hash, like `ab/cdef...`.
+    ///
+    /// Keeps directories small for filesystems that slow down with many entries.
+    #[default]
+    Sharded,
+}
+
+/// Artifact directory of a store, along with its [`Layout`].
+#[derive(Debug, Clone)]
+struct Artifacts {
+    root: PathBuf,
+    layout: Layout,
+}
+
+impl Artifacts {
+    fn path(&self, artifact: &ArtifactId) -> PathBuf {
+        let hex = artifact.to_hex();
+        match self.layout {
+            Layout::Flat => self.root.join(hex.as_str()),
+            Layout::Sharded => {
+                let (shard, rest) = hex.split_at(2);
+                self.root.join(shard).join(rest)
+            }
+        }
+    }
+
+    /// Gets the path of `artifact`, creating its parent directory if needed.
+    fn create_path(&self, artifact: &ArtifactId) -> Result<PathBuf, Error> {
+        let path = self.path(artifact);
+        if let Some(parent) = path.parent() {
+            ensure_dir(parent).wrap()?;
+        }
+
+        Ok(path)
+    }
+
+    /// Moves artifacts stored with a different layout into this one.
+    fn migrate(&self) -> Result<(), Error> {
+        for entry in std::fs::read_dir(&self.root).wrap()? {
+            let entry = entry.wrap()?;
+            let name = entry.file_name();
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+
+            let file_type = entry.file_type().wrap()?;
+            match self.layout {
+                Layout::Sharded if file_type.is_file() => {
+                    if let Ok(artifact) = ArtifactId::from_hex(name) {
+                        std::fs::rename(entry.path(), self.create_path(&artifact)?).wrap()?;
+                    }
+                }
+                Layout::Flat if file_type.is_dir() && is_shard(name) => {
+                    self.migrate_shard(&entry.path(), name)?
+                }
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn migrate_shard(&self, shard: &Path, prefix: &str) -> Result<(), Error> {
+        for entry in std::fs::read_dir(shard).wrap()? {
+            let entry = entry.wrap()?;
+            let artifact = entry
+                .file_name()
+                .to_str()
+                .and_then(|rest| ArtifactId::from_hex(format!("{prefix}{rest}")).ok());
+            if let Some(artifact) = artifact {
+                std::fs::rename(entry.path(), self.path(&artifact)).wrap()?;
+            }
+        }
+
+        // anything left over isn't ours to remove
+        match std::fs::remove_dir(shard) {
+            Err(err) if err.kind() != std::io::ErrorKind::DirectoryNotEmpty => Err(err).wrap(),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn is_shard(name: &str) -> bool {
+    name.len() == 2 && name.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+/// How long a connection waits for another connection's lock, before failing with `SQLITE_BUSY`.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Attempts at a write that keeps failing with `SQLITE_BUSY` after waiting for [`BUSY_TIMEOUT`].
+const BUSY_ATTEMPTS: u32 = 3;
+
+/// Default age an unreferenced artifact must reach before [`SqliteStore::gc`] collects it.
+///
+/// Artifacts are registered before the packages referencing them,
+/// so without a grace period a concurrent collection could delete an artifact in between.
+pub const GC_GRACE_PERIOD: Duration = Duration::from_secs(60 * 60);
+
+/// Retries `write` while the database is locked by another connection, such as another store in a different process.
+///
+/// SQLite returns `SQLITE_BUSY` without waiting for [`BUSY_TIMEOUT`] when waiting could deadlock,
+/// so the busy timeout alone doesn't cover every conflict.
+fn retry_busy<T>(mut write: impl FnMut() -> rusqlite::Result<T>) -> rusqlite::Result<T> {
+    let mut attempt = 1;
+    loop {
+        match write() {
+            Err(rusqlite::Error::SqliteFailure(error, _))
+                if error.code == ErrorCode::DatabaseBusy && attempt < BUSY_ATTEMPTS =>
+            {
+                tracing::debug!(attempt, "database is busy, retrying");
+                std::thread::sleep(Duration::from_millis(50) * attempt);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Schema migrations, applied in order on top of `initialize.sql`.
+///
+/// Each migration must bump `user_version` to its position in this list, plus one.
+const MIGRATIONS: &[&str] = &[
+    include_str!("refcount.sql"),
+    include_str!("names.sql"),
+    include_str!("stats.sql"),
+    include_str!("durations.sql"),
+    include_str!("kinds.sql"),
+];
+
 struct Queries;
 
 impl Queries {
-    const REGISTER_ARTIFACT: &'static str =
-        "INSERT OR REPLACE INTO artifacts (id, created_at) VALUES (:id, :created_at)";
-    const REGISTER_PACKAGE: &'static str = "INSERT OR REPLACE INTO packages (id, artifact, created_at) VALUES (:id, :artifact, :created_at)";
+    // upserts instead of `INSERT OR REPLACE`, so refcount triggers see an update, not a delete
+    const REGISTER_ARTIFACT: &'static str = "INSERT INTO artifacts (id, created_at, size, objects) VALUES (:id, :created_at, :size, :objects) ON CONFLICT(id) DO UPDATE SET created_at = excluded.created_at, size = excluded.size, objects = excluded.objects";
+    const REGISTER_PACKAGE: &'static str = "INSERT INTO packages (id, name, artifact, created_at) VALUES (:id, :name, :artifact, :created_at) ON CONFLICT(id) DO UPDATE SET name = excluded.name, artifact = excluded.artifact, created_at = excluded.created_at";
+    const UNREGISTER_PACKAGE: &'static str = "DELETE FROM packages WHERE id IS :id";
     const GET_PACKAGE: &'static str =
-        "SELECT 1 FROM packages WHERE id IS :id ORDER BY created_at DESC";
-    const GET_ARTIFACT: &'static str = "SELECT 1 FROM artifacts WHERE id IS :id";
+        "SELECT * FROM packages WHERE id IS :id ORDER BY created_at DESC";
+    // `created_at` is compared as a time, since its text form has a variable amount of subsecond digits.
+    // packages from before names were recorded have an empty name, and can't be told apart, so they're left alone
+    const PRUNE_PACKAGES: &'static str = "DELETE FROM packages WHERE rowid IN (SELECT rowid FROM (SELECT rowid, ROW_NUMBER() OVER (PARTITION BY name ORDER BY unixepoch(created_at, 'subsec') DESC, rowid DESC) AS position FROM packages WHERE name != '') WHERE position > :keep)";
+    const LIST_PACKAGES: &'static str =
+        "SELECT * FROM packages ORDER BY name, unixepoch(created_at, 'subsec') DESC, rowid DESC";
+    const GET_ARTIFACT: &'static str = "SELECT * FROM artifacts WHERE id IS :id";
+    // artifacts without a creation time predate it being recorded, so they are always old enough
+    const COLLECT_ARTIFACTS: &'static str = "DELETE FROM artifacts WHERE refcount = 0 AND COALESCE(unixepoch(created_at, 'subsec'), 0) <= :cutoff RETURNING id";
+    // artifacts shared between versions of a package are only counted once for it
+    const DISK_USAGE: &'static str = "SELECT name, COALESCE(SUM(size), 0) AS size FROM (SELECT DISTINCT name, artifact FROM packages) JOIN artifacts ON artifact = artifacts.id GROUP BY name ORDER BY name";
+    const RECORD_BUILD_DURATION: &'static str = "INSERT INTO build_durations (name, milliseconds) VALUES (:name, :milliseconds) ON CONFLICT(name) DO UPDATE SET milliseconds = excluded.milliseconds";
+    const LAST_BUILD_DURATION: &'static str =
+        "SELECT milliseconds FROM build_durations WHERE name IS :name";
 }
 
+type ArchiveStream = Box<dyn Iterator<Item = Result<Event, Error>> + Send>;
+
 #[derive(Educe)]
 #[educe(Debug)]
 enum Task {
     RegisterPackage {
+        name: PackageName,
         package: PackageId,
         artifact: ArtifactId,
         #[educe(Debug(ignore))]
         channel: oneshot::Sender<Result<StorePackage, Error>>,
     },
+    UnregisterPackage {
+        package: PackageId,
+        #[educe(Debug(ignore))]
+        channel: oneshot::Sender<Result<bool, Error>>,
+    },
     GetPackage {
         package: PackageId,
         #[educe(Debug(ignore))]
         channel: oneshot::Sender<Result<Option<StorePackage>, Error>>,
     },
-    RegisterArtifact {
+    PrunePackages {
+        keep: usize,
         #[educe(Debug(ignore))]
-        archive: Vec<Event>,
-        root: PathBuf,
+        channel: oneshot::Sender<Result<usize, Error>>,
+    },
+    ListPackages {
+        #[educe(Debug(ignore))]
+        channel: oneshot::Sender<Result<Vec<StorePackage>, Error>>,
+    },
+    DiskUsage {
+        #[educe(Debug(ignore))]
+        channel: oneshot::Sender<Result<Vec<(PackageName, u64)>, Error>>,
+    },
+    RecordBuildDuration {
+        name: PackageName,
+        duration: Duration,
+        #[educe(Debug(ignore))]
+        channel: oneshot::Sender<Result<(), Error>>,
+    },
+    LastBuildDuration {
+        name: PackageName,
+        #[educe(Debug(ignore))]
+        channel: oneshot::Sender<Result<Option<Duration>, Error>>,
+    },
+    RegisterArtifact {
+        written: WrittenArtifact,
+        artifacts: Artifacts,
         #[educe(Debug(ignore))]
         channel: oneshot::Sender<Result<StoreArtifact, Error>>,
     },
@@ -58,11 +246,17 @@ enum Task {
         #[educe(Debug(ignore))]
         channel: oneshot::Sender<Result<Option<StoreArtifact>, Error>>,
     },
-    DecodeArtifact {
+    CollectGarbage {
+        artifacts: Artifacts,
+        grace: Duration,
+        #[educe(Debug(ignore))]
+        channel: oneshot::Sender<Result<Vec<ArtifactId>, Error>>,
+    },
+    FetchArtifact {
         artifact: ArtifactId,
-        root: PathBuf,
+        artifacts: Artifacts,
         #[educe(Debug(ignore))]
-        channel: oneshot::Sender<Result<Option<Vec<Event>>, Error>>,
+        channel: oneshot::Sender<Result<Option<Bytes>, Error>>,
     },
     Shutdown,
 }
@@ -70,28 +264,29 @@ enum Task {
 #[instrument(skip(db))]
 fn register_package(
     db: &mut Connection,
+    name: PackageName,
     package: PackageId,
     artifact: ArtifactId,
 ) -> Result<StorePackage, Error> {
-    db.execute(
-        Queries::REGISTER_PACKAGE,
-        named_params! {
-            ":id": package.as_bytes(),
-            ":artifact": artifact.as_bytes(),
-            ":created_at": Timestamp::now(),
-        },
-    )
+    retry_busy(|| {
+        db.execute(
+            Queries::REGISTER_PACKAGE,
+            named_params! {
+                ":id": package.as_bytes(),
+                ":name": name.to_string(),
+                ":artifact": artifact.as_bytes(),
+                ":created_at": Timestamp::now(),
+            },
+        )
+    })
     .wrap()?;
 
     db.prepare_cached(Queries::GET_PACKAGE)
         .wrap()?
-        .query_one(named_params! { ":id": package.as_bytes() }, |row| {
-            Ok(StorePackage {
-                id: PackageId::from_bytes(row.get("id")?),
-                artifact: ArtifactId::from_bytes(row.get("artifact")?),
-                created_at: row.get("created_at")?,
-            })
-        })
+        .query_one(
+            named_params! { ":id": package.as_bytes() },
+            package_from_row,
+        )
         .optional()
         .transpose()
         .ok_or(rusqlite::Error::QueryReturnedNoRows)
@@ -99,56 +294,192 @@ fn register_package(
         .wrap()
 }
 
+#[instrument(skip(db))]
+fn unregister_package(db: &mut Connection, package: PackageId) -> Result<bool, Error> {
+    let removed = db
+        .execute(
+            Queries::UNREGISTER_PACKAGE,
+            named_params! { ":id": package.as_bytes() },
+        )
+        .wrap()?;
+
+    Ok(removed != 0)
+}
+
 #[instrument(skip(db))]
 fn get_package(db: &mut Connection, package: PackageId) -> Result<Option<StorePackage>, Error> {
     db.prepare_cached(Queries::GET_PACKAGE)
         .wrap()?
-        .query_one(named_params! { ":id": package.as_bytes() }, |row| {
-            Ok(StorePackage {
-                id: PackageId::from_bytes(row.get("id")?),
-                artifact: ArtifactId::from_bytes(row.get("artifact")?),
-                created_at: row.get("created_at")?,
-            })
-        })
+        .query_one(
+            named_params! { ":id": package.as_bytes() },
+            package_from_row,
+        )
         .optional()
         .wrap()
 }
 
-// TODO: reimplement this in a way that cant break in 200 different ways
-#[instrument(skip(db, archive))]
-fn register_artifact(
+#[instrument(skip(db))]
+fn prune_packages(db: &mut Connection, keep: usize) -> Result<usize, Error> {
+    let keep = i64::try_from(keep).unwrap_or(i64::MAX);
+    db.execute(Queries::PRUNE_PACKAGES, named_params! { ":keep": keep })
+        .wrap()
+}
+
+#[instrument(skip(db))]
+fn list_packages(db: &mut Connection) -> Result<Vec<StorePackage>, Error> {
+    db.prepare_cached(Queries::LIST_PACKAGES)
+        .wrap()?
+        .query_map([], package_from_row)
+        .wrap()?
+        .collect::<StdResult<_, _>>()
+        .wrap()
+}
+
+#[instrument(skip(db))]
+fn disk_usage(db: &mut Connection) -> Result<Vec<(PackageName, u64)>, Error> {
+    db.prepare_cached(Queries::DISK_USAGE)
+        .wrap()?
+        .query_map([], |row| {
+            Ok((name_from_row(row)?, row.get::<_, i64>("size")? as u64))
+        })
+        .wrap()?
+        .collect::<StdResult<_, _>>()
+        .wrap()
+}
+
+#[instrument(skip(db))]
+fn record_build_duration(
     db: &mut Connection,
-    root: PathBuf,
-    archive: &[Event],
-) -> Result<StoreArtifact, Error> {
-    let temp = artifact_path(root.clone(), &xh_common::random_hash());
+    name: PackageName,
+    duration: Duration,
+) -> Result<(), Error> {
+    retry_busy(|| {
+        db.execute(
+            Queries::RECORD_BUILD_DURATION,
+            named_params! {
+                ":name": name.to_string(),
+                ":milliseconds": i64::try_from(duration.as_millis()).unwrap_or(i64::MAX),
+            },
+        )
+    })
+    .wrap()?;
+
+    Ok(())
+}
+
+#[instrument(skip(db))]
+fn last_build_duration(db: &mut Connection, name: PackageName) -> Result<Option<Duration>, Error> {
+    db.query_one(
+        Queries::LAST_BUILD_DURATION,
+        named_params! { ":name": name.to_string() },
+        |row| row.get::<_, i64>("milliseconds"),
+    )
+    .optional()
+    .wrap()
+    .map(|milliseconds| milliseconds.map(|milliseconds| Duration::from_millis(milliseconds as u64)))
+}
+
+fn name_from_row(row: &Row) -> rusqlite::Result<PackageName> {
+    let name: String = row.get("name")?;
+    name.parse().map_err(|report: Report<_>| {
+        let index = row.as_ref().column_index("name").unwrap_or_default();
+        rusqlite::Error::FromSqlConversionFailure(index, Type::Text, report.into_error().into())
+    })
+}
+
+fn package_from_row(row: &Row) -> rusqlite::Result<StorePackage> {
+    Ok(StorePackage {
+        name: name_from_row(row)?,
+        id: PackageId::from_bytes(row.get("id")?),
+        artifact: ArtifactId::from_bytes(row.get("artifact")?),
+        created_at: row.get("created_at")?,
+    })
+}
+
+/// An artifact encoded to a temporary file, yet to be moved into place and registered.
+#[derive(Debug)]
+struct WrittenArtifact {
+    temp: PathBuf,
+    digest: ArtifactId,
+    size: u64,
+    objects: u64,
+}
+
+// TODO: reimplement this in a way that cant break in 200 different ways
+/// Encodes `archive` into a temporary file within the store.
+///
+/// Packing and hashing can take a while, so this runs outside of the processing thread.
+#[instrument(skip(archive))]
+fn write_artifact(
+    artifacts: &Artifacts,
+    mut archive: ArchiveStream,
+) -> Result<WrittenArtifact, Error> {
+    let temp = artifacts.create_path(&xh_common::random_hash())?;
     let file = File::create_new(&temp).wrap()?;
 
     let mut file = BufWriter::new(file);
     let mut buffer = bytes::BytesMut::with_capacity(1024 * 4);
     let mut encoder = xh_archive::encoding::Encoder::new();
 
-    for event in archive {
-        buffer.clear();
-        encoder.encode(&mut buffer, event);
-        file.write_all(&buffer).wrap()?;
+    // events are encoded as they arrive, so the archive is never fully held in memory
+    let (mut size, mut objects) = (0u64, 0u64);
+    let written = archive
+        .try_for_each(|event| {
+            let event = event?;
+            objects += matches!(event, Event::Object(_)) as u64;
+
+            buffer.clear();
+            encoder.encode(&mut buffer, event).wrap()?;
+            size += buffer.len() as u64;
+            file.write_all(&buffer).wrap()
+        })
+        .and_then(|()| file.flush().wrap());
+    if let Err(report) = written {
+        let _ = std::fs::remove_file(&temp);
+        return Err(report);
     }
 
-    let digest = encoder.digest();
-    std::fs::rename(temp, artifact_path(root, &digest)).wrap()?;
+    Ok(WrittenArtifact {
+        temp,
+        digest: ArtifactId::from_bytes(*encoder.digest().as_bytes()),
+        size,
+        objects,
+    })
+}
+
+/// Moves a written artifact into place, and registers it.
+#[instrument(skip(db))]
+fn register_artifact(
+    db: &mut Connection,
+    artifacts: Artifacts,
+    written: WrittenArtifact,
+) -> Result<StoreArtifact, Error> {
+    let WrittenArtifact {
+        temp,
+        digest,
+        size,
+        objects,
+    } = written;
+    std::fs::rename(temp, artifacts.create_path(&digest)?).wrap()?;
 
-    db.execute(
-        Queries::REGISTER_ARTIFACT,
-        named_params! {
-            ":id": digest.as_bytes(),
-            ":created_at": Timestamp::now()
-        },
-    )
+    retry_busy(|| {
+        db.execute(
+            Queries::REGISTER_ARTIFACT,
+            named_params! {
+                ":id": digest.as_bytes(),
+                ":created_at": Timestamp::now(),
+                ":size": i64::try_from(size).unwrap_or(i64::MAX),
+                ":objects": i64::try_from(objects).unwrap_or(i64::MAX),
+            },
+        )
+    })
     .wrap()?;
 
     Ok(StoreArtifact {
         id: digest,
         created_at: Timestamp::now(),
+        size: Some(size),
+        objects: Some(objects),
     })
 }
 
@@ -161,6 +492,11 @@ fn get_artifact(db: &mut Connection, artifact: ArtifactId) -> Result<Option<Stor
             Ok(StoreArtifact {
                 id: ArtifactId::from_bytes(row.get("id")?),
                 created_at: row.get("created_at")?,
+                // stored as signed integers, see `register_artifact`
+                size: row.get::<_, Option<i64>>("size")?.map(|size| size as u64),
+                objects: row
+                    .get::<_, Option<i64>>("objects")?
+                    .map(|objects| objects as u64),
             })
         },
     )
@@ -168,21 +504,58 @@ fn get_artifact(db: &mut Connection, artifact: ArtifactId) -> Result<Option<Stor
     .wrap()
 }
 
+#[instrument(skip(db))]
+fn collect_garbage(
+    db: &mut Connection,
+    artifacts: Artifacts,
+    grace: Duration,
+) -> Result<Vec<ArtifactId>, Error> {
+    let cutoff =
+        Timestamp::now().as_millisecond() - grace.as_millis().try_into().unwrap_or(i64::MAX);
+    let collected = db
+        .prepare_cached(Queries::COLLECT_ARTIFACTS)
+        .wrap()?
+        .query_map(named_params! { ":cutoff": cutoff as f64 / 1000.0 }, |row| {
+            Ok(ArtifactId::from_bytes(row.get("id")?))
+        })
+        .wrap()?
+        .collect::<StdResult<Vec<_>, _>>()
+        .wrap()?;
+
+    for artifact in &collected {
+        match std::fs::remove_file(artifacts.path(artifact)) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err).wrap(),
+            _ => (),
+        }
+    }
+
+    Ok(collected)
+}
+
+/// Maps an artifact's file, so garbage collection can't remove it from under a reader.
 #[instrument]
-fn decode_artifact(root: PathBuf, artifact: ArtifactId) -> Result<Option<Vec<Event>>, Error> {
-    let file = match File::open(artifact_path(root, &artifact)) {
+fn fetch_artifact(artifacts: Artifacts, artifact: ArtifactId) -> Result<Option<Bytes>, Error> {
+    let file = match File::open(artifacts.path(&artifact)) {
         Ok(file) => file,
         Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
         Err(err) => return Err(err).wrap(),
     };
 
-    let mut mmap = Bytes::from_owner(unsafe { memmap2::Mmap::map(&file).wrap()? });
-    let archive = Decoder::new()
-        .decode_iter(&mut mmap)
-        .collect::<Result<Vec<_>, _>>()
-        .wrap()?;
+    Ok(Some(Bytes::from_owner(unsafe {
+        memmap2::Mmap::map(&file).wrap()?
+    })))
+}
 
-    Ok(Some(archive))
+/// Unpacks a fetched artifact into `dest`, decoding it one event at a time.
+#[instrument(skip(archive))]
+fn extract_artifact(mut archive: Bytes, dest: PathBuf) -> Result<(), Error> {
+    std::fs::create_dir_all(&dest).wrap()?;
+    let mut unpacker = Unpacker::new(&dest);
+    Decoder::new()
+        .decode_iter(&mut archive)
+        .try_for_each(|event| unpacker.unpack(event.wrap()?).wrap())?;
+
+    Ok(())
 }
 
 fn processing_thread(mut db: Connection, mut rx: mpsc::Receiver<Task>) {
@@ -190,31 +563,61 @@ fn processing_thread(mut db: Connection, mut rx: mpsc::Receiver<Task>) {
         let _span = tracing::debug_span!("process_task", ?task).entered();
         match task {
             Task::RegisterPackage {
+                name,
                 package,
                 artifact,
                 channel,
             } => {
-                let _ = channel.send(register_package(&mut db, package, artifact));
+                let _ = channel.send(register_package(&mut db, name, package, artifact));
+            }
+            Task::UnregisterPackage { package, channel } => {
+                let _ = channel.send(unregister_package(&mut db, package));
             }
             Task::GetPackage { package, channel } => {
                 let _ = channel.send(get_package(&mut db, package));
             }
+            Task::PrunePackages { keep, channel } => {
+                let _ = channel.send(prune_packages(&mut db, keep));
+            }
+            Task::ListPackages { channel } => {
+                let _ = channel.send(list_packages(&mut db));
+            }
+            Task::DiskUsage { channel } => {
+                let _ = channel.send(disk_usage(&mut db));
+            }
+            Task::RecordBuildDuration {
+                name,
+                duration,
+                channel,
+            } => {
+                let _ = channel.send(record_build_duration(&mut db, name, duration));
+            }
+            Task::LastBuildDuration { name, channel } => {
+                let _ = channel.send(last_build_duration(&mut db, name));
+            }
             Task::RegisterArtifact {
-                archive,
-                root,
+                written,
+                artifacts,
                 channel,
             } => {
-                let _ = channel.send(register_artifact(&mut db, root, &archive));
+                let _ = channel.send(register_artifact(&mut db, artifacts, written));
             }
             Task::GetArtifact { artifact, channel } => {
                 let _ = channel.send(get_artifact(&mut db, artifact));
             }
-            Task::DecodeArtifact {
+            Task::CollectGarbage {
+                artifacts,
+                grace,
+                channel,
+            } => {
+                let _ = channel.send(collect_garbage(&mut db, artifacts, grace));
+            }
+            Task::FetchArtifact {
                 artifact,
-                root,
+                artifacts,
                 channel,
             } => {
-                let _ = channel.send(decode_artifact(root, artifact));
+                let _ = channel.send(fetch_artifact(artifacts, artifact));
             }
             Task::Shutdown => break,
         }
@@ -224,16 +627,30 @@ fn processing_thread(mut db: Connection, mut rx: mpsc::Receiver<Task>) {
 /// A store using SQLite as a database, and locally stored artifacts
 pub struct SqliteStore {
     tx: mpsc::Sender<Task>,
-    root: PathBuf,
+    artifacts: Artifacts,
+    grace: Duration,
 }
 
 impl SqliteStore {
-    pub fn new(mut root: PathBuf) -> Result<Self, Error> {
+    #[inline]
+    pub fn new(root: PathBuf) -> Result<Self, Error> {
+        Self::with_layout(root, Layout::default())
+    }
+
+    /// Opens a store, laying out its artifacts according to `layout`.
+    ///
+    /// Artifacts left in a different layout, by an older version or a different `layout`, are moved over.
+    pub fn with_layout(mut root: PathBuf, layout: Layout) -> Result<Self, Error> {
         root.push("artifacts");
         ensure_dir(&root).wrap()?;
 
         let db = Connection::open(root.join("store.db")).wrap()?;
+        db.busy_timeout(BUSY_TIMEOUT).wrap()?;
         db.execute_batch(include_str!("initialize.sql")).wrap()?;
+        migrate(&db)?;
+
+        let artifacts = Artifacts { root, layout };
+        artifacts.migrate()?;
 
         let (tx, rx) = mpsc::channel(16);
 
@@ -242,7 +659,86 @@ impl SqliteStore {
             .spawn(move || processing_thread(db, rx))
             .wrap()?;
 
-        Ok(Self { tx, root })
+        Ok(Self {
+            tx,
+            artifacts,
+            grace: GC_GRACE_PERIOD,
+        })
+    }
+
+    /// Sets how old an unreferenced artifact must be before it's collected, see [`GC_GRACE_PERIOD`].
+    #[inline]
+    pub fn with_grace_period(mut self, grace: Duration) -> Self {
+        self.grace = grace;
+        self
+    }
+
+    /// Keeps the `keep` most recent packages per name, removing the rest and
+    /// collecting the artifacts they leave unreferenced.
+    ///
+    /// Packages registered before names were recorded are never removed.
+    ///
+    /// Returns the amount of packages removed.
+    pub async fn prune(&self, keep: usize) -> Result<usize, Error> {
+        let pruned = self
+            .queue(|channel| Task::PrunePackages { keep, channel })
+            .await?;
+        let collected = self.gc().await?;
+        tracing::debug!(pruned, collected = collected.len(), "pruned store");
+
+        Ok(pruned)
+    }
+
+    /// Sums the size of the artifacts referenced by each package name, sorted by name.
+    ///
+    /// Artifacts registered before sizes were recorded count as empty.
+    pub async fn disk_usage(&self) -> Result<Vec<(PackageName, u64)>, Error> {
+        self.queue(|channel| Task::DiskUsage { channel }).await
+    }
+
+    /// Records how long the latest build of a package named `name` took, replacing the previous record.
+    pub async fn record_build_duration(
+        &self,
+        name: &PackageName,
+        duration: Duration,
+    ) -> Result<(), Error> {
+        self.queue(|channel| Task::RecordBuildDuration {
+            name: name.clone(),
+            duration,
+            channel,
+        })
+        .await
+    }
+
+    /// Gets how long the latest recorded build of a package named `name` took.
+    pub async fn last_build_duration(&self, name: &PackageName) -> Result<Option<Duration>, Error> {
+        self.queue(|channel| Task::LastBuildDuration {
+            name: name.clone(),
+            channel,
+        })
+        .await
+    }
+
+    /// Deletes every artifact no package references, returning their ids.
+    ///
+    /// Artifacts registered within the store's grace period are kept, see [`Self::with_grace_period`].
+    pub async fn gc(&self) -> Result<Vec<ArtifactId>, Error> {
+        self.queue(|channel| Task::CollectGarbage {
+            artifacts: self.artifacts.clone(),
+            grace: self.grace,
+            channel,
+        })
+        .await
+    }
+
+    /// Maps an artifact's file on the processing thread, to be decoded outside of it.
+    async fn fetch(&self, artifact: &ArtifactId) -> Result<Option<Bytes>, Error> {
+        self.queue(|channel| Task::FetchArtifact {
+            artifact: *artifact,
+            artifacts: self.artifacts.clone(),
+            channel,
+        })
+        .await
     }
 
     async fn queue<R>(
@@ -251,7 +747,12 @@ impl SqliteStore {
     ) -> Result<R, Error> {
         let (req_tx, resp_rx) = oneshot::channel();
 
-        self.tx.send(task(req_tx)).await.wrap()?;
+        // the task isn't `Sync`, so it can't be carried by the report
+        self.tx
+            .send(task(req_tx))
+            .await
+            .map_err(|_| mpsc::error::SendError(()))
+            .wrap()?;
         resp_rx.await.wrap().flatten()
     }
 }
@@ -264,16 +765,28 @@ impl Store for SqliteStore {
 
     fn register_package(
         &mut self,
+        name: &PackageName,
         package: &PackageId,
         artifact: &ArtifactId,
     ) -> impl Future<Output = Result<StorePackage, Error>> {
         self.queue(|channel| Task::RegisterPackage {
+            name: name.clone(),
             package: *package,
             artifact: *artifact,
             channel,
         })
     }
 
+    fn unregister_package(
+        &mut self,
+        package: &PackageId,
+    ) -> impl Future<Output = Result<bool, Error>> {
+        self.queue(|channel| Task::UnregisterPackage {
+            package: *package,
+            channel,
+        })
+    }
+
     fn package(
         &self,
         package: &PackageId,
@@ -284,15 +797,41 @@ impl Store for SqliteStore {
         })
     }
 
+    fn list_packages(&self) -> impl Future<Output = Result<Vec<StorePackage>, Error>> {
+        self.queue(|channel| Task::ListPackages { channel })
+    }
+
     fn register_artifact(
         &mut self,
         archive: Vec<Event>,
     ) -> impl Future<Output = Result<StoreArtifact, Error>> {
-        self.queue(|channel| Task::RegisterArtifact {
-            archive,
-            channel,
-            root: self.root.clone(),
-        })
+        self.register_artifact_iter(archive.into_iter().map(Ok))
+    }
+
+    fn register_artifact_iter<I>(
+        &mut self,
+        archive: I,
+    ) -> impl Future<Output = Result<StoreArtifact, Error>>
+    where
+        I: Iterator<Item = Result<Event, Error>> + Send + 'static,
+    {
+        let artifacts = self.artifacts.clone();
+        async move {
+            let written = {
+                let artifacts = artifacts.clone();
+                tokio::task::spawn_blocking(move || write_artifact(&artifacts, Box::new(archive)))
+                    .await
+                    .wrap()
+                    .flatten()?
+            };
+
+            self.queue(|channel| Task::RegisterArtifact {
+                written,
+                artifacts,
+                channel,
+            })
+            .await
+        }
     }
 
     fn artifact(
@@ -305,15 +844,62 @@ impl Store for SqliteStore {
         })
     }
 
-    fn download(
+    async fn download(&self, artifact: &ArtifactId) -> Result<Option<Vec<Event>>, Error> {
+        let Some(mut archive) = self.fetch(artifact).await? else {
+            return Ok(None);
+        };
+
+        tokio::task::spawn_blocking(move || {
+            Decoder::new()
+                .decode_iter(&mut archive)
+                .collect::<Result<Vec<_>, _>>()
+                .wrap()
+        })
+        .await
+        .wrap()
+        .flatten()
+        .map(Some)
+    }
+
+    async fn download_iter<F, T>(&self, artifact: &ArtifactId, f: F) -> Result<Option<T>, Error>
+    where
+        F: FnOnce(&mut dyn Iterator<Item = Result<Event, Error>>) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let Some(mut archive) = self.fetch(artifact).await? else {
+            return Ok(None);
+        };
+
+        tokio::task::spawn_blocking(move || {
+            let mut decoder = Decoder::new();
+            let mut events = decoder
+                .decode_iter(&mut archive)
+                .map(|result| result.wrap());
+            f(&mut events)
+        })
+        .await
+        .wrap()
+        .map(Some)
+    }
+
+    fn extract(
         &self,
         artifact: &ArtifactId,
-    ) -> impl Future<Output = Result<Option<Vec<Event>>, Error>> {
-        self.queue(|channel| Task::DecodeArtifact {
-            artifact: *artifact,
-            root: self.root.clone(),
-            channel,
-        })
+        dest: &Path,
+    ) -> impl Future<Output = Result<(), Error>> {
+        let dest = dest.to_path_buf();
+        async move {
+            let artifact = *artifact;
+            let archive = self
+                .fetch(&artifact)
+                .await?
+                .ok_or_else(|| MissingArtifactError { artifact }.wrap())?;
+
+            tokio::task::spawn_blocking(move || extract_artifact(archive, dest))
+                .await
+                .wrap()
+                .flatten()
+        }
     }
 }
 
@@ -323,7 +909,545 @@ impl Drop for SqliteStore {
     }
 }
 
-fn artifact_path(mut root: PathBuf, artifact: &ArtifactId) -> PathBuf {
-    root.push(artifact.to_string());
-    root
+/// Brings a database created by an older version up to date, tracked by `user_version`.
+///
+/// Databases from a newer version are rejected, rather than risking misreading their schema.
+fn migrate(db: &Connection) -> Result<(), Error> {
+    let found: u32 = db
+        .pragma_query_value(None, "user_version", |row| row.get(0))
+        .wrap()?;
+    let supported = MIGRATIONS.len() as u32;
+    if found > supported {
+        return Err(SchemaVersionError { found, supported }.wrap());
+    }
+
+    for migration in &MIGRATIONS[found as usize..] {
+        db.execute_batch(migration).wrap()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{path::Path, time::Duration};
+
+    use bytes::{Bytes, BytesMut};
+    use rusqlite::{Connection, OptionalExtension};
+    use xh_archive::{Event, Object, encoding::Encoder, packing::Packer};
+    use xh_engine::{
+        builder::Builder,
+        name::PackageName,
+        planner::PackageId,
+        store::{ArtifactId, Store},
+    };
+
+    use crate::{Layout, MIGRATIONS, SqliteStore, prune_packages};
+
+    fn archive(contents: &'static [u8]) -> Vec<Event> {
+        vec![
+            Event::Header,
+            Event::Object(Object::file(
+                Bytes::from_static(b"file"),
+                0o644,
+                Bytes::from_static(contents),
+            )),
+            Event::Footer(Vec::new()),
+        ]
+    }
+
+    fn refcount(root: &Path, artifact: &ArtifactId) -> Option<i64> {
+        Connection::open(root.join("artifacts/store.db"))
+            .unwrap()
+            .query_one(
+                "SELECT refcount FROM artifacts WHERE id IS ?1",
+                [artifact.as_bytes()],
+                |row| row.get(0),
+            )
+            .optional()
+            .unwrap()
+    }
+
+    fn user_version(db: &Connection) -> u32 {
+        db.pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_migrate() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let path = root.join("artifacts/store.db");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+
+        // a v1 database, from before packages had names
+        let db = Connection::open(&path).unwrap();
+        db.execute_batch(include_str!("initialize.sql")).unwrap();
+        db.execute_batch(MIGRATIONS[0]).unwrap();
+        db.execute(
+            "INSERT INTO artifacts (id, created_at) VALUES (?1, NULL)",
+            [b"artifact".as_slice()],
+        )
+        .unwrap();
+        db.execute(
+            "INSERT INTO packages (id, artifact, created_at) VALUES (?1, ?2, NULL)",
+            [b"package".as_slice(), b"artifact".as_slice()],
+        )
+        .unwrap();
+        assert_eq!(user_version(&db), 1);
+        drop(db);
+
+        for _ in 0..2 {
+            drop(SqliteStore::new(root.to_path_buf()).unwrap());
+
+            let db = Connection::open(&path).unwrap();
+            assert_eq!(user_version(&db), MIGRATIONS.len() as u32);
+            let (name, refcount): (String, i64) = db
+                .query_one(
+                    "SELECT packages.name, artifacts.refcount FROM packages JOIN artifacts ON packages.artifact = artifacts.id",
+                    [],
+                    |row| Ok((row.get(0)?, row.get(1)?)),
+                )
+                .unwrap();
+            assert_eq!((name.as_str(), refcount), ("", 1));
+        }
+
+        // unnamed packages aren't pruned as if they were versions of one package
+        let mut db = Connection::open(&path).unwrap();
+        assert_eq!(prune_packages(&mut db, 0).unwrap(), 0);
+        drop(db);
+
+        // newer than supported
+        let db = Connection::open(&path).unwrap();
+        db.pragma_update(None, "user_version", MIGRATIONS.len() as u32 + 1)
+            .unwrap();
+        drop(db);
+        assert!(SqliteStore::new(root.to_path_buf()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_migrate_names() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let path = root.join("artifacts/store.db");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+
+        // a v4 database, from before names were displayed with their kind
+        let db = Connection::open(&path).unwrap();
+        db.execute_batch(include_str!("initialize.sql")).unwrap();
+        for migration in &MIGRATIONS[..4] {
+            db.execute_batch(migration).unwrap();
+        }
+        db.execute(
+            "INSERT INTO artifacts (id, created_at) VALUES (?1, NULL)",
+            [b"artifact".as_slice()],
+        )
+        .unwrap();
+        for (id, name) in [
+            (b"unnamed".as_slice(), ""),
+            (b"curl", "curl@xuehua"),
+            (b"hello", "hello"),
+        ] {
+            db.execute(
+                "INSERT INTO packages (id, name, artifact, created_at) VALUES (?1, ?2, ?3, NULL)",
+                (id, name, b"artifact".as_slice()),
+            )
+            .unwrap();
+        }
+        db.execute(
+            "INSERT INTO build_durations (name, milliseconds) VALUES ('curl@xuehua', 1000), ('hello', 2000)",
+            [],
+        )
+        .unwrap();
+        drop(db);
+
+        // unnamespaced names are displayed without an `@`, but still gain their kind
+        let store = SqliteStore::new(root.to_path_buf()).unwrap();
+        let name = PackageName::new("curl", ["xuehua".into()]);
+        let bare = PackageName::new("hello", []);
+        assert_eq!(
+            store.last_build_duration(&name).await.unwrap(),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(
+            store.last_build_duration(&bare).await.unwrap(),
+            Some(Duration::from_secs(2))
+        );
+        drop(store);
+
+        let db = Connection::open(&path).unwrap();
+        let names: Vec<String> = db
+            .prepare("SELECT name FROM packages ORDER BY name")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(names, ["", &name.to_string(), &bare.to_string()]);
+
+        let parsed: Vec<PackageName> = names[1..]
+            .iter()
+            .map(|name| name.parse().unwrap())
+            .collect();
+        assert_eq!(parsed, [name, bare]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_concurrent_stores() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+
+        // every store has its own connection, like separate processes sharing a store
+        let stores: Vec<_> = (0..8)
+            .map(|_| SqliteStore::new(root.to_path_buf()).unwrap())
+            .collect();
+        let tasks: Vec<_> = stores
+            .into_iter()
+            .enumerate()
+            .map(|(index, mut store)| {
+                tokio::spawn(async move {
+                    let name = PackageName::new(format!("package-{index}"), []);
+                    for version in 0..16 {
+                        let contents = format!("{index}-{version}").into_bytes();
+                        let events = vec![
+                            Event::Header,
+                            Event::Object(Object::file(
+                                Bytes::from_static(b"file"),
+                                0o644,
+                                contents.into(),
+                            )),
+                            Event::Footer(Vec::new()),
+                        ];
+
+                        let artifact = store.register_artifact(events).await.unwrap().id;
+                        let package = PackageId::from(xh_common::random_hash());
+                        store
+                            .register_package(&name, &package, &artifact)
+                            .await
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        let store = SqliteStore::new(root.to_path_buf()).unwrap();
+        assert_eq!(store.list_packages().await.unwrap().len(), 8 * 16);
+    }
+
+    #[tokio::test]
+    async fn test_refcount() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let mut store = SqliteStore::new(root.to_path_buf()).unwrap();
+
+        let shared = store
+            .register_artifact(archive(b"shared"))
+            .await
+            .unwrap()
+            .id;
+        let single = store
+            .register_artifact(archive(b"single"))
+            .await
+            .unwrap()
+            .id;
+        let [first, second, third] = [(); 3].map(|()| PackageId::from(xh_common::random_hash()));
+        let name = PackageName::new("package", []);
+
+        store
+            .register_package(&name, &first, &shared)
+            .await
+            .unwrap();
+        store
+            .register_package(&name, &second, &shared)
+            .await
+            .unwrap();
+        store
+            .register_package(&name, &third, &single)
+            .await
+            .unwrap();
+        assert_eq!(refcount(root, &shared), Some(2));
+        assert_eq!(refcount(root, &single), Some(1));
+
+        // re-registering moves the reference
+        store
+            .register_package(&name, &second, &single)
+            .await
+            .unwrap();
+        assert_eq!(refcount(root, &shared), Some(1));
+        assert_eq!(refcount(root, &single), Some(2));
+
+        assert!(store.unregister_package(&second).await.unwrap());
+        assert!(store.unregister_package(&third).await.unwrap());
+        assert!(!store.unregister_package(&third).await.unwrap());
+        assert_eq!(refcount(root, &single), Some(0));
+
+        // freshly registered artifacts are within the grace period
+        assert!(store.gc().await.unwrap().is_empty());
+        let store = SqliteStore::new(root.to_path_buf())
+            .unwrap()
+            .with_grace_period(Duration::ZERO);
+        assert_eq!(store.gc().await.unwrap(), [single]);
+        assert_eq!(refcount(root, &single), None);
+        assert_eq!(refcount(root, &shared), Some(1));
+        assert!(store.download(&single).await.unwrap().is_none());
+        assert!(store.download(&shared).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_layout() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path();
+        let artifacts = root.join("artifacts");
+
+        let mut store = SqliteStore::with_layout(root.to_path_buf(), Layout::Flat).unwrap();
+        let mut ids = Vec::new();
+        for contents in [b"first", b"other"] {
+            ids.push(store.register_artifact(archive(contents)).await.unwrap().id);
+        }
+        for id in &ids {
+            assert!(artifacts.join(id.to_hex().as_str()).is_file());
+        }
+        drop(store);
+
+        // flat artifacts are moved into shards on open
+        let store = SqliteStore::new(root.to_path_buf()).unwrap();
+        for id in &ids {
+            let hex = id.to_hex();
+            let (shard, rest) = hex.split_at(2);
+            assert!(artifacts.join(shard).join(rest).is_file());
+            assert!(!artifacts.join(hex.as_str()).exists());
+            assert!(store.download(id).await.unwrap().is_some());
+        }
+
+        let mut entries: Vec<_> = std::fs::read_dir(&artifacts)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .filter(|name| !name.starts_with("store.db"))
+            .collect();
+        entries.sort();
+        let mut shards: Vec<_> = ids.iter().map(|id| id.to_hex()[..2].to_string()).collect();
+        shards.sort();
+        shards.dedup();
+        assert_eq!(entries, shards);
+        drop(store);
+
+        // and back
+        let store = SqliteStore::with_layout(root.to_path_buf(), Layout::Flat).unwrap();
+        for id in &ids {
+            assert!(artifacts.join(id.to_hex().as_str()).is_file());
+            assert!(!artifacts.join(&id.to_hex()[..2]).exists());
+            assert!(store.download(id).await.unwrap().is_some());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_extract() {
+        let temp = tempfile::tempdir().unwrap();
+        let mut store = SqliteStore::new(temp.path().to_path_buf()).unwrap();
+
+        let artifact = store
+            .register_artifact(archive(b"extracted"))
+            .await
+            .unwrap()
+            .id;
+
+        let dest = temp.path().join("export/nested");
+        store.extract(&artifact, &dest).await.unwrap();
+        assert_eq!(std::fs::read(dest.join("file")).unwrap(), b"extracted");
+
+        let missing = xh_common::random_hash();
+        let report = store
+            .extract(&missing, &temp.path().join("missing"))
+            .await
+            .unwrap_err();
+        assert_eq!(report.root_cause().message, "artifact is not in the store");
+        assert!(!temp.path().join("missing").exists());
+    }
+
+    #[tokio::test]
+    async fn test_artifact_stats() {
+        let temp = tempfile::tempdir().unwrap();
+        let mut store = SqliteStore::new(temp.path().to_path_buf()).unwrap();
+
+        let mut events = archive(b"measured");
+        events.insert(
+            1,
+            Event::Object(Object::directory(Bytes::from_static(b"dir"), 0o755)),
+        );
+        let registered = store.register_artifact(events).await.unwrap();
+        let size = std::fs::metadata(store.artifacts.path(&registered.id))
+            .unwrap()
+            .len();
+
+        let artifact = store.artifact(&registered.id).await.unwrap().unwrap();
+        assert_eq!(artifact.size, Some(size));
+        assert_eq!(artifact.objects, Some(2));
+        assert_eq!(registered.size, artifact.size);
+
+        // shared artifacts count once per name
+        let other = store.register_artifact(archive(b"other")).await.unwrap();
+        let name = PackageName::new("package", ["tests".into()]);
+        for artifact in [&registered, &registered, &other] {
+            let package = PackageId::from(xh_common::random_hash());
+            store
+                .register_package(&name, &package, &artifact.id)
+                .await
+                .unwrap();
+        }
+        let unrelated = PackageName::new("unrelated", ["tests".into()]);
+        store
+            .register_package(&unrelated, &xh_common::random_hash(), &other.id)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.disk_usage().await.unwrap(),
+            [
+                (name, size + other.size.unwrap()),
+                (unrelated, other.size.unwrap())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_packages() {
+        let temp = tempfile::tempdir().unwrap();
+        let mut store = SqliteStore::new(temp.path().to_path_buf()).unwrap();
+
+        let archive = vec![Event::Header, Event::Footer(Vec::new())];
+        let artifact = store.register_artifact(archive).await.unwrap().id;
+
+        // versions of each name are registered interleaved, oldest first
+        let mut registered = Vec::new();
+        for identifier in ["beta", "alpha", "beta", "alpha"] {
+            let package = PackageId::from(xh_common::random_hash());
+            let name = PackageName::new(identifier, ["tests".into()]);
+            store
+                .register_package(&name, &package, &artifact)
+                .await
+                .unwrap();
+            registered.push((name, package));
+            tokio::time::sleep(Duration::from_millis(2)).await;
+        }
+
+        let listed: Vec<_> = store
+            .list_packages()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|package| (package.name, package.id))
+            .collect();
+        let expected = [3, 1, 2, 0].map(|index| registered[index].clone());
+        assert_eq!(listed, expected);
+    }
+
+    #[tokio::test]
+    async fn test_build_durations() {
+        let temp = tempfile::tempdir().unwrap();
+        let store = SqliteStore::new(temp.path().to_path_buf()).unwrap();
+        let name = PackageName::new("package", ["tests".into()]);
+        assert_eq!(store.last_build_duration(&name).await.unwrap(), None);
+
+        for seconds in [90, 45] {
+            store
+                .record_build_duration(&name, Duration::from_secs(seconds))
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            store.last_build_duration(&name).await.unwrap(),
+            Some(Duration::from_secs(45))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_prune() {
+        let temp = tempfile::tempdir().unwrap();
+        let mut store = SqliteStore::new(temp.path().to_path_buf())
+            .unwrap()
+            .with_grace_period(Duration::ZERO);
+
+        let name = PackageName::new("package", ["tests".into()]);
+        let other = PackageName::new("other", ["tests".into()]);
+        let mut versions = Vec::new();
+        for contents in [b"v1", b"v2", b"v3", b"v4", b"v5"] {
+            let artifact = store.register_artifact(archive(contents)).await.unwrap().id;
+            let package = PackageId::from(xh_common::random_hash());
+            store
+                .register_package(&name, &package, &artifact)
+                .await
+                .unwrap();
+            versions.push((package, artifact));
+        }
+
+        let artifact = versions[0].1;
+        let unrelated = PackageId::from(xh_common::random_hash());
+        store
+            .register_package(&other, &unrelated, &artifact)
+            .await
+            .unwrap();
+
+        assert_eq!(store.prune(2).await.unwrap(), 3);
+
+        let mut remaining: Vec<_> = store
+            .list_packages()
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|package| package.name == name)
+            .map(|package| package.id)
+            .collect();
+        remaining.sort_by_key(|package| *package.as_bytes());
+        let mut expected = vec![versions[3].0, versions[4].0];
+        expected.sort_by_key(|package| *package.as_bytes());
+        assert_eq!(remaining, expected);
+
+        // the oldest artifact is still referenced by another package
+        assert!(store.download(&versions[0].1).await.unwrap().is_some());
+        for (_, artifact) in &versions[1..3] {
+            assert!(store.download(artifact).await.unwrap().is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fetch_into_store() {
+        let temp = tempfile::tempdir().unwrap();
+        let mut store = SqliteStore::new(temp.path().to_path_buf()).unwrap();
+        let builder = Builder::new(temp.path().join("builds"));
+
+        let package = PackageId::from(xh_common::random_hash());
+        let output = temp.path().join(format!("builds/{package}/output"));
+        std::fs::create_dir_all(output.join("lib")).unwrap();
+        let large: Vec<u8> = (0..16 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+        std::fs::write(output.join("lib/large.bin"), &large).unwrap();
+        std::fs::write(output.join("small"), "xuehua").unwrap();
+
+        let artifact = builder
+            .fetch_into_store(&package, &mut store)
+            .await
+            .unwrap()
+            .expect("build output should exist");
+
+        let packed = Packer::new(output)
+            .pack_iter()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let mut encoder = Encoder::new();
+        for event in &packed {
+            encoder.encode(&mut BytesMut::new(), event).unwrap();
+        }
+
+        assert_eq!(artifact.id.as_bytes(), encoder.digest().as_bytes());
+        assert_eq!(store.download(&artifact.id).await.unwrap(), Some(packed));
+        assert!(
+            builder
+                .fetch_into_store(&xh_common::random_hash(), &mut store)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
 }
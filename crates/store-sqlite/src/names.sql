@@ -0,0 +1,5 @@
+BEGIN;
+ALTER TABLE packages ADD COLUMN name TEXT NOT NULL DEFAULT '';
+CREATE INDEX IF NOT EXISTS packages_by_name ON packages(name);
+PRAGMA user_version = 2;
+COMMIT;
@@ -0,0 +1,17 @@
+BEGIN;
+ALTER TABLE artifacts ADD COLUMN refcount INTEGER NOT NULL DEFAULT 0;
+UPDATE artifacts SET refcount = (
+    SELECT COUNT(*) FROM packages WHERE packages.artifact = artifacts.id
+);
+CREATE TRIGGER IF NOT EXISTS package_inserted AFTER INSERT ON packages BEGIN
+    UPDATE artifacts SET refcount = refcount + 1 WHERE id = NEW.artifact;
+END;
+CREATE TRIGGER IF NOT EXISTS package_deleted AFTER DELETE ON packages BEGIN
+    UPDATE artifacts SET refcount = refcount - 1 WHERE id = OLD.artifact;
+END;
+CREATE TRIGGER IF NOT EXISTS package_updated AFTER UPDATE OF artifact ON packages BEGIN
+    UPDATE artifacts SET refcount = refcount - 1 WHERE id = OLD.artifact;
+    UPDATE artifacts SET refcount = refcount + 1 WHERE id = NEW.artifact;
+END;
+PRAGMA user_version = 1;
+COMMIT;
@@ -0,0 +1,5 @@
+BEGIN;
+ALTER TABLE artifacts ADD COLUMN size INTEGER;
+ALTER TABLE artifacts ADD COLUMN objects INTEGER;
+PRAGMA user_version = 3;
+COMMIT;